@@ -0,0 +1,65 @@
+//! Syncs LED colors to a temperature sensor using the async API.
+//!
+//! ```bash
+//! cargo run --example async_temp --features async -- /sys/class/hwmon/hwmon4/temp1_input
+//! ```
+//!
+//! The futures are executor-agnostic, a minimal executor is used here
+//! to keep the example self-contained.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+
+use nzxtcli::{Color, FindOptions, find_controllers_async};
+
+const BASE_TEMP: f32 = 30.0;
+const WARN_TEMP: f32 = 80.0;
+const INTERVAL: Duration = Duration::from_secs(1);
+
+fn main() -> anyhow::Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| anyhow::anyhow!("usage: async_temp <hwmon temp input>"))?;
+
+    block_on(async {
+        let controllers = find_controllers_async(FindOptions::new()).await?;
+        anyhow::ensure!(!controllers.is_empty(), "no controllers found");
+
+        loop {
+            let millidegrees: f32 = std::fs::read_to_string(&path)?.trim().parse()?;
+            let t = ((millidegrees / 1000.0 - BASE_TEMP) / (WARN_TEMP - BASE_TEMP)).clamp(0.0, 1.0);
+            let color = Color::new((255.0 * t) as u8, 0, (255.0 * (1.0 - t)) as u8);
+
+            for controller in &controllers {
+                if let Err(e) = controller.set_fixed_color(color).await {
+                    eprintln!("{}: {e}", controller.name());
+                }
+            }
+
+            // A real application would use the timer of its executor.
+            std::thread::sleep(INTERVAL);
+        }
+    })
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
@@ -0,0 +1,336 @@
+//! Async wrappers around the blocking controller API.
+//!
+//! Blocking HID calls are executed on a dedicated thread per controller,
+//! so the futures don't depend on a particular executor.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, mpsc};
+use std::task::{Context, Poll, Waker};
+
+use hidapi::HidApi;
+
+use crate::controller::{AioStatus, Controller, Direction, FanStatus, LedMode, RgbChannel};
+use crate::error::{Error, Result};
+use crate::types::Color;
+use crate::{FindOptions, find_controllers_with};
+
+/// Controller which can be used from async code.
+///
+/// Requests are executed one by one in the order they were made. Dropping
+/// a future doesn't cancel a request which was already started: it is
+/// executed to completion in the background, so a frame is either fully
+/// written (and tracked as applied) or not applied at all.
+pub struct AsyncController {
+    jobs: mpsc::Sender<Job>,
+    name: &'static str,
+    product_id: u16,
+    rgb_channels: Vec<RgbChannel>,
+    fan_channels: usize,
+    is_liquid_cooler: bool,
+}
+
+type Job = Box<dyn FnOnce(&mut dyn Controller) + Send>;
+
+impl AsyncController {
+    /// Moves the controller to a new worker thread.
+    pub fn new(controller: Box<dyn Controller>) -> Self {
+        let name = controller.name();
+        let product_id = controller.product_id();
+        let rgb_channels = controller.rgb_channels().to_vec();
+        let fan_channels = controller.fan_channels();
+        let is_liquid_cooler = controller.is_liquid_cooler();
+
+        let (jobs, queue) = mpsc::channel::<Job>();
+        std::thread::spawn(move || {
+            let mut controller = controller;
+            for job in queue {
+                job(controller.as_mut());
+            }
+        });
+
+        Self {
+            jobs,
+            name,
+            product_id,
+            rgb_channels,
+            fan_channels,
+            is_liquid_cooler,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn product_id(&self) -> u16 {
+        self.product_id
+    }
+
+    /// Channel info at the time the controller was created or reconnected.
+    pub fn rgb_channels(&self) -> &[RgbChannel] {
+        &self.rgb_channels
+    }
+
+    pub fn fan_channels(&self) -> usize {
+        self.fan_channels
+    }
+
+    pub fn is_liquid_cooler(&self) -> bool {
+        self.is_liquid_cooler
+    }
+
+    pub async fn set_fixed_color(&self, color: Color) -> Result<()> {
+        self.call(move |c| c.set_fixed_color(color)).await
+    }
+
+    pub async fn set_channel_color(&self, channel: usize, color: Color) -> Result<()> {
+        self.call(move |c| c.set_channel_color(channel, color))
+            .await
+    }
+
+    pub async fn set_channel_leds(&self, channel: usize, colors: Vec<Color>) -> Result<()> {
+        self.call(move |c| c.set_channel_leds(channel, &colors))
+            .await
+    }
+
+    pub async fn set_mode(
+        &self,
+        channel: usize,
+        mode: LedMode,
+        colors: Vec<Color>,
+        speed: u8,
+        direction: Direction,
+    ) -> Result<()> {
+        self.call(move |c| c.set_mode(channel, mode, &colors, speed, direction))
+            .await
+    }
+
+    pub async fn set_brightness(&self, channel: usize, brightness: u8) -> Result<()> {
+        self.call(move |c| c.set_brightness(channel, brightness))
+            .await
+    }
+
+    pub async fn set_fan_duty(&self, fan_channel: usize, percent: u8) -> Result<()> {
+        self.call(move |c| c.set_fan_duty(fan_channel, percent))
+            .await
+    }
+
+    pub async fn fan_status(&self) -> Result<Vec<FanStatus>> {
+        self.call(|c| c.fan_status()).await
+    }
+
+    pub async fn aio_status(&self) -> Result<AioStatus> {
+        self.call(|c| c.aio_status()).await
+    }
+
+    pub async fn set_pump_duty(&self, percent: u8) -> Result<()> {
+        self.call(move |c| c.set_pump_duty(percent)).await
+    }
+
+    pub async fn is_connected(&self) -> Result<bool> {
+        self.call(|c| Ok(c.is_connected())).await
+    }
+
+    /// See [`Controller::reconnect`], the channel info is updated since the
+    /// accessories might have changed.
+    pub async fn reconnect(&mut self, api: Arc<HidApi>) -> Result<()> {
+        let rgb_channels = self
+            .call(move |c| {
+                c.reconnect(&api)?;
+                Ok(c.rgb_channels().to_vec())
+            })
+            .await?;
+        self.rgb_channels = rgb_channels;
+        Ok(())
+    }
+
+    fn call<T, F>(&self, f: F) -> Response<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn Controller) -> Result<T> + Send + 'static,
+    {
+        let (completer, response) = response();
+        let job: Job = Box::new(move |controller| completer.complete(f(controller)));
+        // If the worker has stopped, the job is dropped and the response fails.
+        _ = self.jobs.send(job);
+        response
+    }
+}
+
+/// Async version of [`find_controllers_with`].
+pub async fn find_controllers_async(options: FindOptions) -> Result<Vec<AsyncController>> {
+    let (completer, response) = response();
+    std::thread::spawn(move || {
+        let result = HidApi::new()
+            .map_err(Error::from)
+            .and_then(|api| find_controllers_with(&api, &options));
+        completer.complete(result);
+    });
+
+    let controllers = response.await?;
+    Ok(controllers.into_iter().map(AsyncController::new).collect())
+}
+
+fn response<T>() -> (Completer<T>, Response<T>) {
+    let slot = Arc::new(Mutex::new(Slot {
+        result: None,
+        waker: None,
+    }));
+    (Completer(Some(slot.clone())), Response(slot))
+}
+
+struct Slot<T> {
+    result: Option<Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// Sending half of the [`Response`].
+///
+/// Fails the response if dropped without a result (e.g. on panic).
+struct Completer<T>(Option<Arc<Mutex<Slot<T>>>>);
+
+impl<T> Completer<T> {
+    fn complete(mut self, result: Result<T>) {
+        if let Some(slot) = self.0.take() {
+            fill(&slot, result);
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0.take() {
+            fill(&slot, Err(Error::WorkerStopped));
+        }
+    }
+}
+
+fn fill<T>(slot: &Mutex<Slot<T>>, result: Result<T>) {
+    let mut slot = slot.lock().unwrap();
+    slot.result = Some(result);
+    if let Some(waker) = slot.waker.take() {
+        waker.wake();
+    }
+}
+
+struct Response<T>(Arc<Mutex<Slot<T>>>);
+
+impl<T> Future for Response<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.0.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Wake;
+
+    use super::*;
+    use crate::transport::{self, MockTransport};
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            std::thread::park();
+        }
+    }
+
+    fn mock_controller() -> (MockTransport, AsyncController) {
+        let (transport, controller) = transport::mock_controller(&[&[0x13]]);
+        (transport, AsyncController::new(Box::new(controller)))
+    }
+
+    #[test]
+    fn async_requests() {
+        let (transport, controller) = mock_controller();
+        assert_eq!(controller.rgb_channels()[0].led_count, 18);
+
+        block_on(controller.set_channel_leds(0, vec![Color::RED; 4])).unwrap();
+        let written = transport.written();
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[0][4..7], [0x00, 0xff, 0x00]);
+
+        let result = block_on(controller.set_pump_duty(50));
+        assert!(matches!(result, Err(Error::Unsupported(_))));
+        assert!(block_on(controller.is_connected()).unwrap());
+    }
+
+    #[test]
+    fn async_reconnect() {
+        /// Finds another accessory on each reconnect.
+        struct Replugged(Vec<RgbChannel>);
+
+        impl Controller for Replugged {
+            fn info(&self) -> Option<&hidapi::DeviceInfo> {
+                None
+            }
+
+            fn product_id(&self) -> u16 {
+                0x2012
+            }
+
+            fn name(&self) -> &'static str {
+                "test"
+            }
+
+            fn rgb_channels(&self) -> &[RgbChannel] {
+                &self.0
+            }
+
+            fn set_channel_color(&self, _channel: usize, _color: Color) -> Result<()> {
+                Ok(())
+            }
+
+            fn reconnect(&mut self, _api: &HidApi) -> Result<()> {
+                self.0[0].led_count += 18;
+                Ok(())
+            }
+        }
+
+        let mut controller = AsyncController::new(Box::new(Replugged(vec![RgbChannel::default()])));
+        let api = Arc::new(HidApi::new_without_enumerate().unwrap());
+        block_on(controller.reconnect(api.clone())).unwrap();
+        block_on(controller.reconnect(api)).unwrap();
+        assert_eq!(controller.rgb_channels()[0].led_count, 36);
+    }
+
+    #[test]
+    fn dropped_request_completes() {
+        let (transport, controller) = mock_controller();
+
+        // Start the request and drop the future before it resolves.
+        let waker = Waker::noop();
+        let mut future = Box::pin(controller.set_fixed_color(Color::BLUE));
+        _ = future.as_mut().poll(&mut Context::from_waker(waker));
+        drop(future);
+
+        // Requests are ordered, so the dropped one is finished by now.
+        block_on(controller.set_channel_color(0, Color::BLUE)).unwrap();
+        let written = transport.written();
+        assert!(!written.is_empty());
+        assert!(written.iter().all(|report| report[0] == 0x22));
+    }
+}
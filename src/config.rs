@@ -0,0 +1,1143 @@
+//! Defaults for the command line flags from `config.toml`.
+//!
+//! Only a subset of TOML is supported: tables, arrays of tables and
+//! key-value pairs with strings, numbers, booleans and arrays.
+//!
+//! Top-level keys set the global flags, tables set the options of the
+//! subcommand with the same name (e.g. `[cpu-temp]` or `[pump.set]`).
+//! Values are passed to the parser as if they were appended to the
+//! command line, so flags passed explicitly always take precedence.
+//!
+//! Arrays of tables under `[daemon]` (e.g. `[[daemon.led]]`) are not flags,
+//! they are read by the daemon with [`Config::array_tables`]. The same goes
+//! for the `[mqtt]` table of the daemon, see [`Config::table`].
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use clap::builder::Resettable;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, Command};
+use nzxtcli::ChannelSelector;
+
+/// Parsed config file.
+#[derive(Debug)]
+pub struct Config {
+    path: PathBuf,
+    tables: Vec<Table>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/nzxtcli/config.toml` (or `~/.config/nzxtcli/config.toml`).
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("nzxtcli").join("config.toml"))
+    }
+
+    /// Returns the value of `--config` if it was passed.
+    pub fn explicit_path(argv: &[OsString]) -> Option<PathBuf> {
+        let mut args = argv.iter().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                break;
+            } else if arg == "--config" {
+                return args.next().map(PathBuf::from);
+            } else if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+                return Some(PathBuf::from(path));
+            }
+        }
+        None
+    }
+
+    /// Reads the explicit config or the default one if it exists.
+    pub fn load(explicit: Option<PathBuf>) -> Result<Option<Self>> {
+        let (path, required) = match explicit {
+            Some(path) => (path, true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(None),
+            },
+        };
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read config {}", path.display()));
+            }
+        };
+        Ok(Some(Self::parse(path, &text)?))
+    }
+
+    pub fn parse(path: PathBuf, text: &str) -> Result<Self, ConfigError> {
+        let mut tables = vec![Table {
+            name: Vec::new(),
+            array: false,
+            line: 1,
+            entries: Vec::new(),
+        }];
+
+        let mut parser = Parser::new(text);
+        let error = |(line, key, message): (usize, Option<String>, String)| ConfigError {
+            path: path.clone(),
+            line,
+            key,
+            message,
+        };
+        loop {
+            let statement = match parser.statement() {
+                Ok(Some(statement)) => statement,
+                Ok(None) => break,
+                Err((line, key, message)) => {
+                    let key = key.map(|key| tables.last().unwrap().key_path(&key));
+                    return Err(error((line, key, message)));
+                }
+            };
+            match statement {
+                Statement::Table { name, array, line } => {
+                    if !array && tables.iter().any(|table| table.name == name) {
+                        let message = format!("duplicate table `[{}]`", name.join("."));
+                        return Err(error((line, None, message)));
+                    }
+                    tables.push(Table {
+                        name,
+                        array,
+                        line,
+                        entries: Vec::new(),
+                    });
+                }
+                Statement::Entry(entry) => {
+                    let table = tables.last_mut().unwrap();
+                    if table.entries.iter().any(|prev| prev.key == entry.key) {
+                        let key = table.key_path(&entry.key);
+                        return Err(error((entry.line, Some(key), "duplicate key".to_owned())));
+                    }
+                    table.entries.push(entry);
+                }
+            }
+        }
+
+        Ok(Self { path, tables })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Named channel selectors from the `[groups]` table.
+    pub fn channel_groups(&self) -> Result<BTreeMap<String, ChannelSelector>, ConfigError> {
+        let mut groups = BTreeMap::new();
+        for table in self.tables.iter().filter(|table| table.is("groups")) {
+            for entry in &table.entries {
+                let error = |message: String| {
+                    self.error(entry.line, Some(table.key_path(&entry.key)), message)
+                };
+                if entry.key.parse::<ChannelSelector>().is_ok() {
+                    return Err(error("group name shadows a channel selector".to_owned()));
+                }
+
+                let parts = match &entry.value {
+                    Value::Array(values) => values.iter().map(Value::scalar).collect(),
+                    value => vec![value.scalar()],
+                };
+                let parts = parts
+                    .into_iter()
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| error("expected an array of channels".to_owned()))?;
+                let selector = parts
+                    .join(",")
+                    .parse::<ChannelSelector>()
+                    .map_err(|e| error(e.to_string()))?;
+                groups.insert(entry.key.clone(), selector);
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Checks that all keys are known options and their values are valid.
+    ///
+    /// The command must be built, so that global args are propagated.
+    pub fn check(&self, command: &Command) -> Result<(), ConfigError> {
+        self.channel_groups()?;
+        for table in &self.tables {
+            if table.is("devices") {
+                self.device_arg(command, table)?;
+            } else if table.is_daemon_array() {
+                let name = table.name.join(".");
+                if !crate::daemon::TABLES.contains(&name.as_str()) {
+                    let message = format!("unknown array of tables `[[{name}]]`");
+                    return Err(self.error(table.line, None, message));
+                }
+            } else if !table.is("groups") && !table.is(crate::mqtt::TABLE) {
+                self.flag_args(command, table)?;
+            }
+        }
+        crate::daemon::DaemonConfig::from_config(self)?;
+        crate::mqtt::MqttConfig::from_config(self)?;
+        Ok(())
+    }
+
+    /// Tables of the `[[name]]` array (e.g. `daemon.led`) in the file order.
+    pub fn array_tables<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Fields<'a>> {
+        self.tables
+            .iter()
+            .filter(move |table| table.array && table.name.join(".") == name)
+            .enumerate()
+            .map(move |(index, table)| Fields {
+                config: self,
+                table,
+                path: format!("{name}[{index}]"),
+            })
+    }
+
+    /// A `[name]` table which is read by a command instead of setting its flags.
+    pub fn table<'a>(&'a self, name: &str) -> Option<Fields<'a>> {
+        let table = self.tables.iter().find(|table| table.is(name))?;
+        Some(Fields {
+            config: self,
+            table,
+            path: name.to_owned(),
+        })
+    }
+
+    /// Inserts the config values which were not passed explicitly into the arguments.
+    pub fn apply(
+        &self,
+        mut command: Command,
+        mut argv: Vec<OsString>,
+    ) -> Result<Vec<OsString>, ConfigError> {
+        command.build();
+        self.check(&command)?;
+
+        // Required args might only be set in the config, so errors are ignored here.
+        let Ok(matches) = command
+            .clone()
+            .ignore_errors(true)
+            .try_get_matches_from(&argv)
+        else {
+            // Help or version was requested.
+            return Ok(argv);
+        };
+        let mut path = Vec::new();
+        let mut leaf = &matches;
+        while let Some((name, sub)) = leaf.subcommand() {
+            path.push(name);
+            leaf = sub;
+        }
+        let explicit = |arg: &Arg| {
+            [&matches, leaf].into_iter().any(|matches| {
+                matches.ids().any(|id| id == arg.get_id())
+                    && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            })
+        };
+
+        // Defaults which conflict with the explicit flags are skipped as well
+        // (e.g. `cpu-temp.base` with `--ramp`).
+        let mut target = &command;
+        for name in &path {
+            target = target.find_subcommand(name).unwrap();
+        }
+        let explicit_args = target
+            .get_arguments()
+            .filter(|arg| explicit(arg))
+            .collect::<Vec<_>>();
+        let overridden = |arg: &Arg| {
+            explicit(arg)
+                || target.get_arg_conflicts_with(arg).into_iter().any(explicit)
+                || explicit_args.iter().any(|explicit| {
+                    target
+                        .get_arg_conflicts_with(explicit)
+                        .iter()
+                        .any(|conflict| conflict.get_id() == arg.get_id())
+                })
+        };
+
+        let mut args = Vec::new();
+        for table in &self.tables {
+            if table.is("devices") {
+                args.push(self.device_arg(&command, table)?);
+            } else if !table.is("groups")
+                && !table.is(crate::mqtt::TABLE)
+                && !table.is_daemon_array()
+                && (table.name.is_empty() || table.name == path)
+            {
+                for (arg, values) in self.flag_args(&command, table)? {
+                    if !overridden(arg) {
+                        args.extend(values);
+                    }
+                }
+            }
+        }
+
+        let at = argv
+            .iter()
+            .skip(1)
+            .position(|arg| arg == "--")
+            .map_or(argv.len(), |i| i + 1);
+        argv.splice(at..at, args);
+        Ok(argv)
+    }
+
+    /// Resolves the table keys to the options of its subcommand.
+    fn flag_args<'c>(
+        &self,
+        command: &'c Command,
+        table: &Table,
+    ) -> Result<Vec<(&'c Arg, Vec<OsString>)>, ConfigError> {
+        if table.array {
+            let message = format!("unknown array of tables `[[{}]]`", table.name.join("."));
+            return Err(self.error(table.line, None, message));
+        }
+
+        let mut target = command;
+        for name in &table.name {
+            target = target.find_subcommand(name).ok_or_else(|| {
+                let message = format!("unknown subcommand `{}`", table.name.join(" "));
+                self.error(table.line, None, message)
+            })?;
+        }
+
+        let mut args = Vec::with_capacity(table.entries.len());
+        for entry in &table.entries {
+            let key = table.key_path(&entry.key);
+            let arg = match entry.key.as_str() {
+                "help" | "version" | "config" => None,
+                name => target.get_arguments().find(|arg| {
+                    arg.get_long_and_visible_aliases()
+                        .is_some_and(|longs| longs.contains(&name))
+                }),
+            };
+            let arg = match arg {
+                Some(arg) if table.name.is_empty() && !arg.is_global_set() => None,
+                arg => arg,
+            };
+            let Some(arg) = arg else {
+                let message = if table.name.is_empty() {
+                    "unknown global flag".to_owned()
+                } else {
+                    format!("unknown option of `{}`", table.name.join(" "))
+                };
+                return Err(self.error(entry.line, Some(key), message));
+            };
+
+            let values = flag_values(arg, &entry.value)
+                .map_err(|message| self.error(entry.line, Some(key), message))?;
+            args.push((arg, values));
+        }
+        Ok(args)
+    }
+
+    /// Converts a `[[devices]]` entry into `--add-device`.
+    fn device_arg(&self, command: &Command, table: &Table) -> Result<OsString, ConfigError> {
+        let mut fields = [
+            ("pid", None),
+            ("rgb-channels", None),
+            ("fan-channels", None),
+        ];
+        for entry in &table.entries {
+            let key = table.key_path(&entry.key);
+            let Some((_, field)) = fields.iter_mut().find(|(name, _)| *name == entry.key) else {
+                return Err(self.error(entry.line, Some(key), "unknown device field"));
+            };
+            *field = match &entry.value {
+                Value::Integer(pid) if entry.key == "pid" => Some(format!("0x{pid:04x}")),
+                Value::String(pid) if entry.key == "pid" => Some(pid.clone()),
+                Value::Integer(count) if entry.key != "pid" => Some(count.to_string()),
+                _ => return Err(self.error(entry.line, Some(key), "expected a number")),
+            };
+        }
+
+        let mut values = Vec::with_capacity(fields.len());
+        for (name, value) in fields {
+            let value = value.ok_or_else(|| {
+                self.error(
+                    table.line,
+                    None,
+                    format!("device is missing the `{name}` field"),
+                )
+            })?;
+            values.push(value);
+        }
+
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some("add-device"))
+            .expect("`--add-device` is a global flag");
+        let value = values.join(":");
+        validate(arg, &value).map_err(|message| self.error(table.line, None, message))?;
+        Ok(format!("--add-device={value}").into())
+    }
+
+    fn error(&self, line: usize, key: Option<String>, message: impl Into<String>) -> ConfigError {
+        ConfigError {
+            path: self.path.clone(),
+            line,
+            key,
+            message: message.into(),
+        }
+    }
+}
+
+/// Returns the arguments which set the option to the config value.
+fn flag_values(arg: &Arg, value: &Value) -> Result<Vec<OsString>, String> {
+    let long = arg.get_long().unwrap_or_default();
+    if !arg.get_action().takes_values() {
+        return match value {
+            Value::Boolean(true) => Ok(vec![format!("--{long}").into()]),
+            Value::Boolean(false) => Ok(Vec::new()),
+            _ => Err("expected a boolean".to_owned()),
+        };
+    }
+
+    let values = match value {
+        Value::Array(values) if matches!(arg.get_action(), ArgAction::Append) => {
+            values.iter().collect()
+        }
+        Value::Array(_) => return Err("expected a single value".to_owned()),
+        value => vec![value],
+    };
+    values
+        .into_iter()
+        .map(|value| {
+            let value = value
+                .scalar()
+                .ok_or_else(|| "nested arrays are not supported".to_owned())?;
+            validate(arg, &value)?;
+            Ok(format!("--{long}={value}").into())
+        })
+        .collect()
+}
+
+/// Runs the value parser of the option.
+fn validate(arg: &Arg, value: &str) -> Result<(), String> {
+    // Relations with other args are checked only when the whole command line is parsed.
+    let arg = arg
+        .clone()
+        .global(false)
+        .required(false)
+        .required_unless_present(Resettable::Reset)
+        .conflicts_with(Resettable::Reset)
+        .requires(Resettable::Reset)
+        .overrides_with(Resettable::Reset);
+    let value = format!("--{}={value}", arg.get_long().unwrap_or_default());
+
+    Command::new("config")
+        .no_binary_name(true)
+        .disable_help_flag(true)
+        .arg(arg)
+        .try_get_matches_from([value])
+        .map(drop)
+        .map_err(|e| crate::clap_message(&e))
+}
+
+/// Invalid config file.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub line: usize,
+    /// Dotted path of the invalid key (e.g. `cpu-temp.interval`).
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.path.display(), self.line)?;
+        if let Some(key) = &self.key {
+            write!(f, "`{key}`: ")?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Keys of a table from [`Config::array_tables`].
+///
+/// Errors point at the table by its index (e.g. `daemon.led[1].ramp`).
+pub struct Fields<'a> {
+    config: &'a Config,
+    table: &'a Table,
+    path: String,
+}
+
+impl Fields<'_> {
+    /// Dotted path of the table with its index (e.g. `daemon.led[1]`).
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Fails on the first key which is not in the list, `what` names the table
+    /// in the message (e.g. `a fan binding`).
+    pub fn check_keys(&self, known: &[&str], what: &str) -> Result<(), ConfigError> {
+        match self
+            .table
+            .entries
+            .iter()
+            .find(|entry| !known.contains(&entry.key.as_str()))
+        {
+            Some(entry) => Err(self.key_error(entry, format!("unknown key of {what}"))),
+            None => Ok(()),
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entry(key).is_some()
+    }
+
+    /// Returns the value if it is an integer (e.g. `pid = 0x2012`).
+    pub fn integer(&self, key: &str) -> Option<i64> {
+        match self.entry(key)?.value {
+            Value::Integer(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Parses a string or a number with the `FromStr` of the type.
+    pub fn parse<T>(&self, key: &str) -> Result<Option<T>, ConfigError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.parse_with(key, str::parse)
+    }
+
+    pub fn parse_with<T, E: fmt::Display>(
+        &self,
+        key: &str,
+        parse: impl Fn(&str) -> Result<T, E>,
+    ) -> Result<Option<T>, ConfigError> {
+        let Some(entry) = self.entry(key) else {
+            return Ok(None);
+        };
+        let value = match &entry.value {
+            Value::Array(_) => return Err(self.key_error(entry, "expected a single value")),
+            value => value.scalar().unwrap_or_default(),
+        };
+        parse(&value)
+            .map(Some)
+            .map_err(|e| self.key_error(entry, e.to_string()))
+    }
+
+    /// Parses a single value or each value of an array.
+    pub fn parse_list<T>(&self, key: &str) -> Result<Vec<T>, ConfigError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let Some(entry) = self.entry(key) else {
+            return Ok(Vec::new());
+        };
+        let values = match &entry.value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        values
+            .into_iter()
+            .map(|value| {
+                let value = value
+                    .scalar()
+                    .ok_or_else(|| self.key_error(entry, "nested arrays are not supported"))?;
+                value
+                    .parse()
+                    .map_err(|e: T::Err| self.key_error(entry, e.to_string()))
+            })
+            .collect()
+    }
+
+    /// Error about the whole table, reported at its header.
+    pub fn error(&self, message: impl Into<String>) -> ConfigError {
+        self.config
+            .error(self.table.line, Some(self.path.clone()), message)
+    }
+
+    /// Error about the value of the key.
+    pub fn value_error(&self, key: &str, message: impl Into<String>) -> ConfigError {
+        match self.entry(key) {
+            Some(entry) => self.key_error(entry, message),
+            None => self.error(message),
+        }
+    }
+
+    fn entry(&self, key: &str) -> Option<&Entry> {
+        self.table.entries.iter().find(|entry| entry.key == key)
+    }
+
+    fn key_error(&self, entry: &Entry, message: impl Into<String>) -> ConfigError {
+        let key = format!("{}.{}", self.path, entry.key);
+        self.config.error(entry.line, Some(key), message)
+    }
+}
+
+#[derive(Debug)]
+struct Table {
+    /// Dotted name of the table header (empty for the top-level keys).
+    name: Vec<String>,
+    /// Set for the `[[name]]` tables.
+    array: bool,
+    line: usize,
+    entries: Vec<Entry>,
+}
+
+impl Table {
+    fn is(&self, name: &str) -> bool {
+        self.name.len() == 1 && self.name[0] == name
+    }
+
+    /// `[[daemon.*]]` tables are bindings of the daemon, not flags.
+    fn is_daemon_array(&self) -> bool {
+        self.array && self.name.len() > 1 && self.name[0] == "daemon"
+    }
+
+    fn key_path(&self, key: &str) -> String {
+        self.name
+            .iter()
+            .map(String::as_str)
+            .chain([key])
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// Command line representation of the value.
+    fn scalar(&self) -> Option<String> {
+        match self {
+            Self::String(value) => Some(value.clone()),
+            Self::Integer(value) => Some(value.to_string()),
+            Self::Float(value) => Some(value.to_string()),
+            Self::Boolean(value) => Some(value.to_string()),
+            Self::Array(_) => None,
+        }
+    }
+}
+
+enum Statement {
+    Table {
+        name: Vec<String>,
+        array: bool,
+        line: usize,
+    },
+    Entry(Entry),
+}
+
+/// Line, key and message of the syntax error.
+type ParseError = (usize, Option<String>, String);
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn statement(&mut self) -> Result<Option<Statement>, ParseError> {
+        self.skip_trivia();
+        let line = self.line;
+        let statement = match self.peek() {
+            None => return Ok(None),
+            Some('[') => {
+                self.bump();
+                let array = self.eat('[');
+                self.skip_whitespace();
+                let mut name = vec![self.key()?];
+                loop {
+                    self.skip_whitespace();
+                    if !self.eat('.') {
+                        break;
+                    }
+                    self.skip_whitespace();
+                    name.push(self.key()?);
+                }
+                if !self.eat(']') || (array && !self.eat(']')) {
+                    return Err(self.error("expected `]` after the table name"));
+                }
+                Statement::Table { name, array, line }
+            }
+            Some(_) => {
+                let key = self.key()?;
+                self.skip_whitespace();
+                if self.peek() == Some('.') {
+                    return Err((line, Some(key), "dotted keys are not supported".to_owned()));
+                }
+                if !self.eat('=') {
+                    return Err((line, Some(key), "expected `=` after the key".to_owned()));
+                }
+                self.skip_whitespace();
+                let value = self
+                    .value()
+                    .map_err(|(line, _, message)| (line, Some(key.clone()), message))?;
+                Statement::Entry(Entry { key, value, line })
+            }
+        };
+
+        self.skip_whitespace();
+        self.skip_comment();
+        match self.peek() {
+            None | Some('\n') => Ok(Some(statement)),
+            Some('\r') if self.chars.get(self.pos + 1) == Some(&'\n') => Ok(Some(statement)),
+            Some(_) => Err(self.error("expected a new line")),
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let key = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+                if key.is_empty() {
+                    return Err(self.error("expected a key"));
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => {
+                self.bump();
+                let mut values = Vec::new();
+                loop {
+                    self.skip_trivia();
+                    if self.eat(']') {
+                        break;
+                    }
+                    values.push(self.value()?);
+                    self.skip_trivia();
+                    if self.eat(']') {
+                        break;
+                    } else if !self.eat(',') {
+                        return Err(self.error("expected `,` or `]` in the array"));
+                    }
+                }
+                Ok(Value::Array(values))
+            }
+            Some('{') => Err(self.error("inline tables are not supported")),
+            _ => {
+                let line = self.line;
+                let word = self.take_while(|c| {
+                    c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_' | ':')
+                });
+                parse_scalar(&word).ok_or_else(|| {
+                    let message = if word.is_empty() {
+                        "expected a value".to_owned()
+                    } else {
+                        format!("invalid value `{word}` (strings must be quoted)")
+                    };
+                    (line, None, message)
+                })
+            }
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, ParseError> {
+        self.bump();
+        if self.peek() == Some('"') && self.chars.get(self.pos + 1) == Some(&'"') {
+            return Err(self.error("multi-line strings are not supported"));
+        }
+
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(value),
+                Some('\\') => {
+                    let c = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some(kind @ ('u' | 'U')) => {
+                            let len = if kind == 'u' { 4 } else { 8 };
+                            let code = (0..len).filter_map(|_| self.bump()).collect::<String>();
+                            u32::from_str_radix(&code, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    value.push(c);
+                }
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, ParseError> {
+        self.bump();
+        let value = self.take_while(|c| c != '\'' && c != '\n');
+        if !self.eat('\'') {
+            return Err(self.error("unterminated string"));
+        }
+        Ok(value)
+    }
+
+    /// Skips whitespace, comments and new lines.
+    fn skip_trivia(&mut self) {
+        loop {
+            self.skip_whitespace();
+            self.skip_comment();
+            if !self.eat('\n') && !self.eat('\r') {
+                break;
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(|c| c == ' ' || c == '\t');
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            self.take_while(|c| c != '\n');
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let mut value = String::new();
+        while let Some(c) = self.peek().filter(|&c| f(c)) {
+            value.push(c);
+            self.bump();
+        }
+        value
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matches = self.peek() == Some(c);
+        if matches {
+            self.bump();
+        }
+        matches
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        (self.line, None, message.to_owned())
+    }
+}
+
+/// Parses booleans, integers (with `0x`, `0o` and `0b` prefixes) and floats.
+fn parse_scalar(word: &str) -> Option<Value> {
+    match word {
+        "true" => return Some(Value::Boolean(true)),
+        "false" => return Some(Value::Boolean(false)),
+        "" => return None,
+        _ => {}
+    }
+
+    let digits = word.replace('_', "");
+    let (sign, unsigned) = match digits.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, digits.strip_prefix('+').unwrap_or(&digits)),
+    };
+    let radix = [("0x", 16), ("0o", 8), ("0b", 2)]
+        .into_iter()
+        .find_map(|(prefix, radix)| Some((unsigned.strip_prefix(prefix)?, radix)));
+    if let Some((digits, radix)) = radix {
+        return i64::from_str_radix(digits, radix)
+            .ok()
+            .map(|value| Value::Integer(sign * value));
+    }
+
+    if unsigned.bytes().all(|c| c.is_ascii_digit()) {
+        return digits.parse().ok().map(Value::Integer);
+    }
+    let is_float = unsigned
+        .bytes()
+        .all(|c| c.is_ascii_digit() || matches!(c, b'.' | b'e' | b'E' | b'+' | b'-'))
+        || matches!(unsigned, "inf" | "nan");
+    is_float
+        .then(|| digits.parse().ok().map(Value::Float))
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config, ConfigError> {
+        Config::parse(PathBuf::from("config.toml"), text)
+    }
+
+    fn apply(config: &Config, args: &[&str]) -> Result<Vec<String>, ConfigError> {
+        let argv = args.iter().map(OsString::from).collect();
+        let argv = config.apply(crate::App::command(), argv)?;
+        Ok(argv
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn config_syntax() {
+        let config = parse(
+            r#"
+            # comment
+            log-level = "debug" # trailing comment
+
+            [set-color]
+            "gamma" = 2.2
+            ids = [ 0x1f, -3, 1_000,
+                'C:\path', "a\"b\u00e9", # comment inside
+            ]
+            flags = [true, false, 1e3]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.tables.len(), 2);
+        assert_eq!(config.tables[0].entries[0].key, "log-level");
+        assert_eq!(config.tables[0].entries[0].line, 3);
+        let entries = &config.tables[1].entries;
+        assert_eq!(entries[0].value, Value::Float(2.2));
+        assert_eq!(
+            entries[1].value,
+            Value::Array(vec![
+                Value::Integer(0x1f),
+                Value::Integer(-3),
+                Value::Integer(1000),
+                Value::String("C:\\path".to_owned()),
+                Value::String("a\"b\u{e9}".to_owned()),
+            ])
+        );
+        assert_eq!(
+            entries[2].value,
+            Value::Array(vec![
+                Value::Boolean(true),
+                Value::Boolean(false),
+                Value::Float(1000.0)
+            ])
+        );
+        assert_eq!(entries[2].line, 10);
+
+        let error = |text| parse(text).unwrap_err().to_string();
+        assert_eq!(
+            error("[cpu-temp]\ninterval = 2s"),
+            "config.toml:2: `cpu-temp.interval`: invalid value `2s` (strings must be quoted)"
+        );
+        assert_eq!(error("a = 1\na = 2"), "config.toml:2: `a`: duplicate key");
+        assert_eq!(error("[a]\n[a]"), "config.toml:2: duplicate table `[a]`");
+        assert_eq!(error("a = \"b"), "config.toml:1: `a`: unterminated string");
+        assert_eq!(
+            error("a = { b = 1 }"),
+            "config.toml:1: `a`: inline tables are not supported"
+        );
+        assert_eq!(error("a = 1 2"), "config.toml:1: expected a new line");
+        assert_eq!(
+            error("a = [1,\n2"),
+            "config.toml:2: `a`: expected `,` or `]` in the array"
+        );
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config = parse(
+            r#"
+            serial = "ABC"
+
+            [cpu-temp]
+            interval = "2s"
+            base = 30
+            accessory-override = ["0x1f=16", "0x20=8"]
+
+            [[devices]]
+            pid = 0x2042
+            rgb-channels = 6
+            fan-channels = 3
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            apply(&config, &["nzxtcli", "cpu-temp", "/temp", "--base", "20"]).unwrap(),
+            [
+                "nzxtcli",
+                "cpu-temp",
+                "/temp",
+                "--base",
+                "20",
+                "--device-serial=ABC",
+                "--interval=2s",
+                "--accessory-override=0x1f=16",
+                "--accessory-override=0x20=8",
+                "--add-device=0x2042:6:3",
+            ]
+        );
+        assert_eq!(
+            apply(&config, &["nzxtcli", "--serial", "XYZ", "list"]).unwrap(),
+            [
+                "nzxtcli",
+                "--serial",
+                "XYZ",
+                "list",
+                "--add-device=0x2042:6:3"
+            ]
+        );
+        // `--ramp` conflicts with `--base`.
+        let argv = ["nzxtcli", "cpu-temp", "/temp", "--ramp", "30:blue,90:red"];
+        let args = apply(&config, &argv).unwrap();
+        assert!(args.iter().all(|arg| !arg.starts_with("--base")));
+        assert!(args.iter().any(|arg| arg == "--interval=2s"));
+
+        let app =
+            crate::App::try_parse_from(apply(&config, &["nzxtcli", "cpu-temp", "/temp"]).unwrap());
+        let crate::SubCmd::CpuTemp(cmd) = app.unwrap().cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(cmd.interval, Some(std::time::Duration::from_secs(2)));
+        assert_eq!(cmd.base, 30);
+    }
+
+    #[test]
+    fn config_validation() {
+        let error = |text| {
+            let config = parse(text).unwrap();
+            let mut command = crate::App::command();
+            command.build();
+            config.check(&command).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            error("[cpu-temp]\nintervl = \"2s\""),
+            "config.toml:2: `cpu-temp.intervl`: unknown option of `cpu-temp`"
+        );
+        assert_eq!(
+            error("gamma = 2.2"),
+            "config.toml:1: `gamma`: unknown global flag"
+        );
+        assert_eq!(
+            error("[frobnicate]"),
+            "config.toml:1: unknown subcommand `frobnicate`"
+        );
+        assert_eq!(
+            error("[set-color]\nsave = 1"),
+            "config.toml:2: `set-color.save`: expected a boolean"
+        );
+        assert_eq!(
+            error("[cpu-temp]\nbase = [1, 2]"),
+            "config.toml:2: `cpu-temp.base`: expected a single value"
+        );
+        assert!(error("[cpu-temp]\ninterval = \"soon\"").starts_with(
+            "config.toml:2: `cpu-temp.interval`: invalid value 'soon' for '--interval <INTERVAL>'"
+        ));
+        assert_eq!(
+            error("[[devices]]\npid = 0x2042"),
+            "config.toml:1: device is missing the `rgb-channels` field"
+        );
+        assert_eq!(
+            error("[groups]\nall = [\"0\"]"),
+            "config.toml:2: `groups.all`: group name shadows a channel selector"
+        );
+        assert_eq!(
+            error("[[daemon.leds]]"),
+            "config.toml:1: unknown array of tables `[[daemon.leds]]`"
+        );
+        assert_eq!(
+            error("[daemon]\ninterval = \"1s\"\n[[daemon.led]]\ncolor = \"nope\""),
+            "config.toml:4: `daemon.led[0].color`: invalid color: expected 3 or 6 hex digits, got `nope`"
+        );
+    }
+
+    #[test]
+    fn channel_groups() {
+        let config = parse("[groups]\nfront-fans = [\"0\", 1, \"3-4\"]\ntop = \"2\"").unwrap();
+        let groups = config.channel_groups().unwrap();
+        assert_eq!(
+            groups["front-fans"],
+            ChannelSelector::Channels(vec![0, 1, 3, 4])
+        );
+        assert_eq!(groups["top"], ChannelSelector::Channels(vec![2]));
+    }
+
+    #[test]
+    fn color_round_trip() {
+        use nzxtcli::Color;
+
+        // Colors serialized by the library are read back from the config.
+        let colors = [Color::new(0xff, 0x88, 0x00), Color::GREEN, Color::BLACK];
+        let values = colors
+            .iter()
+            .map(|color| serde_json::to_string(color).unwrap())
+            .collect::<Vec<_>>();
+        let config = parse(&format!(
+            "[daemon]\ncolor = {}\ncolors = [{}]",
+            values[0],
+            values.join(", ")
+        ))
+        .unwrap();
+        let table = config.table("daemon").unwrap();
+        assert_eq!(table.parse::<Color>("color").unwrap(), Some(colors[0]));
+        assert_eq!(table.parse_list::<Color>("colors").unwrap(), colors);
+
+        // Names and the shorthand are accepted as well.
+        let config = parse("[daemon]\ncolors = [\"orange\", \"#0f0\"]").unwrap();
+        let table = config.table("daemon").unwrap();
+        assert_eq!(table.parse_list::<Color>("colors").unwrap(), [
+            Color::new(0xff, 0xa5, 0x00),
+            Color::GREEN,
+        ]);
+    }
+
+    #[test]
+    fn example_config() {
+        let config = parse(include_str!("../contrib/config.toml")).unwrap();
+        crate::init_channel_groups(config.channel_groups().unwrap());
+
+        let mut command = crate::App::command();
+        command.build();
+        config.check(&command).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! Protocol of the `nzxtcli daemon` control socket.
+//!
+//! The daemon listens on a Unix socket (by default [`default_socket_path`]),
+//! clients send requests as JSON objects, one per line, and read a single
+//! line with the response to each of them. Several requests can be sent over
+//! the same connection.
+//!
+//! ```text
+//! > {"version":1,"command":"status"}
+//! < {"version":1,"ok":true,"result":{"pid":4242,"profile":"work",...}}
+//! > {"version":1,"command":"set-color","color":"#ff0000","channels":"0-1","duration_ms":500}
+//! < {"version":1,"ok":true,"result":{"controllers":[{"name":"...","serial":"...","channels":[0,1]}]}}
+//! > {"version":1,"command":"profile","name":"gamin"}
+//! < {"version":1,"ok":false,"error":"unknown profile `gamin`, expected one of: work, gaming"}
+//! ```
+//!
+//! Commands:
+//! - `status`: the active profile and the color, temperature and fan duty
+//!   of each bound channel, the same as `nzxtcli daemon --status`.
+//! - `set-color`: sets `color` on the `channels` (`all` by default) of the
+//!   controllers matching the optional `serial` and `product_id`. The color
+//!   overrides the bindings of the channels for `duration_ms`, or until
+//!   `release` if it's not specified.
+//! - `set-mode`: the same for a hardware effect with the `mode`, `colors`,
+//!   `speed` (`0` to `4`) and `direction` (`forward` or `backward`).
+//! - `release`: ends the overrides of the `channels` (`all` by default)
+//!   of the matching controllers, so that their bindings set them again.
+//! - `set-fan`: sets the `duty` (percent) of the `fans` (fan channels, `all`
+//!   by default) of the matching controllers, overriding their curves until
+//!   `release-fan`.
+//! - `release-fan`: returns the `fans` to their curves.
+//! - `profile`: switches to the profile `name`.
+//!
+//! Each message has the [`PROTOCOL_VERSION`] it was written for. The daemon
+//! rejects requests of other versions, new commands and optional fields
+//! can be added without changing the version, unknown fields are ignored.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::controller::{Direction, LedMode};
+use crate::types::{ChannelSelector, Color};
+
+/// Version of the messages, changed on incompatible changes only.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// `$XDG_RUNTIME_DIR/nzxtcli.sock`, `None` if the runtime dir is not set.
+pub fn default_socket_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty())?;
+    Some(PathBuf::from(dir).join("nzxtcli.sock"))
+}
+
+/// A request line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub version: u32,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Command {
+    Status,
+    SetColor {
+        color: Color,
+        #[serde(default)]
+        channels: ChannelSelector,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        serial: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        product_id: Option<u16>,
+        /// Keeps the color until `release` if not specified.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration_ms: Option<u64>,
+    },
+    SetMode {
+        mode: LedMode,
+        #[serde(default)]
+        colors: Vec<Color>,
+        /// The default speed of `set-mode` if not specified.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        speed: Option<u8>,
+        #[serde(default)]
+        direction: Direction,
+        #[serde(default)]
+        channels: ChannelSelector,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        serial: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        product_id: Option<u16>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration_ms: Option<u64>,
+    },
+    Release {
+        #[serde(default)]
+        channels: ChannelSelector,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        serial: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        product_id: Option<u16>,
+    },
+    SetFan {
+        duty: u8,
+        #[serde(default)]
+        fans: ChannelSelector,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        serial: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        product_id: Option<u16>,
+    },
+    ReleaseFan {
+        #[serde(default)]
+        fans: ChannelSelector,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        serial: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        product_id: Option<u16>,
+    },
+    Profile {
+        name: String,
+    },
+}
+
+impl Request {
+    pub fn new(command: Command) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            command,
+        }
+    }
+
+    /// Parses a request line, the error is meant to be sent back in a response.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: Option<u32>,
+        }
+
+        let header =
+            serde_json::from_str::<Header>(line).map_err(|e| format!("invalid request: {e}"))?;
+        match header.version {
+            Some(PROTOCOL_VERSION) => {}
+            Some(version) => {
+                return Err(format!(
+                    "unsupported protocol version {version}, expected {PROTOCOL_VERSION}"
+                ));
+            }
+            None => return Err("missing the protocol `version`".to_owned()),
+        }
+        serde_json::from_str(line).map_err(|e| format!("invalid request: {e}"))
+    }
+}
+
+/// A response line, either with a `result` or an `error`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub version: u32,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn ok(result: serde_json::Value) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            ok: true,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            ok: false,
+            result: None,
+            error: Some(message.into()),
+        }
+    }
+
+    pub fn into_result(self) -> Result<serde_json::Value, String> {
+        match self.ok {
+            true => Ok(self.result.unwrap_or_default()),
+            false => Err(self.error.unwrap_or_else(|| "unknown error".to_owned())),
+        }
+    }
+}
+
+/// Blocking client of the control socket.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// How long to wait for a response.
+    pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn connect(path: &Path) -> std::io::Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(Self::TIMEOUT))?;
+        stream.set_write_timeout(Some(Self::TIMEOUT))?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    pub fn request(&mut self, command: Command) -> std::io::Result<Response> {
+        let mut line = serde_json::to_string(&Request::new(command))?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+
+        line.clear();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "the daemon closed the connection",
+            ));
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let request = Request::new(Command::SetColor {
+            color: Color::new(255, 0, 0),
+            channels: "0-1".parse().unwrap(),
+            serial: None,
+            product_id: Some(0x2012),
+            duration_ms: Some(500),
+        });
+        let line = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            line,
+            r##"{"version":1,"command":"set-color","color":"#ff0000","channels":"0,1","product_id":8210,"duration_ms":500}"##
+        );
+        assert_eq!(Request::parse(&line), Ok(request));
+
+        assert_eq!(
+            Request::parse(r#"{"version":1,"command":"release","extra":true}"#),
+            Ok(Request::new(Command::Release {
+                channels: ChannelSelector::All,
+                serial: None,
+                product_id: None,
+            }))
+        );
+        assert_eq!(
+            Request::parse(r#"{"version":2,"command":"anything"}"#),
+            Err("unsupported protocol version 2, expected 1".to_owned())
+        );
+        assert_eq!(
+            Request::parse(r#"{"command":"status"}"#),
+            Err("missing the protocol `version`".to_owned())
+        );
+        assert!(
+            Request::parse(r#"{"version":1,"command":"reboot"}"#)
+                .unwrap_err()
+                .starts_with("invalid request: unknown variant `reboot`")
+        );
+    }
+
+    #[test]
+    fn responses() {
+        let line = serde_json::to_string(&Response::error("nope")).unwrap();
+        assert_eq!(line, r#"{"version":1,"ok":false,"error":"nope"}"#);
+        let response = serde_json::from_str::<Response>(&line).unwrap();
+        assert_eq!(response.into_result(), Err("nope".to_owned()));
+
+        let response = Response::ok(serde_json::json!({"profile": "work"}));
+        assert_eq!(
+            response.into_result(),
+            Ok(serde_json::json!({"profile": "work"}))
+        );
+    }
+}
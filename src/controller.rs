@@ -1,238 +1,3141 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::sync::OnceLock;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
-use hidapi::HidResult;
+use hidapi::HidApi;
 
-use crate::types::Color;
+use crate::error::{Error, Result, ensure_arg};
+use crate::info::ControllerInfo;
+use crate::logging::{self, debug, warning};
+use crate::transport::{
+    DryRunTransport, HidRecorder, HidTransport, PacketPrinter, RecordingTransport,
+    native_transport, timeout_ms,
+};
+use crate::types::{Color, ColorOrder, ColorTransform, DeviceType, Layout, LedPosition, Version};
 
+/// Common interface of all supported controllers.
+pub trait Controller: Send {
+    /// Returns `None` for controllers created over a custom transport.
+    fn info(&self) -> Option<&hidapi::DeviceInfo>;
+
+    fn product_id(&self) -> u16;
+
+    fn name(&self) -> &'static str;
+
+    /// Returns the serial number of the device (if reported).
+    fn serial(&self) -> Option<&str> {
+        self.info()?
+            .serial_number()
+            .filter(|serial| !serial.is_empty())
+    }
+
+    fn rgb_channels(&self) -> &[RgbChannel];
+
+    /// Returns an owned summary of the controller and its accessories.
+    fn snapshot(&self) -> ControllerInfo {
+        ControllerInfo::new(self)
+    }
+
+    /// Number of RGB channels declared for the model.
+    fn declared_rgb_channels(&self) -> usize {
+        self.rgb_channels().len()
+    }
+
+    /// Number of RGB channels whose accessories were reported by the device.
+    ///
+    /// Channels past this count are kept in [`Controller::rgb_channels`]
+    /// without accessories.
+    fn probed_rgb_channels(&self) -> usize {
+        self.rgb_channels().len()
+    }
+
+    /// Sets the same color for all channels with connected LEDs.
+    fn set_fixed_color(&self, color: Color) -> Result<()> {
+        self.set_fixed_color_all(color, false)
+    }
+
+    /// Sets the same color for all channels, including the empty ones
+    /// if `include_empty` is set.
+    fn set_fixed_color_all(&self, color: Color, include_empty: bool) -> Result<()> {
+        for (channel, info) in self.rgb_channels().iter().enumerate() {
+            if include_empty || info.led_count > 0 {
+                self.set_channel_color(channel, color)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_channel_color(&self, channel: usize, color: Color) -> Result<()>;
+
+    /// Sets colors of individual LEDs, starting from the first one.
+    fn set_channel_leds(&self, channel: usize, colors: &[Color]) -> Result<()> {
+        _ = (channel, colors);
+        Err(Error::Unsupported("per-LED colors"))
+    }
+
+    /// Sets colors of individual LEDs on several channels at once.
+    ///
+    /// Controllers with batch updates change all channels at the same time
+    /// (see [`NZXTHue2Controller::begin_frame`]), others set them one by one.
+    fn set_frame(&self, channels: &[(usize, &[Color])]) -> Result<()> {
+        for (channel, colors) in channels {
+            self.set_channel_leds(*channel, colors)?;
+        }
+        Ok(())
+    }
+
+    /// Returns positions of the channel LEDs in the order they are addressed.
+    fn led_positions(&self, channel: usize) -> Result<Vec<LedPosition>> {
+        let channels = self.rgb_channels();
+        let Some(channel) = channels.get(channel) else {
+            return Err(Error::InvalidChannel {
+                requested: channel,
+                available: channels.len(),
+            });
+        };
+        Ok(channel.led_positions())
+    }
+
+    /// Queries the firmware version of the device.
+    fn firmware_version(&self) -> Result<Version> {
+        Err(Error::Unsupported("firmware version query"))
+    }
+
+    /// The firmware version if it was already queried, nothing is sent.
+    fn cached_firmware_version(&self) -> Option<Version> {
+        None
+    }
+
+    /// Whether the controller can save its state to the non-volatile memory.
+    fn supports_save(&self) -> bool {
+        false
+    }
+
+    /// Saves the currently applied channel state (fixed colors or mode)
+    /// to the non-volatile memory so that it survives a reboot.
+    fn save_to_device(&self, channel: usize) -> Result<()> {
+        _ = channel;
+        Err(Error::Unsupported("saving state"))
+    }
+
+    fn fan_channels(&self) -> usize {
+        0
+    }
+
+    /// Number of fan channels declared for the model.
+    fn declared_fan_channels(&self) -> usize {
+        self.fan_channels()
+    }
+
+    /// Sets a fixed duty cycle (in percent) for the fan channel.
+    ///
+    /// Values above 100 are clamped.
+    fn set_fan_duty(&self, fan_channel: usize, percent: u8) -> Result<()> {
+        _ = (fan_channel, percent);
+        Err(Error::Unsupported("fan control"))
+    }
+
+    /// Reads current state of all fan channels from the status report.
+    ///
+    /// Returns an empty vec for controllers without fan channels.
+    fn fan_status(&self) -> Result<Vec<FanStatus>> {
+        Ok(Vec::new())
+    }
+
+    /// Reads the noise level (in dB) from the built-in noise sensor.
+    ///
+    /// Returns `None` for devices without the sensor.
+    fn noise_level_db(&self) -> Result<Option<u8>> {
+        Ok(None)
+    }
+
+    fn is_liquid_cooler(&self) -> bool {
+        false
+    }
+
+    /// Reads liquid temperature and pump state of the liquid cooler.
+    fn aio_status(&self) -> Result<AioStatus> {
+        Err(Error::Unsupported("liquid cooler status"))
+    }
+
+    /// Sets a fixed pump duty cycle (in percent) of the liquid cooler.
+    ///
+    /// Values are clamped to the range supported by the pump.
+    fn set_pump_duty(&self, percent: u8) -> Result<()> {
+        _ = percent;
+        Err(Error::Unsupported("pump control"))
+    }
+
+    /// Starts a hardware effect on the channel.
+    ///
+    /// The effect keeps running on the device without the host.
+    fn set_mode(
+        &self,
+        channel: usize,
+        mode: LedMode,
+        colors: &[Color],
+        speed: u8,
+        direction: Direction,
+    ) -> Result<()> {
+        _ = (channel, mode, colors, speed, direction);
+        Err(Error::Unsupported("hardware effects"))
+    }
+
+    /// Sets brightness (in percent) of the channel LEDs.
+    ///
+    /// The level is remembered and used for all subsequent color updates
+    /// of this channel.
+    fn set_brightness(&self, channel: usize, brightness: u8) -> Result<()> {
+        _ = (channel, brightness);
+        Err(Error::Unsupported("brightness control"))
+    }
+
+    /// Returns `false` if the last HID write failed (e.g. the device was unplugged).
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    /// Checks that the device still answers requests.
+    fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Makes sure the device shows the last applied colors.
+    ///
+    /// Intended to be called periodically by long-running processes, so that
+    /// the colors are restored after the firmware was reset (in which case
+    /// it falls back to its default effect). The built-in controllers resend
+    /// the last colors at an interval since a reset isn't reported.
+    fn ensure_applied(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Re-opens the device and restores the last known colors.
+    fn reconnect(&mut self, api: &HidApi) -> Result<()> {
+        _ = api;
+        Err(Error::Unsupported("reconnection"))
+    }
+
+    /// Writes a raw report to the device, returns the number of bytes written.
+    ///
+    /// Intended for protocol experiments only: malformed reports can confuse
+    /// the firmware until the device is power cycled.
+    fn send_raw(&self, report: &[u8]) -> Result<usize> {
+        _ = report;
+        Err(Error::Unsupported("raw reports"))
+    }
+
+    /// Reads a single raw report from the device.
+    ///
+    /// Returns an empty buffer if nothing was received within the timeout.
+    fn read_raw(&self, timeout: Duration) -> Result<Vec<u8>> {
+        _ = timeout;
+        Err(Error::Unsupported("raw reports"))
+    }
+}
+
+/// Controller of the second generation of NZXT RGB devices.
+///
+/// The controller is `Send + Sync` and can be shared between threads.
+/// The transport is locked for each report write and for whole
+/// request/response sequences (e.g. a liquid cooler status query), so
+/// concurrent calls never steal each other's replies. Periodic status reports
+/// are awaited with the transport locked only for each read attempt.
+/// Channel colors are guarded by a separate lock which is held while
+/// a channel update is being written.
 pub struct NZXTHue2Controller<'a> {
-    device: hidapi::HidDevice,
-    info: &'a hidapi::DeviceInfo,
+    device: Mutex<Box<dyn HidTransport>>,
+    info: Option<Cow<'a, hidapi::DeviceInfo>>,
+    product_id: u16,
     name: &'static str,
     rgb_channels: Vec<RgbChannel>,
+    probed_rgb_channels: usize,
+    fan_channels: usize,
+    persistent: bool,
+    /// Last written state of each channel.
+    channel_state: Mutex<Vec<ChannelState>>,
+    /// Whether to send packets even if the channel state hasn't changed.
+    force_writes: AtomicBool,
+    connected: AtomicBool,
+    accessory_overrides: AccessoryOverrides,
+    color_orders: ColorOrders,
+    transform: Mutex<ColorTransform>,
+    reapply: Mutex<ReapplyTimer>,
+    /// Identifies the device in log messages.
+    log_label: String,
+    /// How long to wait for a reply or a status report.
+    read_timeout: Duration,
+    /// Number of times a failed write is repeated.
+    write_retries: u8,
+    /// Logs the transfers of the device, also after reconnecting.
+    recorder: Option<Arc<HidRecorder>>,
+    firmware: OnceLock<Version>,
+    skip_firmware_check: bool,
+    /// Whether the firmware is known to support direct packets.
+    direct_mode_checked: AtomicBool,
+}
+
+pub(crate) struct ReapplyTimer {
+    interval: Option<Duration>,
+    last: Instant,
+}
+
+impl Default for ReapplyTimer {
+    fn default() -> Self {
+        Self::new(Some(DEFAULT_REAPPLY_INTERVAL))
+    }
+}
+
+impl ReapplyTimer {
+    pub(crate) fn new(interval: Option<Duration>) -> Self {
+        Self {
+            interval,
+            last: Instant::now(),
+        }
+    }
+
+    /// Whether the interval has passed since the last time it was due.
+    pub(crate) fn due(&mut self) -> bool {
+        match self.interval {
+            Some(interval) if self.last.elapsed() >= interval => {
+                self.last = Instant::now();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// LED counts of accessories with unknown ids.
+pub type AccessoryOverrides = HashMap<u8, u8>;
+
+/// Color component order of channels which differ from [`ColorOrder::Grb`].
+pub type ColorOrders = HashMap<usize, ColorOrder>;
+
+/// Name, RGB Channels, Fan Channels, Persistent storage support
+pub(crate) type ControllerBriefInfo = (&'static str, usize, usize, bool);
+
+/// Settings of Hue 2 controllers applied when they are opened.
+///
+/// Defaults keep the device behavior unchanged.
+#[derive(Debug, Clone)]
+pub struct ControllerOptions {
+    accessory_overrides: AccessoryOverrides,
+    color_orders: ColorOrders,
+    color_transform: ColorTransform,
+    reapply_interval: Option<Duration>,
+    read_timeout: Duration,
+    write_retries: u8,
+    skip_firmware_check: bool,
+    recorder: Option<Arc<HidRecorder>>,
+    dry_run: Option<Arc<PacketPrinter>>,
+}
+
+impl ControllerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn accessory_overrides(mut self, overrides: AccessoryOverrides) -> Self {
+        self.accessory_overrides = overrides;
+        self
+    }
+
+    pub fn color_orders(mut self, orders: ColorOrders) -> Self {
+        self.color_orders = orders;
+        self
+    }
+
+    pub fn color_transform(mut self, transform: ColorTransform) -> Self {
+        self.color_transform = transform;
+        self
+    }
+
+    /// See [`NZXTHue2Controller::set_reapply_interval`].
+    pub fn reapply_interval(mut self, interval: Option<Duration>) -> Self {
+        self.reapply_interval = interval;
+        self
+    }
+
+    /// How long to wait for replies and status reports (2 seconds by default).
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Number of times a failed write is repeated before giving up.
+    pub fn write_retries(mut self, retries: u8) -> Self {
+        self.write_retries = retries;
+        self
+    }
+
+    /// Allows direct LED control even if the firmware is known not to support it.
+    pub fn skip_firmware_check(mut self, skip: bool) -> Self {
+        self.skip_firmware_check = skip;
+        self
+    }
+
+    /// Logs all transfers to the recorder.
+    pub fn record_hid(mut self, recorder: Arc<HidRecorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Prints the reports instead of sending them once the controller is opened.
+    ///
+    /// Only the accessories and the firmware version (if it has to be checked)
+    /// are queried from the device.
+    pub fn dry_run(mut self, printer: Arc<PacketPrinter>) -> Self {
+        self.dry_run = Some(printer);
+        self
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.is_some()
+    }
 }
 
-/// Name, RGB Channels, Fan Channels
-type ControllerBriefInfo = (&'static str, usize, usize);
+/// Wraps the transport so that its transfers are logged to the recorder (if any).
+fn record_transport(
+    recorder: Option<&Arc<HidRecorder>>,
+    transport: Box<dyn HidTransport>,
+    product_id: u16,
+) -> Box<dyn HidTransport> {
+    match recorder {
+        Some(recorder) => Box::new(RecordingTransport::new(
+            transport,
+            recorder.clone(),
+            format!("{product_id:04x}"),
+        )),
+        None => transport,
+    }
+}
+
+impl Default for ControllerOptions {
+    fn default() -> Self {
+        Self {
+            accessory_overrides: AccessoryOverrides::new(),
+            color_orders: ColorOrders::new(),
+            color_transform: ColorTransform::IDENTITY,
+            reapply_interval: Some(DEFAULT_REAPPLY_INTERVAL),
+            read_timeout: STATUS_TIMEOUT,
+            write_retries: 0,
+            skip_firmware_check: false,
+            recorder: None,
+            dry_run: None,
+        }
+    }
+}
 
 impl<'a> NZXTHue2Controller<'a> {
     pub fn known_controllers() -> &'static HashMap<u16, ControllerBriefInfo> {
         static INFO: OnceLock<HashMap<u16, ControllerBriefInfo>> = OnceLock::new();
         INFO.get_or_init(|| {
+            // Only RGB & Fan controllers, RGB controllers and Smart Devices
+            // are known to persist their state in the non-volatile memory.
             HashMap::from_iter([
                 // Fan controller
-                (0x2009, ("NZXT RGB & Fan Controller", 2, 3)),
-                (0x2010, ("NZXT RGB & Fan Controller", 2, 3)),
-                (0x200E, ("NZXT RGB & Fan Controller", 2, 3)),
+                (0x2009, ("NZXT RGB & Fan Controller", 2, 3, true)),
+                (0x2010, ("NZXT RGB & Fan Controller", 2, 3, true)),
+                (0x200E, ("NZXT RGB & Fan Controller", 2, 3, true)),
                 // Fan controller (6-channel)
-                (0x2011, ("NZXT RGB & Fan Controller", 6, 3)),
-                (0x2019, ("NZXT RGB & Fan Controller", 6, 3)),
-                (0x2020, ("NZXT RGB & Fan Controller", 6, 3)),
-                (0x201F, ("NZXT RGB & Fan Controller", 6, 3)),
-                (0x2022, ("NZXT RGB & Fan Controller 2024", 6, 3)),
-                (0x201B, ("NZXT B650E Motherboard", 6, 3)),
+                (0x2011, ("NZXT RGB & Fan Controller", 6, 3, true)),
+                (0x2019, ("NZXT RGB & Fan Controller", 6, 3, true)),
+                (0x2020, ("NZXT RGB & Fan Controller", 6, 3, true)),
+                (0x201F, ("NZXT RGB & Fan Controller", 6, 3, true)),
+                (0x2022, ("NZXT RGB & Fan Controller 2024", 6, 3, true)),
+                (0x201B, ("NZXT B650E Motherboard", 6, 3, false)),
                 // HUE 2
-                (0x2001, ("NZXT Hue 2", 4, 0)),
-                (0x2002, ("NZXT Hue 2 Ambient", 2, 0)),
-                (0x2005, ("NZXT Hue 2 Motherboard", 2, 3)),
-                (0x200B, ("NZXT Hue 2 Motherboard", 2, 3)),
+                (0x2001, ("NZXT Hue 2", 4, 0, false)),
+                (0x2002, ("NZXT Hue 2 Ambient", 2, 0, false)),
+                (0x2005, ("NZXT Hue 2 Motherboard", 2, 3, false)),
+                (0x200B, ("NZXT Hue 2 Motherboard", 2, 3, false)),
                 // Kraken
-                (0x2007, ("NZXT Kraken X3 Series", 3, 0)),
-                (0x2014, ("NZXT Kraken X3 Series RGB", 3, 0)),
-                (0x3012, ("NZXT Kraken 2024 ELITE Series RGB", 2, 2)),
+                (0x2007, ("NZXT Kraken X3 Series", 3, 0, false)),
+                (0x2014, ("NZXT Kraken X3 Series RGB", 3, 0, false)),
+                (0x3012, ("NZXT Kraken 2024 ELITE Series RGB", 2, 2, false)),
                 // RGB Controller
-                (0x2012, ("NZXT RGB Controller", 3, 0)),
-                (0x2021, ("NZXT RGB Controller", 3, 0)),
+                (0x2012, ("NZXT RGB Controller", 3, 0, true)),
+                (0x2021, ("NZXT RGB Controller", 3, 0, true)),
                 // Smart Device
-                (0x2006, ("NZXT Smart Device V2", 2, 3)),
-                (0x200D, ("NZXT Smart Device V2", 2, 3)),
-                (0x200F, ("NZXT Smart Device V2", 2, 3)),
+                (0x2006, ("NZXT Smart Device V2", 2, 3, true)),
+                (0x200D, ("NZXT Smart Device V2", 2, 3, true)),
+                (0x200F, ("NZXT Smart Device V2", 2, 3, true)),
             ])
         })
     }
 
     pub fn new(
-        api: &'a hidapi::HidApi,
+        api: &hidapi::HidApi,
         info: &'a hidapi::DeviceInfo,
         name: &'static str,
         rgb_channels: usize,
-        _fan_channels: usize,
+        fan_channels: usize,
+        persistent: bool,
     ) -> Result<Self> {
-        let device = api.open_path(info.path())?;
-        let rgb_channels = get_channels_info(&device, rgb_channels)?;
+        let brief = (name, rgb_channels, fan_channels, persistent);
+        Self::new_with(api, info, &brief, &ControllerOptions::default())
+    }
 
-        Ok(Self {
+    /// Same as [`NZXTHue2Controller::new`], but configured by the options.
+    pub fn new_with(
+        api: &hidapi::HidApi,
+        info: &'a hidapi::DeviceInfo,
+        brief: &ControllerBriefInfo,
+        options: &ControllerOptions,
+    ) -> Result<Self> {
+        let &(name, rgb_channels, fan_channels, persistent) = brief;
+        ensure_arg!(
+            rgb_channels <= MAX_RGB_CHANNELS,
+            "too many RGB channels: {rgb_channels}, at most {MAX_RGB_CHANNELS} are supported"
+        );
+
+        let device = record_transport(
+            options.recorder.as_ref(),
+            native_transport(open_device(api, info)?),
+            info.product_id(),
+        );
+        let controller = Self::from_parts(
             device,
+            Some(Cow::Borrowed(info)),
+            info.product_id(),
+            name,
+            rgb_channels,
+            fan_channels,
+            persistent,
+            options.read_timeout,
+        )?;
+        Ok(controller.configure(options))
+    }
+
+    /// Creates a controller which talks to the device through the transport.
+    ///
+    /// Such controllers don't have [`hidapi::DeviceInfo`] and can't reconnect.
+    pub fn with_transport(
+        transport: Box<dyn HidTransport>,
+        product_id: u16,
+        name: &'static str,
+        rgb_channels: usize,
+        fan_channels: usize,
+        persistent: bool,
+    ) -> Result<NZXTHue2Controller<'static>> {
+        let brief = (name, rgb_channels, fan_channels, persistent);
+        Self::with_transport_options(transport, product_id, &brief, &ControllerOptions::default())
+    }
+
+    /// Same as [`NZXTHue2Controller::with_transport`], but configured by the options.
+    pub fn with_transport_options(
+        transport: Box<dyn HidTransport>,
+        product_id: u16,
+        brief: &ControllerBriefInfo,
+        options: &ControllerOptions,
+    ) -> Result<NZXTHue2Controller<'static>> {
+        let &(name, rgb_channels, fan_channels, persistent) = brief;
+        ensure_arg!(
+            rgb_channels <= MAX_RGB_CHANNELS,
+            "too many RGB channels: {rgb_channels}, at most {MAX_RGB_CHANNELS} are supported"
+        );
+
+        let controller = NZXTHue2Controller::from_parts(
+            record_transport(options.recorder.as_ref(), transport, product_id),
+            None,
+            product_id,
+            name,
+            rgb_channels,
+            fan_channels,
+            persistent,
+            options.read_timeout,
+        )?;
+        Ok(controller.configure(options))
+    }
+
+    /// Applies the options which don't affect the device detection.
+    fn configure(mut self, options: &ControllerOptions) -> Self {
+        self.write_retries = options.write_retries;
+        self.recorder = options.recorder.clone();
+        self.set_color_transform(options.color_transform);
+        self.set_reapply_interval(options.reapply_interval);
+        let mut controller = self
+            .with_accessory_overrides(&options.accessory_overrides)
+            .with_color_orders(&options.color_orders)
+            .skip_firmware_check(options.skip_firmware_check);
+
+        if let Some(printer) = &options.dry_run {
+            // The version is cached, so the firmware check doesn't read later.
+            if !controller.skip_firmware_check
+                && min_direct_firmware(controller.product_id).is_some()
+            {
+                _ = controller.firmware_version();
+            }
+            let transport = DryRunTransport::new(printer.clone(), controller.log_label.clone());
+            *controller.device.get_mut().unwrap() = Box::new(transport);
+        }
+        controller
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn from_parts(
+        device: Box<dyn HidTransport>,
+        info: Option<Cow<'a, hidapi::DeviceInfo>>,
+        product_id: u16,
+        name: &'static str,
+        rgb_channels: usize,
+        fan_channels: usize,
+        persistent: bool,
+        read_timeout: Duration,
+    ) -> Result<Self> {
+        let log_label = log_label(name, product_id, info.as_deref());
+        let span = logging::span(&log_label);
+        debug!("accessory query (64 bytes)");
+        let (rgb_channels, probed_rgb_channels) =
+            get_channels_info(device.as_ref(), rgb_channels, read_timeout)?;
+        debug!(
+            "{probed_rgb_channels} of {} channels probed",
+            rgb_channels.len()
+        );
+        drop(span);
+        warn_truncated_channels(name, &rgb_channels);
+        let channel_state = rgb_channels
+            .iter()
+            .map(|channel| ChannelState::new(channel.led_count))
+            .collect();
+
+        Ok(Self {
+            device: Mutex::new(device),
             info,
+            product_id,
             name,
             rgb_channels,
+            probed_rgb_channels,
+            fan_channels,
+            persistent,
+            channel_state: Mutex::new(channel_state),
+            force_writes: AtomicBool::new(false),
+            connected: AtomicBool::new(true),
+            accessory_overrides: AccessoryOverrides::new(),
+            color_orders: ColorOrders::new(),
+            transform: Mutex::new(ColorTransform::IDENTITY),
+            reapply: Mutex::default(),
+            log_label,
+            read_timeout,
+            write_retries: 0,
+            recorder: None,
+            firmware: OnceLock::new(),
+            skip_firmware_check: false,
+            direct_mode_checked: AtomicBool::new(false),
         })
     }
 
-    pub fn info(&self) -> &hidapi::DeviceInfo {
-        self.info
+    /// Opens a known Hue 2 controller with the specified serial number.
+    ///
+    /// Serial numbers are compared case-insensitively.
+    pub fn open_by_serial(api: &'a HidApi, serial: &str) -> Result<Self> {
+        let known = Self::known_controllers();
+
+        let mut seen = Vec::new();
+        for info in api.device_list() {
+            if info.vendor_id() != crate::NZXT_VID {
+                continue;
+            }
+            let Some(&(name, rgb_channels, fan_channels, persistent)) =
+                known.get(&info.product_id())
+            else {
+                continue;
+            };
+            let Some(device_serial) = info.serial_number().filter(|s| !s.is_empty()) else {
+                continue;
+            };
+
+            if device_serial.eq_ignore_ascii_case(serial) {
+                return Self::new(api, info, name, rgb_channels, fan_channels, persistent);
+            }
+            seen.push(device_serial.to_owned());
+        }
+
+        Err(Error::SerialNotFound {
+            serial: serial.to_owned(),
+            seen,
+        })
     }
 
-    pub fn name(&self) -> &'static str {
-        self.name
+    /// Detaches the controller from the borrowed device info.
+    ///
+    /// Owned controllers can be stored alongside the [`HidApi`] or moved to
+    /// another thread.
+    pub fn into_owned(self) -> NZXTHue2Controller<'static> {
+        NZXTHue2Controller {
+            device: self.device,
+            info: self.info.map(|info| Cow::Owned(info.into_owned())),
+            product_id: self.product_id,
+            name: self.name,
+            rgb_channels: self.rgb_channels,
+            probed_rgb_channels: self.probed_rgb_channels,
+            fan_channels: self.fan_channels,
+            persistent: self.persistent,
+            channel_state: self.channel_state,
+            force_writes: self.force_writes,
+            connected: self.connected,
+            accessory_overrides: self.accessory_overrides,
+            color_orders: self.color_orders,
+            transform: self.transform,
+            reapply: self.reapply,
+            log_label: self.log_label,
+            read_timeout: self.read_timeout,
+            write_retries: self.write_retries,
+            recorder: self.recorder,
+            firmware: self.firmware,
+            skip_firmware_check: self.skip_firmware_check,
+            direct_mode_checked: self.direct_mode_checked,
+        }
     }
 
-    pub fn rgb_channels(&self) -> &[RgbChannel] {
-        &self.rgb_channels
+    /// Sets the order in which color components are sent to the channels.
+    ///
+    /// Applied channels are rewritten with the next update.
+    pub fn with_color_orders(mut self, orders: &ColorOrders) -> Self {
+        self.color_orders.clone_from(orders);
+        for state in self.channel_state.get_mut().unwrap() {
+            state.invalidate();
+        }
+        self
+    }
+
+    /// Disables the minimum firmware version check for direct LED control.
+    pub fn skip_firmware_check(mut self, skip: bool) -> Self {
+        self.skip_firmware_check = skip;
+        self
     }
 
-    pub fn set_fixed_color(&self, color: Color) -> Result<()> {
-        let mut colors = Vec::new();
-        for (i, channel) in self.rgb_channels.iter().enumerate() {
-            colors.resize(channel.led_count, color);
-            set_channel_leds(&self.device, i, &colors)?;
+    /// Fails if the firmware is too old to support direct packets.
+    ///
+    /// The version is queried once, only for models with a known minimum.
+    /// Devices which don't answer the query are assumed to be supported.
+    fn check_direct_mode(&self) -> Result<()> {
+        if self.skip_firmware_check || self.direct_mode_checked.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        if let Some(required) = min_direct_firmware(self.product_id) {
+            match self.firmware_version() {
+                Ok(found) => check_firmware(found, required)?,
+                Err(Error::Timeout) => {}
+                Err(e) => return Err(e),
+            }
         }
+        self.direct_mode_checked.store(true, Ordering::Relaxed);
         Ok(())
     }
-}
 
-fn get_channels_info(
-    device: &hidapi::HidDevice,
-    rgb_channels: usize,
-) -> HidResult<Vec<RgbChannel>> {
-    let mut buffer = [0u8; 64];
-    buffer[0] = 0x20;
-    buffer[1] = 0x03;
-    device.write(&buffer)?;
+    /// Sets gamma correction applied to sent colors (`1.0` disables it).
+    pub fn set_gamma(&self, gamma: f32) -> Result<()> {
+        let brightness = self.color_transform().brightness();
+        self.set_color_transform(ColorTransform::new(gamma, brightness)?);
+        Ok(())
+    }
 
-    // TODO: Add some iterations check
-    loop {
-        let ret_val = device.read(&mut buffer)?;
-        if ret_val == 64 && buffer[0] == 0x21 && buffer[1] == 0x03 {
-            break;
+    /// Scales all sent colors, `brightness` must be in range `0..=1`.
+    ///
+    /// Unlike [`Controller::set_brightness`] this is done on the host and
+    /// also affects the saved colors.
+    pub fn set_master_brightness(&self, brightness: f32) -> Result<()> {
+        let gamma = self.color_transform().gamma();
+        self.set_color_transform(ColorTransform::new(gamma, brightness)?);
+        Ok(())
+    }
+
+    /// Replaces the transform applied to colors when building reports.
+    ///
+    /// Colors of all channels are resent with the next update.
+    pub fn set_color_transform(&self, transform: ColorTransform) {
+        *self.transform.lock().unwrap() = transform;
+        for state in self.channel_state.lock().unwrap().iter_mut() {
+            state.invalidate();
         }
     }
 
-    let mut result = Vec::new();
-    for channel in 0..rgb_channels {
-        let start = 0x0f + (HUE_2_NUM_CHANNELS * channel);
-        let mut channel_info = RgbChannel::default();
+    pub fn color_transform(&self) -> ColorTransform {
+        *self.transform.lock().unwrap()
+    }
 
-        for dev in 0..HUE_2_NUM_CHANNELS {
-            let id = buffer[start + dev];
-            let (led_count, name) = match id {
-                0x01 => (10, "Hue 1 strip"),
-                0x02 => (8, "Aer 1 fan"),
-                0x04 => (10, "Hue 2 strip (10 LEDs)"),
-                0x05 => (8, "// Hue 2 strip (8 LEDs)"),
-                0x06 => (6, "Hue 2 strip (6 LEDs)"),
-                0x08 => (14, "Hue 2 Cable Comb (14 LEDs)"),
-                0x09 => (15, "Hue 2 Underglow (300mm) (15 LEDs)"),
-                0x0a => (10, "Hue 2 Underglow (200mm) (10 LEDs)"),
-                0x0b => (8, "Aer 2 fan (120mm)"),
-                0x0c => (8, "Aer 2 fan (140mm)"),
-                0x10 => (8, "Kraken X3 ring"),
-                0x11 => (1, "Kraken X3 logo"),
-                0x13 => (18, "F120 RGB fan (120mm)"),
-                0x14 => (18, "F140 RGB fan (140mm)"),
-                0x15 => (20, "F120 RGB Duo fan (120mm)"),
-                0x16 => (20, "F140 RGB Duo fan (140mm)"),
-                0x17 => (8, "F120 RGB Core fan (120mm)"),
-                0x18 => (8, "F140 RGB Core fan (140mm)"),
-                0x19 => (8, "F120 RGB Core fan case version (120mm)"),
-                0x1d => (24, "F360 RGB Core Fan Case Version (360mm)"),
-                0x1e => (24, "Kraken Elite Ring"),
-                _ => (0, "<unknown>"),
-            };
+    /// Sets how often [`Controller::ensure_applied`] resends the colors,
+    /// `None` disables it.
+    pub fn set_reapply_interval(&self, interval: Option<Duration>) {
+        self.reapply.lock().unwrap().interval = interval;
+    }
 
-            if led_count == 0 {
-                continue;
-            }
+    fn color_order(&self, channel: usize) -> ColorOrder {
+        self.color_orders.get(&channel).copied().unwrap_or_default()
+    }
 
-            channel_info.led_count += led_count as usize;
-            channel_info.devices[dev] = ChannelDeviceInfo {
-                id,
-                name,
-                led_count,
-            };
+    /// Assigns LED counts to accessories with unknown ids.
+    ///
+    /// Channel LED totals are recomputed and the cached channel state is reset.
+    pub fn with_accessory_overrides(mut self, overrides: &AccessoryOverrides) -> Self {
+        if overrides.is_empty() {
+            return self;
         }
 
-        result.push(channel_info);
+        apply_accessory_overrides(&mut self.rgb_channels, overrides);
+        warn_truncated_channels(self.name, &self.rgb_channels);
+        self.accessory_overrides.clone_from(overrides);
+        self.channel_state = Mutex::new(
+            self.rgb_channels
+                .iter()
+                .map(|channel| ChannelState::new(channel.led_count))
+                .collect(),
+        );
+        self
     }
 
-    Ok(result)
-}
+    /// Starts a batch update of multiple channels.
+    ///
+    /// Staged colors are sent on [`Frame::commit`] with a single apply
+    /// packet so that all channels change at the same time.
+    pub fn begin_frame(&self) -> Frame<'_, 'a> {
+        Frame {
+            controller: self,
+            staged: vec![None; self.rgb_channels.len()],
+        }
+    }
 
-fn set_channel_leds(
-    device: &hidapi::HidDevice,
-    channel: usize,
-    mut colors: &[Color],
-) -> HidResult<()> {
-    let mut group = 0;
-    while !colors.is_empty() {
-        let count = std::cmp::min(colors.len(), 20);
-        send_direct(device, channel, group, &colors[..count])?;
-        colors = &colors[count..];
-        group += 1;
+    pub fn set_mode(
+        &self,
+        channel: usize,
+        mode: LedMode,
+        colors: &[Color],
+        speed: u8,
+        direction: Direction,
+    ) -> Result<()> {
+        self.check_channel(channel)?;
+        ensure_arg!(
+            channel < MAX_SHORT_MASK_CHANNELS,
+            "effects are supported only for the first {MAX_SHORT_MASK_CHANNELS} channels"
+        );
+        ensure_arg!(
+            speed <= MAX_EFFECT_SPEED,
+            "invalid speed {speed}, must be in range 0..={MAX_EFFECT_SPEED}"
+        );
+
+        let (min_colors, max_colors) = (mode.min_colors(), mode.max_colors());
+        ensure_arg!(
+            (min_colors..=max_colors).contains(&colors.len()),
+            "mode `{}` requires {} but {} given",
+            mode.name(),
+            match (min_colors, max_colors) {
+                (0, 0) => "no colors".to_owned(),
+                (1, 1) => "exactly 1 color".to_owned(),
+                (min, max) if min == max => format!("exactly {min} colors"),
+                (min, max) => format!("from {min} to {max} colors"),
+            },
+            colors.len()
+        );
+
+        let order = self.color_order(channel);
+        let transform = self.color_transform();
+        self.write(&effect_packet(
+            channel, mode, colors, speed, direction, order, &transform,
+        ))?;
+
+        // Effects replace the direct colors on the device.
+        self.channel_state.lock().unwrap()[channel].invalidate();
+        Ok(())
     }
-    send_apply(device, channel)
-}
 
-fn send_direct(
-    device: &hidapi::HidDevice,
-    channel: usize,
-    group: u8,
-    color_data: &[Color],
-) -> HidResult<()> {
-    let mut buffer = [0u8; 64];
-    buffer[0x00] = 0x22;
-    buffer[0x01] = 0x10 | group;
-    buffer[0x02] = 0x01u8 << channel;
-    buffer[0x03] = 0x00;
-    buffer[0x04..0x04 + (color_data.len() * 3)].copy_from_slice(Color::wrap_slice(color_data));
-    device.write(&buffer)?;
-    Ok(())
-}
+    /// Sets colors for each LED of the channel in order.
+    ///
+    /// The number of colors must not exceed the channel's `led_count`. LEDs
+    /// past the end of `colors` are sent with their last set colors (off if
+    /// they were never set). Packets are built on the stack so this can be
+    /// called at animation rates.
+    pub fn set_channel_leds(&self, channel: usize, colors: &[Color]) -> Result<()> {
+        self.check_channel(channel)?;
 
-fn send_apply(device: &hidapi::HidDevice, channel: usize) -> HidResult<()> {
-    let mut buffer = [0u8; 64];
-    buffer[0x00] = 0x22;
-    buffer[0x01] = 0xa0;
-    buffer[0x02] = 0x01u8 << channel;
-    buffer[0x04] = 0x01;
-    buffer[0x07] = 0x28;
-    buffer[0x0a] = 0x80;
-    buffer[0x0c] = 0x32;
-    buffer[0x0f] = 0x01;
-    device.write(&buffer)?;
-    Ok(())
-}
+        let led_count = self.rgb_channels[channel].led_count;
+        ensure_arg!(
+            colors.len() <= led_count,
+            "too many colors for channel {channel}: {} given, {led_count} LEDs available{}",
+            colors.len(),
+            if self.rgb_channels[channel].truncated {
+                format!(" (the channel is limited to {MAX_CHANNEL_LEDS} LEDs)")
+            } else {
+                String::new()
+            }
+        );
 
-#[derive(Default, Debug, Clone, Copy)]
-pub struct RgbChannel {
-    pub led_count: usize,
-    pub devices: [ChannelDeviceInfo; HUE_2_NUM_CHANNELS],
-}
+        let mut state = self.channel_state.lock().unwrap();
+        let state = &mut state[channel];
+        state.colors[..colors.len()].copy_from_slice(colors);
+        self.write_channel(channel, state, self.force_writes())
+    }
 
-#[derive(Default, Debug, Clone, Copy)]
-pub struct ChannelDeviceInfo {
-    pub id: u8,
-    pub name: &'static str,
-    pub led_count: u8,
-}
+    /// Sets the same color for all LEDs of a single accessory on the channel.
+    pub fn set_device_color(
+        &self,
+        channel: usize,
+        device_index: usize,
+        color: Color,
+    ) -> Result<()> {
+        let leds = self.device_leds(channel, device_index)?;
+        self.write_device_leds(channel, leds, |frame| frame.fill(color))
+    }
 
-#[repr(u8)]
-pub enum LedMode {
-    Fixed = 0x00,
-    Fading = 0x01,
-    Spectrum = 0x02,
-    Marquee = 0x03,
-    CoverMarquee = 0x04,
-    Alternating = 0x05,
-    Pulsing = 0x06,
-    Breathing = 0x07,
-    Candle = 0x08,
-    StarryNight = 0x09,
+    /// Sets colors for LEDs of a single accessory on the channel.
+    ///
+    /// LEDs of other accessories on the same channel keep their previous colors.
+    pub fn set_device_leds(
+        &self,
+        channel: usize,
+        device_index: usize,
+        colors: &[Color],
+    ) -> Result<()> {
+        let leds = self.device_leds(channel, device_index)?;
+        ensure_arg!(
+            colors.len() <= leds.len(),
+            "too many colors for device {device_index} on channel {channel}: \
+            {} given, {} LEDs available",
+            colors.len(),
+            leds.len()
+        );
+        self.write_device_leds(channel, leds, |frame| {
+            frame[..colors.len()].copy_from_slice(colors)
+        })
+    }
 
-    RainbowFlow = 0x0b,
-    SuperRainbow = 0x0c,
-    RainbowPulse = 0x0d,
-}
+    fn device_leds(&self, channel: usize, device_index: usize) -> Result<Range<usize>> {
+        self.check_channel(channel)?;
+        self.rgb_channels[channel]
+            .device_leds(device_index)
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!("no device {device_index} on channel {channel}"))
+            })
+    }
+
+    fn write_device_leds<F>(&self, channel: usize, leds: Range<usize>, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut [Color]),
+    {
+        let mut state = self.channel_state.lock().unwrap();
+        let state = &mut state[channel];
+        f(&mut state.colors[leds]);
+        self.write_channel(channel, state, self.force_writes())
+    }
+
+    /// Sends the stored colors of the channel if they differ from the last
+    /// applied ones (or unconditionally if `force` is set).
+    fn write_channel(&self, channel: usize, state: &mut ChannelState, force: bool) -> Result<()> {
+        self.check_direct_mode()?;
+        if state.begin_write(force) {
+            let order = self.color_order(channel);
+            let transform = self.color_transform();
+            let packets =
+                channel_leds_packets(channel, &state.colors, state.brightness, order, &transform);
+            for packet in packets {
+                self.write(&packet?)?;
+            }
+            state.mark_applied();
+        }
+        Ok(())
+    }
 
-const HUE_2_NUM_CHANNELS: usize = 6;
+    /// Writes the report and marks the controller as disconnected on failure.
+    fn write(&self, packet: &[u8]) -> Result<()> {
+        let device = self.device();
+        self.write_to(device.as_ref(), packet)
+    }
+
+    fn write_to(&self, device: &dyn HidTransport, packet: &[u8]) -> Result<()> {
+        let _span = logging::span(&self.log_label);
+        debug!("{}", describe_packet(packet));
+        let mut result = write_report(device, packet);
+        for _ in 0..self.write_retries {
+            if result.is_ok() {
+                break;
+            }
+            debug!("retrying the write");
+            result = write_report(device, packet);
+        }
+        if let Err(e) = &result {
+            debug!("write failed: {e}");
+        }
+        self.connected.store(result.is_ok(), Ordering::Relaxed);
+        result
+    }
+
+    /// Waits for a report which the device sends periodically.
+    ///
+    /// The transport is locked only for each read attempt, so writes from
+    /// other threads are not delayed until the report arrives.
+    fn read_status_report(&self, header: [u8; 2]) -> Result<[u8; 64]> {
+        let deadline = Instant::now() + self.read_timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout);
+            }
+
+            match read_report(self.device().as_ref(), header, remaining.min(READ_SLICE)) {
+                Err(Error::Timeout) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    fn query_firmware(&self) -> Result<[u8; 64]> {
+        let mut buffer = [0u8; 64];
+        buffer[0x00] = 0x10;
+        buffer[0x01] = 0x01;
+
+        let device = self.device();
+        self.write_to(device.as_ref(), &buffer)?;
+        read_report(device.as_ref(), [0x11, 0x01], self.read_timeout)
+    }
+
+    /// Locks the transport for the duration of a request/response sequence.
+    ///
+    /// Must not be held while acquiring the channel state lock.
+    fn device(&self) -> MutexGuard<'_, Box<dyn HidTransport>> {
+        self.device.lock().unwrap()
+    }
+
+    pub fn brightness(&self, channel: usize) -> Option<u8> {
+        let state = self.channel_state.lock().unwrap();
+        state.get(channel).map(|state| state.brightness)
+    }
+
+    /// Returns colors that were last sent to the channel.
+    ///
+    /// `None` if nothing was written yet or the channel is running an effect.
+    pub fn last_applied(&self, channel: usize) -> Option<Vec<Color>> {
+        let state = self.channel_state.lock().unwrap();
+        state.get(channel)?.applied_colors.clone()
+    }
+
+    /// Disables skipping of writes which don't change the channel state.
+    ///
+    /// Useful to refresh LEDs after the device was reset.
+    pub fn set_force_writes(&self, force: bool) {
+        self.force_writes.store(force, Ordering::Relaxed);
+    }
+
+    fn force_writes(&self) -> bool {
+        self.force_writes.load(Ordering::Relaxed)
+    }
+
+    fn check_channel(&self, channel: usize) -> Result<()> {
+        if channel >= self.rgb_channels.len() {
+            return Err(Error::InvalidChannel {
+                requested: channel,
+                available: self.rgb_channels.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Controller for NZXTHue2Controller<'_> {
+    fn info(&self) -> Option<&hidapi::DeviceInfo> {
+        self.info.as_deref()
+    }
+
+    fn product_id(&self) -> u16 {
+        self.product_id
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn rgb_channels(&self) -> &[RgbChannel] {
+        &self.rgb_channels
+    }
+
+    fn probed_rgb_channels(&self) -> usize {
+        self.probed_rgb_channels
+    }
+
+    fn set_channel_color(&self, channel: usize, color: Color) -> Result<()> {
+        self.check_channel(channel)?;
+
+        let mut state = self.channel_state.lock().unwrap();
+        let state = &mut state[channel];
+        state.colors.fill(color);
+        self.write_channel(channel, state, self.force_writes())
+    }
+
+    fn set_channel_leds(&self, channel: usize, colors: &[Color]) -> Result<()> {
+        NZXTHue2Controller::set_channel_leds(self, channel, colors)
+    }
+
+    fn set_frame(&self, channels: &[(usize, &[Color])]) -> Result<()> {
+        let mut frame = self.begin_frame();
+        for (channel, colors) in channels {
+            frame.set_channel_leds(*channel, colors)?;
+        }
+        frame.commit()
+    }
+
+    fn set_mode(
+        &self,
+        channel: usize,
+        mode: LedMode,
+        colors: &[Color],
+        speed: u8,
+        direction: Direction,
+    ) -> Result<()> {
+        NZXTHue2Controller::set_mode(self, channel, mode, colors, speed, direction)
+    }
+
+    fn supports_save(&self) -> bool {
+        self.persistent
+    }
+
+    fn save_to_device(&self, channel: usize) -> Result<()> {
+        if !self.persistent {
+            return Err(Error::Unsupported("saving state"));
+        }
+        self.check_channel(channel)?;
+        ensure_arg!(
+            channel < MAX_SHORT_MASK_CHANNELS,
+            "saving state is supported only for the first {MAX_SHORT_MASK_CHANNELS} channels"
+        );
+
+        self.write(&save_packet(channel))?;
+        Ok(())
+    }
+
+    fn fan_channels(&self) -> usize {
+        self.fan_channels
+    }
+
+    fn set_fan_duty(&self, fan_channel: usize, percent: u8) -> Result<()> {
+        if self.fan_channels == 0 {
+            return Err(Error::Unsupported("fan control"));
+        }
+        if fan_channel >= self.fan_channels {
+            return Err(Error::InvalidFanChannel {
+                requested: fan_channel,
+                available: self.fan_channels,
+            });
+        }
+
+        self.write(&fan_duty_packet(fan_channel, percent.min(100)))?;
+        Ok(())
+    }
+
+    fn fan_status(&self) -> Result<Vec<FanStatus>> {
+        if self.fan_channels == 0 {
+            return Ok(Vec::new());
+        }
+
+        let report = self.read_status_report([0x67, 0x02])?;
+        Ok(parse_fan_status(&report, self.fan_channels))
+    }
+
+    fn noise_level_db(&self) -> Result<Option<u8>> {
+        if !SMART_DEVICE_V2_PRODUCT_IDS.contains(&self.product_id) {
+            return Ok(None);
+        }
+
+        let report = self.read_status_report([0x67, 0x02])?;
+        Ok(Some(report[NOISE_OFFSET]))
+    }
+
+    fn is_liquid_cooler(&self) -> bool {
+        KRAKEN_PRODUCT_IDS.contains(&self.product_id)
+    }
+
+    /// The version is cached after the first successful query.
+    fn firmware_version(&self) -> Result<Version> {
+        if let Some(version) = self.firmware.get() {
+            return Ok(*version);
+        }
+
+        let report = self.query_firmware()?;
+        let version = Version::from_report(&report)?;
+        Ok(*self.firmware.get_or_init(|| version))
+    }
+
+    fn cached_firmware_version(&self) -> Option<Version> {
+        self.firmware.get().copied()
+    }
+
+    fn aio_status(&self) -> Result<AioStatus> {
+        if !self.is_liquid_cooler() {
+            return Err(Error::Unsupported("liquid cooler status"));
+        }
+
+        let mut buffer = [0u8; 64];
+        buffer[0x00] = 0x74;
+        buffer[0x01] = 0x01;
+
+        let device = self.device();
+        self.write_to(device.as_ref(), &buffer)?;
+        let report = read_report(device.as_ref(), [0x75, 0x01], self.read_timeout)?;
+        Ok(parse_aio_status(&report))
+    }
+
+    fn set_pump_duty(&self, percent: u8) -> Result<()> {
+        if !self.is_liquid_cooler() {
+            return Err(Error::Unsupported("pump control"));
+        }
+
+        let percent = percent.clamp(MIN_PUMP_DUTY, 100);
+        self.write(&pump_curve_packet(percent))?;
+        Ok(())
+    }
+
+    /// Values above [`MAX_BRIGHTNESS`] are clamped.
+    fn set_brightness(&self, channel: usize, brightness: u8) -> Result<()> {
+        self.check_channel(channel)?;
+
+        let mut state = self.channel_state.lock().unwrap();
+        let state = &mut state[channel];
+        state.brightness = brightness.min(MAX_BRIGHTNESS);
+        if self.force_writes() || state.applied_brightness != Some(state.brightness) {
+            self.write(&apply_packet(channel, state.brightness))?;
+            state.applied_brightness = Some(state.brightness);
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Resends applied colors of all channels once per the re-apply interval
+    /// (10 seconds by default).
+    fn ensure_applied(&self) -> Result<()> {
+        if !self.reapply.lock().unwrap().due() {
+            return Ok(());
+        }
+
+        let mut state = self.channel_state.lock().unwrap();
+        for (channel, state) in state.iter_mut().enumerate() {
+            if state.applied_colors.is_some() {
+                self.write_channel(channel, state, true)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends the firmware query, which is answered by a single report.
+    fn ping(&self) -> Result<()> {
+        let result = self.query_firmware().map(|_| ());
+        self.connected.store(result.is_ok(), Ordering::Relaxed);
+        result
+    }
+
+    /// Reports longer than 64 bytes are rejected.
+    fn send_raw(&self, report: &[u8]) -> Result<usize> {
+        ensure_arg!(
+            (1..=64).contains(&report.len()),
+            "invalid raw report length {}, must be in range 1..=64",
+            report.len()
+        );
+        self.device().write(report)
+    }
+
+    fn read_raw(&self, timeout: Duration) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; 64];
+        let len = self
+            .device()
+            .read_timeout(&mut buffer, timeout_ms(timeout))?;
+        buffer.truncate(len);
+        Ok(buffer)
+    }
+
+    /// Opens the device by its serial number (falling back to the stored path),
+    /// re-reads accessories and re-applies the last known colors.
+    ///
+    /// Channel colors are reset if the connected accessories have changed.
+    fn reconnect(&mut self, api: &HidApi) -> Result<()> {
+        let Some(info) = &self.info else {
+            return Err(Error::Unsupported("reconnection"));
+        };
+        let device = record_transport(
+            self.recorder.as_ref(),
+            native_transport(reopen_device(api, info)?),
+            self.product_id,
+        );
+
+        let (mut rgb_channels, probed_rgb_channels) =
+            get_channels_info(device.as_ref(), self.rgb_channels.len(), self.read_timeout)?;
+        apply_accessory_overrides(&mut rgb_channels, &self.accessory_overrides);
+        warn_truncated_channels(self.name, &rgb_channels);
+
+        *self.device.get_mut().unwrap() = device;
+        self.connected.store(true, Ordering::Relaxed);
+
+        let state = self.channel_state.get_mut().unwrap();
+        for (channel, (old, new)) in self.rgb_channels.iter().zip(&rgb_channels).enumerate() {
+            if old.led_count != new.led_count {
+                state[channel] = ChannelState::new(new.led_count);
+            }
+        }
+        self.rgb_channels = rgb_channels;
+        self.probed_rgb_channels = probed_rgb_channels;
+
+        let mut state = self.channel_state.lock().unwrap();
+        for (channel, state) in state.iter_mut().enumerate() {
+            if state.applied_colors.is_some() {
+                self.write_channel(channel, state, true)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Opens the device again by its serial number, so that it's found under a
+/// new path after being plugged back, or by the path without a serial.
+pub(crate) fn reopen_device(api: &HidApi, info: &hidapi::DeviceInfo) -> Result<hidapi::HidDevice> {
+    match info.serial_number() {
+        Some(serial) if !serial.is_empty() => api
+            .open_serial(info.vendor_id(), info.product_id(), serial)
+            .map_err(|e| open_error(info, e)),
+        _ => open_device(api, info),
+    }
+}
+
+/// Opens the device by its path.
+pub(crate) fn open_device(api: &HidApi, info: &hidapi::DeviceInfo) -> Result<hidapi::HidDevice> {
+    api.open_path(info.path()).map_err(|e| open_error(info, e))
+}
+
+fn open_error(info: &hidapi::DeviceInfo, error: hidapi::HidError) -> Error {
+    let kind = match &error {
+        hidapi::HidError::HidApiError { message } => {
+            if message.contains("Permission denied") {
+                Some(std::io::ErrorKind::PermissionDenied)
+            } else if message.contains("Device or resource busy")
+                // Sharing violation on Windows.
+                || message.contains("used by another process")
+            {
+                Some(std::io::ErrorKind::ResourceBusy)
+            } else {
+                None
+            }
+        }
+        hidapi::HidError::IoError { error } => Some(error.kind()),
+        _ => None,
+    };
+
+    let path = info.path().to_string_lossy().into_owned();
+    match kind {
+        Some(std::io::ErrorKind::PermissionDenied) => Error::PermissionDenied { path },
+        Some(std::io::ErrorKind::ResourceBusy) => Error::DeviceBusy { path },
+        _ => Error::Hid(error),
+    }
+}
+
+/// Returns info of all declared channels and the number of channels
+/// covered by the accessory report.
+///
+/// The report has room for the slots of 8 channels, channels past it are
+/// reported without accessories.
+fn get_channels_info(
+    device: &dyn HidTransport,
+    rgb_channels: usize,
+    timeout: Duration,
+) -> Result<(Vec<RgbChannel>, usize)> {
+    let mut buffer = [0u8; 64];
+    buffer[0] = 0x20;
+    buffer[1] = 0x03;
+    write_report(device, &buffer)?;
+
+    let report = read_report(device, [0x21, 0x03], timeout)?;
+    let probed = rgb_channels.min(REPORT_ACCESSORY_CHANNELS);
+    Ok((parse_channels_info(&report, rgb_channels), probed))
+}
+
+/// The slots of each channel follow each other from [`ACCESSORIES_OFFSET`].
+fn parse_channels_info(report: &[u8; 64], rgb_channels: usize) -> Vec<RgbChannel> {
+    let mut result = Vec::with_capacity(rgb_channels);
+    for channel in 0..rgb_channels {
+        let mut channel_info = RgbChannel::default();
+
+        let start = ACCESSORIES_OFFSET + channel * HUE_2_NUM_CHANNELS;
+        for dev in 0..HUE_2_NUM_CHANNELS {
+            // Slots past the end of the report are empty.
+            let id = report.get(start + dev).copied().unwrap_or_default();
+            // Unknown accessories are kept with zero LEDs so that
+            // they can be overridden later.
+            channel_info.devices[dev] = ChannelDeviceInfo::new(DeviceType::from(id));
+        }
+        channel_info.update_led_count();
+
+        result.push(channel_info);
+    }
+
+    result
+}
+
+/// Name, product id, serial (if any) and interface (if known) of the controller.
+///
+/// The interface tells apart the HID interfaces of a single device.
+pub(crate) fn log_label(name: &str, product_id: u16, info: Option<&hidapi::DeviceInfo>) -> String {
+    let mut label = format!("{name} ({product_id:04x}");
+    if let Some(info) = info {
+        if let Some(serial) = info.serial_number().filter(|serial| !serial.is_empty()) {
+            label += &format!(", {serial}");
+        }
+        label += &format!(", interface {}", info.interface_number());
+    }
+    label + ")"
+}
+
+/// Short description of the outgoing report for the debug log.
+pub(crate) fn describe_packet(packet: &[u8]) -> String {
+    let channels = |mask: u16| {
+        (0..MAX_RGB_CHANNELS)
+            .filter(|channel| mask & channel_mask(*channel) != 0)
+            .collect::<Vec<_>>()
+    };
+    let mask = || u16::from_le_bytes([packet[2], packet[3]]);
+
+    let kind = match packet {
+        [0x22, 0xa0, _, _, ..] => format!("apply for channels {:?}", channels(mask())),
+        [0x22, ty, _, _, ..] if ty & 0xf0 == 0x10 => format!(
+            "direct group {} for channels {:?}",
+            ty & 0x0f,
+            channels(mask())
+        ),
+        [0x28, 0x03, mask, ..] => format!("effect for channels {:?}", channels(*mask as u16)),
+        [0x20, 0x03, ..] => "accessory query".to_owned(),
+        [0x10, 0x01, ..] => "firmware query".to_owned(),
+        [0x74, 0x01, ..] => "liquid cooler status query".to_owned(),
+        [a, b, ..] => format!("report {a:02x} {b:02x}"),
+        _ => "report".to_owned(),
+    };
+    format!("{kind} ({} bytes)", packet.len())
+}
+
+/// Minimum firmware version which supports direct packets.
+///
+/// Older firmware silently ignores them.
+fn min_direct_firmware(product_id: u16) -> Option<Version> {
+    match product_id {
+        0x2001 | 0x2002 => Some(Version::new(1, 5, 0)),
+        _ => None,
+    }
+}
+
+fn check_firmware(found: Version, required: Version) -> Result<()> {
+    if found < required {
+        return Err(Error::FirmwareTooOld { found, required });
+    }
+    Ok(())
+}
+
+/// Channel bitmask used by the LED packets.
+///
+/// Direct and apply packets have two bytes for the mask, while effect and
+/// save packets have only one.
+fn channel_mask(channel: usize) -> u16 {
+    debug_assert!(channel < MAX_RGB_CHANNELS);
+    1u16 << channel
+}
+
+fn apply_accessory_overrides(channels: &mut [RgbChannel], overrides: &AccessoryOverrides) {
+    for channel in channels {
+        for device in &mut channel.devices {
+            let DeviceType::Unknown(id) = device.device_type else {
+                continue;
+            };
+            let Some(&led_count) = overrides.get(&id) else {
+                continue;
+            };
+
+            device.led_count = led_count;
+            device.name = "<user-defined>";
+            device.user_defined = true;
+        }
+        channel.update_led_count();
+    }
+}
+
+fn warn_truncated_channels(name: &str, channels: &[RgbChannel]) {
+    for (index, channel) in channels.iter().enumerate() {
+        if channel.truncated {
+            warning!(
+                "{name} channel {index} has {} LEDs, only the first {MAX_CHANNEL_LEDS} \
+                 can be addressed ({} LEDs will stay dark)",
+                channel.accessory_leds(),
+                channel.excess_leds()
+            );
+        }
+    }
+}
+
+/// Splits colors into direct packets groups followed by a single apply packet.
+fn channel_leds_packets(
+    channel: usize,
+    colors: &[Color],
+    brightness: u8,
+    order: ColorOrder,
+    transform: &ColorTransform,
+) -> impl Iterator<Item = Result<[u8; 64]>> {
+    let direct = colors
+        .chunks(MAX_COLORS_PER_PACKET)
+        .enumerate()
+        .map(move |(group, colors)| direct_packet(channel, group, colors, order, transform));
+    direct.chain(std::iter::once(Ok(apply_packet(channel, brightness))))
+}
+
+/// Builds direct packets for all staged channels followed by apply packets
+/// with the combined channel mask (one for each distinct brightness level).
+fn frame_packets<'c, I>(channels: I, transform: &ColorTransform) -> Result<Vec<[u8; 64]>>
+where
+    I: IntoIterator<Item = (usize, &'c [Color], u8, ColorOrder)>,
+{
+    let mut packets = Vec::new();
+    let mut applies = Vec::<(u8, u16)>::new();
+    for (channel, colors, brightness, order) in channels {
+        for (group, colors) in colors.chunks(MAX_COLORS_PER_PACKET).enumerate() {
+            packets.push(direct_packet(channel, group, colors, order, transform)?);
+        }
+
+        match applies.iter_mut().find(|(b, _)| *b == brightness) {
+            Some((_, mask)) => *mask |= channel_mask(channel),
+            None => applies.push((brightness, channel_mask(channel))),
+        }
+    }
+
+    packets.extend(
+        applies
+            .into_iter()
+            .map(|(brightness, mask)| apply_mask_packet(mask, brightness)),
+    );
+    Ok(packets)
+}
+
+/// Builds a direct packet with colors of the LED group (of up to
+/// [`MAX_COLORS_PER_PACKET`] LEDs each).
+fn direct_packet(
+    channel: usize,
+    group: usize,
+    color_data: &[Color],
+    order: ColorOrder,
+    transform: &ColorTransform,
+) -> Result<[u8; 64]> {
+    if color_data.len() > MAX_COLORS_PER_PACKET {
+        return Err(Error::TooManyColorsPerGroup {
+            colors: color_data.len(),
+            max: MAX_COLORS_PER_PACKET,
+        });
+    }
+    // The group index is stored in the low nibble of the packet type.
+    if group >= MAX_DIRECT_GROUPS {
+        return Err(Error::TooManyGroups {
+            groups: group + 1,
+            max: MAX_DIRECT_GROUPS,
+        });
+    }
+
+    let mut buffer = [0u8; 64];
+    buffer[0x00] = 0x22;
+    buffer[0x01] = 0x10 | group as u8;
+    buffer[0x02..0x04].copy_from_slice(&channel_mask(channel).to_le_bytes());
+    let len = order.encode_into(color_data, &mut buffer[0x04..]);
+    transform.apply_slice(&mut buffer[0x04..0x04 + len]);
+    Ok(buffer)
+}
+
+fn apply_packet(channel: usize, brightness: u8) -> [u8; 64] {
+    apply_mask_packet(channel_mask(channel), brightness)
+}
+
+fn apply_mask_packet(mask: u16, brightness: u8) -> [u8; 64] {
+    let mut buffer = [0u8; 64];
+    buffer[0x00] = 0x22;
+    buffer[0x01] = 0xa0;
+    buffer[0x02..0x04].copy_from_slice(&mask.to_le_bytes());
+    buffer[0x04] = 0x01;
+    buffer[0x07] = 0x28;
+    buffer[0x0a] = brightness_byte(brightness);
+    buffer[0x0c] = 0x32;
+    buffer[0x0f] = 0x01;
+    buffer
+}
+
+fn save_packet(channel: usize) -> [u8; 64] {
+    let mut buffer = [0u8; 64];
+    buffer[0x00] = 0x2a;
+    buffer[0x01] = 0x03;
+    buffer[0x02] = channel_mask(channel) as u8;
+    buffer[0x03] = 0x01;
+    buffer
+}
+
+fn brightness_byte(percent: u8) -> u8 {
+    (percent.min(MAX_BRIGHTNESS) as u16 * 0x80 / MAX_BRIGHTNESS as u16) as u8
+}
+
+fn effect_packet(
+    channel: usize,
+    mode: LedMode,
+    colors: &[Color],
+    speed: u8,
+    direction: Direction,
+    order: ColorOrder,
+    transform: &ColorTransform,
+) -> [u8; 64] {
+    let mut buffer = [0u8; 64];
+    buffer[0x00] = 0x28;
+    buffer[0x01] = 0x03;
+    buffer[0x02] = channel_mask(channel) as u8;
+    buffer[0x03] = 0x28;
+    buffer[0x04] = mode as u8;
+    buffer[0x05] = speed;
+    buffer[0x06] = direction as u8;
+    buffer[0x07] = colors.len() as u8;
+    let len = order.encode_into(colors, &mut buffer[0x0a..]);
+    transform.apply_slice(&mut buffer[0x0a..0x0a + len]);
+    buffer
+}
+
+pub(crate) fn write_report(device: &dyn HidTransport, packet: &[u8]) -> Result<()> {
+    let written = device.write(packet)?;
+    check_written(packet.len(), written)?;
+    Ok(())
+}
+
+fn check_written(expected: usize, written: usize) -> Result<(), Error> {
+    // Some backends also count the report id byte.
+    if written < expected {
+        return Err(Error::ShortWrite { expected, written });
+    }
+    Ok(())
+}
+
+/// Waits for the report with the specified header, skipping unrelated ones.
+fn read_report(device: &dyn HidTransport, header: [u8; 2], timeout: Duration) -> Result<[u8; 64]> {
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0u8; 64];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::Timeout);
+        }
+
+        let len = device.read_timeout(&mut buffer, timeout_ms(remaining))?;
+        if is_expected_report(&buffer[..len], header)? {
+            return Ok(buffer);
+        }
+    }
+}
+
+/// Returns whether the received data is a full report with the specified header.
+fn is_expected_report(data: &[u8], header: [u8; 2]) -> Result<bool, Error> {
+    if data.len() < 2 || data[..2] != header {
+        return Ok(false);
+    }
+    if data.len() < 64 {
+        return Err(Error::ShortRead {
+            expected: 64,
+            read: data.len(),
+        });
+    }
+    Ok(true)
+}
+
+fn parse_fan_status(report: &[u8; 64], fan_channels: usize) -> Vec<FanStatus> {
+    const MODE_OFFSET: usize = 0x10;
+    const RPM_OFFSET: usize = 0x18;
+    const DUTY_OFFSET: usize = 0x28;
+
+    (0..fan_channels)
+        .map(|i| {
+            let rpm_offset = RPM_OFFSET + i * 2;
+            FanStatus {
+                rpm: u16::from_le_bytes([report[rpm_offset], report[rpm_offset + 1]]),
+                duty: report[DUTY_OFFSET + i],
+                fan_type: FanType::from(report[MODE_OFFSET + i]),
+            }
+        })
+        .collect()
+}
+
+fn parse_aio_status(report: &[u8; 64]) -> AioStatus {
+    AioStatus {
+        liquid_temp: report[0x0f] as f32 + report[0x10] as f32 / 10.0,
+        pump_rpm: u16::from_le_bytes([report[0x11], report[0x12]]),
+        pump_duty: Some(report[0x13]),
+    }
+}
+
+/// The firmware expects a full duty table for liquid temperatures
+/// from 20 to 59 degrees, so a fixed duty is sent as a flat curve.
+fn pump_curve_packet(percent: u8) -> [u8; 64] {
+    let mut buffer = [0u8; 64];
+    buffer[0x00] = 0x72;
+    buffer[0x01] = 0x01;
+    buffer[0x04..0x04 + PUMP_CURVE_POINTS].fill(percent);
+    buffer
+}
+
+fn fan_duty_packet(fan_channel: usize, percent: u8) -> [u8; 64] {
+    let mut buffer = [0u8; 64];
+    buffer[0x00] = 0x62;
+    buffer[0x01] = 0x01;
+    buffer[0x02] = 0x01u8 << fan_channel;
+    buffer[0x03 + fan_channel] = percent;
+    buffer
+}
+
+/// Batch update of the controller LEDs.
+pub struct Frame<'c, 'a> {
+    controller: &'c NZXTHue2Controller<'a>,
+    staged: Vec<Option<Vec<Color>>>,
+}
+
+impl Frame<'_, '_> {
+    pub fn set_channel_color(&mut self, channel: usize, color: Color) -> Result<&mut Self> {
+        self.staged_channel(channel)?.fill(color);
+        Ok(self)
+    }
+
+    pub fn set_channel_leds(&mut self, channel: usize, colors: &[Color]) -> Result<&mut Self> {
+        let staged = self.staged_channel(channel)?;
+        ensure_arg!(
+            colors.len() <= staged.len(),
+            "too many colors for channel {channel}: {} given, {} LEDs available",
+            colors.len(),
+            staged.len()
+        );
+        staged[..colors.len()].copy_from_slice(colors);
+        Ok(self)
+    }
+
+    pub fn set_led(&mut self, channel: usize, led: usize, color: Color) -> Result<&mut Self> {
+        let staged = self.staged_channel(channel)?;
+        let led_count = staged.len();
+        let Some(target) = staged.get_mut(led) else {
+            return Err(Error::InvalidArgument(format!(
+                "invalid LED {led} on channel {channel}, channel has {led_count} LEDs"
+            )));
+        };
+        *target = color;
+        Ok(self)
+    }
+
+    /// Sends all staged channels to the controller.
+    ///
+    /// Channels whose colors didn't change since the last write are skipped.
+    pub fn commit(self) -> Result<()> {
+        let controller = self.controller;
+        controller.check_direct_mode()?;
+        let force = controller.force_writes();
+        let mut state = controller.channel_state.lock().unwrap();
+
+        let mut dirty = Vec::new();
+        for (channel, colors) in self.staged.into_iter().enumerate() {
+            if let Some(colors) = colors {
+                state[channel].colors = colors;
+                if state[channel].begin_write(force) {
+                    dirty.push(channel);
+                }
+            }
+        }
+
+        let channels = dirty
+            .iter()
+            .map(|&channel| {
+                let state = &state[channel];
+                let order = controller.color_order(channel);
+                (channel, &state.colors[..], state.brightness, order)
+            })
+            .collect::<Vec<_>>();
+        for packet in frame_packets(channels, &controller.color_transform())? {
+            controller.write(&packet)?;
+        }
+
+        for channel in dirty {
+            state[channel].mark_applied();
+        }
+        Ok(())
+    }
+
+    fn staged_channel(&mut self, channel: usize) -> Result<&mut Vec<Color>> {
+        self.controller.check_channel(channel)?;
+        let colors = self.staged[channel].get_or_insert_with(|| {
+            let state = self.controller.channel_state.lock().unwrap();
+            state[channel].colors.clone()
+        });
+        Ok(colors)
+    }
+}
+
+struct ChannelState {
+    colors: Vec<Color>,
+    brightness: u8,
+    /// Colors sent with the last apply packet.
+    applied_colors: Option<Vec<Color>>,
+    applied_brightness: Option<u8>,
+}
+
+impl ChannelState {
+    fn new(led_count: usize) -> Self {
+        Self {
+            colors: vec![Color::BLACK; led_count],
+            brightness: MAX_BRIGHTNESS,
+            applied_colors: None,
+            applied_brightness: None,
+        }
+    }
+
+    /// Returns whether the current state must be sent to the device.
+    fn begin_write(&self, force: bool) -> bool {
+        force
+            || self.applied_brightness != Some(self.brightness)
+            || self.applied_colors.as_ref() != Some(&self.colors)
+    }
+
+    fn mark_applied(&mut self) {
+        match &mut self.applied_colors {
+            Some(applied) => applied.clone_from(&self.colors),
+            None => self.applied_colors = Some(self.colors.clone()),
+        }
+        self.applied_brightness = Some(self.brightness);
+    }
+
+    fn invalidate(&mut self) {
+        self.applied_colors = None;
+        self.applied_brightness = None;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AioStatus {
+    /// Liquid temperature in degrees celsius.
+    pub liquid_temp: f32,
+    pub pump_rpm: u16,
+    /// Pump duty cycle in percent (if reported by the device).
+    pub pump_duty: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FanStatus {
+    pub rpm: u16,
+    pub duty: u8,
+    pub fan_type: FanType,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FanType {
+    /// No fan connected.
+    #[default]
+    None,
+    Dc,
+    Pwm,
+    Unknown(u8),
+}
+
+impl FanType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Dc => "dc",
+            Self::Pwm => "pwm",
+            Self::Unknown(_) => "unknown",
+        }
+    }
+}
+
+impl From<u8> for FanType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => Self::None,
+            0x01 => Self::Dc,
+            0x02 => Self::Pwm,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RgbChannel {
+    /// Number of addressable LEDs (at most [`MAX_CHANNEL_LEDS`]).
+    pub led_count: usize,
+    pub devices: [ChannelDeviceInfo; HUE_2_NUM_CHANNELS],
+    /// Whether connected accessories have more LEDs than the channel supports.
+    pub truncated: bool,
+}
+
+impl RgbChannel {
+    /// Returns a range of LEDs which belong to the device at the specified slot.
+    ///
+    /// Returns `None` if the slot is empty or beyond the channel LED limit.
+    /// The range is cut if the device is only partially addressable.
+    pub fn device_leds(&self, index: usize) -> Option<Range<usize>> {
+        let device = self.devices.get(index)?;
+        if device.led_count == 0 {
+            return None;
+        }
+
+        let start = self.devices[..index]
+            .iter()
+            .map(|device| device.led_count as usize)
+            .sum::<usize>();
+        if start >= self.led_count {
+            return None;
+        }
+        Some(start..(start + device.led_count as usize).min(self.led_count))
+    }
+
+    /// Total number of LEDs of the connected accessories.
+    pub fn accessory_leds(&self) -> usize {
+        self.devices
+            .iter()
+            .map(|device| device.led_count as usize)
+            .sum()
+    }
+
+    /// Number of accessory LEDs which can't be addressed.
+    pub fn excess_leds(&self) -> usize {
+        self.accessory_leds().saturating_sub(self.led_count)
+    }
+
+    /// Recomputes the LED count from the accessories.
+    fn update_led_count(&mut self) {
+        let total = self.accessory_leds();
+        self.led_count = total.min(MAX_CHANNEL_LEDS);
+        self.truncated = total > MAX_CHANNEL_LEDS;
+    }
+
+    /// Returns positions of all channel LEDs in order.
+    pub fn led_positions(&self) -> Vec<LedPosition> {
+        let mut positions = Vec::with_capacity(self.led_count);
+        for (index, device) in self.devices.iter().enumerate() {
+            let layout = device.layout();
+            positions.extend((0..layout.leds()).filter_map(|led| {
+                let position = layout.position(led)?;
+                Some(LedPosition {
+                    device: index,
+                    x: position.x + index as f32,
+                    ..position
+                })
+            }));
+        }
+        positions
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelDeviceInfo {
+    pub id: u8,
+    pub device_type: DeviceType,
+    pub name: &'static str,
+    pub led_count: u8,
+    /// Whether the LED count was specified by the user.
+    pub user_defined: bool,
+}
+
+impl ChannelDeviceInfo {
+    pub fn new(device_type: DeviceType) -> Self {
+        Self {
+            id: device_type.id(),
+            device_type,
+            name: device_type.name(),
+            led_count: device_type.led_count(),
+            user_defined: false,
+        }
+    }
+
+    /// Accessories with user-defined LED counts are treated as strips.
+    pub fn layout(&self) -> Layout {
+        if self.user_defined {
+            Layout::Strip {
+                leds: self.led_count,
+            }
+        } else {
+            self.device_type.layout_with(self.led_count)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LedMode {
+    Fixed = 0x00,
+    Fading = 0x01,
+    Spectrum = 0x02,
+    Marquee = 0x03,
+    CoverMarquee = 0x04,
+    Alternating = 0x05,
+    Pulsing = 0x06,
+    Breathing = 0x07,
+    Candle = 0x08,
+    StarryNight = 0x09,
+
+    RainbowFlow = 0x0b,
+    SuperRainbow = 0x0c,
+    RainbowPulse = 0x0d,
+}
+
+impl LedMode {
+    pub const ALL: [Self; 13] = [
+        Self::Fixed,
+        Self::Fading,
+        Self::Spectrum,
+        Self::Marquee,
+        Self::CoverMarquee,
+        Self::Alternating,
+        Self::Pulsing,
+        Self::Breathing,
+        Self::Candle,
+        Self::StarryNight,
+        Self::RainbowFlow,
+        Self::SuperRainbow,
+        Self::RainbowPulse,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Fixed => "fixed",
+            Self::Fading => "fading",
+            Self::Spectrum => "spectrum",
+            Self::Marquee => "marquee",
+            Self::CoverMarquee => "cover-marquee",
+            Self::Alternating => "alternating",
+            Self::Pulsing => "pulsing",
+            Self::Breathing => "breathing",
+            Self::Candle => "candle",
+            Self::StarryNight => "starry-night",
+            Self::RainbowFlow => "rainbow-flow",
+            Self::SuperRainbow => "super-rainbow",
+            Self::RainbowPulse => "rainbow-pulse",
+        }
+    }
+
+    /// Minimal number of colors accepted by this mode.
+    pub fn min_colors(&self) -> usize {
+        match self {
+            Self::Spectrum | Self::RainbowFlow | Self::SuperRainbow | Self::RainbowPulse => 0,
+            Self::Fading | Self::Alternating => 2,
+            _ => 1,
+        }
+    }
+
+    /// Maximal number of colors accepted by this mode.
+    pub fn max_colors(&self) -> usize {
+        match self {
+            Self::Spectrum | Self::RainbowFlow | Self::SuperRainbow | Self::RainbowPulse => 0,
+            Self::Fixed | Self::Marquee | Self::Candle | Self::StarryNight => 1,
+            Self::Alternating => 2,
+            Self::Fading | Self::CoverMarquee | Self::Pulsing | Self::Breathing => {
+                MAX_EFFECT_COLORS
+            }
+        }
+    }
+}
+
+impl fmt::Display for LedMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Mode names are case-insensitive.
+impl FromStr for LedMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names = Self::ALL.map(|mode| mode.name()).join(", ");
+                Error::InvalidArgument(format!("unknown LED mode `{s}`, expected one of: {names}"))
+            })
+    }
+}
+
+impl TryFrom<u8> for LedMode {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| *mode as u8 == value)
+            .ok_or_else(|| Error::InvalidArgument(format!("unknown LED mode 0x{value:02x}")))
+    }
+}
+
+/// Serialized by the mode name.
+impl serde::Serialize for LedMode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for LedMode {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <Cow<'de, str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Lists every mode in the `--help` output.
+impl clap::ValueEnum for LedMode {
+    fn value_variants<'a>() -> &'a [Self] {
+        &Self::ALL
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.name()))
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Direction {
+    #[default]
+    Forward = 0x00,
+    Backward = 0x01,
+}
+
+impl Direction {
+    pub const ALL: [Self; 2] = [Self::Forward, Self::Backward];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Forward => "forward",
+            Self::Backward => "backward",
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|direction| direction.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "unknown direction `{s}`, expected one of: forward, backward"
+                ))
+            })
+    }
+}
+
+/// Serialized by the direction name.
+impl serde::Serialize for Direction {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Direction {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <Cow<'de, str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl clap::ValueEnum for Direction {
+    fn value_variants<'a>() -> &'a [Self] {
+        &Self::ALL
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.name()))
+    }
+}
+
+/// Smart Device V2 with a built-in noise sensor.
+const SMART_DEVICE_V2_PRODUCT_IDS: [u16; 3] = [0x2006, 0x200D, 0x200F];
+/// Offset of the noise level in the fan status report.
+const NOISE_OFFSET: usize = 0x38;
+
+/// Kraken X3 and Kraken Elite liquid coolers.
+const KRAKEN_PRODUCT_IDS: [u16; 3] = [0x2007, 0x2014, 0x3012];
+
+/// Pumps refuse to work below this duty cycle.
+pub const MIN_PUMP_DUTY: u8 = 20;
+/// Number of points in the pump duty curve.
+const PUMP_CURVE_POINTS: usize = 40;
+
+/// Max time to wait for a periodic status report.
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_REAPPLY_INTERVAL: Duration = Duration::from_secs(10);
+const READ_SLICE: Duration = Duration::from_millis(10);
+
+/// Max brightness of the channel (in percent).
+pub const MAX_BRIGHTNESS: u8 = 100;
+
+/// Max number of colors in a single direct packet.
+pub const MAX_COLORS_PER_PACKET: usize = 20;
+/// Max number of direct packets (LED groups) per channel update.
+const MAX_DIRECT_GROUPS: usize = 16;
+
+/// Max number of colors in a single effect packet.
+pub const MAX_EFFECT_COLORS: usize = 8;
+/// Max value of the effect speed (from slowest to fastest).
+pub const MAX_EFFECT_SPEED: u8 = 4;
+
+pub(crate) const HUE_2_NUM_CHANNELS: usize = 6;
+
+/// Max number of RGB channels addressable by the two-byte channel mask.
+const MAX_RGB_CHANNELS: usize = 16;
+/// Maximum number of LEDs addressable on a single Hue 2 channel.
+pub const MAX_CHANNEL_LEDS: usize = 40;
+/// Max number of RGB channels addressable by the single-byte channel mask.
+const MAX_SHORT_MASK_CHANNELS: usize = 8;
+
+/// Offset of the accessory ids in the 0x21 0x03 report.
+pub(crate) const ACCESSORIES_OFFSET: usize = 0x0f;
+/// Number of channels whose accessory slots fit in the 0x21 0x03 report.
+const REPORT_ACCESSORY_CHANNELS: usize = (64 - ACCESSORIES_OFFSET) / HUE_2_NUM_CHANNELS;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{MockTransport, RecordEntry, mock_accessory_page, mock_controller};
+
+    #[test]
+    fn led_mode_names() {
+        for mode in LedMode::ALL {
+            assert_eq!(mode.to_string().parse::<LedMode>().unwrap(), mode);
+            assert_eq!(LedMode::try_from(mode as u8).unwrap(), mode);
+        }
+        assert_eq!(
+            "Cover-Marquee".parse::<LedMode>().unwrap(),
+            LedMode::CoverMarquee
+        );
+        assert!("rainbow".parse::<LedMode>().is_err());
+        assert!(LedMode::try_from(0x0a).is_err());
+        assert!(LedMode::try_from(0x0e).is_err());
+
+        let mode = <LedMode as clap::ValueEnum>::from_str("breathing", false).unwrap();
+        assert_eq!(mode, LedMode::Breathing);
+
+        let json = serde_json::to_value(LedMode::StarryNight).unwrap();
+        assert_eq!(json, "starry-night");
+        assert_eq!(
+            serde_json::from_value::<LedMode>(json).unwrap(),
+            LedMode::StarryNight
+        );
+        assert!(serde_json::from_value::<LedMode>(serde_json::json!(7)).is_err());
+
+        for direction in Direction::ALL {
+            let json = serde_json::to_value(direction).unwrap();
+            assert_eq!(json, direction.to_string());
+            assert_eq!(
+                serde_json::from_value::<Direction>(json).unwrap(),
+                direction
+            );
+        }
+    }
+
+    #[test]
+    fn effect_packets() {
+        let packet = effect_packet(
+            1,
+            LedMode::Spectrum,
+            &[],
+            2,
+            Direction::Backward,
+            ColorOrder::Grb,
+            &ColorTransform::IDENTITY,
+        );
+        let mut expected = [0u8; 64];
+        expected[..0x08].copy_from_slice(&[0x28, 0x03, 0x02, 0x28, 0x02, 0x02, 0x01, 0x00]);
+        assert_eq!(packet, expected);
+
+        let colors = [Color::RED, Color::new(0x11, 0x22, 0x33)];
+        let packet = effect_packet(
+            0,
+            LedMode::Breathing,
+            &colors,
+            4,
+            Direction::Forward,
+            ColorOrder::Grb,
+            &ColorTransform::IDENTITY,
+        );
+        let mut expected = [0u8; 64];
+        expected[..0x08].copy_from_slice(&[0x28, 0x03, 0x01, 0x28, 0x07, 0x04, 0x00, 0x02]);
+        expected[0x0a..0x10].copy_from_slice(&[0x00, 0xff, 0x00, 0x22, 0x11, 0x33]);
+        assert_eq!(packet, expected);
+    }
+
+    #[test]
+    fn save_packets() {
+        let mut expected = [0u8; 64];
+        expected[..0x04].copy_from_slice(&[0x2a, 0x03, 0x04, 0x01]);
+        assert_eq!(save_packet(2), expected);
+
+        let (transport, controller) = mock_controller(&[&[0x13], &[0x13]]);
+        controller.save_to_device(1).unwrap();
+        expected[0x02] = 0x02;
+        assert_eq!(transport.written(), [expected.to_vec()]);
+    }
+
+    #[test]
+    fn channel_leds_grouping() {
+        let colors = [Color::RED; 41];
+        let packets = channel_leds_packets(
+            2,
+            &colors,
+            MAX_BRIGHTNESS,
+            ColorOrder::Grb,
+            &ColorTransform::IDENTITY,
+        )
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+        assert_eq!(packets.len(), 4);
+
+        for (group, packet) in packets[..3].iter().enumerate() {
+            assert_eq!(packet[..4], [0x22, 0x10 | group as u8, 0x04, 0x00]);
+        }
+        assert!(
+            packets[0][0x04..0x40]
+                .chunks(3)
+                .all(|c| c == [0x00, 0xff, 0x00])
+        );
+        assert!(
+            packets[1][0x04..0x40]
+                .chunks(3)
+                .all(|c| c == [0x00, 0xff, 0x00])
+        );
+        assert_eq!(packets[2][0x04..0x07], [0x00, 0xff, 0x00]);
+        assert!(packets[2][0x07..].iter().all(|&b| b == 0));
+
+        assert_eq!(packets[3], apply_packet(2, MAX_BRIGHTNESS));
+        assert_eq!(packets[3][..3], [0x22, 0xa0, 0x04]);
+
+        let packets = channel_leds_packets(
+            0,
+            &colors[..20],
+            MAX_BRIGHTNESS,
+            ColorOrder::Grb,
+            &ColorTransform::IDENTITY,
+        )
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+        assert_eq!(packets.len(), 2);
+
+        let packets = channel_leds_packets(0, &[], 50, ColorOrder::Grb, &ColorTransform::IDENTITY)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(packets, [apply_packet(0, 50)]);
+    }
+
+    #[test]
+    fn direct_packet_limits() {
+        let colors = [Color::RED; MAX_COLORS_PER_PACKET + 1];
+        let transform = ColorTransform::IDENTITY;
+
+        let packet = direct_packet(0, 15, &colors[..20], ColorOrder::Grb, &transform).unwrap();
+        assert_eq!(packet[..2], [0x22, 0x1f]);
+
+        assert!(matches!(
+            direct_packet(0, 0, &colors, ColorOrder::Grb, &transform),
+            Err(Error::TooManyColorsPerGroup {
+                colors: 21,
+                max: 20
+            })
+        ));
+        assert!(matches!(
+            direct_packet(0, 16, &colors[..1], ColorOrder::Grb, &transform),
+            Err(Error::TooManyGroups {
+                groups: 17,
+                max: 16
+            })
+        ));
+
+        // 17 groups would be needed.
+        let colors = [Color::RED; MAX_COLORS_PER_PACKET * 16 + 1];
+        let result = channel_leds_packets(0, &colors, 50, ColorOrder::Grb, &transform)
+            .collect::<Result<Vec<_>>>();
+        assert!(matches!(
+            result,
+            Err(Error::TooManyGroups { groups: 17, .. })
+        ));
+    }
+
+    #[test]
+    fn transport_accessory_detection() {
+        let transport = MockTransport::new();
+        // Unrelated status report must be skipped.
+        transport.push_response([0x67, 0x02]);
+        transport.push_response(mock_accessory_page(&[&[0x13, 0x13], &[0x14]]));
+        let controller = NZXTHue2Controller::with_transport(
+            Box::new(transport.clone()),
+            0x2012,
+            "NZXT RGB Controller",
+            3,
+            0,
+            true,
+        )
+        .unwrap();
+
+        let written = transport.written();
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0][..2], [0x20, 0x03]);
+
+        let led_counts = controller
+            .rgb_channels()
+            .iter()
+            .map(|c| c.led_count)
+            .collect::<Vec<_>>();
+        assert_eq!(led_counts, [36, 18, 0]);
+    }
+
+    #[test]
+    fn transport_channels_past_the_report() {
+        let mut page = [0u8; 64];
+        page[..2].copy_from_slice(&[0x21, 0x03]);
+        page[ACCESSORIES_OFFSET..].fill(0x13);
+
+        // Slots of 10 channels don't fit in the report.
+        let transport = MockTransport::new();
+        transport.push_response(page);
+        let controller = NZXTHue2Controller::with_transport(
+            Box::new(transport.clone()),
+            0x2012,
+            "NZXT RGB Controller",
+            10,
+            3,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(controller.declared_rgb_channels(), 10);
+        assert_eq!(controller.probed_rgb_channels(), 8);
+        assert_eq!(controller.declared_fan_channels(), 3);
+        assert_eq!(controller.rgb_channels().len(), 10);
+        assert_eq!(controller.rgb_channels()[8].accessory_leds(), 18);
+        assert_eq!(controller.rgb_channels()[9].accessory_leds(), 0);
+    }
+
+    #[test]
+    fn transport_skip_empty_channels() {
+        let (transport, controller) = mock_controller(&[&[], &[0x13]]);
+
+        controller.set_fixed_color(Color::RED).unwrap();
+        let written = transport.written();
+        assert!(!written.is_empty());
+        assert!(written.iter().all(|p| p[0x02..0x04] == [0x02, 0x00]));
+
+        transport.clear_written();
+        controller.set_fixed_color_all(Color::BLUE, true).unwrap();
+        let masks = transport
+            .written()
+            .iter()
+            .map(|p| p[0x02])
+            .fold(0, |masks, mask| masks | mask);
+        assert_eq!(masks, 0b111);
+    }
+
+    #[test]
+    fn firmware_gate() {
+        let required = Version::new(1, 5, 0);
+        assert!(check_firmware(Version::new(1, 5, 0), required).is_ok());
+        assert!(check_firmware(Version::new(1, 5, 1), required).is_ok());
+        assert!(check_firmware(Version::new(2, 0, 0), required).is_ok());
+        assert!(matches!(
+            check_firmware(Version::new(1, 4, 9), required),
+            Err(Error::FirmwareTooOld { found, .. }) if found == Version::new(1, 4, 9)
+        ));
+        assert!(check_firmware(Version::new(0, 9, 0), required).is_err());
+    }
+
+    #[test]
+    fn transport_firmware_too_old() {
+        let mut page = [0u8; 64];
+        page[..2].copy_from_slice(&[0x21, 0x03]);
+        page[ACCESSORIES_OFFSET] = 0x13;
+        let mut firmware = [0u8; 64];
+        firmware[..2].copy_from_slice(&[0x11, 0x01]);
+        firmware[0x11..0x14].copy_from_slice(&[1, 4, 2]);
+
+        let transport = MockTransport::new();
+        transport.push_response(page);
+        transport.push_response(firmware);
+        let controller = NZXTHue2Controller::with_transport(
+            Box::new(transport.clone()),
+            0x2001,
+            "NZXT Hue 2",
+            4,
+            0,
+            false,
+        )
+        .unwrap();
+        transport.clear_written();
+        assert_eq!(controller.snapshot().firmware, None);
+
+        let result = controller.set_channel_color(0, Color::RED);
+        assert!(matches!(result, Err(Error::FirmwareTooOld { .. })));
+        assert_eq!(
+            controller.firmware_version().unwrap(),
+            Version::new(1, 4, 2)
+        );
+        assert_eq!(controller.snapshot().firmware, Some(Version::new(1, 4, 2)));
+        // Only the version query was sent.
+        let written = transport.written();
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0][..2], [0x10, 0x01]);
+
+        let controller = controller.skip_firmware_check(true);
+        controller.set_channel_color(0, Color::RED).unwrap();
+    }
+
+    #[test]
+    fn transport_controller_options() {
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut page = [0u8; 64];
+        page[..2].copy_from_slice(&[0x21, 0x03]);
+        page[ACCESSORIES_OFFSET] = 0x1f;
+
+        let output = Output::default();
+        let transform = ColorTransform::new(1.0, 0.5).unwrap();
+        let options = ControllerOptions::new()
+            .accessory_overrides(AccessoryOverrides::from_iter([(0x1f, 2)]))
+            .color_orders(ColorOrders::from_iter([(0, ColorOrder::Rgb)]))
+            .color_transform(transform)
+            .reapply_interval(None)
+            .read_timeout(Duration::from_millis(20))
+            .write_retries(2)
+            .skip_firmware_check(true)
+            .record_hid(HidRecorder::new(output.clone()));
+
+        let transport = MockTransport::new();
+        transport.push_response(page);
+        let controller = NZXTHue2Controller::with_transport_options(
+            Box::new(transport.clone()),
+            0x2001,
+            &("NZXT Hue 2", 4, 0, false),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(controller.rgb_channels()[0].led_count, 2);
+        assert_eq!(controller.color_transform(), transform);
+        transport.clear_written();
+
+        // Neither the firmware is queried nor colors are reapplied.
+        controller.set_channel_color(0, Color::RED).unwrap();
+        controller.ensure_applied().unwrap();
+        let written = transport.written();
+        assert_eq!(written.len(), 2);
+        // RGB order at half brightness.
+        assert_eq!(written[0][0x04..0x0a], [0x80, 0x00, 0x00, 0x80, 0x00, 0x00]);
+
+        // The short read timeout is used for status replies.
+        let started = Instant::now();
+        assert!(matches!(controller.ping(), Err(Error::Timeout)));
+        assert!(started.elapsed() < STATUS_TIMEOUT);
+
+        // Failed writes are repeated.
+        transport.clear_written();
+        transport.set_write_limit(Some(8));
+        assert!(controller.set_channel_color(0, Color::BLUE).is_err());
+        assert_eq!(transport.written().len(), 3);
+
+        let recorded = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let first: RecordEntry = serde_json::from_str(recorded.lines().next().unwrap()).unwrap();
+        assert_eq!(first.device, "2001");
+        assert!(first.data.starts_with("2003"));
+    }
+
+    #[test]
+    fn transport_led_grouping() {
+        let (transport, controller) = mock_controller(&[&[0x13, 0x13]]);
+
+        controller.set_channel_color(0, Color::RED).unwrap();
+
+        let written = transport.written();
+        let headers = written.iter().map(|p| [p[0], p[1]]).collect::<Vec<_>>();
+        assert_eq!(headers, [[0x22, 0x10], [0x22, 0x11], [0x22, 0xa0]]);
+        assert!(written.iter().all(|p| p.len() == 64));
+        assert_eq!(controller.last_applied(0), Some(vec![Color::RED; 36]));
+    }
+
+    #[test]
+    fn transport_skips_identical_writes() {
+        let (transport, controller) = mock_controller(&[&[0x13], &[0x14]]);
+
+        controller.set_fixed_color(Color::BLUE).unwrap();
+        let first = transport.written().len();
+        for _ in 0..9 {
+            controller.set_fixed_color(Color::BLUE).unwrap();
+        }
+        assert_eq!(transport.written().len(), first);
+
+        controller.set_force_writes(true);
+        controller.set_fixed_color(Color::BLUE).unwrap();
+        assert_eq!(transport.written().len(), first * 2);
+    }
+
+    #[test]
+    fn transport_skips_identical_frames() {
+        let (transport, controller) = mock_controller(&[&[0x13], &[0x14]]);
+        let gradient = (0..18)
+            .map(|i| Color::new(i * 10, 0, 0xff - i * 10))
+            .collect::<Vec<_>>();
+        let frame = [(0, &gradient[..]), (1, &[Color::GREEN; 12][..])];
+
+        controller.set_frame(&frame).unwrap();
+        assert!(!transport.written().is_empty());
+        transport.clear_written();
+
+        controller.set_frame(&frame).unwrap();
+        controller.set_channel_leds(0, &gradient).unwrap();
+        assert!(transport.written().is_empty());
+
+        // A single changed LED is sent again.
+        let mut changed = gradient.clone();
+        changed[17] = Color::WHITE;
+        controller.set_frame(&[(0, &changed)]).unwrap();
+        assert!(!transport.written().is_empty());
+    }
+
+    #[test]
+    fn transport_short_channel_leds() {
+        let (transport, controller) = mock_controller(&[&[0x13, 0x13]]);
+        controller.set_channel_leds(0, &[Color::RED; 36]).unwrap();
+        transport.clear_written();
+
+        // The rest of the LEDs are padded with their last colors.
+        controller.set_channel_leds(0, &[Color::BLUE; 2]).unwrap();
+        let mut expected = vec![Color::BLUE; 2];
+        expected.resize(36, Color::RED);
+        assert_eq!(controller.last_applied(0), Some(expected));
+        let written = transport.written();
+        let headers = written.iter().map(|p| [p[0], p[1]]).collect::<Vec<_>>();
+        assert_eq!(headers, [[0x22, 0x10], [0x22, 0x11], [0x22, 0xa0]]);
+        // GRB order.
+        assert_eq!(written[0][0x04..0x0a], [0x00, 0x00, 0xff, 0x00, 0x00, 0xff]);
+        assert_eq!(written[0][0x0a..0x0d], [0x00, 0xff, 0x00]);
+    }
+
+    #[test]
+    fn transport_short_write() {
+        let (transport, controller) = mock_controller(&[&[0x13]]);
+        transport.set_write_limit(Some(32));
+
+        let result = controller.set_channel_color(0, Color::RED);
+        assert!(matches!(
+            result,
+            Err(Error::ShortWrite {
+                expected: 64,
+                written: 32
+            })
+        ));
+        assert!(!controller.is_connected());
+        assert_eq!(controller.last_applied(0), None);
+    }
+
+    #[test]
+    fn transport_color_order() {
+        let (transport, controller) = mock_controller(&[&[0x13], &[0x13]]);
+        let controller =
+            controller.with_color_orders(&ColorOrders::from_iter([(1, ColorOrder::Rgb)]));
+        transport.clear_written();
+
+        let color = Color::new(0x11, 0x22, 0x33);
+        controller.set_fixed_color(color).unwrap();
+        let written = transport.written();
+        let direct = written
+            .iter()
+            .filter(|p| p[..2] == [0x22, 0x10])
+            .collect::<Vec<_>>();
+        assert_eq!(direct.len(), 2);
+        assert_eq!(direct[0][0x02..0x07], [0x01, 0x00, 0x22, 0x11, 0x33]);
+        assert_eq!(direct[1][0x02..0x07], [0x02, 0x00, 0x11, 0x22, 0x33]);
+
+        // Stored colors keep their meaning.
+        assert_eq!(controller.last_applied(1).unwrap()[0].red(), 0x11);
+    }
+
+    #[test]
+    fn transport_color_transform() {
+        let (transport, controller) = mock_controller(&[&[0x13]]);
+        let color = Color::new(0x80, 0xff, 0x00);
+        controller.set_channel_color(0, color).unwrap();
+        transport.clear_written();
+
+        // Changing the transform resends identical colors.
+        controller.set_master_brightness(0.5).unwrap();
+        controller.set_channel_color(0, color).unwrap();
+        let written = transport.written();
+        assert_eq!(written[0][0x04..0x07], [0x80, 0x40, 0x00]);
+        assert_eq!(controller.last_applied(0).unwrap()[0], color);
+
+        assert!(controller.set_gamma(-1.0).is_err());
+        controller.set_gamma(2.2).unwrap();
+        assert_eq!(controller.color_transform().brightness(), 0.5);
+    }
+
+    #[test]
+    fn transport_led_positions() {
+        let (_, controller) = mock_controller(&[&[0x17, 0x04]]);
+        let positions = controller.led_positions(0).unwrap();
+        assert_eq!(positions.len(), 8 + 10);
+        assert_eq!(positions[0].device, 0);
+        assert_eq!(positions[0].angle, Some(0.0));
+        assert_eq!(positions[8].device, 1);
+        assert_eq!(positions[8].x, 1.0);
+        assert_eq!(positions[17].x, 2.0);
+        assert!(matches!(
+            controller.led_positions(3),
+            Err(Error::InvalidChannel { .. })
+        ));
+    }
+
+    #[test]
+    fn transport_reapply() {
+        let (transport, controller) = mock_controller(&[&[0x13], &[0x13]]);
+        controller.set_reapply_interval(Some(Duration::ZERO));
+        transport.clear_written();
+
+        // Nothing was applied yet.
+        controller.ensure_applied().unwrap();
+        assert!(transport.written().is_empty());
+
+        controller.set_channel_color(1, Color::RED).unwrap();
+        transport.clear_written();
+        controller.ensure_applied().unwrap();
+        let written = transport.written();
+        assert!(!written.is_empty());
+        assert!(written.iter().all(|p| p[0x02..0x04] == [0x02, 0x00]));
+
+        transport.clear_written();
+        controller.set_reapply_interval(None);
+        controller.ensure_applied().unwrap();
+        controller.set_reapply_interval(Some(Duration::from_secs(3600)));
+        controller.ensure_applied().unwrap();
+        assert!(transport.written().is_empty());
+    }
+
+    #[test]
+    fn transport_ping() {
+        let (transport, controller) = mock_controller(&[&[0x13]]);
+        let mut firmware = [0u8; 64];
+        firmware[..2].copy_from_slice(&[0x11, 0x01]);
+        transport.push_response(firmware);
+        controller.ping().unwrap();
+        // A single firmware query instead of the accessory query.
+        let written = transport.written();
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0][..2], [0x10, 0x01]);
+
+        // No reply.
+        assert!(matches!(controller.ping(), Err(Error::Timeout)));
+        assert!(!controller.is_connected());
+    }
+
+    #[test]
+    fn concurrent_status_queries() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<NZXTHue2Controller<'static>>();
+
+        let mut page = [0u8; 64];
+        page[..2].copy_from_slice(&[0x21, 0x03]);
+        page[ACCESSORIES_OFFSET] = 0x13;
+
+        let transport = MockTransport::new();
+        transport.push_response(page);
+        let controller = NZXTHue2Controller::with_transport(
+            Box::new(transport.clone()),
+            0x2007,
+            "NZXT Kraken X3",
+            1,
+            1,
+            false,
+        )
+        .unwrap();
+
+        // Every write is followed by an unsolicited fan status report, so an
+        // unsynchronized status reader would consume (and drop) the replies.
+        transport.set_responder(|request| {
+            let mut status = vec![0u8; 64];
+            status[..2].copy_from_slice(&[0x67, 0x02]);
+            if request[..2] != [0x74, 0x01] {
+                return vec![status];
+            }
+            let mut reply = vec![0u8; 64];
+            reply[..2].copy_from_slice(&[0x75, 0x01]);
+            reply[0x0f] = 30;
+            vec![reply, status]
+        });
+
+        const ITERATIONS: usize = 50;
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut i = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let color = if i % 2 == 0 { Color::RED } else { Color::BLUE };
+                    controller.set_fixed_color(color).unwrap();
+                    i += 1;
+                }
+            });
+            let aio = scope.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    assert_eq!(controller.aio_status().unwrap().liquid_temp, 30.0);
+                }
+            });
+            let fans = scope.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    controller.fan_status().unwrap();
+                }
+            });
+            aio.join().unwrap();
+            fans.join().unwrap();
+            done.store(true, Ordering::Relaxed);
+        });
+
+        let written = transport.written();
+        let requests = written.iter().filter(|r| r[..2] == [0x74, 0x01]).count();
+        assert_eq!(requests, ITERATIONS);
+    }
+
+    #[test]
+    fn six_channel_accessories() {
+        // Fully populated 6-channel RGB & Fan Controller, all 36 slots fit in
+        // a single report: channel 5 takes 0x2d..0x33.
+        #[rustfmt::skip]
+        let page: [u8; 64] = [
+            0x21, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x13,
+            0x13, 0x13, 0x00, 0x00, 0x00, 0x14, 0x14, 0x00,
+            0x00, 0x00, 0x00, 0x0b, 0x0b, 0x0b, 0x0b, 0x0b,
+            0x0b, 0x04, 0x04, 0x00, 0x00, 0x00, 0x00, 0x09,
+            0x0a, 0x00, 0x00, 0x00, 0x00, 0x17, 0x18, 0x19,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(page[ACCESSORIES_OFFSET + 5 * HUE_2_NUM_CHANNELS], 0x17);
+
+        let channels = parse_channels_info(&page, 6);
+        let led_counts = channels
+            .iter()
+            .map(|c| c.accessory_leds())
+            .collect::<Vec<_>>();
+        assert_eq!(led_counts, [54, 36, 48, 20, 25, 24]);
+
+        // Channels are limited to 40 addressable LEDs.
+        let led_counts = channels.iter().map(|c| c.led_count).collect::<Vec<_>>();
+        assert_eq!(led_counts, [40, 36, 40, 20, 25, 24]);
+        let truncated = channels.iter().map(|c| c.truncated).collect::<Vec<_>>();
+        assert_eq!(truncated, [true, false, true, false, false, false]);
+        assert_eq!(channels[0].excess_leds(), 14);
+        assert_eq!(channels[0].device_leds(2), Some(36..40));
+        assert_eq!(channels[2].device_leds(5), None);
+        assert_eq!(channels[4].devices[1].id, 0x0a);
+        assert_eq!(
+            channels[5].devices[2].name,
+            "F120 RGB Core fan case version (120mm)"
+        );
+    }
+
+    #[test]
+    fn accessories_past_the_report() {
+        let mut page = [0u8; 64];
+        page[ACCESSORIES_OFFSET..].fill(0x0b);
+
+        // Slots of the 9th channel are cut off by the end of the report.
+        assert_eq!(REPORT_ACCESSORY_CHANNELS, 8);
+        let channels = parse_channels_info(&page, 10);
+        assert_eq!(channels.len(), 10);
+        assert!(channels[..8].iter().all(|c| c.accessory_leds() == 48));
+        assert_eq!(channels[8].accessory_leds(), 8);
+        assert_eq!(channels[9].accessory_leds(), 0);
+    }
+
+    #[test]
+    fn unknown_accessory_overrides() {
+        let mut page = [0u8; 64];
+        page[ACCESSORIES_OFFSET..ACCESSORIES_OFFSET + 3].copy_from_slice(&[0x13, 0x1f, 0x42]);
+
+        let mut channels = parse_channels_info(&page, 1);
+        assert_eq!(channels[0].led_count, 18);
+        assert_eq!(
+            channels[0].devices[1].device_type,
+            DeviceType::Unknown(0x1f)
+        );
+        assert_eq!(channels[0].device_leds(1), None);
+
+        let overrides = AccessoryOverrides::from_iter([(0x1f, 16), (0x13, 1)]);
+        apply_accessory_overrides(&mut channels, &overrides);
+
+        let channel = &channels[0];
+        assert_eq!(channel.led_count, 18 + 16);
+        assert!(!channel.devices[0].user_defined);
+        assert_eq!(channel.devices[0].led_count, 18);
+        assert!(channel.devices[1].user_defined);
+        assert_eq!(channel.devices[1].id, 0x1f);
+        assert_eq!(channel.device_leds(1), Some(18..34));
+        assert!(!channel.devices[2].user_defined);
+        assert!(!channel.devices[3].user_defined);
+    }
+
+    #[test]
+    fn channel_device_leds() {
+        let mut channel = RgbChannel::default();
+        for (slot, id) in [(0, 0x13), (1, 0x0b), (3, 0x11)] {
+            channel.devices[slot] = ChannelDeviceInfo::new(DeviceType::from(id));
+            channel.led_count += channel.devices[slot].led_count as usize;
+        }
+
+        assert_eq!(channel.device_leds(0), Some(0..18));
+        assert_eq!(channel.device_leds(1), Some(18..26));
+        assert_eq!(channel.device_leds(2), None);
+        assert_eq!(channel.device_leds(3), Some(26..27));
+        assert_eq!(channel.device_leds(HUE_2_NUM_CHANNELS), None);
+    }
+
+    #[test]
+    fn transport_frame() {
+        let (transport, controller) = mock_controller(&[&[0x13], &[0x13], &[0x13]]);
+        let controller: &dyn Controller = &controller;
+
+        let red = [Color::RED; 4];
+        let blue = [Color::BLUE; 2];
+        controller.set_frame(&[(0, &red), (2, &blue)]).unwrap();
+        let written = transport.written();
+        let applies = written.iter().filter(|p| p[..2] == [0x22, 0xa0]);
+        assert_eq!(applies.collect::<Vec<_>>(), [&apply_mask_packet(
+            0b101,
+            MAX_BRIGHTNESS
+        )]);
+
+        // Unchanged channels are skipped.
+        transport.clear_written();
+        controller.set_frame(&[(0, &red)]).unwrap();
+        assert!(transport.written().is_empty());
+        assert!(controller.set_frame(&[(5, &red)]).is_err());
+    }
+
+    #[test]
+    fn frame_single_apply() {
+        let red = [Color::RED; 24];
+        let blue = [Color::BLUE; 8];
+        let packets = frame_packets(
+            [
+                (0, &red[..], MAX_BRIGHTNESS, ColorOrder::Grb),
+                (2, &blue[..], MAX_BRIGHTNESS, ColorOrder::Grb),
+            ],
+            &ColorTransform::IDENTITY,
+        )
+        .unwrap();
+
+        // Two groups for the first channel, one for the second and one apply.
+        assert_eq!(packets.len(), 4);
+        assert!(packets[..3].iter().all(|p| p[..2] != [0x22, 0xa0]));
+        assert_eq!(packets[3], apply_mask_packet(0b101, MAX_BRIGHTNESS));
+
+        let packets = frame_packets(
+            [
+                (0, &red[..8], 50, ColorOrder::Grb),
+                (1, &blue[..], MAX_BRIGHTNESS, ColorOrder::Rgb),
+            ],
+            &ColorTransform::IDENTITY,
+        )
+        .unwrap();
+        let applies = packets
+            .iter()
+            .filter(|p| p[..2] == [0x22, 0xa0])
+            .collect::<Vec<_>>();
+        assert_eq!(applies.len(), 2);
+        assert_eq!(applies[0][0x02..0x04], [0b01, 0x00]);
+        assert_eq!(applies[1][0x02..0x04], [0b10, 0x00]);
+    }
+
+    #[test]
+    fn skip_identical_writes() {
+        let mut state = ChannelState::new(8);
+
+        let mut writes = 0;
+        for _ in 0..10 {
+            state.colors.fill(Color::RED);
+            if state.begin_write(false) {
+                writes += 1;
+                state.mark_applied();
+            }
+        }
+        assert_eq!(writes, 1);
+        assert_eq!(state.applied_colors.as_deref(), Some(&[Color::RED; 8][..]));
+
+        assert!(state.begin_write(true));
+
+        state.brightness = 50;
+        assert!(state.begin_write(false));
+        state.mark_applied();
+
+        state.invalidate();
+        assert!(state.begin_write(false));
+    }
+
+    #[test]
+    fn short_transfers() {
+        assert!(check_written(64, 64).is_ok());
+        assert!(check_written(64, 65).is_ok());
+        assert!(matches!(
+            check_written(64, 32),
+            Err(Error::ShortWrite {
+                expected: 64,
+                written: 32
+            })
+        ));
+
+        let mut report = [0u8; 64];
+        report[..2].copy_from_slice(&[0x21, 0x03]);
+        assert!(matches!(
+            is_expected_report(&report, [0x21, 0x03]),
+            Ok(true)
+        ));
+        assert!(matches!(
+            is_expected_report(&report, [0x67, 0x02]),
+            Ok(false)
+        ));
+        assert!(matches!(is_expected_report(&[], [0x21, 0x03]), Ok(false)));
+        assert!(matches!(
+            is_expected_report(&report[..20], [0x21, 0x03]),
+            Err(Error::ShortRead {
+                expected: 64,
+                read: 20
+            })
+        ));
+    }
+
+    #[test]
+    fn packet_descriptions() {
+        let transform = ColorTransform::IDENTITY;
+        let packet = direct_packet(1, 2, &[Color::RED], ColorOrder::Grb, &transform).unwrap();
+        assert_eq!(
+            describe_packet(&packet),
+            "direct group 2 for channels [1] (64 bytes)"
+        );
+        assert_eq!(
+            describe_packet(&apply_mask_packet(0b101, 50)),
+            "apply for channels [0, 2] (64 bytes)"
+        );
+        assert_eq!(describe_packet(&[0x20, 0x03]), "accessory query (2 bytes)");
+        assert_eq!(describe_packet(&[0x62]), "report (1 bytes)");
+    }
+
+    #[test]
+    fn channel_mask_bytes() {
+        for (channel, mask) in [(0, [0x01, 0x00]), (5, [0x20, 0x00]), (8, [0x00, 0x01])] {
+            assert_eq!(
+                direct_packet(channel, 0, &[], ColorOrder::Grb, &ColorTransform::IDENTITY).unwrap()
+                    [0x02..0x04],
+                mask
+            );
+            assert_eq!(apply_packet(channel, MAX_BRIGHTNESS)[0x02..0x04], mask);
+        }
+        assert_eq!(channel_mask(15), 0x8000);
+    }
+
+    #[test]
+    fn apply_brightness() {
+        assert_eq!(apply_packet(0, MAX_BRIGHTNESS)[0x0a], 0x80);
+        assert_eq!(apply_packet(0, 50)[0x0a], 0x40);
+        assert_eq!(apply_packet(0, 0)[0x0a], 0x00);
+        assert_eq!(apply_packet(0, 250)[0x0a], 0x80);
+    }
+
+    #[test]
+    fn aio_status_report() {
+        let mut report = [0u8; 64];
+        report[..2].copy_from_slice(&[0x75, 0x01]);
+        report[0x0f..0x14].copy_from_slice(&[33, 2, 0x14, 0x07, 70]);
+
+        let status = parse_aio_status(&report);
+        assert!((status.liquid_temp - 33.2).abs() < f32::EPSILON);
+        assert_eq!(status.pump_rpm, 1812);
+        assert_eq!(status.pump_duty, Some(70));
+    }
+
+    #[test]
+    fn pump_curve_packets() {
+        let packet = pump_curve_packet(60);
+        assert_eq!(packet[..4], [0x72, 0x01, 0x00, 0x00]);
+        assert!(packet[0x04..0x2c].iter().all(|&b| b == 60));
+        assert!(packet[0x2c..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn fan_duty_packets() {
+        let packet = fan_duty_packet(0, 40);
+        assert_eq!(packet[..6], [0x62, 0x01, 0x01, 40, 0x00, 0x00]);
+        assert!(packet[6..].iter().all(|&b| b == 0));
+
+        let packet = fan_duty_packet(2, 100);
+        assert_eq!(packet[..6], [0x62, 0x01, 0x04, 0x00, 0x00, 100]);
+    }
+
+    #[test]
+    fn fan_status_report() {
+        let mut report = [0u8; 64];
+        report[..2].copy_from_slice(&[0x67, 0x02]);
+        report[0x10..0x13].copy_from_slice(&[0x02, 0x01, 0x00]);
+        report[0x18..0x1e].copy_from_slice(&[0xdc, 0x05, 0x20, 0x03, 0x00, 0x00]);
+        report[0x28..0x2b].copy_from_slice(&[40, 60, 0]);
+
+        let status = parse_fan_status(&report, 3);
+        assert_eq!(status, [
+            FanStatus {
+                rpm: 1500,
+                duty: 40,
+                fan_type: FanType::Pwm
+            },
+            FanStatus {
+                rpm: 800,
+                duty: 60,
+                fan_type: FanType::Dc
+            },
+            FanStatus {
+                rpm: 0,
+                duty: 0,
+                fan_type: FanType::None
+            },
+        ]);
+    }
+
+    #[test]
+    fn effect_colors_fit_into_packet() {
+        let colors = [Color::WHITE; MAX_EFFECT_COLORS];
+        let packet = effect_packet(
+            5,
+            LedMode::Fading,
+            &colors,
+            0,
+            Direction::Forward,
+            ColorOrder::Grb,
+            &ColorTransform::IDENTITY,
+        );
+        assert_eq!(packet[0x07], MAX_EFFECT_COLORS as u8);
+        assert!(
+            packet[0x0a..0x0a + MAX_EFFECT_COLORS * 3]
+                .iter()
+                .all(|&b| b == 0xff)
+        );
+    }
+}
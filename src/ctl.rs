@@ -0,0 +1,340 @@
+//! `nzxtcli ctl`: requests to the running daemon over its control socket,
+//! see [`nzxtcli::control`] for the protocol.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use nzxtcli::Color;
+use nzxtcli::control::{Client, Command, Request, Response};
+use nzxtcli::logging::warning;
+
+use crate::{
+    AccessoryArgs, ChannelArgs, CmdSetColor, CmdStatus, ColorArg, DeviceArgs, TransformArgs,
+};
+
+/// Send requests to the running daemon.
+///
+/// Without a running daemon `status` and `set-color` fall back
+/// to accessing the controllers directly.
+#[derive(Parser)]
+pub struct CmdCtl {
+    /// Control socket of the daemon [default: `$XDG_RUNTIME_DIR/nzxtcli.sock`].
+    #[clap(long, global = true)]
+    socket: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    cmd: CtlCmd,
+}
+
+#[derive(Subcommand)]
+enum CtlCmd {
+    Status(CmdCtlStatus),
+    SetColor(CmdCtlSetColor),
+    Release(CmdCtlRelease),
+    Profile(CmdCtlProfile),
+}
+
+/// Show the profile and the colors, temperatures and fan duties
+/// of the bound channels.
+#[derive(Parser)]
+struct CmdCtlStatus {}
+
+/// Override the bindings of the channels with a color.
+#[derive(Parser)]
+struct CmdCtlSetColor {
+    #[clap(help = crate::color_help())]
+    color: Color,
+
+    #[clap(flatten)]
+    channels: ChannelArgs,
+
+    /// Return the channels to their bindings after the specified time
+    /// (e.g. `500ms`) [default: until `ctl release`].
+    #[clap(long, value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+}
+
+/// Return the overridden channels to their bindings.
+#[derive(Parser)]
+struct CmdCtlRelease {
+    #[clap(flatten)]
+    channels: ChannelArgs,
+}
+
+/// Switch the daemon to a profile of its config.
+#[derive(Parser)]
+struct CmdCtlProfile {
+    name: String,
+}
+
+impl CmdCtl {
+    pub fn run(self, device: &DeviceArgs) -> Result<()> {
+        let client = match self.socket.or_else(nzxtcli::control::default_socket_path) {
+            Some(path) => connect(&path)?,
+            None => None,
+        };
+
+        let command = match self.cmd {
+            CtlCmd::Status(_) => {
+                if client.is_none() {
+                    warning!("the daemon is not running, reading the controllers directly");
+                    return CmdStatus {}.run(device);
+                }
+                Command::Status
+            }
+            CtlCmd::SetColor(cmd) => {
+                if client.is_none() {
+                    warning!("the daemon is not running, setting the color directly");
+                    return cmd.fallback().run(device);
+                }
+                let (serial, product_id) = selection(device)?;
+                Command::SetColor {
+                    color: cmd.color,
+                    channels: cmd.channels.channels,
+                    serial,
+                    product_id,
+                    duration_ms: cmd.duration.map(|duration| duration.as_millis() as u64),
+                }
+            }
+            CtlCmd::Release(cmd) => {
+                let (serial, product_id) = selection(device)?;
+                Command::Release {
+                    channels: cmd.channels.channels,
+                    serial,
+                    product_id,
+                }
+            }
+            CtlCmd::Profile(cmd) => Command::Profile { name: cmd.name },
+        };
+
+        let mut client = client.context("the daemon is not running")?;
+        let response = client
+            .request(command)
+            .context("failed to send the request to the daemon")?;
+        let result = response.into_result().map_err(anyhow::Error::msg)?;
+        crate::print_json(result)
+    }
+}
+
+impl CmdCtlSetColor {
+    /// `set-color` with the same arguments.
+    fn fallback(self) -> CmdSetColor {
+        CmdSetColor {
+            colors: vec![ColorArg {
+                channel: None,
+                color: self.color,
+            }],
+            channels: self.channels,
+            save: false,
+            duration: self.duration,
+            accessories: AccessoryArgs::default(),
+            transform: TransformArgs {
+                gamma: 1.0,
+                brightness: 100,
+            },
+        }
+    }
+}
+
+/// Returns `None` if no daemon is listening on the socket.
+fn connect(path: &Path) -> Result<Option<Client>> {
+    match Client::connect(path) {
+        Ok(client) => Ok(Some(client)),
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e).with_context(|| format!("failed to connect to {}", path.display())),
+    }
+}
+
+/// Controllers are selected by the daemon, which only knows the serial
+/// and the product id.
+fn selection(device: &DeviceArgs) -> Result<(Option<String>, Option<u16>)> {
+    anyhow::ensure!(
+        device.device_index.is_none(),
+        "the daemon doesn't select controllers by `--device-index`"
+    );
+    Ok((device.device_serial.clone(), device.device_pid))
+}
+
+/// Control socket of the daemon.
+///
+/// Clients are served by background threads, their requests are answered
+/// by [`ControlServer::handle`] on the main thread.
+pub struct ControlServer {
+    path: PathBuf,
+    requests: mpsc::Receiver<(Command, mpsc::Sender<Response>)>,
+    /// Requests which were sent but not handled yet.
+    pending: Arc<AtomicUsize>,
+}
+
+impl ControlServer {
+    pub fn bind(path: PathBuf) -> Result<Self> {
+        // The socket of a killed daemon is left behind.
+        anyhow::ensure!(
+            UnixStream::connect(&path).is_err(),
+            "another daemon is listening on {}",
+            path.display()
+        );
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to remove {}", path.display()));
+            }
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("failed to listen on {}", path.display()))?;
+
+        let (sender, requests) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let counter = pending.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                let counter = counter.clone();
+                std::thread::spawn(move || serve(stream, &sender, &counter));
+            }
+        });
+        Ok(Self {
+            path,
+            requests,
+            pending,
+        })
+    }
+
+    /// Whether there are requests for [`ControlServer::handle`].
+    pub fn pending(&self) -> bool {
+        self.pending.load(Ordering::Relaxed) > 0
+    }
+
+    /// Answers all received requests.
+    pub fn handle(&self, mut f: impl FnMut(Command) -> Result<serde_json::Value>) {
+        while let Ok((command, reply)) = self.requests.try_recv() {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            let response = match f(command) {
+                Ok(result) => Response::ok(result),
+                Err(e) => Response::error(format!("{e:#}")),
+            };
+            _ = reply.send(response);
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Answers the requests of a client until it disconnects or the server is dropped.
+fn serve(
+    stream: UnixStream,
+    requests: &mpsc::Sender<(Command, mpsc::Sender<Response>)>,
+    pending: &AtomicUsize,
+) {
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut writer = stream;
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match Request::parse(&line) {
+            Ok(request) => {
+                let (reply, response) = mpsc::channel();
+                pending.fetch_add(1, Ordering::Relaxed);
+                if requests.send((request.command, reply)).is_err() {
+                    break;
+                }
+                let Ok(response) = response.recv() else {
+                    break;
+                };
+                response
+            }
+            Err(message) => Response::error(message),
+        };
+        let Ok(mut line) = serde_json::to_string(&response) else {
+            break;
+        };
+        line.push('\n');
+        if writer.write_all(line.as_bytes()).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_socket() {
+        let path = std::env::temp_dir().join(format!("nzxtcli-{}.sock", std::process::id()));
+        let server = ControlServer::bind(path.clone()).unwrap();
+        let Err(e) = ControlServer::bind(path.clone()) else {
+            panic!("a second server is bound to the same socket");
+        };
+        assert!(e.to_string().starts_with("another daemon is listening on"));
+
+        // The main thread of the daemon.
+        let handler = std::thread::spawn(move || {
+            let mut handled = 0;
+            while handled < 2 {
+                if !server.pending() {
+                    std::thread::sleep(Duration::from_millis(10));
+                    continue;
+                }
+                server.handle(|command| {
+                    handled += 1;
+                    match command {
+                        Command::Status => Ok(serde_json::json!({"profile": "work"})),
+                        _ => anyhow::bail!("unknown profile"),
+                    }
+                });
+            }
+        });
+
+        let mut client = connect(&path).unwrap().unwrap();
+        let response = client.request(Command::Status).unwrap();
+        assert_eq!(
+            response.into_result(),
+            Ok(serde_json::json!({"profile": "work"}))
+        );
+        // Invalid requests are answered without the main thread.
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"{\"version\":7}\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        let response = serde_json::from_str::<Response>(&line).unwrap();
+        assert_eq!(
+            response.into_result(),
+            Err("unsupported protocol version 7, expected 1".to_owned())
+        );
+
+        let command = Command::Profile {
+            name: "x".to_owned(),
+        };
+        let response = client.request(command).unwrap();
+        assert_eq!(response.into_result(), Err("unknown profile".to_owned()));
+
+        handler.join().unwrap();
+        assert!(!path.exists());
+        assert!(connect(&path).unwrap().is_none());
+    }
+}
@@ -0,0 +1,2343 @@
+//! `nzxtcli daemon`: a single process driving all controllers by the bindings
+//! from the config file.
+//!
+//! ```toml
+//! [daemon]
+//! interval = "2s"
+//!
+//! # Temperature colors of the front fans.
+//! [[daemon.led]]
+//! serial = "MA0123456789"
+//! channels = "front-fans"
+//! sensor = "k10temp:Tctl"
+//! ramp = "30:blue,60:green,85:red"
+//! smooth = "10s"
+//!
+//! # A hardware effect on the strip, only in the `gaming` profile.
+//! [[daemon.led]]
+//! profile = "gaming"
+//! channels = "2"
+//! mode = "fading"
+//! colors = ["red", "blue"]
+//!
+//! [[daemon.fan]]
+//! exec = "nvidia-smi --query-gpu=temperature.gpu --format=csv,noheader"
+//! curve = "30:20,60:50,80:100"
+//! ```
+//!
+//! The bindings are checked along with the rest of the config on startup,
+//! so an invalid one is reported before any device is opened.
+//!
+//! `SIGHUP` reloads the bindings and `SIGUSR1` (`SIGUSR2`) switches to the next
+//! (previous) profile. Only the channels whose binding has changed are set
+//! again, channels which are no longer bound keep their colors.
+//!
+//! Requests from `nzxtcli ctl` are answered over the control socket between
+//! the updates (see [`nzxtcli::control`]). Colors set by them override the
+//! bindings of the channels until they expire or are released.
+//!
+//! With the `dbus` feature `--dbus session` (`system`) also serves the same
+//! requests on the bus, see the `dbus` module.
+//!
+//! The `[mqtt]` table connects the daemon to an MQTT broker for Home
+//! Assistant, see the `mqtt` module. Changes to it require a restart.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use hidapi::HidApi;
+use nzxtcli::control::Command;
+use nzxtcli::logging::{self, error, info, warning};
+use nzxtcli::{
+    ChannelSelector, Color, ColorRamp, Controller, Direction, FanCurve, Interpolation, LedMode,
+};
+
+use crate::config::{Config, ConfigError, Fields};
+use crate::ctl::ControlServer;
+use crate::exec::ExecSource;
+use crate::hwmon::{
+    Aggregate, HWMON_ROOT, HwmonInput, Hysteresis, SensorSpec, Smoother, TempReader,
+};
+use crate::mqtt::{ControllerState, MqttBridge, MqttConfig};
+use crate::notify::Notifier;
+use crate::retry::{Backoff, RetryingSource};
+use crate::{
+    AccessoryArgs, ChannelArgs, ChannelState, DeviceArgs, DutyLimiter, LedState, Lighting,
+    Schedule, TransformArgs, Wake,
+};
+
+/// Arrays of tables with the daemon bindings.
+pub const TABLES: [&str; 2] = ["daemon.led", "daemon.fan"];
+
+/// Drive all controllers by the `[[daemon.led]]` and `[[daemon.fan]]` tables
+/// of the config file.
+///
+/// Each binding selects channels of the controllers by the serial or product id
+/// and sets a static color, a hardware effect or colors (fan duties) from
+/// a temperature. All temperature bindings are updated on a single schedule,
+/// sources shared by several bindings are read once per update.
+///
+/// `SIGHUP` reloads the bindings, `SIGUSR1` and `SIGUSR2` switch to the next
+/// and the previous profile. `nzxtcli ctl` sends requests over the control
+/// socket.
+#[derive(Parser)]
+pub struct CmdDaemon {
+    /// How often the temperatures are read.
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    interval: Duration,
+
+    /// Profile to start with [default: the first one in the config].
+    #[clap(long)]
+    profile: Option<String>,
+
+    /// Print the temperature and the resulting color (fan duty) of each binding.
+    #[clap(long)]
+    verbose: bool,
+
+    /// Print the status of the running daemon as JSON and exit.
+    #[clap(long, conflicts_with_all = ["profile", "verbose", "socket"])]
+    status: bool,
+
+    /// Control socket for `nzxtcli ctl` [default: `$XDG_RUNTIME_DIR/nzxtcli.sock`].
+    #[clap(long)]
+    socket: Option<PathBuf>,
+
+    /// Serve the `io.github.nzxtcli` interface on the D-Bus session or system bus.
+    #[cfg(feature = "dbus")]
+    #[clap(long, value_enum, conflicts_with = "status")]
+    dbus: Option<crate::dbus::Bus>,
+
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+
+    #[clap(flatten)]
+    transform: TransformArgs,
+}
+
+impl CmdDaemon {
+    pub fn run(self, device: &DeviceArgs, config: Option<&Config>) -> Result<()> {
+        const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+        if self.status {
+            return print_status();
+        }
+
+        let Some(config) = config else {
+            anyhow::bail!(
+                "the daemon requires a config file with `[[daemon.led]]` or `[[daemon.fan]]` tables"
+            );
+        };
+        let bindings = DaemonConfig::from_config(config)?;
+        anyhow::ensure!(
+            !bindings.leds.is_empty() || !bindings.fans.is_empty(),
+            "{}: no `[[daemon.led]]` or `[[daemon.fan]]` tables",
+            config.path().display()
+        );
+        let profile = match &self.profile {
+            Some(profile) => Some(bindings.check_profile(profile)?.to_owned()),
+            None => bindings.profiles.first().cloned(),
+        };
+
+        let mut interval = self.interval;
+        if interval < MIN_INTERVAL {
+            warning!(
+                "interval {} is too short, using {}",
+                humantime::format_duration(interval),
+                humantime::format_duration(MIN_INTERVAL)
+            );
+            interval = MIN_INTERVAL;
+        }
+
+        // Bound before opening the devices, so that a second daemon fails early.
+        let server = match self.socket.or_else(nzxtcli::control::default_socket_path) {
+            _ if device.dry_run => None,
+            Some(path) => Some(ControlServer::bind(path)?),
+            None => {
+                warning!("`XDG_RUNTIME_DIR` is not set, the control socket is disabled");
+                None
+            }
+        };
+        #[cfg(feature = "dbus")]
+        let mut bus = match self.dbus {
+            _ if device.dry_run => None,
+            Some(bus) => Some(crate::dbus::DbusServer::connect(bus)?),
+            None => None,
+        };
+        let mut mqtt = match MqttConfig::from_config(config)? {
+            _ if device.dry_run => None,
+            config => config.map(MqttBridge::start),
+        };
+
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let options = device
+            .find_options(&self.accessories)?
+            .color_transform(self.transform.transform()?);
+        let controllers = crate::find_controllers(&api, &options)?;
+
+        let mut daemon = Daemon {
+            path: config.path().to_owned(),
+            bindings: DaemonConfig::default(),
+            profile: None,
+            sources: Sources::default(),
+            leds: Vec::new(),
+            fans: Vec::new(),
+            devices: controllers.into_iter().map(Device::new).collect(),
+            colors: Vec::new(),
+            duties: Vec::new(),
+            led_temps: Vec::new(),
+            fan_temps: Vec::new(),
+            error: None,
+            verbose: self.verbose,
+        };
+        let changes = daemon.activate(bindings, profile)?;
+        for (table, binding) in daemon.bindings.tables() {
+            if !daemon.devices.iter().any(|device| device.uses(binding)) {
+                warning!("`{table}` doesn't match any controller");
+            }
+        }
+        anyhow::ensure!(
+            daemon.devices.iter().any(Device::is_used),
+            nzxtcli::Error::NoMatchingDevice
+        );
+        daemon.apply_lighting(&changes)?;
+
+        let mut notifier = Notifier::from_env();
+        let mut ready = false;
+        catch_signals();
+        crate::catch_interrupts();
+        let mut schedule = Schedule::new(interval, Instant::now());
+        let mut wake = Wake::Tick;
+        let result = loop {
+            if wake == Wake::Tick {
+                daemon.update(&api, Instant::now());
+            }
+            // A single update during a dry run, the colors are kept on exit.
+            if device.dry_run {
+                return Ok(());
+            }
+
+            if RELOAD.swap(false, Ordering::Relaxed) {
+                notifier.reloading();
+                daemon.reload();
+                // Ready again below.
+                ready = false;
+            }
+            let steps = PROFILE_STEPS.swap(0, Ordering::Relaxed);
+            if steps != 0 {
+                daemon.switch_profile(steps);
+            }
+            if let Some(server) = &server {
+                server.handle(|command| daemon.control(command, Instant::now()));
+            }
+            #[cfg(feature = "dbus")]
+            if let Some(bus) = &mut bus {
+                bus.handle(|command| daemon.control(command, Instant::now()));
+                bus.publish(&daemon.bus_properties());
+            }
+            if let Some(mqtt) = &mut mqtt {
+                mqtt.handle(|command| daemon.control(command, Instant::now()));
+                mqtt.publish(&daemon.mqtt_state());
+            }
+            daemon.expire_overrides(Instant::now());
+            daemon.write_status();
+            let summary = daemon.summary();
+            if ready {
+                notifier.status(&summary);
+            } else {
+                notifier.ready(&summary);
+                ready = true;
+            }
+            notifier.watchdog();
+
+            wake = schedule.wait_or(|| {
+                #[cfg(feature = "dbus")]
+                if bus.as_ref().is_some_and(crate::dbus::DbusServer::pending) {
+                    return true;
+                }
+                signals_pending()
+                    || server.as_ref().is_some_and(ControlServer::pending)
+                    || mqtt.as_ref().is_some_and(MqttBridge::pending)
+                    || daemon.overrides_expired(Instant::now())
+                    || notifier.watchdog_due()
+            });
+            if wake == Wake::Interrupted {
+                break Ok(());
+            }
+        };
+
+        notifier.stopping();
+        if let Some(path) = status_path() {
+            _ = std::fs::remove_file(path);
+        }
+        result
+    }
+}
+
+/// Set by `SIGHUP`.
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/// Profiles to move by, `SIGUSR1` adds one and `SIGUSR2` subtracts one.
+static PROFILE_STEPS: AtomicIsize = AtomicIsize::new(0);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    match signal {
+        libc::SIGHUP => RELOAD.store(true, Ordering::Relaxed),
+        libc::SIGUSR1 => _ = PROFILE_STEPS.fetch_add(1, Ordering::Relaxed),
+        libc::SIGUSR2 => _ = PROFILE_STEPS.fetch_sub(1, Ordering::Relaxed),
+        _ => {}
+    }
+}
+
+fn catch_signals() {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: The handler only updates atomics.
+    unsafe {
+        libc::signal(libc::SIGHUP, handler);
+        libc::signal(libc::SIGUSR1, handler);
+        libc::signal(libc::SIGUSR2, handler);
+    }
+}
+
+fn signals_pending() -> bool {
+    RELOAD.load(Ordering::Relaxed) || PROFILE_STEPS.load(Ordering::Relaxed) != 0
+}
+
+/// Validated bindings of the daemon.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DaemonConfig {
+    pub leds: Vec<LedBinding>,
+    pub fans: Vec<FanBinding>,
+    /// Names of the profiles in the config order.
+    pub profiles: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedBinding {
+    pub target: Target,
+    pub action: LedAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LedAction {
+    /// Static color or a hardware effect, set on startup and after reconnecting.
+    Lighting(Lighting),
+    /// Color of the temperature on the ramp over `0..=1` from `base` to `warn`.
+    Temp {
+        input: TempInput,
+        ramp: ColorRamp,
+        base: f32,
+        warn: f32,
+        /// In millidegrees.
+        hysteresis: Option<i64>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FanBinding {
+    /// The channels are fan channels.
+    pub target: Target,
+    pub input: TempInput,
+    pub curve: FanCurve,
+}
+
+/// Channels of the controllers which are selected by a binding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    /// Path of the config table (e.g. `daemon.led[1]`).
+    pub table: String,
+    /// Only active in this profile, bindings without a profile are always active.
+    pub profile: Option<String>,
+    pub serial: Option<String>,
+    pub product_id: Option<u16>,
+    pub channels: ChannelSelector,
+}
+
+impl LedBinding {
+    /// Whether the bindings set the same channels in the same way,
+    /// regardless of their tables and profiles.
+    fn same(&self, other: &Self) -> bool {
+        self.target.same(&other.target) && self.action == other.action
+    }
+}
+
+impl FanBinding {
+    fn same(&self, other: &Self) -> bool {
+        self.target.same(&other.target) && self.input == other.input && self.curve == other.curve
+    }
+}
+
+impl Target {
+    fn same(&self, other: &Self) -> bool {
+        self.serial == other.serial
+            && self.product_id == other.product_id
+            && self.channels == other.channels
+    }
+
+    pub fn matches(&self, controller: &dyn Controller) -> bool {
+        selects(controller, self.serial.as_deref(), self.product_id)
+    }
+}
+
+fn selects(controller: &dyn Controller, serial: Option<&str>, product_id: Option<u16>) -> bool {
+    serial.is_none_or(|serial| controller.serial() == Some(serial))
+        && product_id.is_none_or(|pid| controller.product_id() == pid)
+}
+
+/// Temperature sources of a binding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempInput {
+    pub sources: Vec<SourceSpec>,
+    pub aggregate: Aggregate,
+    pub smooth: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceSpec {
+    Path(PathBuf),
+    Sensor(SensorSpec),
+    Exec(String),
+}
+
+impl DaemonConfig {
+    /// Errors point at the offending table (e.g. `daemon.led[1].ramp`).
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let groups = config.channel_groups()?;
+        let channels = |fields: &Fields<'_>| {
+            let selector = fields.parse_with("channels", |s| match groups.get(s.trim()) {
+                Some(selector) => Ok(selector.clone()),
+                None => s.parse::<ChannelSelector>(),
+            })?;
+            Ok::<_, ConfigError>(selector.unwrap_or_default())
+        };
+
+        let mut leds = Vec::new();
+        for fields in config.array_tables("daemon.led") {
+            let target = Target::parse(&fields, channels(&fields)?)?;
+            leds.push(LedBinding {
+                target,
+                action: LedAction::parse(&fields)?,
+            });
+        }
+
+        let mut fans = Vec::new();
+        for fields in config.array_tables("daemon.fan") {
+            fields.check_keys(
+                &[FAN_KEYS, TARGET_KEYS, INPUT_KEYS].concat(),
+                "a fan binding",
+            )?;
+            let target = Target::parse(&fields, channels(&fields)?)?;
+            let input = TempInput::parse(&fields)?
+                .ok_or_else(|| fields.error(format!("expected a temperature source, {SOURCES}")))?;
+            let curve = fields
+                .parse::<FanCurve>("curve")?
+                .ok_or_else(|| fields.error("missing the `curve` key"))?;
+            fans.push(FanBinding {
+                target,
+                input,
+                curve,
+            });
+        }
+
+        let mut profiles = Vec::<String>::new();
+        let targets = leds
+            .iter()
+            .map(|binding| &binding.target)
+            .chain(fans.iter().map(|binding| &binding.target));
+        for profile in targets.filter_map(|target| target.profile.as_ref()) {
+            if !profiles.contains(profile) {
+                profiles.push(profile.clone());
+            }
+        }
+
+        Ok(Self {
+            leds,
+            fans,
+            profiles,
+        })
+    }
+
+    pub fn check_profile<'a>(&self, profile: &'a str) -> Result<&'a str> {
+        anyhow::ensure!(
+            self.profiles.iter().any(|other| other == profile),
+            "unknown profile `{profile}`, expected one of: {}",
+            match self.profiles.is_empty() {
+                true => "none (set the `profile` key in the bindings)".to_owned(),
+                false => self.profiles.join(", "),
+            }
+        );
+        Ok(profile)
+    }
+
+    /// Temperature inputs of all bindings along with their targets.
+    fn inputs(&self) -> impl Iterator<Item = (&Target, &TempInput)> {
+        let leds = self
+            .leds
+            .iter()
+            .filter_map(|binding| match &binding.action {
+                LedAction::Temp { input, .. } => Some((&binding.target, input)),
+                LedAction::Lighting(_) => None,
+            });
+        let fans = self
+            .fans
+            .iter()
+            .map(|binding| (&binding.target, &binding.input));
+        leds.chain(fans)
+    }
+
+    /// All bindings along with the table paths.
+    fn tables(&self) -> impl Iterator<Item = (&str, Binding)> {
+        let leds = (0..self.leds.len()).map(Binding::Led);
+        let fans = (0..self.fans.len()).map(Binding::Fan);
+        leds.chain(fans).map(|binding| {
+            let target = match binding {
+                Binding::Led(i) => &self.leds[i].target,
+                Binding::Fan(i) => &self.fans[i].target,
+            };
+            (target.table.as_str(), binding)
+        })
+    }
+}
+
+const TARGET_KEYS: &[&str] = &["profile", "serial", "pid", "channels"];
+const INPUT_KEYS: &[&str] = &["sensor", "path", "exec", "aggregate", "smooth"];
+const COLOR_KEYS: &[&str] = &["color"];
+const MODE_KEYS: &[&str] = &["mode", "colors", "speed", "direction"];
+const TEMP_KEYS: &[&str] = &["hysteresis", "ramp", "base", "warn", "interpolation"];
+const FAN_KEYS: &[&str] = &["curve"];
+
+const SOURCES: &str = "`sensor`, `path` or `exec`";
+
+impl Target {
+    /// Reads the `serial`, `pid` and `profile` keys.
+    pub fn parse(fields: &Fields<'_>, channels: ChannelSelector) -> Result<Self, ConfigError> {
+        let product_id = match fields.integer("pid") {
+            Some(pid) => Some(
+                u16::try_from(pid).map_err(|_| fields.value_error("pid", "invalid product id"))?,
+            ),
+            None => fields.parse_with("pid", crate::parse_hex_u16)?,
+        };
+        let profile = fields.parse::<String>("profile")?;
+        if profile
+            .as_ref()
+            .is_some_and(|profile| profile.trim().is_empty())
+        {
+            return Err(fields.value_error("profile", "expected a profile name"));
+        }
+        Ok(Self {
+            table: fields.path().to_owned(),
+            profile,
+            serial: fields.parse("serial")?,
+            product_id,
+            channels,
+        })
+    }
+}
+
+impl LedAction {
+    fn parse(fields: &Fields<'_>) -> Result<Self, ConfigError> {
+        let is_temp = ["sensor", "path", "exec"]
+            .into_iter()
+            .any(|key| fields.contains(key));
+        let kinds = [fields.contains("color"), fields.contains("mode"), is_temp];
+        if kinds.into_iter().filter(|&kind| kind).count() != 1 {
+            return Err(fields.error(format!(
+                "expected exactly one of `color`, `mode` or a temperature source ({SOURCES})"
+            )));
+        }
+
+        if let Some(color) = fields.parse::<Color>("color")? {
+            fields.check_keys(&[TARGET_KEYS, COLOR_KEYS].concat(), "a color binding")?;
+            return Ok(Self::Lighting(Lighting::Fixed { color }));
+        }
+
+        if let Some(mode) = fields.parse::<LedMode>("mode")? {
+            fields.check_keys(&[TARGET_KEYS, MODE_KEYS].concat(), "a mode binding")?;
+            let colors = fields.parse_list::<Color>("colors")?;
+            crate::check_mode_colors(mode, &colors).map_err(|e| {
+                let key = if fields.contains("colors") {
+                    "colors"
+                } else {
+                    "mode"
+                };
+                fields.value_error(key, e.to_string())
+            })?;
+            return Ok(Self::Lighting(Lighting::Mode {
+                mode,
+                colors,
+                speed: fields
+                    .parse_with("speed", crate::parse_effect_speed)?
+                    .unwrap_or_else(crate::default_effect_speed),
+                direction: fields.parse::<Direction>("direction")?.unwrap_or_default(),
+            }));
+        }
+
+        fields.check_keys(
+            &[TARGET_KEYS, INPUT_KEYS, TEMP_KEYS].concat(),
+            "a temperature binding",
+        )?;
+        let input = TempInput::parse(fields)?.expect("sources are checked above");
+        let hysteresis = match fields.parse::<f64>("hysteresis")? {
+            Some(degrees) if !degrees.is_finite() || degrees < 0.0 => {
+                let message = "expected a non-negative number of degrees";
+                return Err(fields.value_error("hysteresis", message));
+            }
+            degrees => degrees.map(|degrees| (degrees * 1000.0).round() as i64),
+        };
+
+        let ramp = fields.parse::<ColorRamp>("ramp")?;
+        let conflict = ["base", "warn"]
+            .into_iter()
+            .find(|&key| fields.contains(key));
+        if let Some(key) = conflict.filter(|_| ramp.is_some()) {
+            return Err(fields.value_error(key, "can't be combined with `ramp`"));
+        }
+        let base = fields.parse::<u64>("base")?.unwrap_or(0);
+        let warn = fields.parse::<u64>("warn")?.unwrap_or(80);
+        let interpolation = fields.parse::<Interpolation>("interpolation")?;
+        let (ramp, base, warn) =
+            crate::scale_ramp(ramp.as_ref(), base, warn, interpolation.unwrap_or_default())
+                .map_err(|e| {
+                    let key = if fields.contains("ramp") {
+                        "ramp"
+                    } else {
+                        "warn"
+                    };
+                    fields.value_error(key, e.to_string())
+                })?;
+
+        Ok(Self::Temp {
+            input,
+            ramp,
+            base,
+            warn,
+            hysteresis,
+        })
+    }
+}
+
+impl TempInput {
+    /// Returns `None` if there are no sources.
+    fn parse(fields: &Fields<'_>) -> Result<Option<Self>, ConfigError> {
+        let mut sources = Vec::new();
+        sources.extend(
+            fields
+                .parse_list::<PathBuf>("path")?
+                .into_iter()
+                .map(SourceSpec::Path),
+        );
+        sources.extend(
+            fields
+                .parse_list::<SensorSpec>("sensor")?
+                .into_iter()
+                .map(SourceSpec::Sensor),
+        );
+        sources.extend(
+            fields
+                .parse_list::<String>("exec")?
+                .into_iter()
+                .map(SourceSpec::Exec),
+        );
+        if sources.is_empty() {
+            return Ok(None);
+        }
+
+        let aggregate = fields.parse_with("aggregate", |s| Aggregate::from_str(s, true))?;
+        Ok(Some(Self {
+            sources,
+            aggregate: aggregate.unwrap_or_default(),
+            smooth: fields.parse_with("smooth", humantime::parse_duration)?,
+        }))
+    }
+}
+
+/// Binding by the index in its table array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Binding {
+    Led(usize),
+    Fan(usize),
+}
+
+/// Running state of the daemon.
+struct Daemon {
+    /// Config file, read again on reload.
+    path: PathBuf,
+    bindings: DaemonConfig,
+    /// `None` if the config has no profiles.
+    profile: Option<String>,
+    sources: Sources,
+    /// State of the temperature bindings by the index of `bindings.leds`.
+    leds: Vec<Option<TempColor>>,
+    /// State of `bindings.fans`.
+    fans: Vec<TempState>,
+    devices: Vec<Device>,
+    /// Results of the last update by the binding index.
+    colors: Vec<Option<Color>>,
+    duties: Vec<Option<u8>>,
+    /// Temperatures of the last update in millidegrees.
+    led_temps: Vec<Option<i64>>,
+    fan_temps: Vec<Option<i64>>,
+    /// The last reload error, cleared by a successful reload.
+    error: Option<String>,
+    verbose: bool,
+}
+
+/// Channels whose binding has changed by activating a config or a profile.
+#[derive(Debug, Default)]
+struct Changes {
+    /// Device index, LED binding index and the channel.
+    channels: Vec<(usize, usize, usize)>,
+    unchanged: usize,
+    /// Channels which are no longer bound, they keep their colors.
+    released: usize,
+    /// Fan channels whose binding has changed.
+    fans: usize,
+}
+
+impl std::fmt::Display for Changes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} channels changed, {} unchanged, {} released, {} fan channels changed",
+            self.channels.len(),
+            self.unchanged,
+            self.released,
+            self.fans
+        )
+    }
+}
+
+impl Daemon {
+    /// Switches to the bindings of the profile.
+    ///
+    /// Nothing is changed on errors (e.g. if a source can't be opened
+    /// or several bindings set the same channel).
+    fn activate(&mut self, bindings: DaemonConfig, profile: Option<String>) -> Result<Changes> {
+        let profile = profile.as_deref();
+        let opened = self.sources.open_missing(&bindings)?;
+        let assignments = self
+            .devices
+            .iter()
+            .map(|device| device.assign(&bindings, profile))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Nothing can fail from here on.
+        let sources = std::mem::take(&mut self.sources).rebuild(opened, &bindings);
+        let mut leds = std::mem::take(&mut self.leds);
+        let leds = bindings
+            .leds
+            .iter()
+            .map(|binding| {
+                let LedAction::Temp {
+                    input, hysteresis, ..
+                } = &binding.action
+                else {
+                    return None;
+                };
+                // The smoothed temperature is kept for unchanged bindings.
+                let old = self
+                    .bindings
+                    .leds
+                    .iter()
+                    .position(|old| old.same(binding))
+                    .and_then(|index| leds[index].take());
+                Some(match old {
+                    Some(old) => old.reuse(sources.state(input)),
+                    None => TempColor {
+                        temp: sources.state(input),
+                        hysteresis: hysteresis.map(Hysteresis::new),
+                    },
+                })
+            })
+            .collect();
+        let mut fans = std::mem::take(&mut self.fans)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        let fans = bindings
+            .fans
+            .iter()
+            .map(|binding| {
+                let state = sources.state(&binding.input);
+                let old = self
+                    .bindings
+                    .fans
+                    .iter()
+                    .position(|old| old.same(binding))
+                    .and_then(|index| fans[index].take());
+                match old {
+                    Some(old) => state.reuse(old),
+                    None => state,
+                }
+            })
+            .collect();
+
+        let mut changes = Changes::default();
+        for (index, (device, (leds, fans))) in self.devices.iter_mut().zip(assignments).enumerate()
+        {
+            device.replace(index, &self.bindings, &bindings, leds, fans, &mut changes);
+        }
+
+        self.colors = vec![None; bindings.leds.len()];
+        self.duties = vec![None; bindings.fans.len()];
+        self.led_temps = vec![None; bindings.leds.len()];
+        self.fan_temps = vec![None; bindings.fans.len()];
+        self.bindings = bindings;
+        self.profile = profile.map(str::to_owned);
+        self.sources = sources;
+        self.leds = leds;
+        self.fans = fans;
+        Ok(changes)
+    }
+
+    /// Reads the config again, an invalid one keeps the current bindings.
+    fn reload(&mut self) {
+        let result = (|| {
+            let config = Config::load(Some(self.path.clone()))?.expect("explicit config is loaded");
+            let bindings = DaemonConfig::from_config(&config)?;
+            // The profile is kept if it still exists.
+            let profile = match &self.profile {
+                Some(profile) if bindings.profiles.contains(profile) => Some(profile.clone()),
+                _ => bindings.profiles.first().cloned(),
+            };
+            self.activate(bindings, profile)
+        })();
+
+        match result {
+            Ok(changes) => {
+                self.error = None;
+                let profile = match &self.profile {
+                    Some(profile) => format!(" (profile `{profile}`)"),
+                    None => String::new(),
+                };
+                info!("reloaded {}{profile}: {changes}", self.path.display());
+                if let Err(e) = self.apply_lighting(&changes) {
+                    error!("{e:?}");
+                }
+            }
+            Err(e) => {
+                warning!(
+                    "failed to reload {}: {e:#}, keeping the previous config",
+                    self.path.display()
+                );
+                self.error = Some(format!("{e:#}"));
+            }
+        }
+    }
+
+    /// Moves by the number of profiles in the config order, wrapping around.
+    fn switch_profile(&mut self, steps: isize) {
+        let profiles = &self.bindings.profiles;
+        if profiles.is_empty() {
+            warning!("the config has no profiles to switch between");
+            return;
+        }
+        let current = self
+            .profile
+            .as_ref()
+            .and_then(|profile| profiles.iter().position(|other| other == profile))
+            .unwrap_or(0);
+        let next = (current as isize + steps).rem_euclid(profiles.len() as isize) as usize;
+        let profile = profiles[next].clone();
+
+        if let Err(e) = self.activate_profile(profile.clone()) {
+            warning!("failed to switch to profile `{profile}`: {e:#}");
+        }
+    }
+
+    fn activate_profile(&mut self, profile: String) -> Result<()> {
+        let changes = self.activate(self.bindings.clone(), Some(profile.clone()))?;
+        info!("switched to profile `{profile}`: {changes}");
+        if let Err(e) = self.apply_lighting(&changes) {
+            error!("{e:?}");
+        }
+        Ok(())
+    }
+
+    /// Sets the static colors and hardware effects of the changed channels,
+    /// overridden channels are set when the override ends.
+    fn apply_lighting(&self, changes: &Changes) -> Result<()> {
+        for &(device, binding, channel) in &changes.channels {
+            let binding = &self.bindings.leds[binding];
+            let LedAction::Lighting(lighting) = &binding.action else {
+                continue;
+            };
+            if self.devices[device].is_overridden(channel) {
+                continue;
+            }
+            let controller = self.devices[device].controller.as_ref();
+            apply_lighting(controller, lighting, channel).with_context(|| {
+                format!(
+                    "failed to apply `{}` to {}",
+                    binding.target.table,
+                    controller.name()
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Reads the temperatures and updates the devices.
+    fn update(&mut self, api: &HidApi, now: Instant) {
+        let readings = self.sources.poll(now);
+
+        for (((binding, state), color), temp) in self
+            .bindings
+            .leds
+            .iter()
+            .zip(&mut self.leds)
+            .zip(&mut self.colors)
+            .zip(&mut self.led_temps)
+        {
+            let (
+                Some(state),
+                LedAction::Temp {
+                    ramp, base, warn, ..
+                },
+            ) = (state, &binding.action)
+            else {
+                continue;
+            };
+            let Some(millidegrees) = state.update(&readings, now) else {
+                continue;
+            };
+            *temp = Some(millidegrees);
+            let shown = *color.insert(crate::temp_color(ramp, millidegrees, *base, *warn));
+            if self.verbose {
+                let celsius = millidegrees as f64 / 1000.0;
+                eprintln!("{}: {celsius:.1} -> {shown}", binding.target.table);
+            }
+        }
+        for (((binding, state), duty), temp) in self
+            .bindings
+            .fans
+            .iter()
+            .zip(&mut self.fans)
+            .zip(&mut self.duties)
+            .zip(&mut self.fan_temps)
+        {
+            let Some(millidegrees) = state.update(&readings, now) else {
+                continue;
+            };
+            *temp = Some(millidegrees);
+            let shown = *duty.insert(binding.curve.duty(millidegrees as f32 / 1000.0));
+            if self.verbose {
+                let celsius = millidegrees as f64 / 1000.0;
+                eprintln!("{}: {celsius:.1} -> {shown}%", binding.target.table);
+            }
+        }
+
+        for device in &mut self.devices {
+            device.update(api, &self.bindings, &self.colors, &self.duties, now);
+        }
+    }
+
+    /// Answers a request from the control socket.
+    fn control(&mut self, command: Command, now: Instant) -> Result<serde_json::Value> {
+        match command {
+            Command::Status => Ok(self.status()),
+            Command::SetColor {
+                color,
+                channels,
+                serial,
+                product_id,
+                duration_ms,
+            } => {
+                let selection = (channels, serial, product_id);
+                let until = duration_ms.map(|ms| now + Duration::from_millis(ms));
+                self.set_override(Lighting::Fixed { color }, selection, until)
+            }
+            Command::SetMode {
+                mode,
+                colors,
+                speed,
+                direction,
+                channels,
+                serial,
+                product_id,
+                duration_ms,
+            } => {
+                crate::check_mode_colors(mode, &colors)?;
+                let lighting = Lighting::Mode {
+                    mode,
+                    colors,
+                    speed: speed.unwrap_or_else(crate::default_effect_speed),
+                    direction,
+                };
+                let selection = (channels, serial, product_id);
+                let until = duration_ms.map(|ms| now + Duration::from_millis(ms));
+                self.set_override(lighting, selection, until)
+            }
+            Command::Release {
+                channels,
+                serial,
+                product_id,
+            } => {
+                let mut released = Vec::new();
+                for index in 0..self.devices.len() {
+                    let device = &mut self.devices[index];
+                    if !selects(device.controller.as_ref(), serial.as_deref(), product_id) {
+                        continue;
+                    }
+                    let ended = device.end_overrides(|other| match &channels {
+                        ChannelSelector::All => true,
+                        ChannelSelector::Channels(channels) => channels.contains(&other.channel),
+                    });
+                    self.restore(index, &ended);
+                    released.push(controller_json(
+                        self.devices[index].controller.as_ref(),
+                        &ended,
+                    ));
+                }
+                anyhow::ensure!(!released.is_empty(), nzxtcli::Error::NoMatchingDevice);
+                Ok(serde_json::json!({ "controllers": released }))
+            }
+            Command::SetFan {
+                duty,
+                fans,
+                serial,
+                product_id,
+            } => {
+                anyhow::ensure!(
+                    duty <= 100,
+                    nzxtcli::Error::InvalidArgument(format!(
+                        "fan duty must be a percentage, got {duty}"
+                    ))
+                );
+                let mut updated = Vec::new();
+                for device in &mut self.devices {
+                    let controller = device.controller.as_ref();
+                    if !selects(controller, serial.as_deref(), product_id) {
+                        continue;
+                    }
+                    // Controllers without fan channels are skipped.
+                    let Some(channels) = fan_channels(controller, &fans)? else {
+                        continue;
+                    };
+                    for &channel in &channels {
+                        controller.set_fan_duty(channel, duty).with_context(|| {
+                            format!("failed to set fan {channel} of {}", controller.name())
+                        })?;
+                    }
+                    updated.push(controller_json(controller, &channels));
+                    device
+                        .fan_overrides
+                        .retain(|(other, _)| !channels.contains(other));
+                    device
+                        .fan_overrides
+                        .extend(channels.into_iter().map(|channel| (channel, duty)));
+                }
+                anyhow::ensure!(!updated.is_empty(), nzxtcli::Error::NoMatchingDevice);
+                Ok(serde_json::json!({ "controllers": updated }))
+            }
+            Command::ReleaseFan {
+                fans,
+                serial,
+                product_id,
+            } => {
+                let mut released = Vec::new();
+                for device in &mut self.devices {
+                    let controller = device.controller.as_ref();
+                    if !selects(controller, serial.as_deref(), product_id) {
+                        continue;
+                    }
+                    let Some(channels) = fan_channels(controller, &fans)? else {
+                        continue;
+                    };
+                    let ended = device
+                        .fan_overrides
+                        .extract_if(.., |(channel, _)| channels.contains(channel))
+                        .map(|(channel, _)| channel)
+                        .collect::<Vec<_>>();
+                    // Set by the curve right away if the temperature is known,
+                    // unbound fans keep the duty.
+                    for (binding, channel, limiter) in &mut device.fans {
+                        if !ended.contains(channel) {
+                            continue;
+                        }
+                        *limiter = DutyLimiter::default();
+                        let Some(duty) = self.duties[*binding] else {
+                            continue;
+                        };
+                        limiter.update(duty, now);
+                        if let Err(e) = controller.set_fan_duty(*channel, duty) {
+                            warning!(
+                                "failed to set fan {channel} duty for {}: {e:?}",
+                                controller.name()
+                            );
+                            *limiter = DutyLimiter::default();
+                        }
+                    }
+                    released.push(controller_json(controller, &ended));
+                }
+                anyhow::ensure!(!released.is_empty(), nzxtcli::Error::NoMatchingDevice);
+                Ok(serde_json::json!({ "controllers": released }))
+            }
+            Command::Profile { name } => {
+                let profile = self.bindings.check_profile(&name)?.to_owned();
+                self.activate_profile(profile)?;
+                Ok(serde_json::json!({ "profile": self.profile }))
+            }
+        }
+    }
+
+    /// Sets the lighting of the channels of the selected controllers
+    /// until the override ends.
+    fn set_override(
+        &mut self,
+        lighting: Lighting,
+        (channels, serial, product_id): (ChannelSelector, Option<String>, Option<u16>),
+        until: Option<Instant>,
+    ) -> Result<serde_json::Value> {
+        let args = ChannelArgs { channels };
+        let mut updated = Vec::new();
+        for device in &mut self.devices {
+            let controller = device.controller.as_ref();
+            if !selects(controller, serial.as_deref(), product_id) {
+                continue;
+            }
+            let channels = args.targets(controller)?;
+            for &channel in &channels {
+                apply_lighting(controller, &lighting, channel).with_context(|| {
+                    format!("failed to set channel {channel} of {}", controller.name())
+                })?;
+            }
+            updated.push(controller_json(controller, &channels));
+            device
+                .overrides
+                .retain(|other| !channels.contains(&other.channel));
+            device
+                .overrides
+                .extend(channels.into_iter().map(|channel| Override {
+                    channel,
+                    lighting: lighting.clone(),
+                    until,
+                }));
+        }
+        anyhow::ensure!(!updated.is_empty(), nzxtcli::Error::NoMatchingDevice);
+        Ok(serde_json::json!({ "controllers": updated }))
+    }
+
+    fn overrides_expired(&self, now: Instant) -> bool {
+        self.devices
+            .iter()
+            .flat_map(|device| &device.overrides)
+            .any(|other| other.expired(now))
+    }
+
+    /// Returns the channels with expired overrides to their bindings.
+    fn expire_overrides(&mut self, now: Instant) {
+        for index in 0..self.devices.len() {
+            let ended = self.devices[index].end_overrides(|other| other.expired(now));
+            self.restore(index, &ended);
+        }
+    }
+
+    /// Sets the channels by their bindings again, unbound channels get
+    /// the colors last set by the CLI.
+    fn restore(&self, device: usize, channels: &[usize]) {
+        let device = &self.devices[device];
+        let controller = device.controller.as_ref();
+        let mut state = None;
+        for &channel in channels {
+            let binding = device
+                .leds
+                .iter()
+                .find(|(_, channels)| channels.contains(&channel))
+                .map(|(binding, _)| *binding);
+            let result = match binding {
+                Some(binding) => match &self.bindings.leds[binding].action {
+                    LedAction::Lighting(lighting) => apply_lighting(controller, lighting, channel),
+                    // Set again on the next reading otherwise.
+                    LedAction::Temp { .. } => match self.colors[binding] {
+                        Some(color) => controller
+                            .set_channel_color(channel, color)
+                            .map_err(Into::into),
+                        None => Ok(()),
+                    },
+                },
+                None => state
+                    .get_or_insert_with(LedState::load)
+                    .channel(controller, channel)
+                    .apply(controller, channel),
+            };
+            if let Err(e) = result {
+                warning!(
+                    "failed to restore channel {channel} of {}: {e:?}",
+                    controller.name()
+                );
+            }
+        }
+    }
+
+    /// A single line for `systemctl status`, reconnecting controllers go first.
+    fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        for device in &self.devices {
+            if device.is_used() && !device.controller.is_connected() {
+                parts.push(format!("reconnecting {}", device.controller.name()));
+            }
+        }
+        if self.error.is_some() {
+            parts.push("the last reload failed".to_owned());
+        }
+        if let Some(profile) = &self.profile {
+            parts.push(format!("profile `{profile}`"));
+        }
+        let used = |binding| self.devices.iter().any(|device| device.uses(binding));
+        for (index, binding) in self.bindings.leds.iter().enumerate() {
+            if let (Some(temp), Some(color), true) = (
+                self.led_temps[index],
+                self.colors[index],
+                used(Binding::Led(index)),
+            ) {
+                let table = &binding.target.table;
+                parts.push(format!("{table}: {:.1}°C -> {color}", celsius(temp)));
+            }
+        }
+        for (index, binding) in self.bindings.fans.iter().enumerate() {
+            if let (Some(temp), Some(duty), true) = (
+                self.fan_temps[index],
+                self.duties[index],
+                used(Binding::Fan(index)),
+            ) {
+                let table = &binding.target.table;
+                parts.push(format!("{table}: {:.1}°C -> {duty}%", celsius(temp)));
+            }
+        }
+        match parts.is_empty() {
+            true => "running".to_owned(),
+            false => parts.join(", "),
+        }
+    }
+
+    /// Current bindings, colors and duties of all channels for `daemon --status`.
+    fn status(&self) -> serde_json::Value {
+        let controllers = self
+            .devices
+            .iter()
+            .filter(|device| device.is_used())
+            .map(|device| {
+                let controller = device.controller.as_ref();
+                let channels = device
+                    .leds
+                    .iter()
+                    .flat_map(|(binding, channels)| {
+                        channels.iter().map(move |&channel| (*binding, channel))
+                    })
+                    .map(|(binding, channel)| {
+                        let color = match &self.bindings.leds[binding].action {
+                            LedAction::Lighting(Lighting::Fixed { color }) => Some(*color),
+                            LedAction::Lighting(_) => None,
+                            LedAction::Temp { .. } => self.colors[binding],
+                        };
+                        serde_json::json!({
+                            "channel": channel,
+                            "binding": self.bindings.leds[binding].target.table,
+                            "color": color,
+                            "temp": self.led_temps[binding].map(celsius),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let fans = device
+                    .fans
+                    .iter()
+                    .map(|&(binding, channel, _)| {
+                        serde_json::json!({
+                            "channel": channel,
+                            "binding": self.bindings.fans[binding].target.table,
+                            "duty": self.duties[binding],
+                            "temp": self.fan_temps[binding].map(celsius),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let overrides = device
+                    .overrides
+                    .iter()
+                    .map(|other| {
+                        serde_json::json!({
+                            "channel": other.channel,
+                            "lighting": other.lighting,
+                            "temporary": other.until.is_some(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let fan_overrides = device
+                    .fan_overrides
+                    .iter()
+                    .map(|(fan, duty)| serde_json::json!({ "channel": fan, "duty": duty }))
+                    .collect::<Vec<_>>();
+                serde_json::json!({
+                    "name": controller.name(),
+                    "serial": controller.serial(),
+                    "connected": controller.is_connected(),
+                    "channels": channels,
+                    "fans": fans,
+                    "overrides": overrides,
+                    "fan_overrides": fan_overrides,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::json!({
+            "pid": std::process::id(),
+            "config": self.path,
+            "profile": self.profile,
+            "profiles": self.bindings.profiles,
+            "error": self.error,
+            "controllers": controllers,
+        })
+    }
+
+    /// Properties of the D-Bus interface, the fans and liquid coolers
+    /// of the connected controllers are read for them.
+    #[cfg(feature = "dbus")]
+    fn bus_properties(&self) -> crate::dbus::Properties {
+        let mut properties = crate::dbus::Properties {
+            profile: self.profile.clone().unwrap_or_default(),
+            profiles: self.bindings.profiles.clone(),
+            ..Default::default()
+        };
+        let leds = self.bindings.leds.iter().map(|binding| &binding.target);
+        let fans = self.bindings.fans.iter().map(|binding| &binding.target);
+        let temps = self.led_temps.iter().chain(&self.fan_temps);
+        for (target, temp) in leds.chain(fans).zip(temps) {
+            if let Some(temp) = temp {
+                properties
+                    .temperatures
+                    .push((target.table.clone(), celsius(*temp)));
+            }
+        }
+        for device in self.devices.iter().filter(|device| device.is_used()) {
+            let controller = device.controller.as_ref();
+            let key = controller.serial().unwrap_or(controller.name()).to_owned();
+            let connected = controller.is_connected();
+            properties.connected.push((key.clone(), connected));
+            if !connected {
+                continue;
+            }
+            if let Ok(fans) = controller.fan_status() {
+                let rpm = fans
+                    .iter()
+                    .map(|fan| u32::from(fan.rpm))
+                    .collect::<Vec<_>>();
+                if !rpm.is_empty() {
+                    properties.fan_rpm.push((key.clone(), rpm));
+                }
+            }
+            if controller.is_liquid_cooler() {
+                if let Ok(status) = controller.aio_status() {
+                    let temp = f64::from(status.liquid_temp);
+                    properties.liquid_temperatures.push((key, temp));
+                }
+            }
+        }
+        properties
+    }
+
+    /// Colors and duties of all channels of the used controllers for the MQTT
+    /// bridge, the first color is shown for effects.
+    fn mqtt_state(&self) -> Vec<ControllerState> {
+        let first = |lighting: &Lighting| match lighting {
+            Lighting::Fixed { color } => Some(*color),
+            Lighting::Leds { colors } | Lighting::Mode { colors, .. } => colors.first().copied(),
+        };
+        self.devices
+            .iter()
+            .filter(|device| device.is_used())
+            .map(|device| {
+                let controller = device.controller.as_ref();
+                let lights = (0..controller.rgb_channels().len())
+                    .map(|channel| {
+                        if let Some(other) = device.overrides.iter().find(|o| o.channel == channel)
+                        {
+                            return first(&other.lighting);
+                        }
+                        let (binding, _) = device
+                            .leds
+                            .iter()
+                            .find(|(_, channels)| channels.contains(&channel))?;
+                        match &self.bindings.leds[*binding].action {
+                            LedAction::Lighting(lighting) => first(lighting),
+                            LedAction::Temp { .. } => self.colors[*binding],
+                        }
+                    })
+                    .collect();
+                let fans = (0..controller.fan_channels())
+                    .map(|channel| {
+                        if let Some((_, duty)) =
+                            device.fan_overrides.iter().find(|(fan, _)| *fan == channel)
+                        {
+                            return (Some(*duty), true);
+                        }
+                        let duty = device
+                            .fans
+                            .iter()
+                            .find(|(_, fan, _)| *fan == channel)
+                            .and_then(|(binding, ..)| self.duties[*binding]);
+                        (duty, false)
+                    })
+                    .collect();
+                ControllerState {
+                    name: controller.name(),
+                    serial: controller.serial().map(str::to_owned),
+                    product_id: controller.product_id(),
+                    connected: controller.is_connected(),
+                    lights,
+                    fans,
+                }
+            })
+            .collect()
+    }
+
+    /// Failures are reported but are not fatal, nothing is written during a dry run.
+    fn write_status(&self) {
+        let Some(path) = status_path() else {
+            return;
+        };
+        if crate::DRY_RUN.load(Ordering::Relaxed) {
+            return;
+        }
+        let result = serde_json::to_string_pretty(&self.status())
+            .map_err(anyhow::Error::from)
+            .and_then(|data| write_if_changed(&path, &(data + "\n")));
+        if let Err(e) = result {
+            warning!("failed to write {}: {e:#}", path.display());
+        }
+    }
+}
+
+fn celsius(millidegrees: i64) -> f64 {
+    millidegrees as f64 / 1000.0
+}
+
+/// `None` for controllers without fan channels.
+fn fan_channels(controller: &dyn Controller, fans: &ChannelSelector) -> Result<Option<Vec<usize>>> {
+    let available = controller.fan_channels();
+    if available == 0 {
+        return Ok(None);
+    }
+    let channels = fans.resolve(available).with_context(|| {
+        format!(
+            "invalid fan channels for {} (valid fan channels: 0..{available})",
+            controller.name()
+        )
+    })?;
+    Ok(Some(channels))
+}
+
+fn controller_json(controller: &dyn Controller, channels: &[usize]) -> serde_json::Value {
+    serde_json::json!({
+        "name": controller.name(),
+        "serial": controller.serial(),
+        "channels": channels,
+    })
+}
+
+/// `$XDG_RUNTIME_DIR/nzxtcli-daemon.json`.
+fn status_path() -> Option<PathBuf> {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty())?;
+    Some(PathBuf::from(dir).join("nzxtcli-daemon.json"))
+}
+
+/// The status is rewritten only when something has changed.
+fn write_if_changed(path: &Path, data: &str) -> Result<()> {
+    if std::fs::read_to_string(path).is_ok_and(|old| old == data) {
+        return Ok(());
+    }
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, data)?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn print_status() -> Result<()> {
+    let path = status_path().context("`XDG_RUNTIME_DIR` is not set")?;
+    let status = match std::fs::read(&path) {
+        Ok(data) => serde_json::from_slice::<serde_json::Value>(&data)
+            .with_context(|| format!("failed to parse {}", path.display()))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!("the daemon is not running")
+        }
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    // The file of a killed daemon is left behind.
+    let pid = status["pid"].as_i64().unwrap_or_default() as libc::pid_t;
+    // SAFETY: Signal 0 only checks that the process exists.
+    let alive = pid > 0
+        && (unsafe { libc::kill(pid, 0) } == 0
+            || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM));
+    anyhow::ensure!(
+        alive,
+        "the daemon is not running (stale status of pid {pid})"
+    );
+    crate::print_json(status)
+}
+
+fn apply_lighting(controller: &dyn Controller, lighting: &Lighting, channel: usize) -> Result<()> {
+    let state = ChannelState {
+        lighting: Some(lighting.clone()),
+        brightness: None,
+    };
+    state.apply(controller, channel)
+}
+
+/// Temperature sources of all bindings, each one is opened and read once.
+#[derive(Default)]
+struct Sources {
+    entries: Vec<(SourceSpec, RetryingSource)>,
+}
+
+impl Sources {
+    /// Opens the sources of the config which are not open yet.
+    fn open_missing(&self, bindings: &DaemonConfig) -> Result<Vec<(SourceSpec, RetryingSource)>> {
+        let mut opened = Vec::<(SourceSpec, RetryingSource)>::new();
+        for (target, input) in bindings.inputs() {
+            for spec in &input.sources {
+                let open = |(other, _): &(SourceSpec, RetryingSource)| other == spec;
+                if self.entries.iter().any(open) || opened.iter().any(open) {
+                    continue;
+                }
+                let source = spec.open().with_context(|| format!("`{}`", target.table))?;
+                opened.push((spec.clone(), RetryingSource::new(source)));
+            }
+        }
+        Ok(opened)
+    }
+
+    /// Keeps the sources used by the config, along with their retry state.
+    fn rebuild(self, opened: Vec<(SourceSpec, RetryingSource)>, bindings: &DaemonConfig) -> Self {
+        let mut pool = self
+            .entries
+            .into_iter()
+            .chain(opened)
+            .map(Some)
+            .collect::<Vec<_>>();
+        let mut entries = Vec::<(SourceSpec, RetryingSource)>::new();
+        for (_, input) in bindings.inputs() {
+            for spec in &input.sources {
+                if entries.iter().any(|(other, _)| other == spec) {
+                    continue;
+                }
+                let entry = pool
+                    .iter_mut()
+                    .find(|entry| entry.as_ref().is_some_and(|(other, _)| other == spec))
+                    .and_then(Option::take)
+                    .expect("all sources are open");
+                entries.push(entry);
+            }
+        }
+        Self { entries }
+    }
+
+    fn state(&self, input: &TempInput) -> TempState {
+        let sources = input
+            .sources
+            .iter()
+            .map(|spec| {
+                self.entries
+                    .iter()
+                    .position(|(other, _)| other == spec)
+                    .expect("all sources are open")
+            })
+            .collect();
+        TempState {
+            sources,
+            aggregate: input.aggregate,
+            smoother: input.smooth.map(Smoother::new),
+            last_reading: None,
+        }
+    }
+
+    /// Failed sources are skipped until they are readable again.
+    fn poll(&mut self, now: Instant) -> Vec<Option<i64>> {
+        self.entries
+            .iter_mut()
+            .map(|(_, reader)| reader.poll(now))
+            .collect()
+    }
+}
+
+impl SourceSpec {
+    fn open(&self) -> Result<Box<dyn nzxtcli::TempSource>> {
+        Ok(match self {
+            Self::Path(path) => Box::new(TempReader::open(
+                HwmonInput::Path(path.clone()),
+                HWMON_ROOT,
+            )?),
+            Self::Sensor(spec) => Box::new(TempReader::open(
+                HwmonInput::Sensor(spec.clone()),
+                HWMON_ROOT,
+            )?),
+            Self::Exec(command) => Box::new(ExecSource::new(
+                command.clone(),
+                ExecSource::DEFAULT_TIMEOUT,
+            )),
+        })
+    }
+}
+
+/// Temperature of a binding from the readings of the shared sources.
+struct TempState {
+    sources: Vec<usize>,
+    aggregate: Aggregate,
+    smoother: Option<Smoother>,
+    last_reading: Option<Instant>,
+}
+
+impl TempState {
+    /// Returns `None` if none of the sources could be read.
+    fn update(&mut self, readings: &[Option<i64>], now: Instant) -> Option<i64> {
+        let millidegrees = self
+            .aggregate
+            .apply(self.sources.iter().filter_map(|&index| readings[index]))?;
+        let elapsed = self.last_reading.map_or(Duration::ZERO, |last| now - last);
+        self.last_reading = Some(now);
+        Some(match &mut self.smoother {
+            Some(smoother) => smoother.update(millidegrees, elapsed),
+            None => millidegrees,
+        })
+    }
+
+    /// Keeps the average of an unchanged binding with the new source indices.
+    fn reuse(self, old: Self) -> Self {
+        Self {
+            smoother: old.smoother,
+            last_reading: old.last_reading,
+            ..self
+        }
+    }
+}
+
+/// State of an LED temperature binding.
+struct TempColor {
+    temp: TempState,
+    hysteresis: Option<Hysteresis>,
+}
+
+impl TempColor {
+    fn reuse(self, temp: TempState) -> Self {
+        Self {
+            temp: temp.reuse(self.temp),
+            hysteresis: self.hysteresis,
+        }
+    }
+
+    fn update(&mut self, readings: &[Option<i64>], now: Instant) -> Option<i64> {
+        let millidegrees = self.temp.update(readings, now)?;
+        Some(match &mut self.hysteresis {
+            Some(hysteresis) => hysteresis.update(millidegrees),
+            None => millidegrees,
+        })
+    }
+}
+
+/// LED binding index with its RGB channels, fan binding index with the fan channel.
+type Assignment = (Vec<(usize, Vec<usize>)>, Vec<(usize, usize)>);
+
+/// Controller with the channels of the bindings which match it.
+struct Device {
+    controller: Box<dyn Controller>,
+    reconnect: Backoff,
+    /// LED binding index and its RGB channels.
+    leds: Vec<(usize, Vec<usize>)>,
+    /// Fan binding index, the fan channel and its limiter.
+    fans: Vec<(usize, usize, DutyLimiter)>,
+    overrides: Vec<Override>,
+    /// Fan channels with the duty set over the control socket, the curves
+    /// of their bindings are not applied until they are released.
+    fan_overrides: Vec<(usize, u8)>,
+}
+
+/// Color set over the control socket, it takes precedence over the binding
+/// of the channel.
+struct Override {
+    channel: usize,
+    lighting: Lighting,
+    /// Kept until released if `None`.
+    until: Option<Instant>,
+}
+
+impl Override {
+    fn expired(&self, now: Instant) -> bool {
+        self.until.is_some_and(|until| until <= now)
+    }
+}
+
+impl Device {
+    fn new(controller: Box<dyn Controller>) -> Self {
+        Self {
+            controller,
+            reconnect: Backoff::default(),
+            leds: Vec::new(),
+            fans: Vec::new(),
+            overrides: Vec::new(),
+            fan_overrides: Vec::new(),
+        }
+    }
+
+    fn is_overridden(&self, channel: usize) -> bool {
+        self.overrides.iter().any(|other| other.channel == channel)
+    }
+
+    /// Removes the matching overrides, returns their channels.
+    fn end_overrides(&mut self, matches: impl Fn(&Override) -> bool) -> Vec<usize> {
+        let mut ended = Vec::new();
+        self.overrides.retain(|other| {
+            let end = matches(other);
+            if end {
+                ended.push(other.channel);
+            }
+            !end
+        });
+        ended
+    }
+
+    /// Channels of the active bindings.
+    ///
+    /// Bindings of the profile take precedence over the ones shared by all
+    /// profiles, fails if the channels don't exist or are set by several
+    /// bindings of the same kind.
+    fn assign(&self, bindings: &DaemonConfig, profile: Option<&str>) -> Result<Assignment> {
+        let controller = self.controller.as_ref();
+        let name = controller.name();
+        let active = |target: &Target| {
+            target.matches(controller)
+                && (target.profile.is_none() || target.profile.as_deref() == profile)
+        };
+
+        let mut leds = Vec::new();
+        let mut owners = Vec::<(usize, &Target)>::new();
+        let targets = bindings.leds.iter().map(|binding| &binding.target);
+        for (index, target) in active_targets(targets, active) {
+            let args = ChannelArgs {
+                channels: target.channels.clone(),
+            };
+            let mut channels = args
+                .targets(controller)
+                .with_context(|| format!("`{}` doesn't match the controller", target.table))?;
+            claim(&mut owners, &mut channels, target, "channel", name)?;
+            leds.push((index, channels));
+        }
+
+        let mut fans = Vec::new();
+        let mut owners = Vec::<(usize, &Target)>::new();
+        let fan_channels = controller.fan_channels();
+        let targets = bindings.fans.iter().map(|binding| &binding.target);
+        for (index, target) in active_targets(targets, active) {
+            // Controllers without fan channels are skipped.
+            if fan_channels == 0 {
+                continue;
+            }
+            let mut channels = target.channels.resolve(fan_channels).with_context(|| {
+                format!(
+                    "`{}`: invalid fan channels for {name} (valid fan channels: 0..{fan_channels})",
+                    target.table
+                )
+            })?;
+            claim(&mut owners, &mut channels, target, "fan channel", name)?;
+            fans.extend(channels.into_iter().map(|channel| (index, channel)));
+        }
+        Ok((leds, fans))
+    }
+
+    /// Switches to the new assignment, records the channels whose binding has changed.
+    fn replace(
+        &mut self,
+        device: usize,
+        old: &DaemonConfig,
+        new: &DaemonConfig,
+        leds: Vec<(usize, Vec<usize>)>,
+        fans: Vec<(usize, usize)>,
+        changes: &mut Changes,
+    ) {
+        let old_owner = |channel: usize| {
+            self.leds
+                .iter()
+                .find(|(_, channels)| channels.contains(&channel))
+                .map(|(binding, _)| &old.leds[*binding])
+        };
+        for (binding, channels) in &leds {
+            for &channel in channels {
+                match old_owner(channel) {
+                    Some(old) if old.same(&new.leds[*binding]) => changes.unchanged += 1,
+                    _ => changes.channels.push((device, *binding, channel)),
+                }
+            }
+        }
+        changes.released += self
+            .leds
+            .iter()
+            .flat_map(|(_, channels)| channels)
+            .filter(|&&channel| !leds.iter().any(|(_, new)| new.contains(&channel)))
+            .count();
+
+        let mut old_fans = std::mem::take(&mut self.fans);
+        self.fans = fans
+            .into_iter()
+            .map(|(binding, channel)| {
+                let unchanged = old_fans.iter().position(|(old_binding, old_channel, _)| {
+                    *old_channel == channel && old.fans[*old_binding].same(&new.fans[binding])
+                });
+                let limiter = match unchanged {
+                    Some(index) => old_fans.swap_remove(index).2,
+                    None => {
+                        changes.fans += 1;
+                        DutyLimiter::default()
+                    }
+                };
+                (binding, channel, limiter)
+            })
+            .collect();
+        self.leds = leds;
+    }
+
+    fn is_used(&self) -> bool {
+        !self.leds.is_empty() || !self.fans.is_empty()
+    }
+
+    fn uses(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Led(index) => self.leds.iter().any(|(led, _)| *led == index),
+            Binding::Fan(index) => self.fans.iter().any(|(fan, ..)| *fan == index),
+        }
+    }
+
+    /// Sets the colors and duties of the temperature bindings.
+    ///
+    /// Errors are printed, since the device might be reconnected later.
+    fn update(
+        &mut self,
+        api: &HidApi,
+        bindings: &DaemonConfig,
+        colors: &[Option<Color>],
+        duties: &[Option<u8>],
+        now: Instant,
+    ) {
+        if !self.is_used() {
+            return;
+        }
+        let _span = logging::controller_span(self.controller.as_ref());
+        // Devices are re-enumerated after resume, they are found again by the serial.
+        if !self.controller.is_connected() {
+            if !self.reconnect.ready(now) {
+                return;
+            }
+            if let Err(e) = self.controller.reconnect(api) {
+                let delay = self.reconnect.failed(now);
+                warning!(
+                    "failed to reconnect: {e:?}, retrying in {}",
+                    humantime::format_duration(delay)
+                );
+                return;
+            }
+            let failures = self.reconnect.succeeded() + 1;
+            info!("reconnected after {failures} attempts");
+
+            // Hardware effects are not restored by the controller.
+            for (binding, channels) in &self.leds {
+                let LedAction::Lighting(lighting) = &bindings.leds[*binding].action else {
+                    continue;
+                };
+                for &channel in channels
+                    .iter()
+                    .filter(|&&channel| !self.is_overridden(channel))
+                {
+                    if let Err(e) = apply_lighting(self.controller.as_ref(), lighting, channel) {
+                        warning!("failed to restore channel {channel}: {e:?}");
+                    }
+                }
+            }
+            for other in &self.overrides {
+                let channel = other.channel;
+                if let Err(e) = apply_lighting(self.controller.as_ref(), &other.lighting, channel) {
+                    warning!("failed to restore channel {channel}: {e:?}");
+                }
+            }
+            for &(fan_channel, duty) in &self.fan_overrides {
+                if let Err(e) = self.controller.set_fan_duty(fan_channel, duty) {
+                    warning!("failed to restore fan {fan_channel} duty: {e:?}");
+                }
+            }
+        }
+
+        let controller = self.controller.as_ref();
+        for (binding, channels) in &self.leds {
+            let Some(color) = colors[*binding] else {
+                continue;
+            };
+            // Unchanged colors are not sent again.
+            for &channel in channels
+                .iter()
+                .filter(|&&channel| !self.is_overridden(channel))
+            {
+                if let Err(e) = controller.set_channel_color(channel, color) {
+                    warning!("failed to set color of channel {channel}: {e:?}");
+                }
+            }
+        }
+        if let Err(e) = controller.ensure_applied() {
+            warning!("failed to re-apply colors: {e:?}");
+        }
+
+        for (binding, fan_channel, limiter) in &mut self.fans {
+            if self
+                .fan_overrides
+                .iter()
+                .any(|(other, _)| other == fan_channel)
+            {
+                continue;
+            }
+            let Some(duty) = duties[*binding].and_then(|duty| limiter.update(duty, now)) else {
+                continue;
+            };
+            if let Err(e) = controller.set_fan_duty(*fan_channel, duty) {
+                warning!("failed to set fan {fan_channel} duty: {e:?}");
+                // Retry on the next reading.
+                *limiter = DutyLimiter::default();
+            }
+        }
+    }
+}
+
+/// Active targets with their indices, the ones of the profile go first,
+/// so that they claim the channels.
+fn active_targets<'a>(
+    targets: impl Iterator<Item = &'a Target>,
+    active: impl Fn(&Target) -> bool,
+) -> Vec<(usize, &'a Target)> {
+    let (mut profiled, shared): (Vec<_>, Vec<_>) = targets
+        .enumerate()
+        .filter(|(_, target)| active(target))
+        .partition(|(_, target)| target.profile.is_some());
+    profiled.extend(shared);
+    profiled
+}
+
+/// Records the target as the owner of the channels.
+///
+/// Channels owned by a profile binding are dropped from a shared one,
+/// other overlaps are errors.
+fn claim<'a>(
+    owners: &mut Vec<(usize, &'a Target)>,
+    channels: &mut Vec<usize>,
+    target: &'a Target,
+    kind: &str,
+    controller: &str,
+) -> Result<()> {
+    let mut result = Ok(());
+    channels.retain(|&channel| {
+        let Some((_, owner)) = owners.iter().find(|(owned, _)| *owned == channel) else {
+            return true;
+        };
+        if owner.profile.is_none() || target.profile.is_some() {
+            result = Err(anyhow::anyhow!(
+                "`{}` and `{}` both set {kind} {channel} of {controller}",
+                owner.table,
+                target.table
+            ));
+        }
+        false
+    });
+    result?;
+    owners.extend(channels.iter().map(|&channel| (channel, target)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use nzxtcli::transport::{self, MockTransport};
+
+    use super::*;
+
+    fn parse(text: &str) -> Result<DaemonConfig, String> {
+        let config = Config::parse(PathBuf::from("nzxt.toml"), text).map_err(|e| e.to_string())?;
+        DaemonConfig::from_config(&config).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn daemon_bindings() {
+        let daemon = parse(
+            r#"
+            [groups]
+            front = ["0", "1"]
+
+            [daemon]
+            interval = "2s"
+
+            [[daemon.led]]
+            serial = "MA123"
+            channels = "front"
+            sensor = ["k10temp:Tctl", "amdgpu:edge"]
+            ramp = "30:blue,80:red"
+            smooth = "10s"
+            hysteresis = 1.5
+
+            [[daemon.led]]
+            pid = 0x2012
+            channels = 2
+            mode = "fading"
+            colors = ["red", "blue"]
+            speed = "slow"
+
+            [[daemon.led]]
+            pid = "2011"
+            color = "white"
+
+            [[daemon.fan]]
+            exec = "echo 45"
+            aggregate = "avg"
+            curve = "30:20,80:100"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(daemon.leds.len(), 3);
+        let target = &daemon.leds[0].target;
+        assert_eq!(target.table, "daemon.led[0]");
+        assert_eq!(target.serial.as_deref(), Some("MA123"));
+        assert_eq!(target.channels, ChannelSelector::Channels(vec![0, 1]));
+        let LedAction::Temp {
+            input,
+            base,
+            warn,
+            hysteresis,
+            ..
+        } = &daemon.leds[0].action
+        else {
+            panic!("expected a temperature binding");
+        };
+        assert_eq!(input.sources.len(), 2);
+        assert_eq!(input.smooth, Some(Duration::from_secs(10)));
+        assert_eq!((*base, *warn, *hysteresis), (30.0, 80.0, Some(1500)));
+
+        assert_eq!(daemon.leds[1].target.product_id, Some(0x2012));
+        assert_eq!(
+            daemon.leds[1].target.channels,
+            ChannelSelector::Channels(vec![2])
+        );
+        assert_eq!(
+            daemon.leds[1].action,
+            LedAction::Lighting(Lighting::Mode {
+                mode: LedMode::Fading,
+                colors: vec![Color::RED, Color::BLUE],
+                speed: 1,
+                direction: Direction::default(),
+            })
+        );
+        assert_eq!(daemon.leds[2].target.product_id, Some(0x2011));
+        assert_eq!(daemon.leds[2].target.channels, ChannelSelector::All);
+        assert_eq!(
+            daemon.leds[2].action,
+            LedAction::Lighting(Lighting::Fixed {
+                color: Color::WHITE
+            })
+        );
+
+        let fan = &daemon.fans[0];
+        assert_eq!(fan.target.table, "daemon.fan[0]");
+        assert_eq!(fan.input.sources, [SourceSpec::Exec("echo 45".to_owned())]);
+        assert_eq!(fan.input.aggregate, Aggregate::Avg);
+        assert_eq!(fan.curve.duty(55.0), 60);
+    }
+
+    #[test]
+    fn daemon_validation() {
+        let error = |text| parse(text).unwrap_err();
+
+        assert_eq!(
+            error(
+                "[[daemon.led]]\ncolor = \"red\"\n[[daemon.led]]\nsensor = \"k10temp\"\nramp = \"30:blue\""
+            ),
+            "nzxt.toml:5: `daemon.led[1].ramp`: color ramp must span a range of temperatures, \
+             all stops are at 30"
+        );
+        assert_eq!(
+            error("[[daemon.led]]\ncolor = \"red\"\nmode = \"fading\""),
+            "nzxt.toml:1: `daemon.led[0]`: expected exactly one of `color`, `mode` or \
+             a temperature source (`sensor`, `path` or `exec`)"
+        );
+        assert_eq!(
+            error("[[daemon.led]]\ncolor = \"red\"\nsmooth = \"1s\""),
+            "nzxt.toml:3: `daemon.led[0].smooth`: unknown key of a color binding"
+        );
+        assert_eq!(
+            error("[[daemon.led]]\nmode = \"fixed\""),
+            "nzxt.toml:2: `daemon.led[0].mode`: mode `fixed` accepts exactly 1 color, 0 given"
+        );
+        assert_eq!(
+            error("[[daemon.led]]\ncolor = \"red\"\nchannels = \"back\""),
+            "nzxt.toml:3: `daemon.led[0].channels`: invalid channel `back` in `back`, \
+             expected `all`, an index or a range (e.g. `1-3`)"
+        );
+        assert_eq!(
+            error("[[daemon.led]]\nsensor = \"k10temp\"\nramp = \"30:blue,80:red\"\nbase = 20"),
+            "nzxt.toml:4: `daemon.led[0].base`: can't be combined with `ramp`"
+        );
+        assert_eq!(
+            error("[[daemon.led]]\nexec = \"echo 1\"\nhysteresis = -1"),
+            "nzxt.toml:3: `daemon.led[0].hysteresis`: expected a non-negative number of degrees"
+        );
+        assert_eq!(
+            error("[[daemon.fan]]\nsensor = \"k10temp\""),
+            "nzxt.toml:1: `daemon.fan[0]`: missing the `curve` key"
+        );
+        assert_eq!(
+            error("[[daemon.fan]]\ncurve = \"30:20\""),
+            "nzxt.toml:1: `daemon.fan[0]`: expected a temperature source, \
+             `sensor`, `path` or `exec`"
+        );
+        assert_eq!(
+            error("[[daemon.fan]]\nexec = \"echo 1\"\ncurve = \"30:20\"\npid = 0x12345"),
+            "nzxt.toml:4: `daemon.fan[0].pid`: invalid product id"
+        );
+    }
+
+    fn mock_daemon(path: PathBuf) -> (MockTransport, Daemon) {
+        let (transport, controller) = transport::mock_controller_boxed();
+        let device = Device::new(controller);
+        let daemon = Daemon {
+            path,
+            bindings: DaemonConfig::default(),
+            profile: None,
+            sources: Sources::default(),
+            leds: Vec::new(),
+            fans: Vec::new(),
+            devices: vec![device],
+            colors: Vec::new(),
+            duties: Vec::new(),
+            led_temps: Vec::new(),
+            fan_temps: Vec::new(),
+            error: None,
+            verbose: false,
+        };
+        (transport, daemon)
+    }
+
+    const PROFILES: &str = r#"
+        [[daemon.led]]
+        color = "white"
+
+        [[daemon.led]]
+        profile = "work"
+        channels = "0"
+        color = "blue"
+
+        [[daemon.led]]
+        profile = "gaming"
+        channels = "0-1"
+        color = "red"
+        "#;
+
+    #[test]
+    fn daemon_profiles() {
+        let bindings = parse(PROFILES).unwrap();
+        assert_eq!(bindings.profiles, ["work", "gaming"]);
+        assert_eq!(
+            bindings.check_profile("home").unwrap_err().to_string(),
+            "unknown profile `home`, expected one of: work, gaming"
+        );
+
+        let (transport, mut daemon) = mock_daemon(PathBuf::from("nzxt.toml"));
+        let changes = daemon.activate(bindings, Some("work".to_owned())).unwrap();
+        // The profile binding takes channel 0 from the shared one.
+        assert_eq!(changes.channels, [(0, 1, 0), (0, 0, 1), (0, 0, 2)]);
+        daemon.apply_lighting(&changes).unwrap();
+        let per_channel = transport.written().len() / 3;
+        assert!(per_channel > 0);
+
+        transport.clear_written();
+        daemon.switch_profile(1);
+        assert_eq!(daemon.profile.as_deref(), Some("gaming"));
+        assert_eq!(daemon.devices[0].leds, [(2, vec![0, 1]), (0, vec![2])]);
+        // Only the channels of the changed bindings are written.
+        assert_eq!(transport.written().len(), 2 * per_channel);
+
+        // Wraps around in both directions.
+        daemon.switch_profile(-3);
+        assert_eq!(daemon.profile.as_deref(), Some("work"));
+        let status = daemon.status();
+        assert_eq!(status["profile"], "work");
+        assert_eq!(
+            status["controllers"][0]["channels"][0]["binding"],
+            "daemon.led[1]"
+        );
+        assert_eq!(status["controllers"][0]["channels"][0]["color"], "#0000ff");
+        assert_eq!(daemon.summary(), "profile `work`");
+    }
+
+    #[test]
+    fn daemon_control() {
+        let (transport, mut daemon) = mock_daemon(PathBuf::from("nzxt.toml"));
+        let bindings = parse(PROFILES).unwrap();
+        let changes = daemon.activate(bindings, Some("work".to_owned())).unwrap();
+        daemon.apply_lighting(&changes).unwrap();
+        let per_channel = transport.written().len() / 3;
+
+        let now = Instant::now();
+        let set_color = |channels: &str, duration_ms| Command::SetColor {
+            color: Color::new(0, 255, 0),
+            channels: channels.parse().unwrap(),
+            serial: None,
+            product_id: None,
+            duration_ms,
+        };
+        transport.clear_written();
+        let result = daemon.control(set_color("0-1", Some(500)), now).unwrap();
+        assert_eq!(
+            result["controllers"][0]["channels"],
+            serde_json::json!([0, 1])
+        );
+        assert_eq!(transport.written().len(), 2 * per_channel);
+        let status = daemon.control(Command::Status, now).unwrap();
+        assert_eq!(
+            status["controllers"][0]["overrides"][1]["lighting"],
+            serde_json::json!({"type": "fixed", "color": "#00ff00"})
+        );
+
+        // Effects are checked like in `set-mode`.
+        let set_mode = |colors| Command::SetMode {
+            mode: LedMode::Fading,
+            colors,
+            speed: None,
+            direction: Direction::Forward,
+            channels: ChannelSelector::All,
+            serial: None,
+            product_id: None,
+            duration_ms: Some(500),
+        };
+        assert!(daemon.control(set_mode(Vec::new()), now).is_err());
+        assert_eq!(daemon.devices[0].overrides.len(), 2);
+
+        // The overridden channels are set by the new profile once the override expires.
+        transport.clear_written();
+        daemon.switch_profile(1);
+        assert!(transport.written().is_empty());
+        assert!(!daemon.overrides_expired(now));
+        let later = now + Duration::from_secs(1);
+        assert!(daemon.overrides_expired(later));
+        daemon.expire_overrides(later);
+        assert!(daemon.devices[0].overrides.is_empty());
+        assert_eq!(transport.written().len(), 2 * per_channel);
+
+        // Kept until released.
+        daemon.control(set_color("2", None), now).unwrap();
+        assert!(!daemon.overrides_expired(later));
+        transport.clear_written();
+        let result = daemon
+            .control(
+                Command::Release {
+                    channels: ChannelSelector::All,
+                    serial: None,
+                    product_id: None,
+                },
+                later,
+            )
+            .unwrap();
+        assert_eq!(result["controllers"][0]["channels"], serde_json::json!([2]));
+        assert_eq!(transport.written().len(), per_channel);
+
+        let profile = |name: &str| Command::Profile {
+            name: name.to_owned(),
+        };
+        let result = daemon.control(profile("work"), later).unwrap();
+        assert_eq!(result["profile"], "work");
+        assert_eq!(
+            daemon
+                .control(profile("home"), later)
+                .unwrap_err()
+                .to_string(),
+            "unknown profile `home`, expected one of: work, gaming"
+        );
+        let command = Command::Release {
+            channels: ChannelSelector::All,
+            serial: Some("nope".to_owned()),
+            product_id: None,
+        };
+        assert_eq!(
+            daemon.control(command, later).unwrap_err().to_string(),
+            nzxtcli::Error::NoMatchingDevice.to_string()
+        );
+
+        // Fan duties override the curves the same way.
+        let set_fan = |duty| Command::SetFan {
+            duty,
+            fans: "1".parse().unwrap(),
+            serial: None,
+            product_id: None,
+        };
+        transport.clear_written();
+        daemon.control(set_fan(40), later).unwrap();
+        assert_eq!(transport.written().len(), 1);
+        assert_eq!(daemon.devices[0].fan_overrides, [(1, 40)]);
+        let status = daemon.control(Command::Status, later).unwrap();
+        assert_eq!(
+            status["controllers"][0]["fan_overrides"],
+            serde_json::json!([{"channel": 1, "duty": 40}])
+        );
+        assert_eq!(
+            daemon.control(set_fan(140), later).unwrap_err().to_string(),
+            "fan duty must be a percentage, got 140"
+        );
+        let release = Command::ReleaseFan {
+            fans: ChannelSelector::All,
+            serial: None,
+            product_id: None,
+        };
+        let result = daemon.control(release, later).unwrap();
+        assert_eq!(result["controllers"][0]["channels"], serde_json::json!([1]));
+        assert!(daemon.devices[0].fan_overrides.is_empty());
+    }
+
+    #[test]
+    fn daemon_reload() {
+        let path = std::env::temp_dir().join(format!("nzxtcli-daemon-{}.toml", std::process::id()));
+        std::fs::write(&path, PROFILES).unwrap();
+        let (transport, mut daemon) = mock_daemon(path.clone());
+        let bindings = parse(PROFILES).unwrap();
+        daemon
+            .activate(bindings, Some("gaming".to_owned()))
+            .unwrap();
+
+        // The shared binding is narrowed to channel 1, which is
+        // owned by the profile: channel 2 keeps its color.
+        let text = PROFILES.replacen(
+            "color = \"white\"",
+            "channels = \"1\"\ncolor = \"white\"",
+            1,
+        );
+        std::fs::write(&path, &text).unwrap();
+        transport.clear_written();
+        daemon.reload();
+        assert_eq!(daemon.error, None);
+        assert_eq!(daemon.profile.as_deref(), Some("gaming"));
+        assert_eq!(daemon.devices[0].leds, [(2, vec![0, 1]), (0, vec![])]);
+        assert!(transport.written().is_empty());
+
+        // An invalid config keeps the previous bindings.
+        std::fs::write(&path, "[[daemon.led]]\ncolor = \"nope\"").unwrap();
+        daemon.reload();
+        let error = daemon.error.clone().unwrap();
+        assert!(error.ends_with(
+            "`daemon.led[0].color`: invalid color: expected 3 or 6 hex digits, got `nope`"
+        ));
+        assert_eq!(daemon.bindings.leds.len(), 3);
+        assert_eq!(daemon.status()["error"], error);
+
+        // Overlapping bindings are rejected before anything is changed.
+        let text = PROFILES.replacen("color = \"white\"", "channels = \"2\"\ncolor = \"white\"\n[[daemon.led]]\nchannels = \"2\"\ncolor = \"green\"", 1);
+        std::fs::write(&path, text).unwrap();
+        daemon.reload();
+        assert!(
+            daemon
+                .error
+                .as_ref()
+                .unwrap()
+                .contains("both set channel 2")
+        );
+        assert_eq!(daemon.bindings.leds.len(), 3);
+    }
+}
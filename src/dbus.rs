@@ -0,0 +1,1412 @@
+//! The `io.github.nzxtcli` D-Bus interface of the daemon (`daemon --dbus`),
+//! for desktop widgets and shell extensions.
+//!
+//! Only the parts of the D-Bus protocol needed by a service are implemented:
+//! the `EXTERNAL` authentication over a Unix socket, the message format and
+//! the standard `Properties`, `Introspectable` and `Peer` interfaces.
+//!
+//! ```console
+//! $ busctl --user introspect io.github.nzxtcli /io/github/nzxtcli
+//! $ busctl --user call io.github.nzxtcli /io/github/nzxtcli io.github.nzxtcli \
+//!     SetColor sss "" 0-1 '#ff0000'
+//! $ busctl --user get-property io.github.nzxtcli /io/github/nzxtcli io.github.nzxtcli \
+//!     Temperatures
+//! ```
+//!
+//! An empty serial selects all controllers and the channels are the same as
+//! for `--channels`. The methods map to the commands of the control socket:
+//! colors and modes override the bindings until `Release`. Errors are named
+//! by the kind of the error, e.g. `io.github.nzxtcli.Error.NoDevice`.
+
+use std::fmt;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+
+use anyhow::{Context, Result};
+use nzxtcli::control::Command;
+use nzxtcli::logging::warning;
+use nzxtcli::{Color, Direction, LedMode};
+
+use crate::ErrorKind;
+
+pub const NAME: &str = "io.github.nzxtcli";
+pub const PATH: &str = "/io/github/nzxtcli";
+pub const INTERFACE: &str = "io.github.nzxtcli";
+
+const BUS_NAME: &str = "org.freedesktop.DBus";
+const BUS_PATH: &str = "/org/freedesktop/DBus";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+const PEER: &str = "org.freedesktop.DBus.Peer";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="io.github.nzxtcli">
+    <method name="SetColor">
+      <arg name="serial" type="s" direction="in"/>
+      <arg name="channels" type="s" direction="in"/>
+      <arg name="color" type="s" direction="in"/>
+    </method>
+    <method name="SetMode">
+      <arg name="serial" type="s" direction="in"/>
+      <arg name="channels" type="s" direction="in"/>
+      <arg name="mode" type="s" direction="in"/>
+      <arg name="colors" type="as" direction="in"/>
+      <arg name="speed" type="y" direction="in"/>
+    </method>
+    <method name="Release">
+      <arg name="serial" type="s" direction="in"/>
+      <arg name="channels" type="s" direction="in"/>
+    </method>
+    <method name="ApplyProfile">
+      <arg name="name" type="s" direction="in"/>
+    </method>
+    <property name="Profile" type="s" access="read"/>
+    <property name="Profiles" type="as" access="read"/>
+    <property name="Temperatures" type="a{sd}" access="read"/>
+    <property name="LiquidTemperatures" type="a{sd}" access="read"/>
+    <property name="FanRpm" type="a{sau}" access="read"/>
+    <property name="Connected" type="a{sb}" access="read"/>
+  </interface>
+  <interface name="org.freedesktop.DBus.Properties">
+    <method name="Get">
+      <arg name="interface" type="s" direction="in"/>
+      <arg name="name" type="s" direction="in"/>
+      <arg name="value" type="v" direction="out"/>
+    </method>
+    <method name="GetAll">
+      <arg name="interface" type="s" direction="in"/>
+      <arg name="properties" type="a{sv}" direction="out"/>
+    </method>
+    <method name="Set">
+      <arg name="interface" type="s" direction="in"/>
+      <arg name="name" type="s" direction="in"/>
+      <arg name="value" type="v" direction="in"/>
+    </method>
+    <signal name="PropertiesChanged">
+      <arg name="interface" type="s"/>
+      <arg name="changed" type="a{sv}"/>
+      <arg name="invalidated" type="as"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml" type="s" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+    <method name="GetMachineId">
+      <arg name="id" type="s" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Bus {
+    Session,
+    System,
+}
+
+impl Bus {
+    /// The address from the environment or the default one.
+    fn address(self) -> Result<String> {
+        let (var, default) = match self {
+            Self::Session => ("DBUS_SESSION_BUS_ADDRESS", None),
+            Self::System => (
+                "DBUS_SYSTEM_BUS_ADDRESS",
+                Some("unix:path=/var/run/dbus/system_bus_socket".to_owned()),
+            ),
+        };
+        let runtime_bus = || {
+            let dir = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty())?;
+            let path = PathBuf::from(dir).join("bus");
+            Some(format!("unix:path={}", path.display()))
+        };
+        std::env::var(var)
+            .ok()
+            .filter(|address| !address.is_empty())
+            .or(default)
+            .or_else(runtime_bus)
+            .with_context(|| format!("`{var}` is not set"))
+    }
+}
+
+/// Properties of the interface, published by [`DbusServer::publish`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Properties {
+    pub profile: String,
+    pub profiles: Vec<String>,
+    /// Celsius by the binding table (e.g. `daemon.led[0]`).
+    pub temperatures: Vec<(String, f64)>,
+    /// Celsius by the controller serial (or name) of the liquid coolers.
+    pub liquid_temperatures: Vec<(String, f64)>,
+    /// RPM of each fan channel by the controller serial (or name).
+    pub fan_rpm: Vec<(String, Vec<u32>)>,
+    pub connected: Vec<(String, bool)>,
+}
+
+impl Properties {
+    fn values(&self) -> Vec<(&'static str, Value)> {
+        let dict = |signature: &str, entries: Vec<(String, Value)>| {
+            let entries = entries
+                .into_iter()
+                .map(|(key, value)| Value::DictEntry(Box::new(Value::Str(key)), Box::new(value)))
+                .collect();
+            Value::Array(signature.to_owned(), entries)
+        };
+        let temperatures = |temperatures: &[(String, f64)]| {
+            let entries = temperatures
+                .iter()
+                .map(|(key, celsius)| (key.clone(), Value::Double(*celsius)))
+                .collect();
+            dict("{sd}", entries)
+        };
+        let fan_rpm = self
+            .fan_rpm
+            .iter()
+            .map(|(key, rpm)| {
+                let rpm = rpm.iter().copied().map(Value::U32).collect();
+                (key.clone(), Value::Array("u".to_owned(), rpm))
+            })
+            .collect();
+        let connected = self
+            .connected
+            .iter()
+            .map(|(key, connected)| (key.clone(), Value::Bool(*connected)))
+            .collect();
+
+        vec![
+            ("Profile", Value::Str(self.profile.clone())),
+            ("Profiles", strings(&self.profiles)),
+            ("Temperatures", temperatures(&self.temperatures)),
+            (
+                "LiquidTemperatures",
+                temperatures(&self.liquid_temperatures),
+            ),
+            ("FanRpm", dict("{sau}", fan_rpm)),
+            ("Connected", dict("{sb}", connected)),
+        ]
+    }
+}
+
+/// Owns [`NAME`] on the bus and serves the object at [`PATH`].
+///
+/// Messages are read by a background thread, the method calls are answered
+/// by [`DbusServer::handle`] on the main thread.
+pub struct DbusServer {
+    writer: UnixStream,
+    serial: u32,
+    calls: mpsc::Receiver<Message>,
+    /// Method calls which were received but not handled yet.
+    pending: Arc<AtomicUsize>,
+    /// The last published properties.
+    properties: Vec<(&'static str, Value)>,
+}
+
+impl DbusServer {
+    pub fn connect(bus: Bus) -> Result<Self> {
+        Self::connect_address(&bus.address()?)
+    }
+
+    fn connect_address(address: &str) -> Result<Self> {
+        let mut stream = connect(address)?;
+        let mut serial = 0;
+        let mut call = |stream: &mut UnixStream, member: &str, body: Vec<Value>| {
+            serial += 1;
+            let message = Message::method_call(BUS_NAME, BUS_PATH, BUS_NAME, member, body);
+            let reply = call(stream, &message, serial)?;
+            Ok::<_, anyhow::Error>((serial, reply))
+        };
+        call(&mut stream, "Hello", Vec::new())?;
+        // Fails instead of waiting for the name, so that a second daemon exits.
+        const DO_NOT_QUEUE: u32 = 4;
+        let body = vec![Value::Str(NAME.to_owned()), Value::U32(DO_NOT_QUEUE)];
+        let (serial, reply) = call(&mut stream, "RequestName", body)?;
+        match reply.body.first() {
+            // The primary owner, or already the owner.
+            Some(Value::U32(1 | 4)) => {}
+            _ => anyhow::bail!("`{NAME}` is already owned by another process on the bus"),
+        }
+
+        let reader = stream.try_clone()?;
+        let (sender, calls) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let counter = pending.clone();
+        std::thread::spawn(move || {
+            let mut reader = reader;
+            loop {
+                match read_message(&mut reader) {
+                    Ok(message) if message.kind == MessageKind::MethodCall => {
+                        counter.fetch_add(1, Ordering::Relaxed);
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warning!("lost the D-Bus connection: {e:#}");
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            writer: stream,
+            serial,
+            calls,
+            pending,
+            properties: Properties::default().values(),
+        })
+    }
+
+    /// Whether there are method calls for [`DbusServer::handle`].
+    pub fn pending(&self) -> bool {
+        self.pending.load(Ordering::Relaxed) > 0
+    }
+
+    /// Answers all received method calls, the methods of the interface are
+    /// passed to `control` as the commands of the control socket.
+    pub fn handle(&mut self, mut control: impl FnMut(Command) -> Result<serde_json::Value>) {
+        while let Ok(call) = self.calls.try_recv() {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            let reply = match self.dispatch(&call, &mut control) {
+                Ok(body) => Message::method_return(&call, body),
+                Err(e) => Message::error(&call, &error_name(&e), format!("{e:#}")),
+            };
+            if call.flags & NO_REPLY_EXPECTED == 0 {
+                self.send(&reply);
+            }
+        }
+    }
+
+    /// Emits `PropertiesChanged` with the properties which have changed.
+    pub fn publish(&mut self, properties: &Properties) {
+        let values = properties.values();
+        let changed = values
+            .iter()
+            .zip(&self.properties)
+            .filter(|((_, new), (_, old))| new != old)
+            .map(|((name, value), _)| {
+                Value::DictEntry(
+                    Box::new(Value::Str((*name).to_owned())),
+                    Box::new(Value::Variant(Box::new(value.clone()))),
+                )
+            })
+            .collect::<Vec<_>>();
+        self.properties = values;
+        if changed.is_empty() {
+            return;
+        }
+        let body = vec![
+            Value::Str(INTERFACE.to_owned()),
+            Value::Array("{sv}".to_owned(), changed),
+            strings(&[]),
+        ];
+        self.send(&Message::signal(
+            PATH,
+            PROPERTIES,
+            "PropertiesChanged",
+            body,
+        ));
+    }
+
+    /// Failures are reported by the reading thread.
+    fn send(&mut self, message: &Message) {
+        self.serial += 1;
+        _ = self.writer.write_all(&message.encode(self.serial));
+    }
+
+    fn dispatch(
+        &self,
+        call: &Message,
+        control: &mut impl FnMut(Command) -> Result<serde_json::Value>,
+    ) -> Result<Vec<Value>> {
+        let path = call.path.as_deref().unwrap_or_default();
+        let member = call.member.as_deref().unwrap_or_default();
+        let interface = call.interface.as_deref();
+        match (interface, member) {
+            (Some(INTROSPECTABLE) | None, "Introspect") if path != PATH => {
+                return Ok(vec![Value::Str(parent_node(path)?)]);
+            }
+            (Some(PEER) | None, "Ping") => return Ok(Vec::new()),
+            (Some(PEER) | None, "GetMachineId") => {
+                let id = std::fs::read_to_string("/etc/machine-id")
+                    .context("failed to read /etc/machine-id")?;
+                return Ok(vec![Value::Str(id.trim().to_owned())]);
+            }
+            _ => {}
+        }
+        if path != PATH {
+            return Err(DbusError::new(
+                "org.freedesktop.DBus.Error.UnknownObject",
+                format!("no object at `{path}`"),
+            )
+            .into());
+        }
+
+        match (interface, member) {
+            (Some(INTROSPECTABLE) | None, "Introspect") => {
+                Ok(vec![Value::Str(INTROSPECTION.to_owned())])
+            }
+            (Some(PROPERTIES), "Get") => {
+                let [Value::Str(interface), Value::Str(name)] = args(call, "ss")? else {
+                    unreachable!("the signature is checked");
+                };
+                check_interface(interface)?;
+                let (_, value) = self
+                    .properties
+                    .iter()
+                    .find(|(other, _)| other == name)
+                    .ok_or_else(|| unknown_property(name))?;
+                Ok(vec![Value::Variant(Box::new(value.clone()))])
+            }
+            (Some(PROPERTIES), "GetAll") => {
+                let [Value::Str(interface)] = args(call, "s")? else {
+                    unreachable!("the signature is checked");
+                };
+                let entries = match interface.as_str() {
+                    INTERFACE | "" => self
+                        .properties
+                        .iter()
+                        .map(|(name, value)| {
+                            Value::DictEntry(
+                                Box::new(Value::Str((*name).to_owned())),
+                                Box::new(Value::Variant(Box::new(value.clone()))),
+                            )
+                        })
+                        .collect(),
+                    // The standard interfaces have no properties.
+                    _ => Vec::new(),
+                };
+                Ok(vec![Value::Array("{sv}".to_owned(), entries)])
+            }
+            (Some(PROPERTIES), "Set") => {
+                let [Value::Str(interface), Value::Str(name), _] = args(call, "ssv")? else {
+                    unreachable!("the signature is checked");
+                };
+                check_interface(interface)?;
+                if !self.properties.iter().any(|(other, _)| other == name) {
+                    return Err(unknown_property(name).into());
+                }
+                Err(DbusError::new(
+                    "org.freedesktop.DBus.Error.PropertyReadOnly",
+                    format!("property `{name}` is read-only"),
+                )
+                .into())
+            }
+            (Some(INTERFACE) | None, "SetColor") => {
+                let [Value::Str(serial), Value::Str(channels), Value::Str(color)] =
+                    args(call, "sss")?
+                else {
+                    unreachable!("the signature is checked");
+                };
+                control(Command::SetColor {
+                    color: color.parse::<Color>()?,
+                    channels: crate::parse_channels(channels)?,
+                    serial: non_empty(serial),
+                    product_id: None,
+                    duration_ms: None,
+                })?;
+                Ok(Vec::new())
+            }
+            (Some(INTERFACE) | None, "SetMode") => {
+                let [
+                    Value::Str(serial),
+                    Value::Str(channels),
+                    Value::Str(mode),
+                    Value::Array(_, colors),
+                    Value::Byte(speed),
+                ] = args(call, "sssasy")?
+                else {
+                    unreachable!("the signature is checked");
+                };
+                let colors = colors
+                    .iter()
+                    .map(|color| match color {
+                        Value::Str(color) => Ok(color.parse::<Color>()?),
+                        _ => unreachable!("the signature is checked"),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                control(Command::SetMode {
+                    mode: mode.parse::<LedMode>()?,
+                    colors,
+                    speed: Some(*speed),
+                    direction: Direction::default(),
+                    channels: crate::parse_channels(channels)?,
+                    serial: non_empty(serial),
+                    product_id: None,
+                    duration_ms: None,
+                })?;
+                Ok(Vec::new())
+            }
+            (Some(INTERFACE) | None, "Release") => {
+                let [Value::Str(serial), Value::Str(channels)] = args(call, "ss")? else {
+                    unreachable!("the signature is checked");
+                };
+                control(Command::Release {
+                    channels: crate::parse_channels(channels)?,
+                    serial: non_empty(serial),
+                    product_id: None,
+                })?;
+                Ok(Vec::new())
+            }
+            (Some(INTERFACE) | None, "ApplyProfile") => {
+                let [Value::Str(name)] = args(call, "s")? else {
+                    unreachable!("the signature is checked");
+                };
+                control(Command::Profile { name: name.clone() })?;
+                Ok(Vec::new())
+            }
+            _ => Err(DbusError::new(
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                format!(
+                    "unknown method `{member}` of interface `{}`",
+                    interface.unwrap_or_default()
+                ),
+            )
+            .into()),
+        }
+    }
+}
+
+/// Introspection of the parents of [`PATH`], so that the object can be found
+/// by walking the tree from `/`.
+fn parent_node(path: &str) -> Result<String> {
+    let prefix = match path {
+        "/" => "/",
+        _ => &format!("{path}/"),
+    };
+    let child = PATH
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.split('/').next())
+        .ok_or_else(|| {
+            DbusError::new(
+                "org.freedesktop.DBus.Error.UnknownObject",
+                format!("no object at `{path}`"),
+            )
+        })?;
+    Ok(format!(
+        "<!DOCTYPE node PUBLIC \"-//freedesktop//DTD D-BUS Object Introspection 1.0//EN\"\n \
+         \"http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd\">\n\
+         <node>\n  <node name=\"{child}\"/>\n</node>\n"
+    ))
+}
+
+fn check_interface(interface: &str) -> Result<(), DbusError> {
+    match interface {
+        INTERFACE | "" => Ok(()),
+        _ => Err(DbusError::new(
+            "org.freedesktop.DBus.Error.UnknownInterface",
+            format!("unknown interface `{interface}`"),
+        )),
+    }
+}
+
+fn unknown_property(name: &str) -> DbusError {
+    DbusError::new(
+        "org.freedesktop.DBus.Error.UnknownProperty",
+        format!("unknown property `{name}`"),
+    )
+}
+
+/// Arguments of the call with the expected signature.
+fn args<'a>(call: &'a Message, signature: &str) -> Result<&'a [Value], DbusError> {
+    let actual = call.signature();
+    if actual != signature {
+        return Err(DbusError::new(
+            "org.freedesktop.DBus.Error.InvalidArgs",
+            format!("expected arguments `{signature}`, got `{actual}`"),
+        ));
+    }
+    Ok(&call.body)
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    Some(s.to_owned()).filter(|s| !s.is_empty())
+}
+
+fn strings(strings: &[String]) -> Value {
+    let strings = strings.iter().cloned().map(Value::Str).collect();
+    Value::Array("s".to_owned(), strings)
+}
+
+/// Errors of the standard interfaces, other errors are named by their kind.
+#[derive(Debug)]
+struct DbusError {
+    name: &'static str,
+    message: String,
+}
+
+impl DbusError {
+    fn new(name: &'static str, message: String) -> Self {
+        Self { name, message }
+    }
+}
+
+impl fmt::Display for DbusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for DbusError {}
+
+/// E.g. `io.github.nzxtcli.Error.NoDevice` for `no-device`.
+fn error_name(error: &anyhow::Error) -> String {
+    if let Some(error) = error.downcast_ref::<DbusError>() {
+        return error.name.to_owned();
+    }
+    let kind = ErrorKind::of(error)
+        .name()
+        .split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect::<String>();
+    format!("{INTERFACE}.Error.{kind}")
+}
+
+/// Connects to the first supported address and authenticates as the current user.
+fn connect(address: &str) -> Result<UnixStream> {
+    let mut last_error = None;
+    for address in address.split(';').filter(|address| !address.is_empty()) {
+        let result = connect_to(address)
+            .with_context(|| format!("failed to connect to the D-Bus address `{address}`"));
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("no D-Bus address in `{address}`")))
+}
+
+fn connect_to(address: &str) -> Result<UnixStream> {
+    let (transport, params) = address
+        .split_once(':')
+        .context("expected `transport:key=value,...`")?;
+    anyhow::ensure!(transport == "unix", "unsupported transport `{transport}`");
+    let param = |key: &str| {
+        params
+            .split(',')
+            .filter_map(|param| param.split_once('='))
+            .find(|(other, _)| *other == key)
+            .map(|(_, value)| unescape(value))
+            .transpose()
+    };
+
+    let mut stream = if let Some(path) = param("path")? {
+        UnixStream::connect(String::from_utf8_lossy(&path).as_ref())?
+    } else if let Some(name) = param("abstract")? {
+        connect_abstract(&name)?
+    } else {
+        anyhow::bail!("expected a `path` or an `abstract` socket");
+    };
+
+    // SAFETY: Always succeeds.
+    let uid = unsafe { libc::getuid() };
+    let uid = uid
+        .to_string()
+        .bytes()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    stream.write_all(format!("\0AUTH EXTERNAL {uid}\r\n").as_bytes())?;
+    let reply = read_line(&mut stream)?;
+    anyhow::ensure!(
+        reply.starts_with("OK "),
+        "authentication failed: `{}`",
+        reply.trim()
+    );
+    stream.write_all(b"BEGIN\r\n")?;
+    Ok(stream)
+}
+
+#[cfg(target_os = "linux")]
+fn connect_abstract(name: &[u8]) -> Result<UnixStream> {
+    use std::os::linux::net::SocketAddrExt;
+
+    let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+    Ok(UnixStream::connect_addr(&address)?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn connect_abstract(_: &[u8]) -> Result<UnixStream> {
+    anyhow::bail!("abstract sockets are only supported on Linux");
+}
+
+/// Values of the addresses are percent-encoded.
+fn unescape(value: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte != b'%' {
+            bytes.push(byte);
+            rest = tail;
+            continue;
+        }
+        let hex = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .with_context(|| format!("invalid escape in `{value}`"))?;
+        bytes.push(hex);
+        rest = &tail[2..];
+    }
+    Ok(bytes)
+}
+
+/// Reads a line of the authentication, without reading past it.
+fn read_line(stream: &mut UnixStream) -> Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    while !line.ends_with(b"\r\n") {
+        anyhow::ensure!(line.len() < 512, "the authentication reply is too long");
+        stream.read_exact(&mut byte)?;
+        line.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Sends a method call and waits for its reply, other messages are dropped.
+fn call(stream: &mut UnixStream, message: &Message, serial: u32) -> Result<Message> {
+    stream.write_all(&message.encode(serial))?;
+    loop {
+        let reply = read_message(stream)?;
+        if reply.reply_serial != Some(serial) {
+            continue;
+        }
+        if reply.kind == MessageKind::Error {
+            let text = match reply.body.first() {
+                Some(Value::Str(text)) => text.as_str(),
+                _ => "",
+            };
+            anyhow::bail!(
+                "{} failed: {}: {text}",
+                message.member.as_deref().unwrap_or_default(),
+                reply.error_name.as_deref().unwrap_or_default()
+            );
+        }
+        return Ok(reply);
+    }
+}
+
+const NO_REPLY_EXPECTED: u8 = 0x1;
+
+/// The limit of the specification.
+const MAX_MESSAGE_LEN: usize = 1 << 27;
+
+/// Nesting limit of the values, arrays and structs count separately
+/// in the specification.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum MessageKind {
+    #[default]
+    MethodCall = 1,
+    MethodReturn = 2,
+    Error = 3,
+    Signal = 4,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Message {
+    kind: MessageKind,
+    flags: u8,
+    serial: u32,
+    path: Option<String>,
+    interface: Option<String>,
+    member: Option<String>,
+    error_name: Option<String>,
+    reply_serial: Option<u32>,
+    destination: Option<String>,
+    sender: Option<String>,
+    body: Vec<Value>,
+}
+
+impl Message {
+    fn method_call(
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        body: Vec<Value>,
+    ) -> Self {
+        Self {
+            kind: MessageKind::MethodCall,
+            destination: Some(destination.to_owned()),
+            path: Some(path.to_owned()),
+            interface: Some(interface.to_owned()),
+            member: Some(member.to_owned()),
+            body,
+            ..Self::default()
+        }
+    }
+
+    fn method_return(call: &Message, body: Vec<Value>) -> Self {
+        Self {
+            kind: MessageKind::MethodReturn,
+            flags: NO_REPLY_EXPECTED,
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            body,
+            ..Self::default()
+        }
+    }
+
+    fn error(call: &Message, name: &str, message: String) -> Self {
+        Self {
+            kind: MessageKind::Error,
+            flags: NO_REPLY_EXPECTED,
+            error_name: Some(name.to_owned()),
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            body: vec![Value::Str(message)],
+            ..Self::default()
+        }
+    }
+
+    fn signal(path: &str, interface: &str, member: &str, body: Vec<Value>) -> Self {
+        Self {
+            kind: MessageKind::Signal,
+            flags: NO_REPLY_EXPECTED,
+            path: Some(path.to_owned()),
+            interface: Some(interface.to_owned()),
+            member: Some(member.to_owned()),
+            body,
+            ..Self::default()
+        }
+    }
+
+    fn signature(&self) -> String {
+        self.body.iter().map(Value::signature).collect()
+    }
+
+    /// Little-endian, the serial is assigned by the sender.
+    fn encode(&self, serial: u32) -> Vec<u8> {
+        let mut body = Writer::default();
+        for value in &self.body {
+            body.write(value);
+        }
+
+        let mut fields = Vec::new();
+        let mut field = |code: u8, value: Value| {
+            fields.push(Value::Struct(vec![
+                Value::Byte(code),
+                Value::Variant(Box::new(value)),
+            ]));
+        };
+        let string = |value: &Option<String>| value.clone().map(Value::Str);
+        let header = [
+            (1, self.path.clone().map(Value::Path)),
+            (2, string(&self.interface)),
+            (3, string(&self.member)),
+            (4, string(&self.error_name)),
+            (5, self.reply_serial.map(Value::U32)),
+            (6, string(&self.destination)),
+            (7, string(&self.sender)),
+            (
+                8,
+                Some(self.signature())
+                    .filter(|signature| !signature.is_empty())
+                    .map(Value::Signature),
+            ),
+        ];
+        for (code, value) in header {
+            if let Some(value) = value {
+                field(code, value);
+            }
+        }
+
+        let mut message = Writer::default();
+        for byte in [b'l', self.kind as u8, self.flags, 1] {
+            message.write(&Value::Byte(byte));
+        }
+        message.write(&Value::U32(body.data.len() as u32));
+        message.write(&Value::U32(serial));
+        message.write(&Value::Array("(yv)".to_owned(), fields));
+        message.align(8);
+        message.data.extend(body.data);
+        message.data
+    }
+}
+
+fn read_message(reader: &mut impl Read) -> Result<Message> {
+    let mut data = vec![0; 16];
+    reader.read_exact(&mut data)?;
+    let big_endian = match data[0] {
+        b'l' => false,
+        b'B' => true,
+        other => anyhow::bail!("invalid endianness `{}`", other.escape_ascii()),
+    };
+    anyhow::ensure!(data[3] == 1, "unsupported protocol version {}", data[3]);
+    let u32_at = |offset: usize| {
+        let bytes = data[offset..offset + 4].try_into().unwrap();
+        match big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    };
+    let body_len = u32_at(4) as usize;
+    let serial = u32_at(8);
+    let header_len = (16 + u32_at(12) as usize).next_multiple_of(8);
+    anyhow::ensure!(
+        header_len + body_len <= MAX_MESSAGE_LEN,
+        "the message is too long"
+    );
+    data.resize(header_len + body_len, 0);
+    reader.read_exact(&mut data[16..])?;
+
+    let mut decoder = Reader {
+        data: &data,
+        pos: 12,
+        big_endian,
+    };
+    let kind = match data[1] {
+        1 => MessageKind::MethodCall,
+        2 => MessageKind::MethodReturn,
+        3 => MessageKind::Error,
+        4 => MessageKind::Signal,
+        other => anyhow::bail!("unknown message type {other}"),
+    };
+    let mut message = Message {
+        kind,
+        flags: data[2],
+        serial,
+        ..Message::default()
+    };
+    let Value::Array(_, fields) = decoder.read("a(yv)", 0)? else {
+        unreachable!("arrays are read as arrays");
+    };
+    let mut signature = String::new();
+    for field in fields {
+        let Value::Struct(field) = field else {
+            unreachable!("structs are read as structs");
+        };
+        let [Value::Byte(code), Value::Variant(value)] = &field[..] else {
+            unreachable!("the signature is `(yv)`");
+        };
+        match (code, *value.clone()) {
+            (1, Value::Path(path)) => message.path = Some(path),
+            (2, Value::Str(interface)) => message.interface = Some(interface),
+            (3, Value::Str(member)) => message.member = Some(member),
+            (4, Value::Str(name)) => message.error_name = Some(name),
+            (5, Value::U32(serial)) => message.reply_serial = Some(serial),
+            (6, Value::Str(destination)) => message.destination = Some(destination),
+            (7, Value::Str(sender)) => message.sender = Some(sender),
+            (8, Value::Signature(value)) => signature = value,
+            // Unknown fields must be ignored.
+            _ => {}
+        }
+    }
+
+    decoder.pos = header_len;
+    let mut rest = signature.as_str();
+    while !rest.is_empty() {
+        let (ty, tail) = split_type(rest)?;
+        message.body.push(decoder.read(ty, 0)?);
+        rest = tail;
+    }
+    Ok(message)
+}
+
+/// Value of the D-Bus type system, arrays have the signature of their elements.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Byte(u8),
+    Bool(bool),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    Double(f64),
+    Str(String),
+    Path(String),
+    Signature(String),
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+    Variant(Box<Value>),
+}
+
+impl Value {
+    fn signature(&self) -> String {
+        match self {
+            Self::Byte(_) => "y".to_owned(),
+            Self::Bool(_) => "b".to_owned(),
+            Self::I16(_) => "n".to_owned(),
+            Self::U16(_) => "q".to_owned(),
+            Self::I32(_) => "i".to_owned(),
+            Self::U32(_) => "u".to_owned(),
+            Self::I64(_) => "x".to_owned(),
+            Self::U64(_) => "t".to_owned(),
+            Self::Double(_) => "d".to_owned(),
+            Self::Str(_) => "s".to_owned(),
+            Self::Path(_) => "o".to_owned(),
+            Self::Signature(_) => "g".to_owned(),
+            Self::Array(element, _) => format!("a{element}"),
+            Self::Struct(fields) => {
+                format!(
+                    "({})",
+                    fields.iter().map(Self::signature).collect::<String>()
+                )
+            }
+            Self::DictEntry(key, value) => format!("{{{}{}}}", key.signature(), value.signature()),
+            Self::Variant(_) => "v".to_owned(),
+        }
+    }
+}
+
+/// Alignment of the values of the type.
+fn alignment(ty: &str) -> usize {
+    match ty.as_bytes().first() {
+        Some(b'n' | b'q') => 2,
+        Some(b'b' | b'i' | b'u' | b's' | b'o' | b'a' | b'h') => 4,
+        Some(b'x' | b't' | b'd' | b'(' | b'{') => 8,
+        _ => 1,
+    }
+}
+
+/// Splits off the first complete type of the signature.
+fn split_type(signature: &str) -> Result<(&str, &str)> {
+    let bytes = signature.as_bytes();
+    let len = match bytes.first() {
+        Some(b'a') => 1 + split_type(&signature[1..])?.0.len(),
+        Some(b'(' | b'{') => {
+            let mut depth = 0;
+            let end = bytes.iter().position(|&b| {
+                match b {
+                    b'(' | b'{' => depth += 1,
+                    b')' | b'}' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            });
+            end.with_context(|| format!("unbalanced signature `{signature}`"))? + 1
+        }
+        Some(b'y' | b'b' | b'n' | b'q' | b'i' | b'u' | b'x' | b't' | b'd' | b's' | b'o')
+        | Some(b'g' | b'v') => 1,
+        _ => anyhow::bail!("invalid signature `{signature}`"),
+    };
+    Ok(signature.split_at(len))
+}
+
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, alignment: usize) {
+        let len = self.data.len().next_multiple_of(alignment);
+        self.data.resize(len, 0);
+    }
+
+    fn write(&mut self, value: &Value) {
+        let ty = value.signature();
+        self.align(alignment(&ty));
+        match value {
+            Value::Byte(value) => self.data.push(*value),
+            Value::Bool(value) => self.data.extend((*value as u32).to_le_bytes()),
+            Value::I16(value) => self.data.extend(value.to_le_bytes()),
+            Value::U16(value) => self.data.extend(value.to_le_bytes()),
+            Value::I32(value) => self.data.extend(value.to_le_bytes()),
+            Value::U32(value) => self.data.extend(value.to_le_bytes()),
+            Value::I64(value) => self.data.extend(value.to_le_bytes()),
+            Value::U64(value) => self.data.extend(value.to_le_bytes()),
+            Value::Double(value) => self.data.extend(value.to_le_bytes()),
+            Value::Str(value) | Value::Path(value) => {
+                self.data.extend((value.len() as u32).to_le_bytes());
+                self.data.extend(value.as_bytes());
+                self.data.push(0);
+            }
+            Value::Signature(value) => {
+                self.data.push(value.len() as u8);
+                self.data.extend(value.as_bytes());
+                self.data.push(0);
+            }
+            Value::Array(element, values) => {
+                let len_pos = self.data.len();
+                self.data.extend([0; 4]);
+                // The padding before the first element is not counted.
+                self.align(alignment(element));
+                let start = self.data.len();
+                for value in values {
+                    self.write(value);
+                }
+                let len = (self.data.len() - start) as u32;
+                self.data[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                for field in fields {
+                    self.write(field);
+                }
+            }
+            Value::DictEntry(key, value) => {
+                self.write(key);
+                self.write(value);
+            }
+            Value::Variant(value) => {
+                self.write(&Value::Signature(value.signature()));
+                self.write(value);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self.bytes(N)?;
+        let mut array: [u8; N] = bytes.try_into().unwrap();
+        if self.big_endian {
+            array.reverse();
+        }
+        Ok(array)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .context("truncated message")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn string(&mut self, len: usize) -> Result<String> {
+        let bytes = self.bytes(len)?.to_vec();
+        anyhow::ensure!(self.bytes(1)? == [0], "string without a terminating NUL");
+        String::from_utf8(bytes).context("invalid UTF-8 in a string")
+    }
+
+    /// Reads a value of a single complete type.
+    fn read(&mut self, ty: &str, depth: usize) -> Result<Value> {
+        anyhow::ensure!(depth < MAX_DEPTH, "the values are nested too deeply");
+        self.pos = self.pos.next_multiple_of(alignment(ty));
+        Ok(match ty.as_bytes()[0] {
+            b'y' => Value::Byte(self.take::<1>()?[0]),
+            b'b' => match u32::from_le_bytes(self.take()?) {
+                value @ (0 | 1) => Value::Bool(value == 1),
+                value => anyhow::bail!("invalid boolean {value}"),
+            },
+            b'n' => Value::I16(i16::from_le_bytes(self.take()?)),
+            b'q' => Value::U16(u16::from_le_bytes(self.take()?)),
+            b'i' => Value::I32(i32::from_le_bytes(self.take()?)),
+            b'u' => Value::U32(u32::from_le_bytes(self.take()?)),
+            b'x' => Value::I64(i64::from_le_bytes(self.take()?)),
+            b't' => Value::U64(u64::from_le_bytes(self.take()?)),
+            b'd' => Value::Double(f64::from_le_bytes(self.take()?)),
+            b's' => {
+                let len = u32::from_le_bytes(self.take()?) as usize;
+                Value::Str(self.string(len)?)
+            }
+            b'o' => {
+                let len = u32::from_le_bytes(self.take()?) as usize;
+                Value::Path(self.string(len)?)
+            }
+            b'g' => {
+                let len = self.take::<1>()?[0] as usize;
+                Value::Signature(self.string(len)?)
+            }
+            b'a' => {
+                let len = u32::from_le_bytes(self.take()?) as usize;
+                let element = &ty[1..];
+                self.pos = self.pos.next_multiple_of(alignment(element));
+                let end = self
+                    .pos
+                    .checked_add(len)
+                    .filter(|&end| end <= self.data.len())
+                    .context("truncated array")?;
+                let mut values = Vec::new();
+                while self.pos < end {
+                    values.push(self.read(element, depth + 1)?);
+                }
+                anyhow::ensure!(self.pos == end, "array elements past its length");
+                Value::Array(element.to_owned(), values)
+            }
+            b'(' | b'{' => {
+                let mut rest = &ty[1..ty.len() - 1];
+                let mut fields = Vec::new();
+                while !rest.is_empty() {
+                    let (field, tail) = split_type(rest)?;
+                    fields.push(self.read(field, depth + 1)?);
+                    rest = tail;
+                }
+                match (ty.as_bytes()[0], <[Value; 2]>::try_from(fields)) {
+                    (b'{', Ok([key, value])) => Value::DictEntry(Box::new(key), Box::new(value)),
+                    (b'{', Err(_)) => anyhow::bail!("dict entry `{ty}` without a key and a value"),
+                    (_, Ok(fields)) => Value::Struct(fields.into()),
+                    (_, Err(fields)) => Value::Struct(fields),
+                }
+            }
+            b'v' => {
+                let len = self.take::<1>()?[0] as usize;
+                let signature = self.string(len)?;
+                let (inner, rest) = split_type(&signature)?;
+                anyhow::ensure!(rest.is_empty(), "variant of several types `{signature}`");
+                Value::Variant(Box::new(self.read(inner, depth + 1)?))
+            }
+            _ => anyhow::bail!("unsupported type `{ty}`"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufRead;
+    use std::process::{Child, Stdio};
+    use std::sync::atomic::AtomicBool;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn marshalling() {
+        // The padding after the length of an array of 8-byte values is not counted.
+        let mut writer = Writer::default();
+        writer.write(&Value::Array("t".to_owned(), vec![Value::U64(1)]));
+        assert_eq!(writer.data, [
+            8, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0
+        ]);
+
+        let body = vec![
+            Value::Str("A1".to_owned()),
+            Value::Byte(2),
+            Value::Array("{sv}".to_owned(), vec![Value::DictEntry(
+                Box::new(Value::Str("Profile".to_owned())),
+                Box::new(Value::Variant(Box::new(Value::Double(45.5)))),
+            )]),
+            Value::Struct(vec![
+                Value::Bool(true),
+                Value::I16(-1),
+                Value::Path("/".to_owned()),
+            ]),
+        ];
+        let message = Message::method_call(NAME, PATH, INTERFACE, "SetMode", body);
+        assert_eq!(message.signature(), "sya{sv}(bno)");
+        let data = message.encode(7);
+        let decoded = read_message(&mut data.as_slice()).unwrap();
+        assert_eq!(decoded, Message {
+            serial: 7,
+            ..message
+        });
+
+        assert_eq!(split_type("a{sv}as").unwrap(), ("a{sv}", "as"));
+        assert_eq!(split_type("(i(ss))u").unwrap(), ("(i(ss))", "u"));
+        assert!(split_type("(ii").is_err());
+        assert!(split_type("z").is_err());
+
+        // Truncated.
+        assert!(read_message(&mut &data[..data.len() - 1]).is_err());
+
+        assert_eq!(unescape("/tmp/a%20b").unwrap(), b"/tmp/a b");
+        assert!(unescape("%2").is_err());
+    }
+
+    struct BusDaemon(Child);
+
+    impl Drop for BusDaemon {
+        fn drop(&mut self) {
+            _ = self.0.kill();
+            _ = self.0.wait();
+        }
+    }
+
+    /// Starts a private bus, `None` if `dbus-daemon` is not installed.
+    fn private_bus() -> Option<(BusDaemon, String)> {
+        let child = std::process::Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => BusDaemon(child),
+            Err(e) => {
+                eprintln!("skipping the D-Bus test, failed to start dbus-daemon: {e}");
+                return None;
+            }
+        };
+        let mut address = String::new();
+        let stdout = child.0.stdout.take().unwrap();
+        std::io::BufReader::new(stdout)
+            .read_line(&mut address)
+            .unwrap();
+        Some((child, address.trim().to_owned()))
+    }
+
+    #[test]
+    fn dbus_interface() {
+        let Some((_bus, address)) = private_bus() else {
+            return;
+        };
+
+        let mut server = DbusServer::connect_address(&address).unwrap();
+        let Err(e) = DbusServer::connect_address(&address) else {
+            panic!("the name is owned twice");
+        };
+        assert!(e.to_string().contains("is already owned"));
+
+        let mut properties = Properties {
+            profile: "work".to_owned(),
+            profiles: vec!["work".to_owned(), "gaming".to_owned()],
+            temperatures: vec![("daemon.led[0]".to_owned(), 45.5)],
+            ..Properties::default()
+        };
+        server.publish(&properties);
+
+        // The main thread of the daemon.
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let handler = std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                if !server.pending() {
+                    std::thread::sleep(Duration::from_millis(5));
+                    continue;
+                }
+                let mut profile = None;
+                server.handle(|command| match command {
+                    Command::Profile { name } if name == "gaming" => {
+                        profile = Some(name);
+                        Ok(serde_json::Value::Null)
+                    }
+                    Command::Profile { .. } => anyhow::bail!("unknown profile"),
+                    Command::SetColor { serial: None, .. } => Ok(serde_json::Value::Null),
+                    _ => Err(nzxtcli::Error::NoMatchingDevice.into()),
+                });
+                if let Some(profile) = profile {
+                    properties.profile = profile;
+                    server.publish(&properties);
+                }
+            }
+        });
+
+        let mut client = connect(&address).unwrap();
+        let mut serial = 0;
+        let mut call = |destination: &str, interface: &str, member: &str, body: Vec<Value>| {
+            serial += 1;
+            let path = match destination {
+                BUS_NAME => BUS_PATH,
+                _ => PATH,
+            };
+            let message = Message::method_call(destination, path, interface, member, body);
+            super::call(&mut client, &message, serial)
+        };
+        call(BUS_NAME, BUS_NAME, "Hello", Vec::new()).unwrap();
+        let rule = format!("type='signal',interface='{PROPERTIES}',path='{PATH}'");
+        call(BUS_NAME, BUS_NAME, "AddMatch", vec![Value::Str(rule)]).unwrap();
+
+        let str = |s: &str| Value::Str(s.to_owned());
+        let reply = call(NAME, PROPERTIES, "Get", vec![
+            str(INTERFACE),
+            str("Profile"),
+        ])
+        .unwrap();
+        assert_eq!(reply.body, [Value::Variant(Box::new(str("work")))]);
+        let reply = call(NAME, PROPERTIES, "GetAll", vec![str(INTERFACE)]).unwrap();
+        let [Value::Array(_, entries)] = &reply.body[..] else {
+            panic!("unexpected reply {:?}", reply.body);
+        };
+        assert_eq!(entries.len(), 6);
+        assert!(entries.contains(&Value::DictEntry(
+            Box::new(str("Temperatures")),
+            Box::new(Value::Variant(Box::new(Value::Array(
+                "{sd}".to_owned(),
+                vec![Value::DictEntry(
+                    Box::new(str("daemon.led[0]")),
+                    Box::new(Value::Double(45.5))
+                )]
+            ))))
+        )));
+
+        let body = vec![str(""), str("0-1"), str("#ff0000")];
+        call(NAME, INTERFACE, "SetColor", body).unwrap();
+        let body = vec![str("A1"), str("all"), str("#ff0000")];
+        let e = call(NAME, INTERFACE, "SetColor", body).unwrap_err();
+        assert!(e.to_string().contains("io.github.nzxtcli.Error.NoDevice"));
+        let body = vec![str(""), str("0-1"), str("red-ish")];
+        let e = call(NAME, INTERFACE, "SetColor", body).unwrap_err();
+        assert!(e.to_string().contains("io.github.nzxtcli.Error.Usage"));
+        let e = call(NAME, INTERFACE, "SetColor", vec![str("")]).unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("org.freedesktop.DBus.Error.InvalidArgs")
+        );
+        let e = call(NAME, INTERFACE, "Reboot", Vec::new()).unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("org.freedesktop.DBus.Error.UnknownMethod")
+        );
+        let body = vec![
+            str(INTERFACE),
+            str("Profile"),
+            Value::Variant(Box::new(str("x"))),
+        ];
+        let e = call(NAME, PROPERTIES, "Set", body).unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("org.freedesktop.DBus.Error.PropertyReadOnly")
+        );
+
+        // Only the changed properties are sent.
+        call(NAME, INTERFACE, "ApplyProfile", vec![str("gaming")]).unwrap();
+        let signal = loop {
+            let message = read_message(&mut client).unwrap();
+            if message.member.as_deref() == Some("PropertiesChanged") {
+                break message;
+            }
+        };
+        assert_eq!(signal.body, [
+            str(INTERFACE),
+            Value::Array("{sv}".to_owned(), vec![Value::DictEntry(
+                Box::new(str("Profile")),
+                Box::new(Value::Variant(Box::new(str("gaming"))))
+            )]),
+            Value::Array("s".to_owned(), Vec::new()),
+        ]);
+
+        // Other clients see the interface too.
+        let busctl = std::process::Command::new("busctl")
+            .args(["--address", &address, "get-property", NAME, PATH, INTERFACE])
+            .arg("Profiles")
+            .output();
+        match busctl {
+            Ok(output) => {
+                assert!(output.status.success(), "{output:?}");
+                assert_eq!(
+                    String::from_utf8_lossy(&output.stdout),
+                    "as 2 \"work\" \"gaming\"\n"
+                );
+            }
+            Err(e) => eprintln!("skipping busctl: {e}"),
+        }
+        if let Ok(output) = std::process::Command::new("busctl")
+            .args(["--address", &address, "tree", NAME])
+            .output()
+        {
+            assert!(output.status.success(), "{output:?}");
+            assert!(String::from_utf8_lossy(&output.stdout).contains(PATH));
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        handler.join().unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+use std::fmt;
+
+use crate::types::Version;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Underlying HID error.
+    Hid(hidapi::HidError),
+    /// The device node exists but the current user is not allowed to open it.
+    PermissionDenied {
+        path: String,
+    },
+    /// Another program has opened the device exclusively.
+    DeviceBusy {
+        path: String,
+    },
+    /// No devices matched the selection filters.
+    NoMatchingDevice,
+    /// No supported controller has the requested serial number.
+    SerialNotFound {
+        serial: String,
+        seen: Vec<String>,
+    },
+    /// The device didn't send the expected report in time.
+    Timeout,
+    InvalidChannel {
+        requested: usize,
+        available: usize,
+    },
+    InvalidFanChannel {
+        requested: usize,
+        available: usize,
+    },
+    /// Argument is out of the range supported by the device.
+    InvalidArgument(String),
+    /// The operation is not supported by the device.
+    Unsupported(&'static str),
+    InvalidColor(String),
+    /// More colors than fit into a single direct packet.
+    TooManyColorsPerGroup {
+        colors: usize,
+        max: usize,
+    },
+    /// More LED groups than the direct packet type can address.
+    TooManyGroups {
+        groups: usize,
+        max: usize,
+    },
+    /// The firmware doesn't support the requested feature.
+    FirmwareTooOld {
+        found: Version,
+        required: Version,
+    },
+    /// Replayed session doesn't match the recording.
+    Replay(String),
+    /// The background thread of an async controller has stopped.
+    WorkerStopped,
+    /// The device accepted fewer bytes than the report length.
+    ShortWrite {
+        expected: usize,
+        written: usize,
+    },
+    /// The device returned a truncated report.
+    ShortRead {
+        expected: usize,
+        read: usize,
+    },
+    /// Replies can't be read since nothing is sent to the device.
+    DryRun,
+    /// A temperature source failed to produce a reading.
+    TempSource(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hid(e) => write!(f, "HID error: {e}"),
+            Self::PermissionDenied { path } => {
+                write!(
+                    f,
+                    "permission denied while opening {path} (check udev rules)"
+                )
+            }
+            Self::DeviceBusy { path } => write!(
+                f,
+                "device {path} is busy (another program such as NZXT CAM may have opened it \
+                 exclusively)"
+            ),
+            Self::NoMatchingDevice => f.write_str("no devices matched the selection filters"),
+            Self::SerialNotFound { serial, seen } if seen.is_empty() => {
+                write!(
+                    f,
+                    "no controller with serial `{serial}`, no controllers found"
+                )
+            }
+            Self::SerialNotFound { serial, seen } => write!(
+                f,
+                "no controller with serial `{serial}`, found: {}",
+                seen.join(", ")
+            ),
+            Self::Timeout => f.write_str("timed out waiting for the device response"),
+            Self::InvalidChannel {
+                requested,
+                available,
+            } => write!(
+                f,
+                "invalid channel {requested}, controller has {available} channels"
+            ),
+            Self::InvalidFanChannel {
+                requested,
+                available,
+            } => write!(
+                f,
+                "invalid fan channel {requested}, controller has {available} fan channels"
+            ),
+            Self::InvalidArgument(message) => f.write_str(message),
+            Self::Unsupported(feature) => write!(f, "{feature} is not supported by the device"),
+            Self::InvalidColor(message) => write!(f, "invalid color: {message}"),
+            Self::TooManyColorsPerGroup { colors, max } => {
+                write!(
+                    f,
+                    "too many colors per LED group: {colors}, at most {max} are supported"
+                )
+            }
+            Self::TooManyGroups { groups, max } => {
+                write!(
+                    f,
+                    "too many LED groups: {groups}, at most {max} are supported"
+                )
+            }
+            Self::FirmwareTooOld { found, required } => write!(
+                f,
+                "firmware {found} doesn't support direct LED control, {required} or newer \
+                 is required (update the firmware with NZXT CAM)"
+            ),
+            Self::Replay(message) => write!(f, "replay error: {message}"),
+            Self::WorkerStopped => f.write_str("controller worker thread has stopped"),
+            Self::ShortWrite { expected, written } => {
+                write!(f, "short write: {written} of {expected} bytes written")
+            }
+            Self::ShortRead { expected, read } => {
+                write!(f, "short read: {read} of {expected} bytes received")
+            }
+            Self::DryRun => f.write_str("the device can't be read during a dry run"),
+            Self::TempSource(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Hid(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Failure to open a supported device during discovery.
+#[derive(Debug)]
+pub struct DiscoveryError {
+    pub product_id: u16,
+    pub product_string: Option<String>,
+    pub path: String,
+    pub interface_number: i32,
+    pub error: Error,
+}
+
+impl DiscoveryError {
+    pub(crate) fn new(info: &hidapi::DeviceInfo, error: Error) -> Self {
+        Self {
+            product_id: info.product_id(),
+            product_string: info.product_string().map(str::to_owned),
+            path: info.path().to_string_lossy().into_owned(),
+            interface_number: info.interface_number(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for DiscoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to open device {:04x}", self.product_id)?;
+        if let Some(product) = &self.product_string {
+            write!(f, " ({product})")?;
+        }
+        write!(
+            f,
+            " at {} (interface {}): {}",
+            self.path, self.interface_number, self.error
+        )
+    }
+}
+
+impl std::error::Error for DiscoveryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<hidapi::HidError> for Error {
+    fn from(value: hidapi::HidError) -> Self {
+        Self::Hid(value)
+    }
+}
+
+/// Returns [`Error::InvalidArgument`] with the formatted message
+/// if the condition is false.
+macro_rules! ensure_arg {
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            return Err($crate::error::Error::InvalidArgument(format!($($arg)+)));
+        }
+    };
+}
+
+pub(crate) use ensure_arg;
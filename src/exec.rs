@@ -0,0 +1,152 @@
+//! Temperature from the output of a command (e.g. `nvidia-smi` for GPUs
+//! which aren't exposed under hwmon).
+
+use std::fmt;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use nzxtcli::{Error, TempSource};
+
+/// Runs a shell command on each read and parses the first number
+/// of its output as degrees celsius.
+pub struct ExecSource {
+    command: String,
+    timeout: Duration,
+}
+
+impl ExecSource {
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub fn new(command: String, timeout: Duration) -> Self {
+        Self { command, timeout }
+    }
+
+    fn run(&self) -> Result<String, String> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| format!("failed to run the command: {e}"))?;
+
+        // Read in the background, so that a long output can't block the command.
+        let mut stdout = child.stdout.take().unwrap();
+        let output = std::thread::spawn(move || {
+            let mut output = Vec::new();
+            stdout.read_to_end(&mut output).map(|_| output)
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() >= deadline => {
+                    _ = child.kill();
+                    _ = child.wait();
+                    return Err(format!(
+                        "the command timed out after {}",
+                        humantime::format_duration(self.timeout)
+                    ));
+                }
+                Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+                Err(e) => return Err(format!("failed to wait for the command: {e}")),
+            }
+        };
+        if !status.success() {
+            return Err(format!("the command failed with {status}"));
+        }
+
+        let output = output
+            .join()
+            .unwrap()
+            .map_err(|e| format!("failed to read the command output: {e}"))?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+}
+
+impl TempSource for ExecSource {
+    fn read(&mut self) -> nzxtcli::Result<i64> {
+        let output = self.run().map_err(Error::TempSource)?;
+        let celsius = first_number(&output).ok_or_else(|| {
+            Error::TempSource(format!("no temperature in the output `{}`", output.trim()))
+        })?;
+        Ok((celsius * 1000.0).round() as i64)
+    }
+}
+
+impl fmt::Display for ExecSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`", self.command)
+    }
+}
+
+/// Returns the first (possibly negative or fractional) number in the text.
+fn first_number(s: &str) -> Option<f64> {
+    let bytes = s.as_bytes();
+    let start = (0..bytes.len()).find(|&i| {
+        bytes[i].is_ascii_digit()
+            || (bytes[i] == b'-' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+    })?;
+
+    let mut end = start + 1;
+    let mut seen_dot = false;
+    while let Some(&c) = bytes.get(end) {
+        match c {
+            b'0'..=b'9' => {}
+            b'.' if !seen_dot && bytes.get(end + 1).is_some_and(u8::is_ascii_digit) => {
+                seen_dot = true;
+            }
+            _ => break,
+        }
+        end += 1;
+    }
+    s[start..end]
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(first_number("45\n"), Some(45.0));
+        assert_eq!(first_number("temp: 47.5 C"), Some(47.5));
+        assert_eq!(first_number("-3.25"), Some(-3.25));
+        assert_eq!(first_number("1. 2"), Some(1.0));
+        assert_eq!(first_number("a-b 12"), Some(12.0));
+        assert_eq!(first_number("N/A"), None);
+        assert_eq!(first_number(""), None);
+    }
+
+    #[test]
+    fn commands() {
+        let read = |command: &str| {
+            ExecSource::new(command.to_owned(), Duration::from_secs(5))
+                .read()
+                .map_err(|e| e.to_string())
+        };
+        assert_eq!(read("echo 45"), Ok(45_000));
+        assert_eq!(read("printf 'edge: +51.5°C\\n'"), Ok(51_500));
+        assert_eq!(
+            read("echo N/A"),
+            Err("no temperature in the output `N/A`".to_owned())
+        );
+        assert_eq!(
+            read("echo 45; exit 3"),
+            Err("the command failed with exit status: 3".to_owned())
+        );
+
+        let mut source = ExecSource::new("sleep 5".to_owned(), Duration::from_millis(50));
+        assert_eq!(
+            source.read().unwrap_err().to_string(),
+            "the command timed out after 50ms"
+        );
+        assert_eq!(source.to_string(), "`sleep 5`");
+    }
+}
@@ -0,0 +1,228 @@
+//! Human-readable output of `nzxtcli list`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use nzxtcli::{ControllerInfo, NzxtDeviceInfo};
+use serde::Serialize;
+
+/// NZXT interface which isn't used by any listed controller (`list --all`).
+#[derive(Serialize)]
+pub struct OtherDevice {
+    #[serde(flatten)]
+    pub device: NzxtDeviceInfo,
+    /// Why the supported device failed to open.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Columns aligned by the widest cell, the last one is not padded.
+pub struct Table {
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new<const N: usize>(header: [&str; N]) -> Self {
+        Self {
+            rows: vec![header.map(str::to_owned).to_vec()],
+        }
+    }
+
+    pub fn push<const N: usize>(&mut self, row: [String; N]) {
+        debug_assert_eq!(row.len(), self.rows[0].len());
+        self.rows.push(row.to_vec());
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut widths = vec![0; self.rows[0].len()];
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        for row in &self.rows {
+            let (last, cells) = row.split_last().unwrap();
+            for (cell, width) in cells.iter().zip(&widths) {
+                write!(f, "{cell:width$}  ")?;
+            }
+            writeln!(f, "{last}")?;
+        }
+        Ok(())
+    }
+}
+
+/// One row per controller (by the device index) followed by other devices.
+pub fn list_table(controllers: &[ControllerInfo], others: &[OtherDevice]) -> Table {
+    let mut table = Table::new([
+        "#",
+        "NAME",
+        "SERIAL",
+        "PID",
+        "CHANNELS",
+        "LEDS",
+        "ACCESSORIES",
+    ]);
+
+    for (index, info) in controllers.iter().enumerate() {
+        let mut channels = format!("{} rgb", info.rgb_channels.len());
+        if info.declared_fan_channels > 0 {
+            channels += &format!(", {} fan", info.declared_fan_channels);
+        }
+        let leds = info
+            .rgb_channels
+            .iter()
+            .map(|channel| channel.led_count)
+            .sum::<usize>();
+
+        table.push([
+            index.to_string(),
+            info.name.clone(),
+            info.serial.clone().unwrap_or_else(|| "-".to_owned()),
+            format!("{:04x}", info.product_id),
+            channels,
+            leds.to_string(),
+            accessories_summary(info),
+        ]);
+    }
+
+    for other in others {
+        let device = &other.device;
+        let status = match &other.error {
+            Some(error) => format!("error: {error}"),
+            None if !device.supported => "unsupported".to_owned(),
+            None => format!("unused interface {}", device.interface_number),
+        };
+        table.push([
+            "-".to_owned(),
+            device
+                .product_string
+                .clone()
+                .unwrap_or_else(|| "-".to_owned()),
+            device.serial.clone().unwrap_or_else(|| "-".to_owned()),
+            format!("{:04x}", device.product_id),
+            "-".to_owned(),
+            "-".to_owned(),
+            status,
+        ]);
+    }
+    table
+}
+
+/// Counts accessories of all channels by name (e.g. `2x F140 RGB fan (140mm)`).
+fn accessories_summary(info: &ControllerInfo) -> String {
+    let mut counts = BTreeMap::<&str, usize>::new();
+    for device in info
+        .rgb_channels
+        .iter()
+        .flat_map(|channel| &channel.devices)
+    {
+        if device.led_count > 0 {
+            *counts.entry(device.name).or_default() += 1;
+        }
+    }
+    if counts.is_empty() {
+        return "-".to_owned();
+    }
+
+    counts
+        .into_iter()
+        .map(|(name, count)| format!("{count}x {name}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use nzxtcli::{ChannelDeviceInfo, DeviceType, RgbChannel};
+
+    use super::*;
+
+    fn controller(serial: Option<&str>, fans: usize, accessories: &[DeviceType]) -> ControllerInfo {
+        let mut channel = RgbChannel::default();
+        for (slot, ty) in channel.devices.iter_mut().zip(accessories) {
+            *slot = ChannelDeviceInfo::new(*ty);
+            channel.led_count += slot.led_count as usize;
+        }
+        ControllerInfo {
+            vendor_id: nzxtcli::NZXT_VID,
+            product_id: if fans > 0 { 0x2011 } else { 0x2012 },
+            name: if fans > 0 {
+                "NZXT RGB & Fan Controller"
+            } else {
+                "NZXT RGB Controller"
+            }
+            .to_owned(),
+            serial: serial.map(str::to_owned),
+            path: None,
+            firmware: None,
+            declared_rgb_channels: 3,
+            probed_rgb_channels: 3,
+            declared_fan_channels: fans,
+            rgb_channels: vec![channel, RgbChannel::default(), RgbChannel::default()],
+        }
+    }
+
+    #[test]
+    fn table_alignment() {
+        let mut table = Table::new(["A", "LONG HEADER", "C"]);
+        table.push(["long cell".to_owned(), "b".to_owned(), "c".to_owned()]);
+        assert_eq!(
+            table.to_string(),
+            "A          LONG HEADER  C\n\
+             long cell  b            c\n"
+        );
+    }
+
+    #[test]
+    fn list_snapshot() {
+        let controllers = [
+            controller(Some("MA123"), 0, &[
+                DeviceType::F140RgbFan,
+                DeviceType::F140RgbFan,
+            ]),
+            controller(None, 3, &[]),
+        ];
+        let others = [
+            OtherDevice {
+                device: NzxtDeviceInfo {
+                    product_id: 0x2042,
+                    product_string: Some("NZXT Unknown".to_owned()),
+                    serial: None,
+                    path: "/dev/hidraw7".to_owned(),
+                    interface_number: 0,
+                    usage_page: 0xff00,
+                    usage: 1,
+                    supported: false,
+                },
+                error: None,
+            },
+            OtherDevice {
+                device: NzxtDeviceInfo {
+                    product_id: 0x2021,
+                    product_string: None,
+                    serial: Some("MA456".to_owned()),
+                    path: "/dev/hidraw8".to_owned(),
+                    interface_number: 0,
+                    usage_page: 0xff00,
+                    usage: 1,
+                    supported: true,
+                },
+                error: Some("permission denied".to_owned()),
+            },
+        ];
+
+        assert_eq!(
+            list_table(&controllers, &others).to_string(),
+            "\
+#  NAME                       SERIAL  PID   CHANNELS      LEDS  ACCESSORIES
+0  NZXT RGB Controller        MA123   2012  3 rgb         36    2x F140 RGB fan (140mm)
+1  NZXT RGB & Fan Controller  -       2011  3 rgb, 3 fan  0     -
+-  NZXT Unknown               -       2042  -             -     unsupported
+-  -                          MA456   2021  -             -     error: permission denied
+"
+        );
+    }
+}
@@ -0,0 +1,543 @@
+//! Temperature sensors of the `hwmon` sysfs class.
+//!
+//! The numbering of `hwmon*` directories depends on the driver load order,
+//! so sensors are looked up by the chip name and the temperature label
+//! (e.g. `k10temp:Tctl`) instead.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use nzxtcli::TempSource;
+
+pub const HWMON_ROOT: &str = "/sys/class/hwmon";
+
+/// Chip name with an optional temperature label (e.g. `k10temp:Tctl`).
+///
+/// Without the label the first temperature of the chip is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SensorSpec {
+    pub chip: String,
+    pub label: Option<String>,
+}
+
+impl SensorSpec {
+    /// Names are compared case-insensitively.
+    fn matches(&self, sensor: &Sensor) -> bool {
+        sensor.chip.eq_ignore_ascii_case(&self.chip)
+            && self
+                .label
+                .as_ref()
+                .is_none_or(|label| sensor.label.eq_ignore_ascii_case(label))
+    }
+}
+
+impl FromStr for SensorSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (chip, label) = match s.split_once(':') {
+            Some((chip, label)) => (chip.trim(), Some(label.trim())),
+            None => (s.trim(), None),
+        };
+        anyhow::ensure!(
+            !chip.is_empty(),
+            "empty chip name, expected e.g. `k10temp:Tctl`"
+        );
+        anyhow::ensure!(
+            label.is_none_or(|label| !label.is_empty()),
+            "empty temperature label, expected e.g. `k10temp:Tctl`"
+        );
+
+        Ok(Self {
+            chip: chip.to_owned(),
+            label: label.map(str::to_owned),
+        })
+    }
+}
+
+impl fmt::Display for SensorSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.chip)?;
+        if let Some(label) = &self.label {
+            write!(f, ":{label}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Temperature input of a hwmon chip.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sensor {
+    pub chip: String,
+    /// Contents of `temp*_label`, or the input name (e.g. `temp1`) if there is none.
+    pub label: String,
+    /// Path of the `temp*_input` file.
+    pub path: PathBuf,
+    /// `None` if the input couldn't be read.
+    pub celsius: Option<f64>,
+}
+
+impl Sensor {
+    /// Spec which selects this sensor.
+    pub fn spec(&self) -> SensorSpec {
+        SensorSpec {
+            chip: self.chip.clone(),
+            label: Some(self.label.clone()),
+        }
+    }
+}
+
+/// Returns temperature inputs of all chips in the hwmon class directory,
+/// ordered by the chip and input numbers.
+pub fn scan(root: &Path) -> Result<Vec<Sensor>> {
+    let entries =
+        std::fs::read_dir(root).with_context(|| format!("failed to read {}", root.display()))?;
+
+    let mut chips = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let index = name.to_str()?.strip_prefix("hwmon")?.parse::<u32>().ok()?;
+            Some((index, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    chips.sort_unstable();
+
+    let mut sensors = Vec::new();
+    for (_, dir) in chips {
+        // Chips without a name can't be selected.
+        let Ok(chip) = std::fs::read_to_string(dir.join("name")) else {
+            continue;
+        };
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        let mut inputs = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                let index = name
+                    .to_str()?
+                    .strip_prefix("temp")?
+                    .strip_suffix("_input")?
+                    .parse::<u32>()
+                    .ok()?;
+                Some((index, entry.path()))
+            })
+            .collect::<Vec<_>>();
+        inputs.sort_unstable();
+
+        for (index, path) in inputs {
+            let label = std::fs::read_to_string(dir.join(format!("temp{index}_label")))
+                .map(|label| label.trim().to_owned())
+                .unwrap_or_else(|_| format!("temp{index}"));
+            let celsius = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|value| value.trim().parse::<i64>().ok())
+                .map(|millidegrees| millidegrees as f64 / 1000.0);
+
+            sensors.push(Sensor {
+                chip: chip.trim().to_owned(),
+                label,
+                path,
+                celsius,
+            });
+        }
+    }
+    Ok(sensors)
+}
+
+/// Returns the `temp*_input` path of the sensor.
+pub fn resolve(root: &Path, spec: &SensorSpec) -> Result<PathBuf> {
+    let sensors = scan(root)?;
+    let mut found = sensors
+        .iter()
+        .filter(|sensor| spec.matches(sensor))
+        .collect::<Vec<_>>();
+    // Only the first input of each chip is used without the label.
+    if spec.label.is_none() {
+        found.dedup_by(|a, b| a.path.parent() == b.path.parent());
+    }
+
+    let mut found = found.into_iter();
+    match (found.next(), found.next()) {
+        (Some(sensor), None) => Ok(sensor.path.clone()),
+        (Some(first), Some(second)) => anyhow::bail!(
+            "hwmon sensor `{spec}` is ambiguous, it matches {} and {}",
+            first.path.display(),
+            second.path.display()
+        ),
+        (None, _) if sensors.is_empty() => anyhow::bail!(
+            "hwmon sensor `{spec}` not found, {} has no temperature sensors",
+            root.display()
+        ),
+        (None, _) => {
+            let names = sensors
+                .iter()
+                .map(|sensor| sensor.spec().to_string())
+                .collect::<Vec<_>>();
+            anyhow::bail!(
+                "hwmon sensor `{spec}` not found in {}, found: {}",
+                root.display(),
+                names.join(", ")
+            )
+        }
+    }
+}
+
+/// Where the hwmon temperature is read from.
+#[derive(Debug, Clone)]
+pub enum HwmonInput {
+    Path(PathBuf),
+    Sensor(SensorSpec),
+}
+
+impl fmt::Display for HwmonInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => path.display().fmt(f),
+            Self::Sensor(spec) => spec.fmt(f),
+        }
+    }
+}
+
+/// How readings of multiple sensors are combined.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Aggregate {
+    /// The hottest sensor.
+    #[default]
+    Max,
+    /// Mean of all sensors.
+    Avg,
+    /// The coldest sensor.
+    Min,
+}
+
+impl Aggregate {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Max => "max",
+            Self::Avg => "avg",
+            Self::Min => "min",
+        }
+    }
+
+    /// Returns `None` if there are no values.
+    pub fn apply(self, values: impl IntoIterator<Item = i64>) -> Option<i64> {
+        let mut values = values.into_iter();
+        let first = values.next()?;
+        Some(match self {
+            Self::Max => values.fold(first, i64::max),
+            Self::Min => values.fold(first, i64::min),
+            Self::Avg => {
+                let (sum, count) =
+                    values.fold((first, 1), |(sum, count), value| (sum + value, count + 1));
+                sum / count
+            }
+        })
+    }
+}
+
+/// Exponential moving average of readings with the given time constant.
+///
+/// A step of the temperature is followed by ~63% after `time_constant`.
+#[derive(Debug, Clone)]
+pub struct Smoother {
+    time_constant: Duration,
+    value: Option<f64>,
+}
+
+impl Smoother {
+    pub fn new(time_constant: Duration) -> Self {
+        Self {
+            time_constant,
+            value: None,
+        }
+    }
+
+    /// Adds a reading taken `elapsed` after the previous one, returns the average.
+    ///
+    /// The first reading is returned as is.
+    pub fn update(&mut self, millidegrees: i64, elapsed: Duration) -> i64 {
+        let reading = millidegrees as f64;
+        let value = match self.value {
+            Some(value) if !self.time_constant.is_zero() => {
+                let alpha = 1.0 - (-elapsed.as_secs_f64() / self.time_constant.as_secs_f64()).exp();
+                value + alpha * (reading - value)
+            }
+            _ => reading,
+        };
+        self.value = Some(value);
+        value.round() as i64
+    }
+}
+
+/// Keeps the shown temperature until the value moves by more than the threshold.
+#[derive(Debug, Clone)]
+pub struct Hysteresis {
+    threshold: i64,
+    shown: Option<i64>,
+}
+
+impl Hysteresis {
+    /// The threshold is in millidegrees.
+    pub fn new(threshold: i64) -> Self {
+        Self {
+            threshold,
+            shown: None,
+        }
+    }
+
+    /// Returns the temperature to show for the current value.
+    pub fn update(&mut self, millidegrees: i64) -> i64 {
+        match self.shown {
+            Some(shown) if (millidegrees - shown).abs() <= self.threshold => shown,
+            _ => *self.shown.insert(millidegrees),
+        }
+    }
+}
+
+/// Reads the temperature, reopening the file after failures.
+///
+/// Sensors are resolved again on reopening, since the input can move
+/// to a different `hwmon*` directory when the driver is reloaded.
+pub struct TempReader {
+    input: HwmonInput,
+    root: PathBuf,
+    file: Option<File>,
+    buffer: Vec<u8>,
+}
+
+impl TempReader {
+    /// Opens the source immediately, so that misconfiguration is reported early.
+    pub fn open(input: HwmonInput, root: impl Into<PathBuf>) -> Result<Self> {
+        let mut reader = Self {
+            input,
+            root: root.into(),
+            file: None,
+            buffer: Vec::new(),
+        };
+        reader.file = Some(reader.reopen()?);
+        Ok(reader)
+    }
+
+    fn reopen(&self) -> Result<File> {
+        let path = match &self.input {
+            HwmonInput::Path(path) => path.clone(),
+            HwmonInput::Sensor(spec) => resolve(&self.root, spec)?,
+        };
+        File::open(&path).with_context(|| format!("failed to open `hwmon` file {}", path.display()))
+    }
+
+    fn read_input(&mut self) -> Result<i64> {
+        if self.file.is_none() {
+            self.file = Some(self.reopen()?);
+        }
+        let file = self.file.as_mut().unwrap();
+
+        self.buffer.clear();
+        let result = file
+            .seek(std::io::SeekFrom::Start(0))
+            .and_then(|_| file.read_to_end(&mut self.buffer));
+        if let Err(e) = result {
+            self.file = None;
+            return Err(e).context("failed to read the temperature");
+        }
+
+        let value = std::str::from_utf8(&self.buffer)?.trim();
+        value
+            .parse::<i64>()
+            .with_context(|| format!("invalid temperature `{value}`"))
+    }
+}
+
+impl TempSource for TempReader {
+    fn read(&mut self) -> nzxtcli::Result<i64> {
+        self.read_input()
+            .map_err(|e| nzxtcli::Error::TempSource(format!("{e:#}")))
+    }
+}
+
+impl fmt::Display for TempReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.input.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Optional label and the value in millidegrees.
+    type Input<'a> = (Option<&'a str>, i64);
+
+    /// Creates a fake hwmon class directory with the named chips.
+    fn fake_hwmon(name: &str, chips: &[(&str, &[Input<'_>])]) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("nzxtcli-hwmon-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for (index, (chip, inputs)) in chips.iter().enumerate() {
+            let dir = root.join(format!("hwmon{}", index * 5));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("name"), format!("{chip}\n")).unwrap();
+            for (input, (label, value)) in inputs.iter().enumerate() {
+                let input = input + 1;
+                std::fs::write(dir.join(format!("temp{input}_input")), format!("{value}\n"))
+                    .unwrap();
+                if let Some(label) = label {
+                    std::fs::write(dir.join(format!("temp{input}_label")), label).unwrap();
+                }
+            }
+        }
+        root
+    }
+
+    #[test]
+    fn sensor_specs() {
+        let spec = "k10temp:Tctl".parse::<SensorSpec>().unwrap();
+        assert_eq!(spec.chip, "k10temp");
+        assert_eq!(spec.label.as_deref(), Some("Tctl"));
+        assert_eq!(spec.to_string(), "k10temp:Tctl");
+
+        let spec = "coretemp:Package id 0".parse::<SensorSpec>().unwrap();
+        assert_eq!(spec.label.as_deref(), Some("Package id 0"));
+        assert_eq!("nvme".parse::<SensorSpec>().unwrap().label, None);
+
+        assert!(":Tctl".parse::<SensorSpec>().is_err());
+        assert!("k10temp:".parse::<SensorSpec>().is_err());
+    }
+
+    #[test]
+    fn aggregate_readings() {
+        let values = [45_000, 38_000, 52_000];
+        assert_eq!(Aggregate::Max.apply(values), Some(52_000));
+        assert_eq!(Aggregate::Min.apply(values), Some(38_000));
+        assert_eq!(Aggregate::Avg.apply(values), Some(45_000));
+        assert_eq!(Aggregate::Avg.apply([41_000]), Some(41_000));
+        assert_eq!(Aggregate::Max.apply([]), None);
+    }
+
+    #[test]
+    fn smooth_readings() {
+        let second = Duration::from_secs(1);
+
+        // A step is followed exponentially.
+        let mut smoother = Smoother::new(second);
+        assert_eq!(smoother.update(40_000, second), 40_000);
+        let steps = (0..4)
+            .map(|_| smoother.update(50_000, second))
+            .collect::<Vec<_>>();
+        assert_eq!(steps, [46_321, 48_647, 49_502, 49_817]);
+
+        // A spike is damped and decays.
+        let mut smoother = Smoother::new(Duration::from_secs(10));
+        smoother.update(40_000, second);
+        assert_eq!(smoother.update(90_000, second), 44_758);
+        assert_eq!(smoother.update(40_000, second), 44_305);
+
+        // Longer intervals between readings give more weight to the last one.
+        let mut smoother = Smoother::new(second);
+        smoother.update(40_000, second);
+        assert_eq!(smoother.update(50_000, Duration::from_secs(5)), 49_933);
+
+        let mut smoother = Smoother::new(Duration::ZERO);
+        smoother.update(40_000, second);
+        assert_eq!(smoother.update(50_000, second), 50_000);
+    }
+
+    #[test]
+    fn hysteresis() {
+        let mut hysteresis = Hysteresis::new(2_000);
+        let shown = [
+            45_000, 46_000, 47_000, 47_500, 44_000, 43_000, 41_999, 43_500,
+        ]
+        .map(|value| hysteresis.update(value));
+        assert_eq!(shown, [
+            45_000, 45_000, 45_000, 47_500, 44_000, 44_000, 41_999, 41_999
+        ]);
+
+        let mut hysteresis = Hysteresis::new(0);
+        assert_eq!([40_000, 40_001].map(|value| hysteresis.update(value)), [
+            40_000, 40_001
+        ]);
+    }
+
+    #[test]
+    fn resolve_sensors() {
+        let root = fake_hwmon("resolve", &[
+            ("nvme", &[(Some("Composite"), 38_850)]),
+            ("k10temp", &[
+                (Some("Tctl"), 45_125),
+                (Some("Tccd1"), 40_000),
+            ]),
+            ("acpitz", &[(None, 16_800)]),
+            ("acpitz", &[(None, 27_800)]),
+        ]);
+
+        let sensors = scan(&root).unwrap();
+        let names = sensors
+            .iter()
+            .map(|sensor| sensor.spec().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [
+            "nvme:Composite",
+            "k10temp:Tctl",
+            "k10temp:Tccd1",
+            "acpitz:temp1",
+            "acpitz:temp1"
+        ]);
+        assert_eq!(sensors[1].celsius, Some(45.125));
+
+        let resolve = |spec: &str| resolve(&root, &spec.parse().unwrap());
+        assert_eq!(
+            resolve("K10TEMP:tctl").unwrap(),
+            root.join("hwmon5/temp1_input")
+        );
+        assert_eq!(resolve("nvme").unwrap(), root.join("hwmon0/temp1_input"));
+        assert!(
+            resolve("acpitz:temp1")
+                .unwrap_err()
+                .to_string()
+                .contains("is ambiguous, it matches")
+        );
+
+        let error = resolve("coretemp:Package id 0").unwrap_err().to_string();
+        assert!(
+            error.contains("`coretemp:Package id 0` not found"),
+            "{error}"
+        );
+        assert!(
+            error.contains("found: nvme:Composite, k10temp:Tctl"),
+            "{error}"
+        );
+        assert_eq!(resolve("k10temp").unwrap(), root.join("hwmon5/temp1_input"));
+
+        let mut reader =
+            TempReader::open(HwmonInput::Sensor("k10temp:Tccd1".parse().unwrap()), &root).unwrap();
+        assert_eq!(reader.read().unwrap(), 40_000);
+
+        // The driver is reloaded as a different hwmon device.
+        std::fs::rename(root.join("hwmon5"), root.join("hwmon7")).unwrap();
+        reader.file = None;
+        assert_eq!(reader.read().unwrap(), 40_000);
+
+        // Suspend: the sensor is gone for a while.
+        std::fs::rename(root.join("hwmon7"), root.join("hwmon-gone")).unwrap();
+        reader.file = None;
+        let error = reader.read().unwrap_err().to_string();
+        assert!(error.contains("`k10temp:Tccd1` not found"), "{error}");
+        std::fs::rename(root.join("hwmon-gone"), root.join("hwmon9")).unwrap();
+        assert_eq!(reader.read().unwrap(), 40_000);
+        assert_eq!(reader.to_string(), "k10temp:Tccd1");
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let error = scan(&root).unwrap_err();
+        assert!(error.to_string().contains("failed to read"));
+    }
+}
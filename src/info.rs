@@ -0,0 +1,461 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::controller::{ChannelDeviceInfo, Controller, HUE_2_NUM_CHANNELS, RgbChannel};
+use crate::types::{DeviceType, Version};
+
+/// Summary of the controller and its accessories (as printed by `nzxtcli list`).
+///
+/// Contains only owned data, so it can be cached and compared with
+/// later snapshots.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ControllerInfo {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub name: String,
+    #[serde(default)]
+    pub serial: Option<String>,
+    /// HID path of the opened interface.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Only known if the firmware was queried before the snapshot.
+    #[serde(default, rename = "firmware_version")]
+    pub firmware: Option<Version>,
+    /// Channel counts are missing in the output of older versions.
+    #[serde(default)]
+    pub declared_rgb_channels: usize,
+    #[serde(default)]
+    pub probed_rgb_channels: usize,
+    #[serde(default)]
+    pub declared_fan_channels: usize,
+    #[serde(deserialize_with = "indexed_channels::deserialize")]
+    pub rgb_channels: Vec<RgbChannel>,
+}
+
+impl ControllerInfo {
+    pub fn new<C: Controller + ?Sized>(controller: &C) -> Self {
+        Self {
+            vendor_id: controller
+                .info()
+                .map_or(crate::NZXT_VID, |info| info.vendor_id()),
+            product_id: controller.product_id(),
+            name: controller.name().to_owned(),
+            serial: controller.serial().map(str::to_owned),
+            path: controller
+                .info()
+                .map(|info| info.path().to_string_lossy().into_owned()),
+            firmware: controller.cached_firmware_version(),
+            declared_rgb_channels: controller.declared_rgb_channels(),
+            probed_rgb_channels: controller.probed_rgb_channels(),
+            declared_fan_channels: controller.declared_fan_channels(),
+            rgb_channels: controller.rgb_channels().to_vec(),
+        }
+    }
+}
+
+impl Serialize for ControllerInfo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Repr<'a> {
+            vendor_id: u16,
+            vendor_id_hex: String,
+            product_id: u16,
+            product_id_hex: String,
+            name: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            serial: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            path: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            firmware_version: Option<Version>,
+            declared_rgb_channels: usize,
+            probed_rgb_channels: usize,
+            declared_fan_channels: usize,
+            #[serde(serialize_with = "indexed_channels::serialize")]
+            rgb_channels: &'a [RgbChannel],
+        }
+
+        Repr {
+            vendor_id: self.vendor_id,
+            vendor_id_hex: format!("{:04x}", self.vendor_id),
+            product_id: self.product_id,
+            product_id_hex: format!("{:04x}", self.product_id),
+            name: &self.name,
+            serial: self.serial.as_deref(),
+            path: self.path.as_deref(),
+            firmware_version: self.firmware,
+            declared_rgb_channels: self.declared_rgb_channels,
+            probed_rgb_channels: self.probed_rgb_channels,
+            declared_fan_channels: self.declared_fan_channels,
+            rgb_channels: &self.rgb_channels,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// HID interface with the NZXT vendor id (as printed by `nzxtcli list --all`).
+///
+/// Unlike [`ControllerInfo`] it's created without opening the device,
+/// so it's available for unknown devices as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NzxtDeviceInfo {
+    pub product_id: u16,
+    pub product_string: Option<String>,
+    pub serial: Option<String>,
+    pub path: String,
+    pub interface_number: i32,
+    pub usage_page: u16,
+    pub usage: u16,
+    /// Whether the interface is opened by [`crate::find_controllers_with`].
+    pub supported: bool,
+}
+
+impl NzxtDeviceInfo {
+    pub(crate) fn new(info: &hidapi::DeviceInfo, supported: bool) -> Self {
+        Self {
+            product_id: info.product_id(),
+            product_string: info.product_string().map(str::to_owned),
+            serial: info.serial_number().map(str::to_owned),
+            path: info.path().to_string_lossy().into_owned(),
+            interface_number: info.interface_number(),
+            usage_page: info.usage_page(),
+            usage: info.usage(),
+            supported,
+        }
+    }
+}
+
+impl Serialize for NzxtDeviceInfo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Repr<'a> {
+            vendor_id: u16,
+            vendor_id_hex: String,
+            product_id: u16,
+            product_id_hex: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            product_string: Option<&'a str>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            serial: Option<&'a str>,
+            path: &'a str,
+            interface_number: i32,
+            usage_page_hex: String,
+            usage_hex: String,
+            supported: bool,
+        }
+
+        Repr {
+            vendor_id: crate::NZXT_VID,
+            vendor_id_hex: format!("{:04x}", crate::NZXT_VID),
+            product_id: self.product_id,
+            product_id_hex: format!("{:04x}", self.product_id),
+            product_string: self.product_string.as_deref(),
+            serial: self.serial.as_deref(),
+            path: &self.path,
+            interface_number: self.interface_number,
+            usage_page_hex: format!("{:04x}", self.usage_page),
+            usage_hex: format!("{:04x}", self.usage),
+            supported: self.supported,
+        }
+        .serialize(serializer)
+    }
+}
+
+mod indexed_channels {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct IndexedChannel {
+        id: usize,
+        #[serde(flatten)]
+        channel: RgbChannel,
+    }
+
+    pub fn serialize<S: Serializer>(
+        channels: &&[RgbChannel],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            channels
+                .iter()
+                .enumerate()
+                .map(|(id, channel)| IndexedChannel {
+                    id,
+                    channel: *channel,
+                }),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<RgbChannel>, D::Error> {
+        let mut entries = Vec::<IndexedChannel>::deserialize(deserializer)?;
+        entries.sort_by_key(|entry| entry.id);
+        Ok(entries.into_iter().map(|entry| entry.channel).collect())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RgbChannelRepr {
+    led_count: usize,
+    devices: Vec<IndexedDevice>,
+    /// Set if accessories have more LEDs than the channel can address.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    excess_leds: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexedDevice {
+    id: usize,
+    #[serde(skip_deserializing)]
+    id_hex: String,
+    #[serde(flatten)]
+    device: ChannelDeviceInfo,
+}
+
+/// Only non-empty accessory slots are serialized.
+impl Serialize for RgbChannel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let devices = self
+            .devices
+            .iter()
+            .enumerate()
+            .filter(|(_, device)| device.led_count > 0)
+            .map(|(id, device)| IndexedDevice {
+                id,
+                id_hex: format!("{id:02x}"),
+                device: *device,
+            })
+            .collect();
+
+        RgbChannelRepr {
+            led_count: self.led_count,
+            devices,
+            truncated: self.truncated,
+            excess_leds: self.truncated.then(|| self.excess_leds()),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RgbChannel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = RgbChannelRepr::deserialize(deserializer)?;
+
+        let mut channel = RgbChannel {
+            led_count: repr.led_count,
+            truncated: repr.truncated,
+            ..Default::default()
+        };
+        for entry in repr.devices {
+            let Some(slot) = channel.devices.get_mut(entry.id) else {
+                return Err(serde::de::Error::custom(format_args!(
+                    "invalid device slot {}, channel has {HUE_2_NUM_CHANNELS} slots",
+                    entry.id
+                )));
+            };
+            *slot = entry.device;
+        }
+        Ok(channel)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChannelDeviceRepr {
+    name: String,
+    #[serde(rename = "type")]
+    device_type: String,
+    led_count: u8,
+    #[serde(default)]
+    user_defined: bool,
+    #[serde(default)]
+    accessory_id: Option<u8>,
+}
+
+impl Serialize for ChannelDeviceInfo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ChannelDeviceRepr {
+            name: self.name.to_owned(),
+            device_type: self.device_type.type_name().to_owned(),
+            led_count: self.led_count,
+            user_defined: self.user_defined,
+            accessory_id: Some(self.id),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Names are restored from the device type since they are static strings.
+impl<'de> Deserialize<'de> for ChannelDeviceInfo {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ChannelDeviceRepr::deserialize(deserializer)?;
+
+        let device_type = match repr.accessory_id {
+            Some(id) => DeviceType::from(id),
+            None => (0..=u8::MAX)
+                .map(DeviceType::from)
+                .find(|ty| ty.type_name() == repr.device_type)
+                .unwrap_or_default(),
+        };
+
+        Ok(Self {
+            id: device_type.id(),
+            device_type,
+            name: if repr.user_defined {
+                "<user-defined>"
+            } else {
+                device_type.name()
+            },
+            led_count: repr.led_count,
+            user_defined: repr.user_defined,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_json_round_trip() {
+        let mut channel = RgbChannel {
+            led_count: 26,
+            ..Default::default()
+        };
+        channel.devices[0] = ChannelDeviceInfo::new(DeviceType::F140RgbFan);
+        channel.devices[1] = ChannelDeviceInfo {
+            led_count: 8,
+            name: "<user-defined>",
+            user_defined: true,
+            ..ChannelDeviceInfo::new(DeviceType::Unknown(0x1f))
+        };
+
+        let json = serde_json::to_value(channel).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "led_count": 26,
+                "devices": [
+                    {
+                        "id": 0,
+                        "id_hex": "00",
+                        "name": "F140 RGB fan (140mm)",
+                        "type": "f140-rgb-fan",
+                        "led_count": 18,
+                        "user_defined": false,
+                        "accessory_id": DeviceType::F140RgbFan.id(),
+                    },
+                    {
+                        "id": 1,
+                        "id_hex": "01",
+                        "name": "<user-defined>",
+                        "type": "unknown",
+                        "led_count": 8,
+                        "user_defined": true,
+                        "accessory_id": 0x1f,
+                    }
+                ]
+            })
+        );
+
+        let parsed: RgbChannel = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.led_count, 26);
+        assert_eq!(parsed.devices[0].device_type, DeviceType::F140RgbFan);
+        assert_eq!(parsed.devices[1].device_type, DeviceType::Unknown(0x1f));
+        assert!(parsed.devices[1].user_defined);
+        assert_eq!(parsed.devices[2].led_count, 0);
+        assert!(!parsed.truncated);
+    }
+
+    #[test]
+    fn truncated_channel_json() {
+        let mut channel = RgbChannel {
+            led_count: 40,
+            truncated: true,
+            ..Default::default()
+        };
+        channel.devices[..3].fill(ChannelDeviceInfo::new(DeviceType::F120RgbFan));
+
+        let json = serde_json::to_value(channel).unwrap();
+        assert_eq!(json["truncated"], true);
+        assert_eq!(json["excess_leds"], 14);
+
+        let parsed: RgbChannel = serde_json::from_value(json).unwrap();
+        assert!(parsed.truncated);
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut channel = RgbChannel {
+            led_count: 18,
+            ..Default::default()
+        };
+        channel.devices[0] = ChannelDeviceInfo::new(DeviceType::F140RgbFan);
+        let info = ControllerInfo {
+            vendor_id: crate::NZXT_VID,
+            product_id: 0x2012,
+            name: "NZXT RGB Controller".to_owned(),
+            serial: Some("123".to_owned()),
+            path: Some("/dev/hidraw1".to_owned()),
+            firmware: Some(Version::new(1, 10, 2)),
+            declared_rgb_channels: 3,
+            probed_rgb_channels: 3,
+            declared_fan_channels: 0,
+            rgb_channels: vec![channel, RgbChannel::default(), RgbChannel::default()],
+        };
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["path"], "/dev/hidraw1");
+        assert_eq!(json["product_id_hex"], "2012");
+        assert_eq!(json["firmware_version"], "1.10.2");
+
+        let parsed: ControllerInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, info);
+
+        // Not queried before the snapshot.
+        let info = ControllerInfo {
+            firmware: None,
+            ..info
+        };
+        let json = serde_json::to_value(&info).unwrap();
+        assert!(json.get("firmware_version").is_none());
+        assert_eq!(
+            serde_json::from_value::<ControllerInfo>(json).unwrap(),
+            info
+        );
+    }
+
+    #[test]
+    fn nzxt_device_json() {
+        let info = NzxtDeviceInfo {
+            product_id: 0x2042,
+            product_string: Some("NZXT RGB Controller".to_owned()),
+            serial: None,
+            path: "/dev/hidraw4".to_owned(),
+            interface_number: 0,
+            usage_page: 0xff00,
+            usage: 1,
+            supported: false,
+        };
+
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["product_id_hex"], "2042");
+        assert_eq!(json["usage_page_hex"], "ff00");
+        assert_eq!(json["supported"], false);
+        assert!(json.get("serial").is_none());
+    }
+
+    #[test]
+    fn legacy_device_json() {
+        // Output of older versions has neither `accessory_id` nor `user_defined`.
+        let device: ChannelDeviceInfo = serde_json::from_value(serde_json::json!({
+            "name": "F140 RGB fan (140mm)",
+            "type": "f140-rgb-fan",
+            "led_count": 18,
+        }))
+        .unwrap();
+        assert_eq!(device.device_type, DeviceType::F140RgbFan);
+        assert_eq!(device.name, DeviceType::F140RgbFan.name());
+    }
+}
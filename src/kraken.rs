@@ -0,0 +1,347 @@
+use std::borrow::Cow;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use hidapi::HidApi;
+
+use crate::controller::{
+    AioStatus, ChannelDeviceInfo, Controller, MIN_PUMP_DUTY, ReapplyTimer, RgbChannel, open_device,
+    reopen_device, write_report,
+};
+use crate::error::{Error, Result};
+use crate::transport::{HidTransport, timeout_ms};
+use crate::types::Color;
+
+/// NZXT Kraken X52/X62/X72 liquid coolers.
+///
+/// LEDs are split into two channels: the logo (1 LED) and the ring (8 LEDs).
+pub struct NZXTKrakenX2<'a> {
+    device: Box<dyn HidTransport>,
+    info: Option<Cow<'a, hidapi::DeviceInfo>>,
+    rgb_channels: Vec<RgbChannel>,
+    /// Last color of each channel, restored on reconnect.
+    colors: Mutex<[Option<Color>; 2]>,
+    reapply: Mutex<ReapplyTimer>,
+    connected: AtomicBool,
+}
+
+impl<'a> NZXTKrakenX2<'a> {
+    pub const PRODUCT_ID: u16 = 0x170E;
+    pub const NAME: &'static str = "NZXT Kraken X2 Series";
+
+    pub fn new(api: &hidapi::HidApi, info: &'a hidapi::DeviceInfo) -> Result<Self> {
+        // The reports start with their report id, so they are written as is.
+        let device = Box::new(open_device(api, info)?);
+        Ok(Self::from_parts(device, Some(Cow::Borrowed(info))))
+    }
+
+    /// Creates a controller which talks to the device through the transport.
+    pub fn with_transport(transport: Box<dyn HidTransport>) -> NZXTKrakenX2<'static> {
+        NZXTKrakenX2::from_parts(transport, None)
+    }
+
+    fn from_parts(
+        device: Box<dyn HidTransport>,
+        info: Option<Cow<'a, hidapi::DeviceInfo>>,
+    ) -> Self {
+        let rgb_channels = [(LOGO_LEDS, "Kraken X2 logo"), (RING_LEDS, "Kraken X2 ring")]
+            .into_iter()
+            .map(|(led_count, name)| {
+                let mut channel = RgbChannel {
+                    led_count: led_count as usize,
+                    ..Default::default()
+                };
+                channel.devices[0] = ChannelDeviceInfo {
+                    name,
+                    led_count,
+                    ..Default::default()
+                };
+                channel
+            })
+            .collect();
+
+        Self {
+            device,
+            info,
+            rgb_channels,
+            colors: Mutex::default(),
+            reapply: Mutex::default(),
+            connected: AtomicBool::new(true),
+        }
+    }
+
+    /// Detaches the controller from the borrowed device info.
+    pub fn into_owned(self) -> NZXTKrakenX2<'static> {
+        NZXTKrakenX2 {
+            device: self.device,
+            info: self.info.map(|info| Cow::Owned(info.into_owned())),
+            rgb_channels: self.rgb_channels,
+            colors: self.colors,
+            reapply: self.reapply,
+            connected: self.connected,
+        }
+    }
+
+    /// Reads liquid temperature, fan and pump speed from the status report.
+    pub fn status(&self) -> Result<KrakenX2Status> {
+        let deadline = Instant::now() + STATUS_TIMEOUT;
+        let mut buffer = [0u8; 64];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout);
+            }
+
+            let len = self
+                .device
+                .read_timeout(&mut buffer, timeout_ms(remaining))?;
+            if len >= STATUS_LEN && buffer[0] == 0x04 {
+                return Ok(parse_status(&buffer));
+            }
+        }
+    }
+
+    fn restore_colors(&self) -> Result<()> {
+        let colors = *self.colors.lock().unwrap();
+        for (channel, color) in colors.into_iter().enumerate() {
+            if let Some(color) = color {
+                self.set_channel_color(channel, color)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A failed write marks the controller as disconnected.
+    fn write(&self, packet: &[u8]) -> Result<()> {
+        let result = write_report(self.device.as_ref(), packet);
+        self.connected.store(result.is_ok(), Ordering::Relaxed);
+        result
+    }
+}
+
+impl Controller for NZXTKrakenX2<'_> {
+    fn info(&self) -> Option<&hidapi::DeviceInfo> {
+        self.info.as_deref()
+    }
+
+    fn product_id(&self) -> u16 {
+        Self::PRODUCT_ID
+    }
+
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn rgb_channels(&self) -> &[RgbChannel] {
+        &self.rgb_channels
+    }
+
+    fn set_channel_color(&self, channel: usize, color: Color) -> Result<()> {
+        let packet = match channel {
+            LOGO_CHANNEL => color_packet(CHANNEL_ID_LOGO, color, Color::BLACK),
+            RING_CHANNEL => color_packet(CHANNEL_ID_RING, Color::BLACK, color),
+            _ => {
+                return Err(Error::InvalidChannel {
+                    requested: channel,
+                    available: self.rgb_channels.len(),
+                });
+            }
+        };
+
+        self.write(&packet)?;
+        self.colors.lock().unwrap()[channel] = Some(color);
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// The status is sent by the device on its own, so nothing is written.
+    fn ping(&self) -> Result<()> {
+        let result = self.status().map(|_| ());
+        self.connected.store(result.is_ok(), Ordering::Relaxed);
+        result
+    }
+
+    /// Opens the device by its serial number and restores the last colors.
+    fn reconnect(&mut self, api: &HidApi) -> Result<()> {
+        let Some(info) = &self.info else {
+            return Err(Error::Unsupported("reconnection"));
+        };
+        self.device = Box::new(reopen_device(api, info)?);
+        self.connected.store(true, Ordering::Relaxed);
+        self.restore_colors()
+    }
+
+    /// Resends the last colors every 10 seconds, since a reset of the
+    /// firmware can't be detected.
+    fn ensure_applied(&self) -> Result<()> {
+        match self.reapply.lock().unwrap().due() {
+            true => self.restore_colors(),
+            false => Ok(()),
+        }
+    }
+
+    fn is_liquid_cooler(&self) -> bool {
+        true
+    }
+
+    fn aio_status(&self) -> Result<AioStatus> {
+        let status = self.status()?;
+        Ok(AioStatus {
+            liquid_temp: status.liquid_temp,
+            pump_rpm: status.pump_rpm,
+            pump_duty: None,
+        })
+    }
+
+    fn set_pump_duty(&self, percent: u8) -> Result<()> {
+        let percent = percent.clamp(MIN_PUMP_DUTY, 100);
+        self.write(&pump_duty_packet(percent))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KrakenX2Status {
+    /// Liquid temperature in degrees celsius.
+    pub liquid_temp: f32,
+    pub fan_rpm: u16,
+    pub pump_rpm: u16,
+}
+
+fn parse_status(report: &[u8]) -> KrakenX2Status {
+    KrakenX2Status {
+        liquid_temp: report[1] as f32 + report[2] as f32 / 10.0,
+        fan_rpm: u16::from_be_bytes([report[3], report[4]]),
+        pump_rpm: u16::from_be_bytes([report[5], report[6]]),
+    }
+}
+
+/// Unlike Hue 2, all LEDs are sent in a single report. The logo color
+/// uses the GRB order while the ring colors use the RGB order.
+fn color_packet(channel_id: u8, logo: Color, ring: Color) -> [u8; 65] {
+    let mut buffer = [0u8; 65];
+    buffer[0x00] = 0x02;
+    buffer[0x01] = 0x4c;
+    buffer[0x02] = channel_id;
+    buffer[0x03] = MODE_FIXED;
+    buffer[0x04] = SPEED_NORMAL;
+    buffer[0x05..0x08].copy_from_slice(logo.inner());
+    for chunk in buffer[0x08..0x08 + RING_LEDS as usize * 3].chunks_exact_mut(3) {
+        chunk.copy_from_slice(&[ring.red(), ring.green(), ring.blue()]);
+    }
+    buffer
+}
+
+fn pump_duty_packet(percent: u8) -> [u8; 65] {
+    let mut buffer = [0u8; 65];
+    buffer[..5].copy_from_slice(&[0x02, 0x4d, 0x40, 0x00, percent]);
+    buffer
+}
+
+const STATUS_LEN: usize = 17;
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+const LOGO_CHANNEL: usize = 0;
+const RING_CHANNEL: usize = 1;
+const LOGO_LEDS: u8 = 1;
+const RING_LEDS: u8 = 8;
+
+const CHANNEL_ID_LOGO: u8 = 0x01;
+const CHANNEL_ID_RING: u8 = 0x02;
+
+const MODE_FIXED: u8 = 0x00;
+const SPEED_NORMAL: u8 = 0x02;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+
+    #[test]
+    fn status_report() {
+        let mut report = [0u8; STATUS_LEN];
+        report[..7].copy_from_slice(&[0x04, 31, 5, 0x03, 0x20, 0x0a, 0x8c]);
+
+        let status = parse_status(&report);
+        assert_eq!(status.liquid_temp, 31.5);
+        assert_eq!(status.fan_rpm, 800);
+        assert_eq!(status.pump_rpm, 2700);
+    }
+
+    #[test]
+    fn color_packets() {
+        let color = Color::new(0x11, 0x22, 0x33);
+
+        let packet = color_packet(CHANNEL_ID_LOGO, color, Color::BLACK);
+        assert_eq!(packet[..8], [
+            0x02, 0x4c, 0x01, 0x00, 0x02, 0x22, 0x11, 0x33
+        ]);
+        assert!(packet[8..].iter().all(|&b| b == 0));
+
+        let packet = color_packet(CHANNEL_ID_RING, Color::BLACK, color);
+        assert_eq!(packet[..8], [
+            0x02, 0x4c, 0x02, 0x00, 0x02, 0x00, 0x00, 0x00
+        ]);
+        assert!(packet[8..32].chunks(3).all(|c| c == [0x11, 0x22, 0x33]));
+        assert!(packet[32..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn transport_ping() {
+        let transport = MockTransport::new();
+        let controller = NZXTKrakenX2::with_transport(Box::new(transport.clone()));
+        let mut report = [0u8; STATUS_LEN];
+        report[0] = 0x04;
+        transport.push_response([0x02, 0x4c]);
+        transport.push_response(report);
+
+        controller.ping().unwrap();
+        assert!(transport.written().is_empty());
+    }
+
+    #[test]
+    fn transport_reapply() {
+        let transport = MockTransport::new();
+        let controller = NZXTKrakenX2::with_transport(Box::new(transport.clone()));
+        controller
+            .set_channel_color(LOGO_CHANNEL, Color::RED)
+            .unwrap();
+        controller.ensure_applied().unwrap();
+        assert_eq!(transport.written().len(), 1);
+
+        *controller.reapply.lock().unwrap() = ReapplyTimer::new(Some(Duration::ZERO));
+        controller.ensure_applied().unwrap();
+        let written = transport.written();
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[1], written[0]);
+    }
+
+    #[test]
+    fn transport_short_write() {
+        let transport = MockTransport::new();
+        let controller = NZXTKrakenX2::with_transport(Box::new(transport.clone()));
+        controller
+            .set_channel_color(RING_CHANNEL, Color::RED)
+            .unwrap();
+        assert_eq!(transport.written().len(), 1);
+
+        transport.set_write_limit(Some(32));
+        assert!(matches!(
+            controller.set_channel_color(LOGO_CHANNEL, Color::RED),
+            Err(Error::ShortWrite {
+                expected: 65,
+                written: 32
+            })
+        ));
+        assert!(matches!(
+            controller.set_pump_duty(60),
+            Err(Error::ShortWrite { .. })
+        ));
+        assert!(!controller.is_connected());
+        // Only the written color is restored on reconnect.
+        assert_eq!(*controller.colors.lock().unwrap(), [None, Some(Color::RED)]);
+    }
+}
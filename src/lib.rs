@@ -1,12 +1,44 @@
-use std::sync::{Mutex, OnceLock};
+use std::sync::{Arc, OnceLock};
 
 use hidapi::HidApi;
 
-pub use self::controller::{ChannelDeviceInfo, LedMode, NZXTHue2Controller, RgbChannel};
-pub use self::types::Color;
+#[cfg(feature = "async")]
+pub use self::async_controller::{AsyncController, find_controllers_async};
+pub use self::controller::{
+    AccessoryOverrides, AioStatus, ChannelDeviceInfo, ColorOrders, Controller, ControllerOptions,
+    Direction, FanStatus, FanType, Frame, LedMode, MAX_BRIGHTNESS, MAX_CHANNEL_LEDS,
+    MAX_COLORS_PER_PACKET, MAX_EFFECT_COLORS, MAX_EFFECT_SPEED, MIN_PUMP_DUTY, NZXTHue2Controller,
+    RgbChannel,
+};
+pub use self::error::{DiscoveryError, Error, Result};
+pub use self::info::{ControllerInfo, NzxtDeviceInfo};
+pub use self::kraken::{KrakenX2Status, NZXTKrakenX2};
+pub use self::registry::ControllerRegistry;
+pub use self::smart_device::NZXTSmartDeviceV1;
+pub use self::temp::TempSource;
+use self::transport::{HidRecorder, PacketPrinter};
+pub use self::types::{
+    AccessoryInfo, ChannelSelector, Color, ColorOrder, ColorRamp, ColorTransform, DeviceType,
+    FanCurve, Interpolation, Layout, LedPosition, Version, known_accessories,
+};
+pub use self::watch::{
+    ControllerEvent, ControllerWatcher, watch_controllers, watch_controllers_with,
+};
 
+#[cfg(feature = "async")]
+mod async_controller;
+pub mod control;
 mod controller;
+mod error;
+mod info;
+mod kraken;
+pub mod logging;
+mod registry;
+mod smart_device;
+mod temp;
+pub mod transport;
 mod types;
+mod watch;
 
 pub const NZXT_VID: u16 = 0x1E71;
 
@@ -17,28 +49,383 @@ pub fn version_string() -> &'static str {
     STRING.get_or_init(|| format!("(release {BIN_VERSION})"))
 }
 
-pub fn find_controllers<'a>(api: &'a HidApi) -> Vec<NZXTHue2Controller<'a>> {
-    let known = NZXTHue2Controller::known_controllers();
-    let result = Mutex::new(Vec::new());
+pub fn find_controllers<'a>(api: &'a HidApi) -> Vec<Box<dyn Controller + 'a>> {
+    find_controllers_with_overrides(api, &AccessoryOverrides::new())
+}
 
-    std::thread::scope(|scope| {
-        for device in api.device_list() {
-            if device.vendor_id() != NZXT_VID {
-                continue;
-            }
+pub fn find_controllers_with_overrides<'a>(
+    api: &'a HidApi,
+    overrides: &AccessoryOverrides,
+) -> Vec<Box<dyn Controller + 'a>> {
+    find_controllers_owned_with_overrides(api, overrides)
+}
 
-            scope.spawn(|| {
-                if let Some(&(name, rgb_channels, fan_channels)) = known.get(&device.product_id()) {
-                    match NZXTHue2Controller::new(api, device, name, rgb_channels, fan_channels) {
-                        Ok(entry) => result.lock().unwrap().push(entry),
-                        Err(e) => {
-                            eprintln!("failed to create controller: {e:?}");
-                        }
-                    }
-                }
-            });
-        }
+/// Same as [`find_controllers`] but returned controllers don't borrow the `api`.
+pub fn find_controllers_owned(api: &HidApi) -> Vec<Box<dyn Controller>> {
+    find_controllers_owned_with_overrides(api, &AccessoryOverrides::new())
+}
+
+pub fn find_controllers_owned_with_overrides(
+    api: &HidApi,
+    overrides: &AccessoryOverrides,
+) -> Vec<Box<dyn Controller>> {
+    let options = FindOptions::new().accessory_overrides(overrides.clone());
+    let (controllers, errors) = find_controllers_verbose(api, &options);
+    print_discovery_errors(errors);
+    controllers
+}
+
+/// Returns controllers matching the specified filters.
+///
+/// Fails with [`Error::NoMatchingDevice`] if a filter was provided
+/// but no devices matched it.
+pub fn find_controllers_with(
+    api: &HidApi,
+    options: &FindOptions,
+) -> Result<Vec<Box<dyn Controller>>> {
+    let (controllers, errors) = find_controllers_verbose(api, options);
+    print_discovery_errors(errors);
+
+    if controllers.is_empty() && options.has_filters() {
+        return Err(Error::NoMatchingDevice);
+    }
+    Ok(controllers)
+}
+
+/// Returns all found controllers along with errors for supported devices
+/// which failed to open.
+///
+/// Controllers are returned in the enumeration order of the `api`. Devices
+/// which enumerate on multiple interfaces are returned once, preferring the
+/// interface which answers the accessory query.
+pub fn find_controllers_verbose(
+    api: &HidApi,
+    options: &FindOptions,
+) -> (Vec<Box<dyn Controller>>, Vec<DiscoveryError>) {
+    let devices = api
+        .device_list()
+        .filter(|device| is_supported(device, options))
+        .collect::<Vec<_>>();
+
+    let devices = physical_devices(devices)
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| options.index.is_none_or(|n| n == *index))
+        .map(|(_, interfaces)| interfaces)
+        .collect::<Vec<_>>();
+
+    let results = std::thread::scope(|scope| {
+        let handles = devices
+            .iter()
+            .map(|interfaces| {
+                interfaces
+                    .iter()
+                    .map(|&device| scope.spawn(|| open_controller(api, device, options)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handles| {
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
     });
 
-    result.into_inner().unwrap()
+    let mut controllers = Vec::new();
+    let mut errors = Vec::new();
+    for interfaces in results {
+        match pick_interface(interfaces) {
+            Ok(controller) => controllers.push(controller),
+            Err(e) => errors.extend(e),
+        }
+    }
+    (controllers, errors)
+}
+
+/// Returns all HID interfaces with the NZXT vendor id, including the ones
+/// of unknown devices, without opening them.
+///
+/// Interfaces are filtered by the `options` except for the index.
+pub fn enumerate_nzxt_devices(api: &HidApi, options: &FindOptions) -> Vec<NzxtDeviceInfo> {
+    api.device_list()
+        .filter(|device| device.vendor_id() == NZXT_VID && options.matches(device))
+        .map(|device| NzxtDeviceInfo::new(device, is_supported(device, options)))
+        .collect()
+}
+
+/// Supported controllers which don't speak the Hue 2 protocol.
+const OTHER_PRODUCTS: [(u16, &str); 2] = [
+    (NZXTSmartDeviceV1::PRODUCT_ID, NZXTSmartDeviceV1::NAME),
+    (NZXTKrakenX2::PRODUCT_ID, NZXTKrakenX2::NAME),
+];
+
+/// Returns product ids and names of all devices which are opened with the
+/// `registry`, sorted by product id.
+pub fn supported_products(registry: &ControllerRegistry) -> Vec<(u16, &'static str)> {
+    let mut products = registry
+        .iter()
+        .map(|(product_id, &(name, ..))| (product_id, name))
+        .chain(OTHER_PRODUCTS)
+        .collect::<Vec<_>>();
+    products.sort_unstable();
+    products
+}
+
+fn is_supported(device: &hidapi::DeviceInfo, options: &FindOptions) -> bool {
+    device.vendor_id() == NZXT_VID
+        && (options.registry.contains(device.product_id())
+            || OTHER_PRODUCTS
+                .iter()
+                .any(|&(product_id, _)| product_id == device.product_id()))
+        && is_vendor_interface(device)
+        && options.matches(device)
+}
+
+fn open_controller(
+    api: &HidApi,
+    device: &hidapi::DeviceInfo,
+    options: &FindOptions,
+) -> Result<Box<dyn Controller>, DiscoveryError> {
+    let known = &options.registry;
+    let controller: Result<Box<dyn Controller>> = match device.product_id() {
+        NZXTSmartDeviceV1::PRODUCT_ID | NZXTKrakenX2::PRODUCT_ID
+            if options.controller.is_dry_run() =>
+        {
+            Err(Error::Unsupported("dry run"))
+        }
+        NZXTSmartDeviceV1::PRODUCT_ID => {
+            NZXTSmartDeviceV1::new(api, device).map(|c| Box::new(c.into_owned()) as _)
+        }
+        NZXTKrakenX2::PRODUCT_ID => {
+            NZXTKrakenX2::new(api, device).map(|c| Box::new(c.into_owned()) as _)
+        }
+        product_id => {
+            let brief = known.get(product_id).unwrap();
+            NZXTHue2Controller::new_with(api, device, brief, &options.controller)
+                .map(|c| Box::new(c.into_owned()) as _)
+        }
+    };
+    controller.map_err(|e| DiscoveryError::new(device, e))
+}
+
+/// Controllers are accessed through the vendor-defined usage page.
+///
+/// Some backends don't report usages, such interfaces are kept.
+fn is_vendor_interface(device: &hidapi::DeviceInfo) -> bool {
+    device.usage_page() == 0 || device.usage_page() >= 0xff00
+}
+
+/// Groups interfaces of the same controller by the serial number.
+fn physical_devices(devices: Vec<&hidapi::DeviceInfo>) -> Vec<Vec<&hidapi::DeviceInfo>> {
+    group_by_serial(devices, |device| {
+        let serial = device.serial_number().filter(|s| !s.is_empty())?;
+        Some((device.product_id(), serial.to_ascii_lowercase()))
+    })
+}
+
+/// Groups interfaces of the same physical device, keeping the order
+/// of first appearance. Items without a key are never grouped.
+fn group_by_serial<T, K, F>(items: Vec<T>, key: F) -> Vec<Vec<T>>
+where
+    K: PartialEq,
+    F: Fn(&T) -> Option<K>,
+{
+    let mut groups: Vec<(Option<K>, Vec<T>)> = Vec::new();
+    for item in items {
+        let item_key = key(&item);
+        match groups
+            .iter_mut()
+            .find(|(k, _)| k.is_some() && *k == item_key)
+        {
+            Some((_, group)) => group.push(item),
+            None => groups.push((item_key, vec![item])),
+        }
+    }
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+/// Returns the first interface which was opened successfully,
+/// or all errors if none were.
+fn pick_interface<T, E>(results: Vec<Result<T, E>>) -> Result<T, Vec<E>> {
+    let mut errors = Vec::new();
+    for result in results {
+        match result {
+            Ok(item) => return Ok(item),
+            Err(e) => errors.push(e),
+        }
+    }
+    Err(errors)
+}
+
+fn print_discovery_errors(errors: Vec<DiscoveryError>) {
+    for e in errors {
+        logging::warning!("failed to create controller: {e}");
+    }
+}
+
+/// Device selection filters for [`find_controllers_with`].
+#[derive(Debug, Default, Clone)]
+pub struct FindOptions {
+    product_id: Option<u16>,
+    serial: Option<String>,
+    path: Option<String>,
+    index: Option<usize>,
+    registry: ControllerRegistry,
+    controller: ControllerOptions,
+}
+
+impl FindOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn product_id(mut self, product_id: u16) -> Self {
+        self.product_id = Some(product_id);
+        self
+    }
+
+    /// Serial numbers are compared case-insensitively.
+    pub fn serial(mut self, serial: impl Into<String>) -> Self {
+        self.serial = Some(serial.into());
+        self
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Selects the n-th supported device (after other filters are applied).
+    pub fn index(mut self, index: usize) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    pub fn accessory_overrides(mut self, overrides: AccessoryOverrides) -> Self {
+        self.controller = self.controller.accessory_overrides(overrides);
+        self
+    }
+
+    /// Color component order of Hue 2 controller channels.
+    pub fn color_orders(mut self, orders: ColorOrders) -> Self {
+        self.controller = self.controller.color_orders(orders);
+        self
+    }
+
+    /// Gamma correction and brightness scaling of Hue 2 controller colors.
+    pub fn color_transform(mut self, transform: ColorTransform) -> Self {
+        self.controller = self.controller.color_transform(transform);
+        self
+    }
+
+    /// Replaces the table of known Hue 2 controllers.
+    pub fn registry(mut self, registry: ControllerRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Logs all transfers of Hue 2 controllers to the recorder.
+    pub fn record_hid(mut self, recorder: Arc<HidRecorder>) -> Self {
+        self.controller = self.controller.record_hid(recorder);
+        self
+    }
+
+    /// Prints the reports of Hue 2 controllers instead of sending them.
+    ///
+    /// Other controllers can't be opened in this mode.
+    pub fn dry_run(mut self, printer: Arc<PacketPrinter>) -> Self {
+        self.controller = self.controller.dry_run(printer);
+        self
+    }
+
+    /// Allows direct LED control even if the firmware is known not to support it.
+    pub fn skip_firmware_check(mut self, skip: bool) -> Self {
+        self.controller = self.controller.skip_firmware_check(skip);
+        self
+    }
+
+    /// Replaces settings of the opened Hue 2 controllers (including the ones
+    /// set by the builder methods above).
+    pub fn controller_options(mut self, options: ControllerOptions) -> Self {
+        self.controller = options;
+        self
+    }
+
+    pub fn has_filters(&self) -> bool {
+        self.product_id.is_some()
+            || self.serial.is_some()
+            || self.path.is_some()
+            || self.index.is_some()
+    }
+
+    fn matches(&self, device: &hidapi::DeviceInfo) -> bool {
+        if self
+            .product_id
+            .is_some_and(|pid| pid != device.product_id())
+        {
+            return false;
+        }
+        if let Some(serial) = &self.serial {
+            match device.serial_number() {
+                Some(s) if s.eq_ignore_ascii_case(serial) => {}
+                _ => return false,
+            }
+        }
+        if let Some(path) = &self.path {
+            if device.path().to_string_lossy() != path.as_str() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedupe_interfaces() {
+        let interfaces = vec![
+            (0x2012, Some("A1"), 0),
+            (0x2012, None, 0),
+            (0x2012, Some("A1"), 1),
+            (0x2021, Some("A1"), 0),
+            (0x2012, None, 1),
+        ];
+        let groups = group_by_serial(interfaces, |&(pid, serial, _)| Some((pid, serial?)));
+        assert_eq!(groups, [
+            vec![(0x2012, Some("A1"), 0), (0x2012, Some("A1"), 1)],
+            vec![(0x2012, None, 0)],
+            vec![(0x2021, Some("A1"), 0)],
+            vec![(0x2012, None, 1)],
+        ]);
+
+        assert_eq!(pick_interface::<u8, u8>(vec![Err(0), Ok(1), Ok(2)]), Ok(1));
+        assert_eq!(
+            pick_interface::<u8, u8>(vec![Err(0), Err(1)]),
+            Err(vec![0, 1])
+        );
+    }
+
+    #[test]
+    fn products() {
+        let mut registry = ControllerRegistry::default();
+        let products = supported_products(&registry);
+        assert_eq!(
+            products.len(),
+            NZXTHue2Controller::known_controllers().len() + 2
+        );
+        assert!(products.is_sorted());
+        assert!(products.contains(&(0x1714, "NZXT Smart Device V1")));
+        assert!(products.contains(&(0x2012, "NZXT RGB Controller")));
+
+        registry.register(0x2042, "Test", 6, 3);
+        assert!(supported_products(&registry).contains(&(0x2042, "Test")));
+    }
 }
@@ -0,0 +1,222 @@
+//! Minimal leveled logging to stderr.
+//!
+//! Only warnings are printed by default, protocol messages of the controllers
+//! are logged at the debug level. Messages logged inside a [`Span`] are
+//! prefixed with its label, e.g. the controller they are about.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::controller::{Controller, log_label};
+use crate::error::Error;
+
+/// Verbosity of the log messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    /// Disables all messages.
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub const ALL: [Self; 6] = [
+        Self::Off,
+        Self::Error,
+        Self::Warn,
+        Self::Info,
+        Self::Debug,
+        Self::Trace,
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+
+    fn prefix(&self) -> &'static str {
+        match self {
+            Self::Warn => "warning",
+            level => level.name(),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Level names are case-insensitive.
+impl FromStr for Level {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|level| level.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "invalid log level `{s}`, expected one of: off, error, warn, info, debug, trace"
+                ))
+            })
+    }
+}
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+
+/// Sets the most verbose level of printed messages.
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn max_level() -> Level {
+    Level::ALL[MAX_LEVEL.load(Ordering::Relaxed) as usize]
+}
+
+#[doc(hidden)]
+pub fn enabled(level: Level) -> bool {
+    level != Level::Off && level <= max_level()
+}
+
+thread_local! {
+    static SPANS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Prefixes the messages of the current thread with the label until dropped.
+#[must_use = "the label is removed when the span is dropped"]
+pub struct Span {
+    /// The labels are kept per thread.
+    _thread: PhantomData<*const ()>,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        SPANS.with_borrow_mut(|spans| spans.pop());
+    }
+}
+
+pub fn span(label: impl fmt::Display) -> Span {
+    SPANS.with_borrow_mut(|spans| spans.push(label.to_string()));
+    Span {
+        _thread: PhantomData,
+    }
+}
+
+/// A span labeled with the name, product id, serial and interface of the controller.
+pub fn controller_span(controller: &(impl Controller + ?Sized)) -> Span {
+    span(log_label(
+        controller.name(),
+        controller.product_id(),
+        controller.info(),
+    ))
+}
+
+#[doc(hidden)]
+pub fn write(level: Level, args: fmt::Arguments<'_>) {
+    eprintln!("{}", format(level, args));
+}
+
+fn format(level: Level, args: fmt::Arguments<'_>) -> String {
+    let mut message = format!("{}: ", level.prefix());
+    SPANS.with_borrow(|spans| {
+        for label in spans {
+            message.push_str(label);
+            message.push_str(": ");
+        }
+    });
+    fmt::write(&mut message, args).expect("formatting into a string doesn't fail");
+    message
+}
+
+/// Prints the message if the level is enabled.
+///
+/// Arguments are not evaluated otherwise.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __log {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::logging::enabled($level) {
+            $crate::logging::write($level, format_args!($($arg)+));
+        }
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __error {
+    ($($arg:tt)+) => { $crate::logging::log!($crate::logging::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __warning {
+    ($($arg:tt)+) => { $crate::logging::log!($crate::logging::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __info {
+    ($($arg:tt)+) => { $crate::logging::log!($crate::logging::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! __debug {
+    ($($arg:tt)+) => { $crate::logging::log!($crate::logging::Level::Debug, $($arg)+) };
+}
+
+#[doc(inline)]
+pub use crate::{
+    __debug as debug, __error as error, __info as info, __log as log, __warning as warning,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_names() {
+        for level in Level::ALL {
+            assert_eq!(level.to_string().parse::<Level>().unwrap(), level);
+        }
+        assert_eq!("DEBUG".parse::<Level>().unwrap(), Level::Debug);
+        assert!("verbose".parse::<Level>().is_err());
+
+        assert!(Level::Error < Level::Warn);
+        assert!(Level::Debug < Level::Trace);
+    }
+
+    #[test]
+    fn spans() {
+        assert_eq!(
+            format(Level::Warn, format_args!("no labels")),
+            "warning: no labels"
+        );
+        {
+            let _controller = span("NZXT RGB Controller (2012, 1234)");
+            let _channel = span("channel 1");
+            assert_eq!(
+                format(Level::Error, format_args!("write failed")),
+                "error: NZXT RGB Controller (2012, 1234): channel 1: write failed"
+            );
+        }
+        assert_eq!(
+            format(Level::Info, format_args!("dropped")),
+            "info: dropped"
+        );
+    }
+}
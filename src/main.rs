@@ -1,14 +1,59 @@
-use std::io::{IsTerminal, Read, Seek};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt::{self, Write as _};
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use hidapi::HidApi;
-use nzxtcli::{Color, find_controllers};
-use serde::Serialize;
+use nzxtcli::logging::{info, warning};
+use nzxtcli::transport::{HidRecorder, PacketPrinter};
+use nzxtcli::{
+    AccessoryOverrides, ChannelSelector, Color, ColorOrder, ColorOrders, ColorRamp, ColorTransform,
+    Controller, ControllerRegistry, Direction, DiscoveryError, FanCurve, FindOptions,
+    Interpolation, LedMode, enumerate_nzxt_devices, find_controllers_verbose, logging,
+    supported_products, watch_controllers_with,
+};
+use serde::{Deserialize, Serialize};
 
-fn main() -> Result<()> {
+use self::config::{Config, ConfigError};
+use self::ctl::CmdCtl;
+use self::daemon::CmdDaemon;
+use self::exec::ExecSource;
+use self::format::OtherDevice;
+use self::hwmon::{
+    Aggregate, HWMON_ROOT, HwmonInput, Hysteresis, SensorSpec, Smoother, TempReader,
+};
+use self::notify::Notifier;
+use self::openrgb::CmdServeOpenrgb;
+use self::prometheus::CmdExportPrometheus;
+use self::retry::{Backoff, RetryingSource};
+use self::sacn::CmdServeSacn;
+
+mod config;
+mod ctl;
+mod daemon;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod exec;
+mod format;
+mod hwmon;
+mod mqtt;
+mod notify;
+mod openrgb;
+mod prometheus;
+mod retry;
+mod sacn;
+#[cfg(feature = "tls")]
+mod tls;
+
+fn main() -> ExitCode {
     if std::env::var("RUST_BACKTRACE").is_err() {
         // Enable backtraces on panics by default.
         // SAFETY: There is only a single thread at the moment.
@@ -20,18 +65,88 @@ fn main() -> Result<()> {
         unsafe { std::env::set_var("RUST_LIB_BACKTRACE", "0") };
     }
 
-    match App::parse().cmd {
-        SubCmd::List(cmd) => cmd.run(),
-        SubCmd::SetColor(cmd) => cmd.run(),
-        SubCmd::CpuTemp(cmd) => cmd.run(),
+    let argv = std::env::args_os().collect::<Vec<_>>();
+    let format = OutputFormat::from_args(&argv);
+    match run(argv) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => report_error(&e, format),
+    }
+}
+
+fn run(mut argv: Vec<OsString>) -> Result<()> {
+    let config = Config::load(Config::explicit_path(&argv))?;
+    if let Some(config) = &config {
+        init_channel_groups(config.channel_groups()?);
+        argv = config.apply(App::command(), argv)?;
+    }
+
+    let app = match App::try_parse_from(argv) {
+        Ok(app) => app,
+        // Help and version are printed to stdout.
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => return Err(e.into()),
+    };
+    let log_level = match app.log_level {
+        Some(level) => Some(level),
+        None => std::env::var("RUST_LOG")
+            .ok()
+            .and_then(|filter| parse_log_filter(&filter)),
+    };
+    if let Some(level) = log_level {
+        logging::set_max_level(level);
+    }
+    DRY_RUN.store(app.device.dry_run, Ordering::Relaxed);
+
+    let device = &app.device;
+    match app.cmd {
+        SubCmd::List(cmd) => cmd.run(device),
+        SubCmd::ListAccessories(cmd) => cmd.run(),
+        SubCmd::Status(cmd) => cmd.run(device),
+        SubCmd::SetColor(cmd) => cmd.run(device),
+        SubCmd::Off(cmd) => cmd.run(device),
+        SubCmd::SetMode(cmd) => cmd.run(device),
+        SubCmd::SetLeds(cmd) => cmd.run(device),
+        SubCmd::SetGradient(cmd) => cmd.run(device),
+        SubCmd::Identify(cmd) => cmd.run(device),
+        SubCmd::TestLeds(cmd) => cmd.run(device),
+        SubCmd::Stream(cmd) => cmd.run(device),
+        SubCmd::SetBrightness(cmd) => cmd.run(device),
+        SubCmd::Profile(cmd) => cmd.run(device),
+        SubCmd::Config(cmd) => cmd.run(config.as_ref()),
+        SubCmd::UdevRules(cmd) => cmd.run(device),
+        SubCmd::Doctor(cmd) => cmd.run(device),
+        SubCmd::CpuTemp(cmd) => cmd.run(device),
+        SubCmd::Daemon(cmd) => cmd.run(device, config.as_ref()),
+        SubCmd::Ctl(cmd) => cmd.run(device),
+        SubCmd::ExportPrometheus(cmd) => cmd.run(device),
+        SubCmd::ServeOpenrgb(cmd) => cmd.run(device),
+        SubCmd::ServeSacn(cmd) => cmd.run(device),
+        SubCmd::Sensors(cmd) => cmd.run(),
+        SubCmd::Pump(cmd) => cmd.run(device),
+        SubCmd::Raw(cmd) => cmd.run(device),
     }
 }
 
 /// A simple NZXT tool for managing fans and LEDs.
 #[derive(Parser)]
 #[clap(version = nzxtcli::version_string())]
-#[clap(subcommand_required = true)]
+#[clap(subcommand_required = true, after_help = exit_codes_help())]
 struct App {
+    #[clap(flatten)]
+    device: DeviceArgs,
+
+    /// Verbosity of the log messages (overrides `RUST_LOG`).
+    #[clap(long, global = true, value_name = "LEVEL")]
+    log_level: Option<logging::Level>,
+
+    /// Config file with the default flags [default: ~/.config/nzxtcli/config.toml].
+    #[clap(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Format of the error reports on stderr.
+    #[clap(long, global = true, value_enum, default_value_t)]
+    format: OutputFormat,
+
     #[clap(subcommand)]
     cmd: SubCmd,
 }
@@ -39,63 +154,203 @@ struct App {
 #[derive(Subcommand)]
 enum SubCmd {
     List(CmdList),
+    ListAccessories(CmdListAccessories),
+    Status(CmdStatus),
     SetColor(CmdSetColor),
+    Off(CmdOff),
+    SetMode(CmdSetMode),
+    SetLeds(CmdSetLeds),
+    SetGradient(CmdSetGradient),
+    Identify(CmdIdentify),
+    TestLeds(CmdTestLeds),
+    Stream(CmdStream),
+    SetBrightness(CmdSetBrightness),
+    #[clap(subcommand)]
+    Profile(CmdProfile),
+    #[clap(subcommand)]
+    Config(CmdConfig),
+    UdevRules(CmdUdevRules),
+    Doctor(CmdDoctor),
+    #[clap(visible_alias = "temp")]
     CpuTemp(CmdCpuTemp),
+    Daemon(CmdDaemon),
+    Ctl(CmdCtl),
+    ExportPrometheus(CmdExportPrometheus),
+    ServeOpenrgb(CmdServeOpenrgb),
+    ServeSacn(CmdServeSacn),
+    Sensors(CmdSensors),
+    #[clap(subcommand)]
+    Pump(CmdPump),
+    #[clap(hide = true)]
+    Raw(CmdRaw),
 }
 
 /// List all supported NZXT devices.
 #[derive(Parser)]
-struct CmdList {}
+struct CmdList {
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+
+    /// Also list NZXT devices which are unknown or failed to open.
+    #[clap(long, conflicts_with = "watch")]
+    all: bool,
+
+    /// Output format [default: `json-pretty` if stdout is a terminal, `json` otherwise].
+    #[clap(long, value_enum, conflicts_with = "watch")]
+    output: Option<ListOutput>,
+
+    /// Keep running and print a JSON line for each connected or removed device.
+    #[clap(long)]
+    watch: bool,
+
+    /// How often to check for device changes in the watch mode.
+    #[clap(long, default_value = "1s", value_parser = humantime::parse_duration)]
+    watch_interval: Duration,
+}
 
 impl CmdList {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let mut api = HidApi::new().context("failed to initialize HID api")?;
+        let options = device.find_options(&self.accessories)?;
+        if self.watch {
+            watch_controllers_with(&mut api, self.watch_interval, &options, |event| {
+                match serde_json::to_string(&event) {
+                    Ok(line) => println!("{line}"),
+                    Err(e) => warning!("failed to serialize event: {e}"),
+                }
+            })
+            .context("failed to watch devices")?;
+            return Ok(());
+        }
+
+        let (controllers, errors) = find_controllers_verbose(&api, &options);
+        print_discovery_errors(&errors);
+        let controllers = controllers
+            .iter()
+            .map(|controller| {
+                // Listed if the device supports the query.
+                _ = controller.firmware_version();
+                controller.snapshot()
+            })
+            .collect::<Vec<_>>();
+
+        // Interfaces of the listed controllers are skipped.
+        let mut others = Vec::new();
+        if self.all {
+            for device in enumerate_nzxt_devices(&api, &options) {
+                if controllers
+                    .iter()
+                    .any(|info| info.path.as_ref() == Some(&device.path))
+                {
+                    continue;
+                }
+                let error = errors
+                    .iter()
+                    .find(|e| e.path == device.path)
+                    .map(|e| e.error.to_string());
+                others.push(OtherDevice { device, error });
+            }
+        }
+        anyhow::ensure!(
+            !(controllers.is_empty() && others.is_empty() && options.has_filters()),
+            nzxtcli::Error::NoMatchingDevice
+        );
+
+        let output = self.output.unwrap_or(if std::io::stdout().is_terminal() {
+            ListOutput::JsonPretty
+        } else {
+            ListOutput::Json
+        });
+        match output {
+            ListOutput::Json | ListOutput::JsonPretty => {
+                let info = controllers
+                    .iter()
+                    .map(serde_json::to_value)
+                    .chain(others.iter().map(serde_json::to_value))
+                    .collect::<Result<Vec<_>, _>>()?;
+                write_json(info, output == ListOutput::JsonPretty)?;
+            }
+            ListOutput::Table => print!("{}", format::list_table(&controllers, &others)),
+            // Controllers without serial numbers can only be selected by the index.
+            ListOutput::Ids => {
+                for serial in controllers.iter().filter_map(|info| info.serial.as_ref()) {
+                    println!("{serial}");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ListOutput {
+    /// Compact JSON array.
+    Json,
+    /// Indented JSON array.
+    JsonPretty,
+    /// Aligned columns with a summary of each controller.
+    Table,
+    /// Serial numbers of the controllers, one per line.
+    Ids,
+}
+
+/// List all accessory types known to nzxtcli.
+#[derive(Parser)]
+struct CmdListAccessories {}
+
+impl CmdListAccessories {
     fn run(self) -> Result<()> {
+        print_json(nzxtcli::known_accessories())
+    }
+}
+
+/// Show fans, noise and liquid cooler status of all devices.
+#[derive(Parser)]
+struct CmdStatus {}
+
+impl CmdStatus {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
         let api = HidApi::new().context("failed to initialize HID api")?;
-        let controllers = find_controllers(&api);
+        let controllers = device.find_controllers(&api, &AccessoryArgs::default())?;
 
         let mut info = Vec::with_capacity(controllers.len());
         for controller in controllers {
-            let vendor_id = controller.info().vendor_id();
-            let product_id = controller.info().product_id();
+            let context = || format!("failed to get status of {}", controller.name());
 
-            let rgb_channels = controller.rgb_channels();
-            let rgb_channels = rgb_channels
-                .iter()
+            let fans = controller
+                .fan_status()
+                .with_context(context)?
+                .into_iter()
                 .enumerate()
-                .map(|(id, channel)| {
-                    let devices = channel
-                        .devices
-                        .iter()
-                        .enumerate()
-                        .filter_map(|(id, device)| {
-                            if device.led_count == 0 {
-                                None
-                            } else {
-                                Some(serde_json::json!({
-                                    "id": id,
-                                    "id_hex": format!("{id:02x}"),
-                                    "name": device.name,
-                                    "led_count": device.led_count,
-                                }))
-                            }
-                        })
-                        .collect::<Vec<_>>();
-
+                .map(|(id, fan)| {
                     serde_json::json!({
                         "id": id,
-                        "led_count": channel.led_count,
-                        "devices": devices,
+                        "type": fan.fan_type.name(),
+                        "rpm": fan.rpm,
+                        "duty": fan.duty,
                     })
                 })
                 .collect::<Vec<_>>();
 
-            info.push(serde_json::json!({
-                "vendor_id": vendor_id,
-                "vendor_id_hex": format!("{vendor_id:04x}"),
-                "product_id": product_id,
-                "product_id_hex": format!("{product_id:04x}"),
+            let noise_level_db = controller.noise_level_db().with_context(context)?;
+
+            let mut entry = serde_json::json!({
                 "name": controller.name(),
-                "rgb_channels": rgb_channels,
-            }));
+                "serial": controller.serial(),
+                "fans": fans,
+                "noise_level_db": noise_level_db,
+            });
+
+            if controller.is_liquid_cooler() {
+                let aio = controller.aio_status().with_context(context)?;
+                entry["liquid_cooler"] = serde_json::json!({
+                    "liquid_temp": aio.liquid_temp,
+                    "pump_rpm": aio.pump_rpm,
+                    "pump_duty": aio.pump_duty,
+                });
+            }
+
+            info.push(entry);
         }
 
         print_json(info).unwrap();
@@ -104,142 +359,3948 @@ impl CmdList {
 }
 
 /// Set the same color for all devices and channels.
+///
+/// Colors prefixed with a channel (e.g. `ch1=#0044ff`) are set only
+/// for that channel, other channels are left untouched.
 #[derive(Parser)]
 struct CmdSetColor {
-    #[clap()]
-    color: Color,
+    #[clap(
+        required = true,
+        value_name = "[CHANNEL=]COLOR",
+        help = color_help(),
+        value_parser = parse_color_arg
+    )]
+    colors: Vec<ColorArg>,
+
+    #[clap(flatten)]
+    channels: ChannelArgs,
+
+    /// Save the color to the controller memory (where supported).
+    #[clap(long, conflicts_with = "duration")]
+    save: bool,
+
+    /// Restore the previous colors after the specified time (e.g. `30s`).
+    #[clap(long, value_parser = humantime::parse_duration)]
+    duration: Option<Duration>,
+
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+
+    #[clap(flatten)]
+    transform: TransformArgs,
 }
 
 impl CmdSetColor {
-    fn run(self) -> Result<()> {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
         let api = HidApi::new().context("failed to initialize HID api")?;
-        let controllers = find_controllers(&api);
+        let options = device
+            .find_options(&self.accessories)?
+            .color_transform(self.transform.transform()?);
+        let mut common = self
+            .colors
+            .iter()
+            .filter(|arg| arg.channel.is_none())
+            .map(|arg| arg.color);
+        let per_channel = self
+            .colors
+            .iter()
+            .filter_map(|arg| Some((arg.channel?, arg.color)))
+            .collect::<Vec<_>>();
+        let (common, None) = (common.next(), common.next()) else {
+            anyhow::bail!("only one color can be specified without a channel");
+        };
+        anyhow::ensure!(
+            common.is_some() || self.channels.channels == ChannelSelector::All,
+            "`--channels` requires a color without a channel prefix"
+        );
 
-        for controller in controllers {
+        let controllers = find_controllers(&api, &options)?;
+        let mut state = LedState::load();
+        let previous = state.clone();
+
+        let mut updates = Updates::default();
+        let mut updated = Vec::with_capacity(controllers.len());
+        for controller in &controllers {
+            let controller = controller.as_ref();
+            let result = self.update(controller, common, &per_channel);
+            if let Some(colors) = updates.record(controller, result) {
+                state.record_colors(controller, &colors);
+                updated.push((controller, colors));
+            }
+        }
+
+        let Some(duration) = self.duration.filter(|_| !updated.is_empty()) else {
+            state.store();
+            return updates.finish();
+        };
+
+        if !wait_interruptible(duration) {
+            eprintln!("interrupted, restoring the previous colors");
+        }
+        for (controller, colors) in updated {
+            for (channel, _) in colors {
+                previous
+                    .channel(controller, channel)
+                    .apply(controller, channel)
+                    .with_context(|| {
+                        format!("failed to restore color for {}", controller.name())
+                    })?;
+            }
+        }
+        updates.finish()
+    }
+
+    /// Returns the updated channels and their colors.
+    fn update(
+        &self,
+        controller: &dyn Controller,
+        common: Option<Color>,
+        per_channel: &[(usize, Color)],
+    ) -> Result<Vec<(usize, Color)>> {
+        let mut channels = Vec::new();
+        if common.is_some() {
+            channels = self.channels.resolve(controller)?;
+        }
+        let mut colors = Vec::new();
+        if let Some(color) = common {
+            for channel in self.channels.targets(controller)? {
+                colors.push((channel, color));
+            }
+        }
+        for &(channel, color) in per_channel {
+            check_channel(controller, channel)?;
+            if !channels.contains(&channel) {
+                channels.push(channel);
+            }
+            colors.retain(|&(prev, _)| prev != channel);
+            colors.push((channel, color));
+        }
+
+        for &(channel, color) in &colors {
             controller
-                .set_fixed_color(self.color)
+                .set_channel_color(channel, color)
                 .with_context(|| format!("failed to set color for {}", controller.name()))?;
         }
 
-        Ok(())
+        if !self.save {
+            return Ok(colors);
+        } else if !controller.supports_save() {
+            warning!(
+                "{} does not support saving state, skipping",
+                controller.name()
+            );
+            return Ok(colors);
+        }
+        for channel in channels {
+            controller
+                .save_to_device(channel)
+                .with_context(|| format!("failed to save color for {}", controller.name()))?;
+        }
+        Ok(colors)
     }
 }
 
-/// Sync LED colors with the CPU temp.
+/// Turn off all LEDs.
 #[derive(Parser)]
-struct CmdCpuTemp {
-    /// Full path of temperature sysfs path.
-    #[clap()]
-    hwmon_path: PathBuf,
+struct CmdOff {
+    #[clap(flatten)]
+    channels: ChannelArgs,
 
-    #[clap(long, value_parser = humantime::parse_duration)]
-    interval: Duration,
+    /// Save the state to the controller memory, so the LEDs stay off after a reboot.
+    #[clap(long)]
+    save: bool,
+}
 
-    /// Base temperature for where to start the ramp (in degrees celsius).
-    #[clap(long, default_value_t = 0)]
-    base: u64,
+impl CmdOff {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let controllers = device.find_controllers(&api, &AccessoryArgs::default())?;
+        let mut state = LedState::load();
 
-    /// Threshold temperature to display the hottest color (in degrees celsius).
-    #[clap(long, default_value_t = 80)]
-    warn: u64,
+        let mut updates = Updates::default();
+        for controller in controllers {
+            let controller = controller.as_ref();
+            let result = self.update(controller);
+            if let Some(colors) = updates.record(controller, result) {
+                state.record_colors(controller, &colors);
+            }
+        }
+
+        state.store();
+        updates.finish()
+    }
+
+    fn update(&self, controller: &dyn Controller) -> Result<Vec<(usize, Color)>> {
+        let context = || format!("failed to turn off {}", controller.name());
+        let channels = self.channels.resolve(controller)?;
+        let colors = channels
+            .iter()
+            .map(|&channel| (channel, Color::BLACK))
+            .collect::<Vec<_>>();
+        for &(channel, color) in &colors {
+            controller
+                .set_channel_color(channel, color)
+                .with_context(context)?;
+        }
+
+        if !self.save {
+            return Ok(colors);
+        } else if !controller.supports_save() {
+            warning!(
+                "{} does not support saving state, skipping",
+                controller.name()
+            );
+            return Ok(colors);
+        }
+        for channel in channels {
+            controller.save_to_device(channel).with_context(context)?;
+        }
+        Ok(colors)
+    }
 }
 
-impl CmdCpuTemp {
-    fn run(mut self) -> Result<()> {
-        const MIN_TEMP: Duration = Duration::from_millis(100);
+/// Desired LED state of the controllers.
+///
+/// The CLI stores it in `$XDG_STATE_HOME/nzxtcli/state.json` after each
+/// successful `set-*` command since the state can't be read back from the
+/// devices. Profiles use the same format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct LedState {
+    version: u32,
+    /// Keyed by the controller serial (or product id without a serial).
+    #[serde(default)]
+    controllers: BTreeMap<String, ControllerState>,
+}
 
-        anyhow::ensure!(
-            self.base < self.warn,
-            "'warn' temperature must be greater than the 'base'"
-        );
+impl Default for LedState {
+    fn default() -> Self {
+        Self {
+            version: Self::VERSION,
+            controllers: BTreeMap::new(),
+        }
+    }
+}
 
-        let ramp = [
-            (0u64, Color::new(0x07, 0x05, 0x02)),
-            (250, Color::new(0x1B, 0x2E, 0x04)),
-            (600, Color::new(0x39, 0x20, 0x02)),
-            (700, Color::new(0x79, 0x09, 0x00)),
-            (900, Color::new(0xff, 0x00, 0x00)),
-        ];
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct ControllerState {
+    /// Only informational, controllers are matched by the key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default)]
+    channels: BTreeMap<usize, ChannelState>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct ChannelState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lighting: Option<Lighting>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    brightness: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Lighting {
+    Fixed {
+        color: Color,
+    },
+    Leds {
+        colors: Vec<Color>,
+    },
+    Mode {
+        mode: LedMode,
+        #[serde(default)]
+        colors: Vec<Color>,
+        #[serde(default = "default_effect_speed")]
+        speed: u8,
+        #[serde(default)]
+        direction: Direction,
+    },
+}
+
+fn default_effect_speed() -> u8 {
+    2
+}
+
+impl LedState {
+    const VERSION: u32 = 1;
+
+    fn path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+        };
+        Some(dir.join("nzxtcli/state.json"))
+    }
+
+    /// A missing or broken state file is treated as empty.
+    fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        if !path.exists() {
+            return Self::default();
+        }
+        Self::read(&path).unwrap_or_else(|e| {
+            warning!("ignoring state file: {e:#}");
+            Self::default()
+        })
+    }
+
+    fn read(path: &std::path::Path) -> Result<Self> {
+        let parse = || {
+            let data = std::fs::read(path)?;
+            let state = serde_json::from_slice::<Self>(&data)?;
+            anyhow::ensure!(
+                state.version <= Self::VERSION,
+                "unsupported version {}, at most {} is supported",
+                state.version,
+                Self::VERSION
+            );
+            Ok(state)
+        };
+        parse().with_context(|| format!("failed to read {}", path.display()))
+    }
+
+    fn write(&self, path: &std::path::Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data + "\n")
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Failures are reported but are not fatal since the LEDs are already set.
+    /// Nothing is stored during a dry run.
+    fn store(&self) {
+        if DRY_RUN.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(path) = Self::path() else {
+            return;
+        };
+        if let Err(e) = self.write(&path) {
+            warning!("failed to update state file: {e:#}");
+        }
+    }
+
+    fn key(controller: &dyn Controller) -> String {
+        match controller.serial() {
+            Some(serial) => serial.to_owned(),
+            None => format!("{:04x}", controller.product_id()),
+        }
+    }
+
+    fn channel_mut(&mut self, controller: &dyn Controller, channel: usize) -> &mut ChannelState {
+        let state = self.controllers.entry(Self::key(controller)).or_default();
+        state.name = Some(controller.name().to_owned());
+        state.channels.entry(channel).or_default()
+    }
+
+    fn record(&mut self, controller: &dyn Controller, channel: usize, lighting: Lighting) {
+        self.channel_mut(controller, channel).lighting = Some(lighting);
+    }
+
+    fn record_colors(&mut self, controller: &dyn Controller, colors: &[(usize, Color)]) {
+        for &(channel, color) in colors {
+            self.record(controller, channel, Lighting::Fixed { color });
+        }
+    }
+
+    fn channel(&self, controller: &dyn Controller, channel: usize) -> ChannelState {
+        self.controllers
+            .get(&Self::key(controller))
+            .and_then(|state| state.channels.get(&channel))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl ChannelState {
+    /// Colors of the channel LEDs, hardware effects are shown as black.
+    fn colors(&self, led_count: usize) -> Vec<Color> {
+        match &self.lighting {
+            Some(Lighting::Fixed { color }) => vec![*color; led_count],
+            Some(Lighting::Leds { colors }) => {
+                let mut colors = colors.clone();
+                colors.resize(led_count, Color::BLACK);
+                colors
+            }
+            Some(Lighting::Mode { .. }) | None => vec![Color::BLACK; led_count],
+        }
+    }
+
+    /// Channels without a recorded state are turned off.
+    fn apply(&self, controller: &dyn Controller, channel: usize) -> Result<()> {
+        if let Some(brightness) = self.brightness {
+            controller.set_brightness(channel, brightness)?;
+        }
+        match &self.lighting {
+            Some(Lighting::Fixed { color }) => controller.set_channel_color(channel, *color)?,
+            Some(Lighting::Leds { colors }) => controller.set_channel_leds(channel, colors)?,
+            Some(Lighting::Mode {
+                mode,
+                colors,
+                speed,
+                direction,
+            }) => controller.set_mode(channel, *mode, colors, *speed, *direction)?,
+            None => controller.set_channel_color(channel, Color::BLACK)?,
+        }
+        Ok(())
+    }
+}
+
+/// Set by `--dry-run`.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Sets a flag on Ctrl-C or `SIGTERM` instead of terminating the process.
+fn catch_interrupts() {
+    let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: The handler only stores to an atomic.
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+const INTERRUPT_POLL: Duration = Duration::from_millis(50);
+
+/// Sleeps for the duration, returns `false` if interrupted
+/// by Ctrl-C or `SIGTERM`.
+fn wait_interruptible(duration: Duration) -> bool {
+    wait_until(duration, || false)
+}
+
+/// Same as [`wait_interruptible`], but returns early once `wake` is true.
+fn wait_until(duration: Duration, wake: impl Fn() -> bool) -> bool {
+    catch_interrupts();
+
+    let deadline = Instant::now() + duration;
+    loop {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || wake() {
+            return true;
+        }
+        std::thread::sleep(remaining.min(INTERRUPT_POLL));
+    }
+}
+
+/// Waits for the next line from stdin, returns `false` if interrupted.
+fn wait_enter(lines: &std::sync::mpsc::Receiver<()>) -> bool {
+    catch_interrupts();
+
+    loop {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return false;
+        }
+        match lines.recv_timeout(INTERRUPT_POLL) {
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+            // Closed stdin continues without waiting.
+            Ok(()) | Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return true,
+        }
+    }
+}
+
+/// Start a hardware effect which keeps running without the host.
+#[derive(Parser)]
+struct CmdSetMode {
+    /// Effect to run.
+    mode: LedMode,
+
+    /// Effect colors, the accepted number depends on the mode.
+    #[clap(value_name = "COLOR")]
+    colors: Vec<Color>,
+
+    /// Effect speed: `slowest`, `slow`, `normal`, `fast`, `fastest` or `0-4`.
+    #[clap(long, default_value = "normal", value_parser = parse_effect_speed)]
+    speed: u8,
+
+    #[clap(long, value_enum, default_value_t)]
+    direction: Direction,
+
+    /// Save the mode to the controller memory (where supported).
+    #[clap(long)]
+    save: bool,
 
-        self.interval = std::cmp::max(self.interval, MIN_TEMP);
+    #[clap(flatten)]
+    channels: ChannelArgs,
 
-        let mut file = std::fs::OpenOptions::new()
-            .read(true)
-            .open(self.hwmon_path)
-            .context("failed to open `hwmon` file")?;
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+
+    #[clap(flatten)]
+    transform: TransformArgs,
+}
+
+impl CmdSetMode {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        check_mode_colors(self.mode, &self.colors)?;
 
         let api = HidApi::new().context("failed to initialize HID api")?;
-        let controllers = find_controllers(&api);
+        let options = device
+            .find_options(&self.accessories)?
+            .color_transform(self.transform.transform()?);
+        let controllers = find_controllers(&api, &options)?;
+        let mut state = LedState::load();
 
-        let mut wait_until = Instant::now();
-        let mut buffer = Vec::new();
-        loop {
-            buffer.clear();
-            file.seek(std::io::SeekFrom::Start(0))?;
-            file.read_to_end(&mut buffer)?;
-
-            let temp = str::from_utf8(&buffer)?
-                .trim()
-                .parse::<u64>()?
-                .clamp(self.base * SCALE, self.warn * SCALE);
-
-            let normalized_temp = (temp - self.base * SCALE) / (self.warn - self.base);
-
-            let mut color = ramp[0];
-            let mut next_color = None;
-            for ramp_item @ (threshold, ramp_color) in ramp {
-                if normalized_temp >= threshold {
-                    color = ramp_item;
-                } else {
-                    let t = (normalized_temp - color.0) * SCALE / (threshold - color.0);
-                    next_color = Some((t, ramp_color));
-                    break;
+        let mut updates = Updates::default();
+        for controller in controllers {
+            let controller = controller.as_ref();
+            match self.update(controller) {
+                Err(e) if matches!(e.downcast_ref(), Some(nzxtcli::Error::Unsupported(_))) => {
+                    warning!(
+                        "{} does not support hardware effects, skipping",
+                        controller.name()
+                    );
+                }
+                result => {
+                    let Some(channels) = updates.record(controller, result) else {
+                        continue;
+                    };
+                    for channel in channels {
+                        let lighting = Lighting::Mode {
+                            mode: self.mode,
+                            colors: self.colors.clone(),
+                            speed: self.speed,
+                            direction: self.direction,
+                        };
+                        state.record(controller, channel, lighting);
+                    }
                 }
             }
+        }
 
-            let color = match next_color {
-                None => color.1,
-                Some((t, next_color)) => interpolate(color.1, next_color, t),
-            };
+        state.store();
+        updates.finish()
+    }
 
-            for controller in &controllers {
-                controller.set_fixed_color(color)?;
-            }
+    /// Returns the updated channels.
+    fn update(&self, controller: &dyn Controller) -> Result<Vec<usize>> {
+        let context = || format!("failed to set mode for {}", controller.name());
+        let channels = self.channels.targets(controller)?;
+        for &channel in &channels {
+            controller
+                .set_mode(channel, self.mode, &self.colors, self.speed, self.direction)
+                .with_context(context)?;
+        }
 
-            wait_until += self.interval;
-            std::thread::sleep(wait_until.duration_since(Instant::now()));
+        if !self.save {
+            return Ok(channels);
+        } else if !controller.supports_save() {
+            warning!(
+                "{} does not support saving state, skipping",
+                controller.name()
+            );
+            return Ok(channels);
+        }
+        for &channel in &channels {
+            controller
+                .save_to_device(channel)
+                .with_context(|| format!("failed to save mode for {}", controller.name()))?;
         }
+        Ok(channels)
     }
 }
 
-fn print_json<T: Serialize>(output: T) -> Result<()> {
-    let output = if std::io::stdin().is_terminal() {
-        serde_json::to_string_pretty(&output)
-    } else {
-        serde_json::to_string(&output)
-    }?;
-
-    println!("{output}");
+fn check_mode_colors(mode: LedMode, colors: &[Color]) -> Result<()> {
+    let (min, max) = (mode.min_colors(), mode.max_colors());
+    match max {
+        0 => anyhow::ensure!(colors.is_empty(), "mode `{mode}` doesn't accept colors"),
+        1 => anyhow::ensure!(
+            colors.len() == 1,
+            "mode `{mode}` accepts exactly 1 color, {} given",
+            colors.len()
+        ),
+        _ => anyhow::ensure!(
+            (min..=max).contains(&colors.len()),
+            "mode `{mode}` accepts from {min} to {max} colors, {} given",
+            colors.len()
+        ),
+    }
     Ok(())
 }
 
-fn interpolate(mut a: Color, b: Color, mut t: u64) -> Color {
-    t = u64::clamp(t, 0, SCALE);
-    for (a, b) in std::iter::zip(a.inner_mut(), b.inner()) {
-        *a = (((*a as u64) * (SCALE - t) + (*b as u64) * t) / SCALE) as u8;
+fn parse_effect_speed(s: &str) -> Result<u8> {
+    const NAMES: [&str; 5] = ["slowest", "slow", "normal", "fast", "fastest"];
+
+    if let Some(speed) = NAMES.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+        return Ok(speed as u8);
     }
-    a
+    let speed = s
+        .trim()
+        .parse::<u8>()
+        .ok()
+        .filter(|&speed| speed <= nzxtcli::MAX_EFFECT_SPEED)
+        .with_context(|| {
+            format!(
+                "expected one of: {} or a number in range 0..={}",
+                NAMES.join(", "),
+                nzxtcli::MAX_EFFECT_SPEED
+            )
+        })?;
+    Ok(speed)
 }
 
-const SCALE: u64 = 1000;
+/// Set colors of individual LEDs from a JSON file.
+///
+/// The file contains either an array of colors for the selected channels
+/// or an object with arrays keyed by the channel index.
+#[derive(Parser)]
+struct CmdSetLeds {
+    /// JSON file with colors (`-` for stdin).
+    #[clap(required_unless_present = "file", conflicts_with = "file")]
+    input: Option<PathBuf>,
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Same as the positional input.
+    #[clap(long, value_name = "PATH")]
+    file: Option<PathBuf>,
 
-    #[test]
-    fn color_interpolate() {
-        let gray = interpolate(Color::BLACK, Color::WHITE, 500);
-        println!("{gray:?}");
+    /// How to handle arrays shorter than the channel.
+    #[clap(long, value_enum, default_value_t = LedFill::Black)]
+    fill: LedFill,
+
+    #[clap(flatten)]
+    channels: ChannelArgs,
+
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+
+    #[clap(flatten)]
+    transform: TransformArgs,
+}
+
+impl CmdSetLeds {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let path = self.input.as_ref().or(self.file.as_ref()).unwrap();
+        let json = if path.as_os_str() == "-" {
+            let mut json = String::new();
+            std::io::stdin()
+                .read_to_string(&mut json)
+                .context("failed to read colors from stdin")?;
+            json
+        } else {
+            std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?
+        };
+        let frame = parse_led_frame(&json)?;
+        anyhow::ensure!(
+            matches!(frame, LedFrame::Flat(_)) || self.channels.channels == ChannelSelector::All,
+            "`--channels` can't be used with colors keyed by channel"
+        );
+
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let options = device
+            .find_options(&self.accessories)?
+            .color_transform(self.transform.transform()?);
+        let controllers = find_controllers(&api, &options)?;
+
+        let mut state = LedState::load();
+        let mut updates = Updates::default();
+        for controller in controllers {
+            let controller = controller.as_ref();
+            let result = frame
+                .updates(controller, &self.channels, self.fill)
+                .and_then(|leds| write_leds(controller, &leds).map(|()| leds));
+            for (channel, colors) in updates.record(controller, result).unwrap_or_default() {
+                state.record(controller, channel, Lighting::Leds { colors });
+            }
+        }
+
+        state.store();
+        updates.finish()
+    }
+}
+
+/// Paint a gradient across the LEDs of each channel.
+#[derive(Parser)]
+struct CmdSetGradient {
+    /// Gradient colors from the first to the last LED.
+    #[clap(value_name = "COLOR", required_unless_present = "stops")]
+    colors: Vec<Color>,
+
+    /// Comma-separated gradient colors (e.g. `#00f,#0ff,#fff`).
+    #[clap(long, conflicts_with = "colors", value_parser = parse_color_list)]
+    stops: Option<ColorList>,
+
+    /// Go from the last LED to the first one.
+    #[clap(long)]
+    reverse: bool,
+
+    /// Restart the gradient on each accessory of the channel.
+    #[clap(long)]
+    per_device: bool,
+
+    /// Mix colors between the stops by RGB components or along the hue circle.
+    #[clap(long, value_enum, default_value_t)]
+    interpolation: Interpolation,
+
+    #[clap(flatten)]
+    channels: ChannelArgs,
+
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+
+    #[clap(flatten)]
+    transform: TransformArgs,
+}
+
+impl CmdSetGradient {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let colors = match &self.stops {
+            Some(ColorList(colors)) => colors,
+            None => &self.colors,
+        };
+        anyhow::ensure!(colors.len() >= 2, "a gradient needs at least 2 colors");
+        let ramp = ColorRamp::uniform(colors)?.with_interpolation(self.interpolation);
+
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let options = device
+            .find_options(&self.accessories)?
+            .color_transform(self.transform.transform()?);
+        let controllers = find_controllers(&api, &options)?;
+        let mut state = LedState::load();
+
+        let mut updates = Updates::default();
+        for controller in controllers {
+            let controller = controller.as_ref();
+            let result = self.update(controller, &ramp);
+            for (channel, colors) in updates.record(controller, result).unwrap_or_default() {
+                state.record(controller, channel, Lighting::Leds { colors });
+            }
+        }
+
+        state.store();
+        updates.finish()
+    }
+
+    /// Returns the updated channels and their LED colors.
+    fn update(&self, controller: &dyn Controller, ramp: &ColorRamp) -> Result<LedUpdates> {
+        let mut updated = Vec::new();
+        for channel in self.channels.targets(controller)? {
+            let info = &controller.rgb_channels()[channel];
+            let segments = if self.per_device {
+                device_segments(info)
+            } else {
+                vec![info.led_count]
+            };
+            let colors = gradient_colors(ramp, &segments, self.reverse);
+            controller
+                .set_channel_leds(channel, &colors)
+                .with_context(|| format!("failed to set LEDs for {}", controller.name()))?;
+            updated.push((channel, colors));
+        }
+        Ok(updated)
+    }
+}
+
+/// Blink a channel or accessory to find it in the case.
+///
+/// Without `--channel` all channels of all controllers blink one at a time.
+/// The previous colors are restored afterwards.
+#[derive(Parser)]
+struct CmdIdentify {
+    /// RGB channel to blink.
+    #[clap(long)]
+    channel: Option<usize>,
+
+    /// Accessory slot on the channel (as the `id` in `nzxtcli list`).
+    #[clap(long, requires = "channel")]
+    device: Option<usize>,
+
+    /// Number of blinks.
+    #[clap(long, default_value_t = 5)]
+    times: usize,
+}
+
+impl CmdIdentify {
+    /// Blinks twice a second.
+    const HALF_PERIOD: Duration = Duration::from_millis(250);
+
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let controllers = device.find_controllers(&api, &AccessoryArgs::default())?;
+        let state = LedState::load();
+
+        for controller in &controllers {
+            let controller = controller.as_ref();
+            let channels = match self.channel {
+                Some(channel) => {
+                    check_channel(controller, channel)?;
+                    vec![channel]
+                }
+                None => (0..controller.rgb_channels().len()).collect(),
+            };
+
+            for channel in channels {
+                let info = &controller.rgb_channels()[channel];
+                let leds = match self.device {
+                    Some(device) => device_leds(info, device).with_context(|| {
+                        format!(
+                            "invalid device for channel {channel} of {}",
+                            controller.name()
+                        )
+                    })?,
+                    None => 0..info.led_count,
+                };
+
+                let devices = info
+                    .devices
+                    .iter()
+                    .enumerate()
+                    .filter(|(id, device)| {
+                        device.led_count > 0 && self.device.is_none_or(|selected| selected == *id)
+                    })
+                    .map(|(id, device)| {
+                        serde_json::json!({
+                            "id": id,
+                            "name": device.name,
+                            "led_count": device.led_count,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let line = serde_json::json!({
+                    "identify": controller.name(),
+                    "serial": controller.serial(),
+                    "channel": channel,
+                    "devices": devices,
+                });
+                println!("{line}");
+
+                if leds.is_empty() {
+                    continue;
+                }
+
+                let previous = state.channel(controller, channel);
+                let mut interrupted = false;
+                for i in 0..self.times * 2 {
+                    let color = if i % 2 == 0 {
+                        Color::WHITE
+                    } else {
+                        Color::BLACK
+                    };
+                    let mut colors = previous.colors(info.led_count);
+                    colors[leds.clone()].fill(color);
+                    controller
+                        .set_channel_leds(channel, &colors)
+                        .with_context(|| format!("failed to set LEDs for {}", controller.name()))?;
+
+                    if !wait_interruptible(Self::HALF_PERIOD) {
+                        interrupted = true;
+                        break;
+                    }
+                }
+
+                previous.apply(controller, channel).with_context(|| {
+                    format!("failed to restore color for {}", controller.name())
+                })?;
+                if interrupted {
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Light LEDs one at a time to check their order and find dead ones.
+///
+/// The previous colors are restored afterwards.
+#[derive(Parser)]
+struct CmdTestLeds {
+    /// How long each LED stays lit.
+    #[clap(long, default_value = "200ms", value_parser = humantime::parse_duration)]
+    delay: Duration,
+
+    /// Color of the lit LED.
+    #[clap(long, default_value = "white")]
+    color: Color,
+
+    /// Keep each LED lit until Enter is pressed.
+    #[clap(long)]
+    hold: bool,
+
+    #[clap(flatten)]
+    channels: ChannelArgs,
+}
+
+impl CmdTestLeds {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let controllers = device.find_controllers(&api, &AccessoryArgs::default())?;
+        let state = LedState::load();
+
+        let (tx, lines) = std::sync::mpsc::channel();
+        if self.hold {
+            std::thread::spawn(move || {
+                for line in std::io::stdin().lines() {
+                    if line.is_err() || tx.send(()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        for controller in &controllers {
+            let controller = controller.as_ref();
+            for channel in self.channels.targets(controller)? {
+                let info = &controller.rgb_channels()[channel];
+                let previous = state.channel(controller, channel);
+
+                let mut interrupted = false;
+                for led in 0..info.led_count {
+                    let mut line = serde_json::json!({
+                        "controller": controller.name(),
+                        "channel": channel,
+                        "led": led,
+                    });
+                    if let Some((slot, offset)) = led_owner(info, led) {
+                        line["device"] = slot.into();
+                        line["name"] = info.devices[slot].name.into();
+                        line["offset"] = offset.into();
+                    }
+                    println!("{line}");
+
+                    let mut colors = vec![Color::BLACK; info.led_count];
+                    colors[led] = self.color;
+                    controller
+                        .set_channel_leds(channel, &colors)
+                        .with_context(|| format!("failed to set LEDs for {}", controller.name()))?;
+
+                    let done = if self.hold {
+                        wait_enter(&lines)
+                    } else {
+                        wait_interruptible(self.delay)
+                    };
+                    if !done {
+                        interrupted = true;
+                        break;
+                    }
+                }
+
+                previous.apply(controller, channel).with_context(|| {
+                    format!("failed to restore color for {}", controller.name())
+                })?;
+                if interrupted {
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the accessory slot of the LED and its index within the accessory.
+fn led_owner(channel: &nzxtcli::RgbChannel, led: usize) -> Option<(usize, usize)> {
+    let mut start = 0;
+    for (slot, device) in channel.devices.iter().enumerate() {
+        let leds = device.led_count as usize;
+        if led < start + leds {
+            return Some((slot, led - start));
+        }
+        start += leds;
+    }
+    None
+}
+
+/// Range of the accessory LEDs on the channel.
+fn device_leds(channel: &nzxtcli::RgbChannel, device: usize) -> Result<std::ops::Range<usize>> {
+    let Some(info) = channel.devices.get(device) else {
+        anyhow::bail!(
+            "invalid device slot {device}, the channel has {} slots",
+            channel.devices.len()
+        );
+    };
+    anyhow::ensure!(info.led_count > 0, "nothing is connected to slot {device}");
+
+    let start = channel.devices[..device]
+        .iter()
+        .map(|device| device.led_count as usize)
+        .sum::<usize>()
+        .min(channel.led_count);
+    let end = (start + info.led_count as usize).min(channel.led_count);
+    Ok(start..end)
+}
+
+/// LED counts of the connected accessories, limited to the channel LEDs.
+fn device_segments(channel: &nzxtcli::RgbChannel) -> Vec<usize> {
+    let mut remaining = channel.led_count;
+    let mut segments = Vec::new();
+    for device in &channel.devices {
+        let leds = (device.led_count as usize).min(remaining);
+        if leds > 0 {
+            segments.push(leds);
+            remaining -= leds;
+        }
+    }
+    if remaining > 0 {
+        segments.push(remaining);
+    }
+    segments
+}
+
+/// Samples the ramp over each segment of consecutive LEDs.
+fn gradient_colors(ramp: &ColorRamp, segments: &[usize], reverse: bool) -> Vec<Color> {
+    let mut colors = Vec::with_capacity(segments.iter().sum());
+    for &leds in segments {
+        let last = leds.saturating_sub(1).max(1) as f32;
+        colors.extend((0..leds).map(|i| {
+            let t = i as f32 / last;
+            ramp.sample(if reverse { 1.0 - t } else { t })
+        }));
+    }
+    colors
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ColorList(Vec<Color>);
+
+/// Splits colors by commas outside of parentheses (e.g. `red,rgb(0, 0, 255)`).
+fn parse_color_list(s: &str) -> Result<ColorList> {
+    let mut colors = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, c) in s.char_indices().chain([(s.len(), ',')]) {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                colors.push(s[start..i].trim().parse::<Color>()?);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    Ok(ColorList(colors))
+}
+
+/// Apply LED frames from stdin as they arrive (one JSON frame per line).
+///
+/// Frames have the same format as in `set-leds`. If frames arrive faster
+/// than they can be applied, only the latest one is used.
+#[derive(Parser)]
+struct CmdStream {
+    /// Max number of applied frames per second.
+    #[clap(long, default_value_t = 30.0)]
+    fps: f32,
+
+    /// How to handle frames shorter than the channel.
+    #[clap(long, value_enum, default_value_t = LedFill::Black)]
+    fill: LedFill,
+
+    /// Color to set when the input ends (the last frame stays otherwise).
+    #[clap(long, value_name = "COLOR")]
+    on_exit: Option<Color>,
+
+    #[clap(flatten)]
+    channels: ChannelArgs,
+
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+
+    #[clap(flatten)]
+    transform: TransformArgs,
+}
+
+impl CmdStream {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        anyhow::ensure!(
+            self.fps.is_finite() && self.fps > 0.0,
+            "frame rate must be positive"
+        );
+        let frame_interval = Duration::from_secs_f32(1.0 / self.fps);
+
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let options = device
+            .find_options(&self.accessories)?
+            .color_transform(self.transform.transform()?);
+        let mut controllers = find_controllers(&api, &options)?;
+        for controller in &controllers {
+            self.channels.resolve(controller.as_ref())?;
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if !line.trim().is_empty() && tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut stats = StreamStats::default();
+        let mut next_frame = Instant::now();
+        while let Ok(mut line) = rx.recv() {
+            // Skip frames which arrived while the previous one was applied.
+            while let Ok(newer) = rx.try_recv() {
+                line = newer;
+                stats.dropped += 1;
+            }
+
+            let updates = match frame_updates(&line, &controllers, &self.channels, self.fill) {
+                Ok(updates) => updates,
+                Err(e) => {
+                    stats.malformed(e);
+                    continue;
+                }
+            };
+
+            for (controller, updates) in controllers.iter_mut().zip(&updates) {
+                if !controller.is_connected() {
+                    if let Err(e) = controller.reconnect(&api) {
+                        warning!("failed to reconnect {}: {e:?}", controller.name());
+                        continue;
+                    }
+                }
+                if let Err(e) = write_leds(controller.as_ref(), updates) {
+                    warning!("{e:#}");
+                }
+            }
+            stats.applied += 1;
+
+            next_frame = std::cmp::max(next_frame + frame_interval, Instant::now());
+            std::thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+        }
+
+        if let Some(color) = self.on_exit {
+            for controller in &controllers {
+                self.channels
+                    .set_color(controller.as_ref(), color)
+                    .with_context(|| format!("failed to set color for {}", controller.name()))?;
+            }
+        }
+
+        eprintln!(
+            "{} frames applied, {} dropped, {} malformed",
+            stats.applied, stats.dropped, stats.malformed
+        );
+        Ok(())
+    }
+}
+
+/// Updates of each controller, nothing is applied if the frame doesn't fit
+/// any of them.
+fn frame_updates(
+    line: &str,
+    controllers: &[Box<dyn Controller>],
+    channels: &ChannelArgs,
+    fill: LedFill,
+) -> Result<Vec<LedUpdates>> {
+    let frame = parse_led_frame(line)?;
+    controllers
+        .iter()
+        .map(|controller| frame.updates(controller.as_ref(), channels, fill))
+        .collect()
+}
+
+#[derive(Default)]
+struct StreamStats {
+    applied: usize,
+    dropped: usize,
+    malformed: usize,
+}
+
+impl StreamStats {
+    /// Only the first error is printed, the rest are counted.
+    fn malformed(&mut self, error: anyhow::Error) {
+        if self.malformed == 0 {
+            warning!("skipping malformed frame: {error:#}");
+        }
+        self.malformed += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum LedFill {
+    /// Repeat the last color.
+    Repeat,
+    /// Turn off the remaining LEDs.
+    Black,
+    /// Fail unless there is a color for each LED.
+    Error,
+}
+
+/// LED colors of the updated channels of a controller.
+type LedUpdates = Vec<(usize, Vec<Color>)>;
+
+#[derive(Debug, PartialEq)]
+enum LedFrame {
+    Flat(Vec<Color>),
+    Channels(BTreeMap<usize, Vec<Color>>),
+}
+
+impl LedFrame {
+    /// Returns colors for each updated channel of the controller.
+    ///
+    /// All channels are validated, so nothing is written for invalid frames.
+    fn updates(
+        &self,
+        controller: &dyn Controller,
+        channels: &ChannelArgs,
+        fill: LedFill,
+    ) -> Result<LedUpdates> {
+        let mut updates = Vec::new();
+        match self {
+            Self::Flat(colors) => {
+                for channel in channels.targets(controller)? {
+                    updates.push((channel, colors));
+                }
+            }
+            Self::Channels(channels) => {
+                for (&channel, colors) in channels {
+                    check_channel(controller, channel)?;
+                    updates.push((channel, colors));
+                }
+            }
+        }
+
+        updates
+            .into_iter()
+            .map(|(channel, colors)| {
+                let led_count = controller.rgb_channels()[channel].led_count;
+                let colors = fit_leds(colors, led_count, fill).with_context(|| {
+                    format!(
+                        "invalid colors for channel {channel} of {}",
+                        controller.name()
+                    )
+                })?;
+                Ok((channel, colors))
+            })
+            .collect()
+    }
+}
+
+fn write_leds(controller: &dyn Controller, updates: &[(usize, Vec<Color>)]) -> Result<()> {
+    for (channel, colors) in updates {
+        controller
+            .set_channel_leds(*channel, colors)
+            .with_context(|| format!("failed to set LEDs for {}", controller.name()))?;
+    }
+    Ok(())
+}
+
+fn parse_led_frame(json: &str) -> Result<LedFrame> {
+    let value = serde_json::from_str::<serde_json::Value>(json).context("invalid JSON")?;
+    let frame = match value {
+        serde_json::Value::Array(_) => LedFrame::Flat(serde_json::from_value(value)?),
+        serde_json::Value::Object(_) => LedFrame::Channels(
+            serde_json::from_value(value).context("invalid colors keyed by channel")?,
+        ),
+        _ => anyhow::bail!("expected an array of colors or an object keyed by channel"),
+    };
+    Ok(frame)
+}
+
+/// Adjusts the colors to the number of channel LEDs.
+fn fit_leds(colors: &[Color], led_count: usize, fill: LedFill) -> Result<Vec<Color>> {
+    anyhow::ensure!(
+        colors.len() <= led_count,
+        "{} colors given, but the channel has only {led_count} LEDs",
+        colors.len()
+    );
+
+    let padding = match fill {
+        LedFill::Repeat => colors.last().copied().unwrap_or(Color::BLACK),
+        LedFill::Black => Color::BLACK,
+        LedFill::Error => {
+            anyhow::ensure!(
+                colors.len() == led_count,
+                "{} colors given, but the channel has {led_count} LEDs",
+                colors.len()
+            );
+            Color::BLACK
+        }
+    };
+
+    let mut colors = colors.to_vec();
+    colors.resize(led_count, padding);
+    Ok(colors)
+}
+
+/// Set the same brightness for all devices and channels.
+#[derive(Parser)]
+struct CmdSetBrightness {
+    /// Brightness in percent.
+    #[clap(value_parser = clap::value_parser!(u8).range(0..=nzxtcli::MAX_BRIGHTNESS as i64))]
+    brightness: u8,
+}
+
+impl CmdSetBrightness {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let controllers = device.find_controllers(&api, &AccessoryArgs::default())?;
+        let mut state = LedState::load();
+
+        let mut updates = Updates::default();
+        for controller in controllers {
+            let controller = controller.as_ref();
+            let channels = 0..controller.rgb_channels().len();
+            let result = channels.clone().try_for_each(|channel| {
+                controller
+                    .set_brightness(channel, self.brightness)
+                    .with_context(|| format!("failed to set brightness for {}", controller.name()))
+            });
+            if updates.record(controller, result).is_some() {
+                for channel in channels {
+                    state.channel_mut(controller, channel).brightness = Some(self.brightness);
+                }
+            }
+        }
+
+        state.store();
+        updates.finish()
+    }
+}
+
+/// Save and apply LED profiles.
+///
+/// Profiles contain the colors, modes and brightness last set by
+/// the `set-*` commands for each controller.
+#[derive(Subcommand)]
+enum CmdProfile {
+    Save(CmdProfileSave),
+    Apply(CmdProfileApply),
+}
+
+impl CmdProfile {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        match self {
+            Self::Save(cmd) => cmd.run(device),
+            Self::Apply(cmd) => cmd.run(device),
+        }
+    }
+}
+
+/// Save the current state of the connected controllers to a profile.
+#[derive(Parser)]
+struct CmdProfileSave {
+    /// Profile file (JSON).
+    path: PathBuf,
+}
+
+impl CmdProfileSave {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let controllers = device.find_controllers(&api, &AccessoryArgs::default())?;
+        anyhow::ensure!(!controllers.is_empty(), nzxtcli::Error::NoMatchingDevice);
+
+        let state = LedState::load();
+        let mut profile = LedState::default();
+        for controller in &controllers {
+            let key = LedState::key(controller.as_ref());
+            match state.controllers.get(&key) {
+                Some(controller_state) => {
+                    profile.controllers.insert(key, controller_state.clone());
+                }
+                None => warning!(
+                    "no state recorded for {} ({key}), skipping",
+                    controller.name()
+                ),
+            }
+        }
+
+        profile.write(&self.path)
+    }
+}
+
+/// Apply a saved profile to the connected controllers.
+#[derive(Parser)]
+struct CmdProfileApply {
+    /// Profile file (JSON).
+    path: PathBuf,
+}
+
+impl CmdProfileApply {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let profile = LedState::read(&self.path)?;
+
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let controllers = device.find_controllers(&api, &AccessoryArgs::default())?;
+        let mut state = LedState::load();
+
+        let mut updates = Updates::default();
+        for (key, controller_state) in &profile.controllers {
+            let Some(controller) = controllers
+                .iter()
+                .find(|controller| LedState::key(controller.as_ref()) == *key)
+            else {
+                warning!(
+                    "{} ({key}) from the profile is not connected, skipping",
+                    controller_state.name.as_deref().unwrap_or("controller")
+                );
+                continue;
+            };
+            let controller = controller.as_ref();
+
+            let mut applied = Vec::new();
+            let result = controller_state
+                .channels
+                .iter()
+                .filter(|&(&channel, _)| match check_channel(controller, channel) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warning!("{e}, skipping");
+                        false
+                    }
+                })
+                .try_for_each(|(&channel, channel_state)| {
+                    channel_state.apply(controller, channel).with_context(|| {
+                        format!("failed to apply profile to {}", controller.name())
+                    })?;
+                    applied.push((channel, channel_state.clone()));
+                    Ok(())
+                });
+            updates.record(controller, result);
+            for (channel, channel_state) in applied {
+                *state.channel_mut(controller, channel) = channel_state;
+            }
+        }
+
+        state.store();
+        updates.finish()
+    }
+}
+
+/// Inspect the config file.
+#[derive(Subcommand)]
+enum CmdConfig {
+    /// Check that the config file is valid.
+    Check,
+}
+
+impl CmdConfig {
+    /// The config is validated on startup, so only its presence is checked here.
+    fn run(self, config: Option<&Config>) -> Result<()> {
+        match self {
+            Self::Check => {
+                let Some(config) = config else {
+                    let path = Config::default_path().unwrap_or_default();
+                    anyhow::bail!("no config file found at {}", path.display());
+                };
+                println!("{}: ok", config.path().display());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Print udev rules which allow non-root users to access all supported devices.
+#[derive(Parser)]
+struct CmdUdevRules {
+    /// Which users are allowed to access the devices.
+    #[clap(long, value_enum, default_value_t)]
+    access: UdevAccess,
+
+    /// Write the rules to `/etc/udev/rules.d` and reload them (requires root).
+    #[clap(long)]
+    install: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum UdevAccess {
+    /// Users of the local seat (`TAG+="uaccess"`).
+    #[default]
+    Uaccess,
+    /// Members of the `plugdev` group (`MODE="0660", GROUP="plugdev"`).
+    Plugdev,
+}
+
+const UDEV_RULES_PATH: &str = "/etc/udev/rules.d/60-nzxtcli.rules";
+
+impl CmdUdevRules {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let rules = udev_rules(&supported_products(&device.registry()), self.access);
+        if !self.install {
+            print!("{rules}");
+            return Ok(());
+        }
+
+        // SAFETY: `geteuid` has no preconditions and never fails.
+        anyhow::ensure!(
+            unsafe { libc::geteuid() } == 0,
+            "refusing to install the udev rules without root privileges, run \
+             `sudo nzxtcli udev-rules --install` or save the output of `nzxtcli udev-rules` \
+             to {UDEV_RULES_PATH}"
+        );
+        std::fs::write(UDEV_RULES_PATH, rules)
+            .with_context(|| format!("failed to write {UDEV_RULES_PATH}"))?;
+        eprintln!("installed {UDEV_RULES_PATH}");
+
+        for args in [&["control", "--reload-rules"][..], &["trigger"]] {
+            let status = std::process::Command::new("udevadm")
+                .args(args)
+                .status()
+                .context("failed to run `udevadm`")?;
+            anyhow::ensure!(
+                status.success(),
+                "`udevadm {}` failed with {status}",
+                args.join(" ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Rules for both `usb` and `hidraw` nodes, so that either hidapi backend works.
+fn udev_rules(products: &[(u16, &str)], access: UdevAccess) -> String {
+    let access = match access {
+        UdevAccess::Uaccess => r#"TAG+="uaccess""#,
+        UdevAccess::Plugdev => r#"MODE="0660", GROUP="plugdev""#,
+    };
+
+    let mut rules = String::from("# Generated by `nzxtcli udev-rules`.\n");
+    for (pid, name) in products {
+        let _ = write!(
+            rules,
+            "\n# {name}\n\
+             SUBSYSTEMS==\"usb|hidraw\", ATTRS{{idVendor}}==\"{:04x}\", \
+             ATTRS{{idProduct}}==\"{pid:04x}\", {access}\n",
+            nzxtcli::NZXT_VID
+        );
+    }
+    rules
+}
+
+/// Check that the connected devices can be controlled (e.g. for bug reports).
+///
+/// Prints a line for each check and fails if any of them failed.
+#[derive(Parser)]
+struct CmdDoctor {}
+
+impl CmdDoctor {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let mut checks = Checks::default();
+        println!("nzxtcli {}", nzxtcli::version_string());
+
+        let api = match HidApi::new() {
+            Ok(api) => {
+                checks.pass("hidapi initialized");
+                api
+            }
+            Err(e) => {
+                checks.fail(format!("failed to initialize hidapi: {e}"));
+                return checks.finish();
+            }
+        };
+
+        let interfaces = api
+            .device_list()
+            .filter(|info| info.vendor_id() == nzxtcli::NZXT_VID)
+            .collect::<Vec<_>>();
+        if interfaces.is_empty() {
+            checks.fail(format!(
+                "no devices with the NZXT vendor id {:04x} found",
+                nzxtcli::NZXT_VID
+            ));
+            checks.hint("make sure the controller is connected to a USB header");
+            return checks.finish();
+        }
+        checks.pass(format!("found {} NZXT HID interfaces", interfaces.len()));
+
+        let registry = device.registry();
+        let products = supported_products(&registry);
+        for info in interfaces {
+            let pid = info.product_id();
+            let path = info.path().to_string_lossy();
+            let label = format!(
+                "{pid:04x} at {path} (interface {})",
+                info.interface_number()
+            );
+
+            let Some(&(_, name)) = products.iter().find(|&&(product_id, _)| product_id == pid)
+            else {
+                checks.fail(format!("{label}: unknown product id"));
+                checks.hint(format!(
+                    "if the device is a Hue 2 compatible controller, try \
+                     `--add-device {pid:04x}:RGB:FANS` with its channel counts"
+                ));
+                continue;
+            };
+            checks.pass(format!("{label}: known device ({name})"));
+
+            if let Err(e) = api.open_path(info.path()) {
+                let errno = errno_name(&e).map(|name| format!(" ({name})"));
+                checks.fail(format!(
+                    "{label}: failed to open: {e}{}",
+                    errno.unwrap_or_default()
+                ));
+                match errno_name(&e) {
+                    Some("EACCES" | "EPERM") => checks.hint(
+                        "install the udev rules with `sudo nzxtcli udev-rules --install` \
+                         and replug the device",
+                    ),
+                    Some("EBUSY") => checks.busy_hint(&path),
+                    _ => {}
+                }
+                continue;
+            }
+            checks.pass(format!("{label}: opened"));
+
+            let options = FindOptions::new()
+                .registry(registry.clone())
+                .path(path.as_ref());
+            let (controllers, errors) = find_controllers_verbose(&api, &options);
+            let query = if registry.contains(pid) {
+                "accessory query"
+            } else {
+                "initialization"
+            };
+            let controller = match (controllers.into_iter().next(), errors.into_iter().next()) {
+                (Some(controller), _) => controller,
+                (None, Some(e)) => {
+                    checks.fail(format!("{label}: {query} failed: {}", e.error));
+                    if matches!(e.error, nzxtcli::Error::Timeout) {
+                        checks.busy_hint(&path);
+                    }
+                    continue;
+                }
+                (None, None) => {
+                    checks.skip(format!("{label}: not a vendor interface"));
+                    continue;
+                }
+            };
+            checks.pass(format!(
+                "{label}: {query} answered ({} RGB channels)",
+                controller.rgb_channels().len()
+            ));
+
+            match controller.firmware_version() {
+                Ok(version) => checks.pass(format!("{label}: firmware {version}")),
+                Err(nzxtcli::Error::Unsupported(_)) => {
+                    checks.skip(format!("{label}: firmware version can't be queried"));
+                }
+                Err(e) => checks.fail(format!("{label}: failed to read firmware version: {e}")),
+            }
+        }
+        checks.finish()
+    }
+}
+
+/// Results of the `doctor` checks.
+#[derive(Default)]
+struct Checks {
+    total: usize,
+    failed: usize,
+}
+
+impl Checks {
+    fn pass(&mut self, message: impl fmt::Display) {
+        self.total += 1;
+        println!("[ ok ] {message}");
+    }
+
+    fn fail(&mut self, message: impl fmt::Display) {
+        self.total += 1;
+        self.failed += 1;
+        println!("[FAIL] {message}");
+    }
+
+    fn skip(&self, message: impl fmt::Display) {
+        println!("[skip] {message}");
+    }
+
+    fn hint(&self, message: impl fmt::Display) {
+        println!("       hint: {message}");
+    }
+
+    /// Replies are lost or the device can't be opened if other software talks to it.
+    fn busy_hint(&self, path: &str) {
+        let holders = file_holders(path);
+        if holders.is_empty() {
+            self.hint("close NZXT CAM or other software which controls the device");
+        } else {
+            self.hint(format!(
+                "the device is also opened by: {}",
+                holders.join(", ")
+            ));
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        anyhow::ensure!(
+            self.failed == 0,
+            "{} of {} checks failed",
+            self.failed,
+            self.total
+        );
+        Ok(())
+    }
+}
+
+/// Returns the name of a common OS error in the hidapi error.
+fn errno_name(error: &hidapi::HidError) -> Option<&'static str> {
+    // `ENXIO` goes before `ENODEV` since the message of the latter is a prefix.
+    const ERRNOS: [(i32, &str); 6] = [
+        (libc::EACCES, "EACCES"),
+        (libc::EPERM, "EPERM"),
+        (libc::EBUSY, "EBUSY"),
+        (libc::ENOENT, "ENOENT"),
+        (libc::ENXIO, "ENXIO"),
+        (libc::ENODEV, "ENODEV"),
+    ];
+
+    let found = match error {
+        hidapi::HidError::IoError { error } => {
+            let code = error.raw_os_error()?;
+            ERRNOS.iter().find(|&&(errno, _)| errno == code)
+        }
+        // The C library only reports the `strerror` message.
+        hidapi::HidError::HidApiError { message } => ERRNOS.iter().find(|&&(errno, _)| {
+            let description = std::io::Error::from_raw_os_error(errno).to_string();
+            let description = description.split(" (os error").next().unwrap_or_default();
+            message.contains(description)
+        }),
+        _ => None,
+    };
+    found.map(|&(_, name)| name)
+}
+
+/// Returns other processes which opened the device node.
+#[cfg(target_os = "linux")]
+fn file_holders(path: &str) -> Vec<String> {
+    let Ok(path) = std::fs::canonicalize(path) else {
+        return Vec::new();
+    };
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+
+    let mut holders = Vec::new();
+    for process in processes.flatten() {
+        let Some(pid) = process
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid == std::process::id() {
+            continue;
+        }
+        // Only processes of the same user can be inspected without root.
+        let Ok(fds) = std::fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        if fds
+            .flatten()
+            .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|target| target == path))
+        {
+            let name = std::fs::read_to_string(process.path().join("comm")).unwrap_or_default();
+            holders.push(format!("{} (pid {pid})", name.trim()));
+        }
+    }
+    holders
+}
+
+#[cfg(not(target_os = "linux"))]
+fn file_holders(_: &str) -> Vec<String> {
+    Vec::new()
+}
+
+/// Sync LED colors with the CPU temp (or any other temperature).
+#[derive(Parser)]
+struct CmdCpuTemp {
+    /// Full path of temperature sysfs path.
+    #[clap(required_unless_present_any = ["sensor", "exec", "preview"])]
+    hwmon_path: Option<PathBuf>,
+
+    /// Hwmon chip name and temperature label instead of the path (e.g. `k10temp:Tctl`
+    /// or `amdgpu:edge`).
+    ///
+    /// The sensor is looked up on startup and after read failures, `nzxtcli sensors`
+    /// lists the available ones. Can be repeated to combine several sensors.
+    #[clap(long, visible_alias = "source")]
+    sensor: Vec<SensorSpec>,
+
+    /// Shell command printing the temperature in degrees celsius, run on each update
+    /// (e.g. `nvidia-smi --query-gpu=temperature.gpu --format=csv,noheader`).
+    ///
+    /// The first number of the output is used. Failures and timeouts (5s) are
+    /// retried like failed sensors. Can be repeated and combined with sensors.
+    #[clap(long, value_name = "COMMAND")]
+    exec: Vec<String>,
+
+    /// How the readings of several sensors are combined.
+    #[clap(long, value_enum, default_value_t)]
+    aggregate: Aggregate,
+
+    /// Print the readings of each sensor and the resulting color.
+    #[clap(long)]
+    verbose: bool,
+
+    #[clap(
+        long,
+        value_parser = humantime::parse_duration,
+        required_unless_present_any = ["preview", "oneshot"]
+    )]
+    interval: Option<Duration>,
+
+    /// Average the readings over this time constant to ignore short spikes (e.g. `20s`).
+    #[clap(long, value_parser = humantime::parse_duration)]
+    smooth: Option<Duration>,
+
+    /// Change the color only when the temperature moves by more than this
+    /// (in degrees celsius).
+    #[clap(long)]
+    hysteresis: Option<f64>,
+
+    /// Base temperature for where to start the ramp (in degrees celsius).
+    #[clap(long, default_value_t = 0)]
+    base: u64,
+
+    /// Threshold temperature to display the hottest color (in degrees celsius).
+    #[clap(long, default_value_t = 80)]
+    warn: u64,
+
+    /// Colors at temperatures in degrees celsius (e.g. `30:#0044ff,50:#00ff88,85:red`).
+    ///
+    /// The first and the last stops are used instead of `--base` and `--warn`.
+    #[clap(long, conflicts_with_all = ["base", "warn"])]
+    ramp: Option<ColorRamp>,
+
+    /// Mix colors between the ramp stops by RGB components or along the hue circle.
+    #[clap(long, value_enum, default_value_t)]
+    interpolation: Interpolation,
+
+    /// Print the color of each temperature between the base and the warn ones and exit.
+    #[clap(long)]
+    preview: bool,
+
+    /// Read the temperature once, set the color and exit.
+    ///
+    /// The reading and the color are printed as JSON.
+    #[clap(long, conflicts_with = "preview")]
+    oneshot: bool,
+
+    /// Color to set on Ctrl-C, `SIGTERM` or when the sensors stop working
+    /// (`keep` leaves the last temperature color).
+    #[clap(long, value_name = "COLOR", default_value = "white")]
+    on_exit: ExitColor,
+
+    /// Fan duty cycles in percent at temperatures (e.g. `30:20,60:50,80:100`).
+    ///
+    /// Applies to all fan channels, or to a single one with the `N=` prefix
+    /// (e.g. `1=30:40,70:100`). Can be repeated.
+    #[clap(long, value_name = "[CHANNEL=]CURVE")]
+    fan_curve: Vec<FanCurveArg>,
+
+    /// Give up after this many updates in a row have failed (retries forever by default).
+    ///
+    /// An update fails if no sensor could be read or any controller couldn't be updated.
+    #[clap(long, value_name = "N")]
+    max_errors: Option<u32>,
+
+    #[clap(flatten)]
+    channels: ChannelArgs,
+
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+
+    #[clap(flatten)]
+    transform: TransformArgs,
+}
+
+impl CmdCpuTemp {
+    fn run(mut self, device: &DeviceArgs) -> Result<()> {
+        const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+        let (ramp, base, warn) = self.ramp()?;
+        if self.preview {
+            print_ramp_preview(&ramp, base, warn);
+            return Ok(());
+        }
+
+        let mut interval = self.interval.unwrap_or(MIN_INTERVAL);
+        if interval < MIN_INTERVAL {
+            warning!(
+                "interval {} is too short, using {}",
+                humantime::format_duration(interval),
+                humantime::format_duration(MIN_INTERVAL)
+            );
+            interval = MIN_INTERVAL;
+        }
+        let mut smoother = self.smooth.map(Smoother::new);
+        let mut hysteresis = match self.hysteresis {
+            Some(degrees) => {
+                anyhow::ensure!(
+                    degrees.is_finite() && degrees >= 0.0,
+                    "hysteresis must be a non-negative number of degrees"
+                );
+                Some(Hysteresis::new((degrees * 1000.0).round() as i64))
+            }
+            None => None,
+        };
+
+        let mut readers = Vec::new();
+        let inputs = self.hwmon_path.take().map(HwmonInput::Path).into_iter();
+        for input in inputs.chain(self.sensor.drain(..).map(HwmonInput::Sensor)) {
+            let reader = TempReader::open(input, HWMON_ROOT)?;
+            readers.push(RetryingSource::new(Box::new(reader)));
+        }
+        for command in self.exec.drain(..) {
+            let source = ExecSource::new(command, ExecSource::DEFAULT_TIMEOUT);
+            readers.push(RetryingSource::new(Box::new(source)));
+        }
+
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let options = device
+            .find_options(&self.accessories)?
+            .color_transform(self.transform.transform()?);
+        let mut controllers = find_controllers(&api, &options)?;
+        for controller in &controllers {
+            self.channels.resolve(controller.as_ref())?;
+            self.check_fan_curves(controller.as_ref())?;
+        }
+        let mut fan_limiters = controllers
+            .iter()
+            .map(|controller| vec![DutyLimiter::default(); controller.fan_channels()])
+            .collect::<Vec<_>>();
+
+        let mut reconnects = vec![Backoff::default(); controllers.len()];
+
+        let mut notifier = Notifier::from_env();
+        let mut ready = false;
+        catch_interrupts();
+        let mut schedule = Schedule::new(interval, Instant::now());
+        let mut last_reading = None::<Instant>;
+        let mut errors = 0;
+        let result = loop {
+            notifier.watchdog();
+
+            let now = Instant::now();
+            // Failed sensors are skipped until they are readable again.
+            let readings = readers
+                .iter_mut()
+                .map(|reader| reader.poll(now))
+                .collect::<Vec<_>>();
+            let aggregated = self.aggregate.apply(readings.iter().flatten().copied());
+
+            // Colors are kept until any sensor is back (e.g. after resume or a driver reload).
+            let Some(millidegrees) = aggregated else {
+                anyhow::ensure!(
+                    !device.dry_run && !self.oneshot,
+                    "failed to read the temperature"
+                );
+                errors += 1;
+                notifier.status("no sensor could be read");
+                if self.max_errors.is_some_and(|max| errors > max) {
+                    break Err(anyhow::anyhow!(
+                        "giving up after {errors} failed updates, no sensor could be read"
+                    ));
+                }
+                if !schedule.wait(&mut notifier) {
+                    break Ok(());
+                }
+                continue;
+            };
+
+            let elapsed = last_reading.map_or(interval, |last| now - last);
+            last_reading = Some(now);
+            let smoothed = smoother
+                .as_mut()
+                .map(|smoother| smoother.update(millidegrees, elapsed));
+            let shown = hysteresis
+                .as_mut()
+                .map(|hysteresis| hysteresis.update(smoothed.unwrap_or(millidegrees)));
+            let shown = shown.or(smoothed).unwrap_or(millidegrees);
+            let color = temp_color(&ramp, shown, base, warn);
+
+            if self.verbose {
+                let values = readers
+                    .iter()
+                    .zip(&readings)
+                    .map(|(reader, reading)| match reading {
+                        Some(value) => format!("{}={:.1}", reader.source(), *value as f64 / 1000.0),
+                        None => format!("{}=failed", reader.source()),
+                    })
+                    .collect::<Vec<_>>();
+                let mut line = format!(
+                    "{}, {}={:.1}",
+                    values.join(", "),
+                    self.aggregate.name(),
+                    millidegrees as f64 / 1000.0
+                );
+                if let Some(value) = smoothed {
+                    line += &format!(", smooth={:.1}", value as f64 / 1000.0);
+                }
+                if self.hysteresis.is_some() {
+                    line += &format!(", shown={:.1}", shown as f64 / 1000.0);
+                }
+                eprintln!("{line} -> {color}");
+            }
+
+            // A single update, the colors are kept on exit.
+            if device.dry_run || self.oneshot {
+                for (controller, limiters) in controllers.iter().zip(&mut fan_limiters) {
+                    self.channels
+                        .set_color(controller.as_ref(), color)
+                        .with_context(|| {
+                            format!("failed to set color for {}", controller.name())
+                        })?;
+                    self.update_fans(controller.as_ref(), limiters, shown, now);
+                }
+                if !self.oneshot {
+                    return Ok(());
+                }
+
+                let sensors = readers
+                    .iter()
+                    .zip(&readings)
+                    .map(|(reader, reading)| {
+                        serde_json::json!({
+                            "source": reader.source().to_string(),
+                            "celsius": reading.map(|value| value as f64 / 1000.0),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                return print_json(serde_json::json!({
+                    "celsius": shown as f64 / 1000.0,
+                    "sensors": sensors,
+                    "color": color,
+                }));
+            }
+
+            let mut failed = false;
+            let mut status = format!("{:.1}°C -> {color}", shown as f64 / 1000.0);
+            let devices = controllers
+                .iter_mut()
+                .zip(&mut fan_limiters)
+                .zip(&mut reconnects);
+            for ((controller, limiters), reconnect) in devices {
+                // Devices are re-enumerated after resume, they are found again by the serial.
+                if !controller.is_connected() {
+                    status += &format!(", reconnecting {}", controller.name());
+                    if !reconnect.ready(now) {
+                        failed = true;
+                        continue;
+                    }
+                    if let Err(e) = controller.reconnect(&api) {
+                        let delay = reconnect.failed(now);
+                        warning!(
+                            "failed to reconnect {}: {e:?}, retrying in {}",
+                            controller.name(),
+                            humantime::format_duration(delay)
+                        );
+                        failed = true;
+                        continue;
+                    }
+                    let failures = reconnect.succeeded() + 1;
+                    info!(
+                        "reconnected {} after {failures} attempts",
+                        controller.name()
+                    );
+                }
+
+                // Keep running on errors since the device might be reconnected later.
+                // Unchanged colors are not sent again, so held temperatures cause no writes.
+                if let Err(e) = self.channels.set_color(controller.as_ref(), color) {
+                    warning!("failed to set color for {}: {e:?}", controller.name());
+                    failed = true;
+                } else if let Err(e) = controller.ensure_applied() {
+                    warning!("failed to re-apply color for {}: {e:?}", controller.name());
+                    failed = true;
+                }
+                self.update_fans(controller.as_ref(), limiters, shown, now);
+            }
+
+            errors = if failed { errors + 1 } else { 0 };
+            // Ready once the color has been set on all controllers.
+            if ready || failed {
+                notifier.status(&status);
+            } else {
+                notifier.ready(&status);
+                ready = true;
+            }
+            if self.max_errors.is_some_and(|max| errors > max) {
+                break Err(anyhow::anyhow!(
+                    "giving up after {errors} failed updates, controllers couldn't be updated"
+                ));
+            }
+
+            if !schedule.wait(&mut notifier) {
+                break Ok(());
+            }
+        };
+
+        notifier.stopping();
+        if let ExitColor::Color(color) = self.on_exit {
+            for controller in controllers.iter().filter(|c| c.is_connected()) {
+                if let Err(e) = self.channels.set_color(controller.as_ref(), color) {
+                    warning!(
+                        "failed to set the exit color for {}: {e:?}",
+                        controller.name()
+                    );
+                }
+            }
+        }
+        result
+    }
+}
+
+impl CmdCpuTemp {
+    /// Returns the curve of the fan channel, a per-channel one takes precedence.
+    fn fan_curve(&self, fan_channel: usize) -> Option<&FanCurve> {
+        let curves = || self.fan_curve.iter();
+        curves()
+            .find(|arg| arg.channel == Some(fan_channel))
+            .or_else(|| curves().find(|arg| arg.channel.is_none()))
+            .map(|arg| &arg.curve)
+    }
+
+    /// Controllers without fan channels are skipped.
+    fn check_fan_curves(&self, controller: &dyn Controller) -> Result<()> {
+        for (i, arg) in self.fan_curve.iter().enumerate() {
+            anyhow::ensure!(
+                !self.fan_curve[..i]
+                    .iter()
+                    .any(|other| other.channel == arg.channel),
+                "duplicate fan curve for {}",
+                match arg.channel {
+                    Some(channel) => format!("fan channel {channel}"),
+                    None => "all fan channels".to_owned(),
+                }
+            );
+
+            let fan_channels = controller.fan_channels();
+            if let Some(channel) = arg.channel.filter(|_| fan_channels > 0) {
+                anyhow::ensure!(
+                    channel < fan_channels,
+                    "invalid fan channel {channel} for {} (valid fan channels: 0..{fan_channels})",
+                    controller.name(),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets duty cycles of the controller fans from their curves at the temperature.
+    fn update_fans(
+        &self,
+        controller: &dyn Controller,
+        limiters: &mut [DutyLimiter],
+        millidegrees: i64,
+        now: Instant,
+    ) {
+        for (fan_channel, limiter) in limiters.iter_mut().enumerate() {
+            let Some(curve) = self.fan_curve(fan_channel) else {
+                continue;
+            };
+            let Some(duty) = limiter.update(curve.duty(millidegrees as f32 / 1000.0), now) else {
+                continue;
+            };
+
+            match controller.set_fan_duty(fan_channel, duty) {
+                Ok(()) if self.verbose => {
+                    eprintln!("{}: fan {fan_channel} -> {duty}%", controller.name());
+                }
+                Ok(()) => {}
+                Err(e) => {
+                    warning!(
+                        "failed to set fan {fan_channel} duty for {}: {e:?}",
+                        controller.name()
+                    );
+                    // Retry on the next reading.
+                    *limiter = DutyLimiter::default();
+                }
+            }
+        }
+    }
+}
+
+/// Fan curve of `cpu-temp`, optionally for a single fan channel (`N=curve`).
+#[derive(Debug, Clone)]
+struct FanCurveArg {
+    channel: Option<usize>,
+    curve: FanCurve,
+}
+
+impl FromStr for FanCurveArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (channel, curve) = match s.split_once('=') {
+            Some((channel, curve)) => {
+                let channel = channel
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid fan channel `{}`", channel.trim()))?;
+                (Some(channel), curve)
+            }
+            None => (None, s),
+        };
+        Ok(Self {
+            channel,
+            curve: curve.parse()?,
+        })
+    }
+}
+
+/// Holds the fan duty for a while after each change and ignores small changes,
+/// so the fans don't surge audibly with every spike of the temperature.
+#[derive(Debug, Default, Clone)]
+struct DutyLimiter {
+    last: Option<(u8, Instant)>,
+}
+
+impl DutyLimiter {
+    const MIN_INTERVAL: Duration = Duration::from_secs(5);
+    /// In percentage points, except for going to the full speed.
+    const MIN_STEP: u8 = 3;
+
+    /// Returns the duty cycle to set, if it should be changed.
+    fn update(&mut self, duty: u8, now: Instant) -> Option<u8> {
+        if let Some((last, changed)) = self.last {
+            let small = duty.abs_diff(last) < Self::MIN_STEP && duty < 100;
+            if duty == last || small || now.duration_since(changed) < Self::MIN_INTERVAL {
+                return None;
+            }
+        }
+        self.last = Some((duty, now));
+        Some(duty)
+    }
+}
+
+/// Ticks at fixed intervals, ticks missed by slow updates are skipped
+/// instead of being caught up without sleeping.
+struct Schedule {
+    interval: Duration,
+    next: Instant,
+    /// The last wait returned before the tick.
+    woken: bool,
+}
+
+/// Why [`Schedule::wait_or`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Wake {
+    Tick,
+    /// Before the tick, the next wait is for the same tick.
+    Early,
+    Interrupted,
+}
+
+impl Schedule {
+    /// The first tick is at `start`.
+    fn new(interval: Duration, start: Instant) -> Self {
+        Self {
+            interval,
+            next: start,
+            woken: false,
+        }
+    }
+
+    /// Moves to the next tick after `now`, returns the number of skipped ticks.
+    fn advance(&mut self, now: Instant) -> u32 {
+        self.next += self.interval;
+        if self.next > now {
+            return 0;
+        }
+        let behind = now.duration_since(self.next).as_nanos() / self.interval.as_nanos();
+        let skipped = u32::try_from(behind + 1).unwrap_or(u32::MAX);
+        self.next += self.interval * skipped;
+        skipped
+    }
+
+    /// Sleeps until the next tick, returns `false` if interrupted.
+    ///
+    /// The watchdog is pinged in between if the interval is longer than its timeout.
+    fn wait(&mut self, notifier: &mut Notifier) -> bool {
+        loop {
+            match self.wait_or(|| notifier.watchdog_due()) {
+                Wake::Tick => return true,
+                Wake::Early => notifier.watchdog(),
+                Wake::Interrupted => return false,
+            }
+        }
+    }
+
+    /// Sleeps until the next tick or until `wake` is true.
+    fn wait_or(&mut self, wake: impl Fn() -> bool) -> Wake {
+        if !std::mem::take(&mut self.woken) {
+            let skipped = self.advance(Instant::now());
+            if skipped > 0 {
+                warning!("the update took longer than the interval, skipped {skipped} updates");
+            }
+        }
+        if !wait_until(self.next.saturating_duration_since(Instant::now()), wake) {
+            return Wake::Interrupted;
+        }
+        self.woken = Instant::now() < self.next;
+        if self.woken { Wake::Early } else { Wake::Tick }
+    }
+}
+
+/// Color applied when `cpu-temp` stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitColor {
+    Keep,
+    Color(Color),
+}
+
+impl FromStr for ExitColor {
+    type Err = nzxtcli::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("keep") {
+            Ok(Self::Keep)
+        } else {
+            s.parse().map(Self::Color)
+        }
+    }
+}
+
+impl CmdCpuTemp {
+    /// Returns the ramp over `0..=1` along with the base and warn temperatures.
+    fn ramp(&self) -> Result<(ColorRamp, f32, f32)> {
+        scale_ramp(self.ramp.as_ref(), self.base, self.warn, self.interpolation)
+    }
+}
+
+/// Returns the ramp over `0..=1` along with the base and warn temperatures,
+/// which are taken from the first and the last stops of a custom ramp.
+fn scale_ramp(
+    ramp: Option<&ColorRamp>,
+    base: u64,
+    warn: u64,
+    interpolation: Interpolation,
+) -> Result<(ColorRamp, f32, f32)> {
+    let Some(ramp) = ramp else {
+        anyhow::ensure!(
+            base < warn,
+            "'warn' temperature must be greater than the 'base'"
+        );
+        let ramp = temp_ramp().with_interpolation(interpolation);
+        return Ok((ramp, base as f32, warn as f32));
+    };
+
+    let stops = ramp.stops();
+    let (base, warn) = (stops[0].0, stops[stops.len() - 1].0);
+    anyhow::ensure!(
+        base < warn,
+        "color ramp must span a range of temperatures, all stops are at {base}"
+    );
+    let stops = stops
+        .iter()
+        .map(|&(temp, color)| ((temp - base) / (warn - base), color))
+        .collect();
+    let ramp = ColorRamp::new(stops)?.with_interpolation(interpolation);
+    Ok((ramp, base, warn))
+}
+
+/// Prints the color of each whole degree as hex, followed by a colored block
+/// if stdout is a terminal.
+fn print_ramp_preview(ramp: &ColorRamp, base: f32, warn: f32) {
+    let terminal = std::io::stdout().is_terminal();
+    for temp in base.floor() as i64..=warn.ceil() as i64 {
+        let color = temp_color(ramp, temp * 1000, base, warn);
+        if terminal {
+            println!(
+                "{temp:>4}°C  {color}  \x1b[48;2;{};{};{}m      \x1b[0m",
+                color.red(),
+                color.green(),
+                color.blue()
+            );
+        } else {
+            println!("{temp:>4}°C  {color}");
+        }
+    }
+}
+
+/// List hwmon temperature sensors along with their names for `cpu-temp --sensor`.
+#[derive(Parser)]
+struct CmdSensors {}
+
+impl CmdSensors {
+    fn run(self) -> Result<()> {
+        let info = hwmon::scan(Path::new(HWMON_ROOT))?
+            .into_iter()
+            .map(|sensor| {
+                serde_json::json!({
+                    "sensor": sensor.spec().to_string(),
+                    "chip": sensor.chip,
+                    "label": sensor.label,
+                    "path": sensor.path,
+                    "celsius": sensor.celsius,
+                })
+            })
+            .collect::<Vec<_>>();
+        print_json(info)
+    }
+}
+
+/// Manage pumps of liquid coolers.
+#[derive(Subcommand)]
+enum CmdPump {
+    Set(CmdPumpSet),
+}
+
+impl CmdPump {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        match self {
+            Self::Set(cmd) => cmd.run(device),
+        }
+    }
+}
+
+/// Set a fixed pump duty cycle for all liquid coolers.
+#[derive(Parser)]
+struct CmdPumpSet {
+    /// Duty cycle in percent (values below the pump minimum are raised).
+    #[clap(value_parser = clap::value_parser!(u8).range(0..=100))]
+    percent: u8,
+}
+
+impl CmdPumpSet {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let controllers = device.find_controllers(&api, &AccessoryArgs::default())?;
+
+        let mut updates = Updates::default();
+        for controller in controllers {
+            if !controller.is_liquid_cooler() {
+                continue;
+            }
+
+            let result = controller
+                .set_pump_duty(self.percent)
+                .with_context(|| format!("failed to set pump duty for {}", controller.name()));
+            updates.record(controller.as_ref(), result);
+        }
+
+        updates.finish()
+    }
+}
+
+/// Write a raw HID report and print replies (for protocol research).
+///
+/// Malformed reports can confuse the firmware until the device is power cycled.
+/// The target device must be selected explicitly (e.g. with `--device-pid`).
+#[derive(Parser)]
+struct CmdRaw {
+    /// Report bytes in hex (e.g. `2003` or `20 03`).
+    report: String,
+
+    /// How long to wait for replies.
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "200ms")]
+    timeout: Duration,
+}
+
+impl CmdRaw {
+    fn run(self, device: &DeviceArgs) -> Result<()> {
+        let report = parse_hex_bytes(&self.report).context("invalid report")?;
+        let options = device.find_options(&AccessoryArgs::default())?;
+        anyhow::ensure!(
+            options.has_filters(),
+            "raw reports require an explicit device selection"
+        );
+
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let controllers = find_controllers(&api, &options)?;
+
+        for controller in controllers {
+            let written = controller.send_raw(&report)?;
+            eprintln!("{}: {written} bytes written", controller.name());
+
+            let deadline = Instant::now() + self.timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+
+                let reply = controller.read_raw(remaining)?;
+                if !reply.is_empty() {
+                    println!("{}", format_hex(&reply));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(clap::Args)]
+struct ChannelArgs {
+    /// RGB channels to update: `all`, indices and ranges (e.g. `0,2-3`)
+    /// or a group from the config.
+    #[clap(long, visible_alias = "channel", default_value_t, value_parser = parse_channels)]
+    channels: ChannelSelector,
+}
+
+/// Named channel selectors from the config file.
+static CHANNEL_GROUPS: OnceLock<BTreeMap<String, ChannelSelector>> = OnceLock::new();
+
+fn init_channel_groups(groups: BTreeMap<String, ChannelSelector>) {
+    // Groups are the same for all configs in tests.
+    let _ = CHANNEL_GROUPS.set(groups);
+}
+
+fn parse_channels(s: &str) -> Result<ChannelSelector> {
+    let group = CHANNEL_GROUPS.get().and_then(|groups| groups.get(s.trim()));
+    match group {
+        Some(selector) => Ok(selector.clone()),
+        None => Ok(s.parse()?),
+    }
+}
+
+impl ChannelArgs {
+    fn resolve(&self, controller: &dyn Controller) -> Result<Vec<usize>> {
+        self.channels
+            .resolve(controller.rgb_channels().len())
+            .with_context(|| {
+                format!(
+                    "invalid channels for {} (valid channels: {})",
+                    controller.name(),
+                    valid_channels(controller)
+                )
+            })
+    }
+
+    /// Returns the channels to update, channels without LEDs are skipped
+    /// unless selected explicitly.
+    fn targets(&self, controller: &dyn Controller) -> Result<Vec<usize>> {
+        let mut channels = self.resolve(controller)?;
+        if self.channels == ChannelSelector::All {
+            channels.retain(|&channel| controller.rgb_channels()[channel].led_count > 0);
+        }
+        Ok(channels)
+    }
+
+    /// Channels without LEDs are skipped unless selected explicitly.
+    fn set_color(&self, controller: &dyn Controller, color: Color) -> Result<()> {
+        match &self.channels {
+            ChannelSelector::All => controller.set_fixed_color(color)?,
+            ChannelSelector::Channels(_) => {
+                for channel in self.resolve(controller)? {
+                    controller.set_channel_color(channel, color)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_channel(controller: &dyn Controller, channel: usize) -> Result<()> {
+    anyhow::ensure!(
+        channel < controller.rgb_channels().len(),
+        "invalid channel {channel} for {} (valid channels: {})",
+        controller.name(),
+        valid_channels(controller)
+    );
+    Ok(())
+}
+
+fn valid_channels(controller: &dyn Controller) -> String {
+    match controller.rgb_channels().len() {
+        0 => "none".to_owned(),
+        1 => "0".to_owned(),
+        count => format!("0-{}", count - 1),
+    }
+}
+
+/// Color for all selected channels or for a single one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ColorArg {
+    channel: Option<usize>,
+    color: Color,
+}
+
+fn parse_color_arg(s: &str) -> Result<ColorArg> {
+    let (channel, color) = match s.split_once('=') {
+        Some((channel, color)) => (Some(parse_channel_key(channel)?), color),
+        None => (None, s),
+    };
+    let color = color.parse::<Color>()?;
+    Ok(ColorArg { channel, color })
+}
+
+/// Parses channel keys like `ch2` or `2`.
+fn parse_channel_key(s: &str) -> Result<usize> {
+    let s = s.trim();
+    s.strip_prefix("ch")
+        .unwrap_or(s)
+        .parse::<usize>()
+        .context("invalid channel")
+}
+
+fn parse_hex_bytes(s: &str) -> Result<Vec<u8>> {
+    let digits = s
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect::<String>();
+    let digits = digits.strip_prefix("0x").unwrap_or(&digits);
+    anyhow::ensure!(digits.is_ascii(), "invalid hex string");
+    anyhow::ensure!(digits.len() % 2 == 0, "odd number of hex digits");
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).context("invalid hex byte"))
+        .collect()
+}
+
+fn parse_device_entry(s: &str) -> Result<(u16, usize, usize)> {
+    let mut parts = s.split(':');
+    let (Some(pid), Some(rgb_channels), Some(fan_channels), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        anyhow::bail!("expected device in the `PID:RGB:FANS` format");
+    };
+
+    let pid = parse_hex_u16(pid)?;
+    let rgb_channels = rgb_channels
+        .trim()
+        .parse::<usize>()
+        .context("invalid RGB channel count")?;
+    let fan_channels = fan_channels
+        .trim()
+        .parse::<usize>()
+        .context("invalid fan channel count")?;
+    Ok((pid, rgb_channels, fan_channels))
+}
+
+fn parse_hex_u16(s: &str) -> Result<u16> {
+    let s = s.trim();
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    u16::from_str_radix(s, 16).context("invalid hex number")
+}
+
+/// Extracts the level from the `RUST_LOG` filter (e.g. `warn,nzxtcli=debug`).
+///
+/// Directives for other targets are ignored.
+fn parse_log_filter(filter: &str) -> Option<logging::Level> {
+    let mut default = None;
+    for directive in filter.split(',').map(str::trim) {
+        match directive.split_once('=') {
+            Some(("nzxtcli", level)) => return level.parse().ok(),
+            Some(_) => {}
+            None => default = directive.parse().ok().or(default),
+        }
+    }
+    default
+}
+
+fn color_help() -> String {
+    let names = Color::NAMED
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    format!(
+        "Hex color (e.g. `ff8800` or `#f80`), `rgb(255, 128, 0)`, `hsv(30, 100, 100)`, \
+         color temperature (e.g. `2700K`) or one of: {}",
+        names.join(", ")
+    )
+}
+
+fn format_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Device filters are combined, so only devices matching all of them are used.
+#[derive(clap::Args)]
+struct DeviceArgs {
+    /// Only use the device with the specified serial number.
+    #[clap(long, global = true, visible_alias = "serial")]
+    device_serial: Option<String>,
+
+    /// Only use devices with the specified product id (in hex).
+    #[clap(long, global = true, visible_alias = "pid", value_parser = parse_hex_u16)]
+    device_pid: Option<u16>,
+
+    /// Only use the n-th matching device (starting from 0).
+    #[clap(long, global = true, visible_alias = "index")]
+    device_index: Option<usize>,
+
+    /// Treat an unknown product id as a Hue 2 controller (e.g. `0x2042:6:3`).
+    #[clap(long = "add-device", global = true, value_name = "PID:RGB:FANS", value_parser = parse_device_entry)]
+    extra_devices: Vec<(u16, usize, usize)>,
+
+    /// Try direct LED control even if the firmware is known not to support it.
+    #[clap(long, global = true)]
+    skip_firmware_check: bool,
+
+    /// Print the HID reports instead of sending them (only Hue 2 controllers).
+    ///
+    /// The devices are only queried for their accessories, `cpu-temp` sets
+    /// the color once.
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    /// Log all HID transfers to the file (JSON lines).
+    #[clap(long, global = true, hide = true, value_name = "PATH")]
+    record_hid: Option<PathBuf>,
+}
+
+impl DeviceArgs {
+    /// Known controllers along with the ones added by `--add-device`.
+    fn registry(&self) -> ControllerRegistry {
+        let mut registry = ControllerRegistry::default();
+        for &(pid, rgb_channels, fan_channels) in &self.extra_devices {
+            let name = "NZXT Hue 2 compatible controller";
+            if let Some(prev) = registry.register(pid, name, rgb_channels, fan_channels) {
+                warning!("overriding known device {pid:04x} ({prev})");
+            }
+        }
+        registry
+    }
+
+    fn find_options(&self, accessories: &AccessoryArgs) -> Result<FindOptions> {
+        let mut options = FindOptions::new()
+            .accessory_overrides(accessories.overrides())
+            .color_orders(accessories.color_orders())
+            .skip_firmware_check(self.skip_firmware_check)
+            .registry(self.registry());
+        if let Some(serial) = &self.device_serial {
+            options = options.serial(serial);
+        }
+        if let Some(pid) = self.device_pid {
+            options = options.product_id(pid);
+        }
+        if let Some(index) = self.device_index {
+            options = options.index(index);
+        }
+        if self.dry_run {
+            options = options.dry_run(PacketPrinter::stdout());
+        }
+        if let Some(path) = &self.record_hid {
+            let recorder = HidRecorder::create(path)
+                .with_context(|| format!("failed to create HID recording {}", path.display()))?;
+            options = options.record_hid(recorder);
+        }
+        Ok(options)
+    }
+
+    fn find_controllers(
+        &self,
+        api: &HidApi,
+        accessories: &AccessoryArgs,
+    ) -> Result<Vec<Box<dyn Controller>>> {
+        let controllers = find_controllers(api, &self.find_options(accessories)?)?;
+        Ok(controllers)
+    }
+}
+
+#[derive(Default, clap::Args)]
+struct AccessoryArgs {
+    /// Declare LED count of an unknown accessory id (e.g. `0x1f=16`).
+    ///
+    /// Ids of known accessories and of empty slots (0) are rejected.
+    #[clap(long = "accessory-override", value_name = "ID=LEDS", value_parser = parse_accessory_override)]
+    overrides: Vec<(u8, u8)>,
+
+    /// Color component order of the channel LEDs (e.g. `ch2=rgb`).
+    #[clap(long = "color-order", value_name = "CHANNEL=ORDER", value_parser = parse_color_order)]
+    color_orders: Vec<(usize, ColorOrder)>,
+}
+
+impl AccessoryArgs {
+    fn overrides(&self) -> AccessoryOverrides {
+        AccessoryOverrides::from_iter(self.overrides.iter().copied())
+    }
+
+    fn color_orders(&self) -> ColorOrders {
+        ColorOrders::from_iter(self.color_orders.iter().copied())
+    }
+}
+
+fn parse_accessory_override(s: &str) -> Result<(u8, u8)> {
+    let (id, led_count) = s
+        .split_once('=')
+        .context("expected accessory override in the `ID=LEDS` format")?;
+
+    let id = id.trim();
+    let id = match id.strip_prefix("0x").or_else(|| id.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => id.parse::<u8>(),
+    }
+    .context("invalid accessory id")?;
+    anyhow::ensure!(id != 0, "accessory id 0 marks an empty slot");
+    let device_type = nzxtcli::DeviceType::from(id);
+    anyhow::ensure!(
+        matches!(device_type, nzxtcli::DeviceType::Unknown(_)),
+        "accessory id {id:#04x} is the known {}, only unknown ids can be overridden",
+        device_type.name()
+    );
+    let led_count = led_count
+        .trim()
+        .parse::<u8>()
+        .context("invalid LED count")?;
+
+    Ok((id, led_count))
+}
+
+#[derive(clap::Args)]
+struct TransformArgs {
+    /// Gamma correction of the sent colors (e.g. `2.2`).
+    #[clap(long, default_value_t = 1.0)]
+    gamma: f32,
+
+    /// Scale all colors on the host (in percent).
+    #[clap(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    brightness: u8,
+}
+
+impl TransformArgs {
+    fn transform(&self) -> Result<ColorTransform> {
+        let transform = ColorTransform::new(self.gamma, self.brightness as f32 / 100.0)?;
+        Ok(transform)
+    }
+}
+
+fn parse_color_order(s: &str) -> Result<(usize, ColorOrder)> {
+    let (channel, order) = s
+        .split_once('=')
+        .context("expected color order in the `CHANNEL=ORDER` format")?;
+
+    let channel = parse_channel_key(channel)?;
+    let order = order.trim().parse::<ColorOrder>()?;
+
+    Ok((channel, order))
+}
+
+/// Same as [`nzxtcli::find_controllers_with`] but devices which failed to open
+/// are reported along with the hints.
+fn find_controllers(api: &HidApi, options: &FindOptions) -> Result<Vec<Box<dyn Controller>>> {
+    let (controllers, errors) = find_controllers_verbose(api, options);
+    print_discovery_errors(&errors);
+    anyhow::ensure!(
+        !(controllers.is_empty() && options.has_filters()),
+        nzxtcli::Error::NoMatchingDevice
+    );
+    Ok(controllers)
+}
+
+fn print_discovery_errors(errors: &[DiscoveryError]) {
+    if errors.is_empty() {
+        return;
+    }
+
+    // A single message, so that the hints are printed at the same level.
+    let mut message = String::from("some supported devices could not be opened:");
+    for e in errors {
+        let _ = write!(message, "\n  {e}");
+    }
+    if errors
+        .iter()
+        .any(|e| matches!(e.error, nzxtcli::Error::PermissionDenied { .. }))
+    {
+        message += "\nhint: install the udev rules with `sudo nzxtcli udev-rules --install` \
+            and replug the device if it still can't be opened";
+    }
+    if errors
+        .iter()
+        .any(|e| matches!(e.error, nzxtcli::Error::DeviceBusy { .. }))
+    {
+        message += "\nhint: close NZXT CAM or other software which controls the device";
+    }
+    warning!("{message}");
+}
+
+/// Exit codes and error kinds of the JSON error reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorKind {
+    Failure = 1,
+    Usage = 2,
+    NoDevice = 3,
+    PermissionDenied = 4,
+    Timeout = 5,
+    Unsupported = 6,
+    Device = 7,
+    PartialFailure = 8,
+}
+
+impl ErrorKind {
+    const ALL: [Self; 8] = [
+        Self::Failure,
+        Self::Usage,
+        Self::NoDevice,
+        Self::PermissionDenied,
+        Self::Timeout,
+        Self::Unsupported,
+        Self::Device,
+        Self::PartialFailure,
+    ];
+
+    fn of(error: &anyhow::Error) -> Self {
+        if error.is::<PartialFailure>() {
+            return Self::PartialFailure;
+        }
+        let error = match error.downcast_ref::<ControllerError>() {
+            Some(e) => &e.error,
+            None => error,
+        };
+        if error
+            .chain()
+            .any(|e| e.is::<clap::Error>() || e.is::<ConfigError>())
+        {
+            return Self::Usage;
+        }
+
+        use nzxtcli::Error;
+        match error.chain().find_map(|e| e.downcast_ref::<Error>()) {
+            Some(Error::NoMatchingDevice | Error::SerialNotFound { .. }) => Self::NoDevice,
+            Some(Error::PermissionDenied { .. }) => Self::PermissionDenied,
+            Some(Error::Timeout) => Self::Timeout,
+            Some(Error::Unsupported(_) | Error::FirmwareTooOld { .. } | Error::DryRun) => {
+                Self::Unsupported
+            }
+            Some(
+                Error::InvalidChannel { .. }
+                | Error::InvalidFanChannel { .. }
+                | Error::InvalidArgument(_)
+                | Error::InvalidColor(_)
+                | Error::TooManyColorsPerGroup { .. }
+                | Error::TooManyGroups { .. },
+            ) => Self::Usage,
+            Some(
+                Error::Hid(_)
+                | Error::DeviceBusy { .. }
+                | Error::ShortWrite { .. }
+                | Error::ShortRead { .. }
+                | Error::WorkerStopped
+                | Error::Replay(_),
+            ) => Self::Device,
+            _ => Self::Failure,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Failure => "failure",
+            Self::Usage => "usage",
+            Self::NoDevice => "no-device",
+            Self::PermissionDenied => "permission-denied",
+            Self::Timeout => "timeout",
+            Self::Unsupported => "unsupported",
+            Self::Device => "device",
+            Self::PartialFailure => "partial-failure",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Self::Failure => "other errors",
+            Self::Usage => "invalid arguments or config",
+            Self::NoDevice => "no matching device",
+            Self::PermissionDenied => "permission denied while opening a device",
+            Self::Timeout => "device didn't respond in time",
+            Self::Unsupported => "not supported by the device or its firmware",
+            Self::Device => "device communication error",
+            Self::PartialFailure => "some of the controllers failed to update",
+        }
+    }
+}
+
+fn exit_codes_help() -> String {
+    let mut help = "Exit codes:\n  0  success\n".to_owned();
+    for kind in ErrorKind::ALL {
+        help += &format!("  {}  {}\n", kind as u8, kind.description());
+    }
+    help
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    #[default]
+    Text,
+    /// A single JSON object.
+    Json,
+}
+
+impl OutputFormat {
+    /// Errors can happen before the arguments are parsed, so `--format` is looked up early.
+    fn from_args(argv: &[OsString]) -> Self {
+        let mut args = argv.iter().skip(1).take_while(|arg| *arg != "--");
+        while let Some(arg) = args.next() {
+            let value = if arg == "--format" {
+                args.next().and_then(|arg| arg.to_str())
+            } else {
+                arg.to_str().and_then(|arg| arg.strip_prefix("--format="))
+            };
+            if value == Some("json") {
+                return Self::Json;
+            } else if value.is_some() {
+                return Self::Text;
+            }
+        }
+        Self::Text
+    }
+}
+
+/// Prints the error to stderr and returns its exit code.
+fn report_error(error: &anyhow::Error, format: OutputFormat) -> ExitCode {
+    let kind = ErrorKind::of(error);
+    let clap_error = error.downcast_ref::<clap::Error>();
+    match format {
+        OutputFormat::Text => match clap_error {
+            Some(e) => {
+                let _ = e.print();
+            }
+            None => eprintln!("Error: {error:?}"),
+        },
+        OutputFormat::Json => {
+            let message = match clap_error {
+                Some(e) => clap_message(e),
+                None => format!("{error:#}"),
+            };
+            let device = error
+                .downcast_ref::<ControllerError>()
+                .map(|e| e.device.as_str());
+            let report = serde_json::json!({
+                "error": {
+                    "kind": kind.name(),
+                    "message": message,
+                    "device": device,
+                }
+            });
+            eprintln!("{report}");
+        }
+    }
+    ExitCode::from(kind as u8)
+}
+
+/// First paragraph of the clap error (without usage and tips) on a single line.
+fn clap_message(error: &clap::Error) -> String {
+    let rendered = error.to_string();
+    let message = rendered
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    match message.strip_prefix("error: ") {
+        Some(message) => message.to_owned(),
+        None => message,
+    }
+}
+
+/// Failure of a single controller, remembers which one for the error report.
+#[derive(Debug)]
+struct ControllerError {
+    device: String,
+    error: anyhow::Error,
+}
+
+/// Formatted as the inner error, so the report looks the same.
+impl fmt::Display for ControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for ControllerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Only some of the controllers were updated.
+#[derive(Debug)]
+struct PartialFailure {
+    failed: usize,
+    total: usize,
+}
+
+impl fmt::Display for PartialFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} controllers failed to update",
+            self.failed, self.total
+        )
+    }
+}
+
+impl std::error::Error for PartialFailure {}
+
+/// Results of the per-controller updates.
+///
+/// A failed controller doesn't stop the update of the others, a JSON line
+/// is printed for each of them instead.
+#[derive(Default)]
+struct Updates {
+    updated: usize,
+    failures: Vec<ControllerError>,
+}
+
+impl Updates {
+    /// Returns the value of the successful update.
+    fn record<T>(&mut self, controller: &dyn Controller, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                print_updated(controller);
+                self.updated += 1;
+                Some(value)
+            }
+            Err(error) => {
+                let line = serde_json::json!({
+                    "failed": controller.name(),
+                    "product_id_hex": format!("{:04x}", controller.product_id()),
+                    "serial": controller.serial(),
+                    "error": {
+                        "kind": ErrorKind::of(&error).name(),
+                        "message": format!("{error:#}"),
+                    },
+                });
+                println!("{line}");
+                self.failures.push(ControllerError {
+                    device: controller.name().to_owned(),
+                    error,
+                });
+                None
+            }
+        }
+    }
+
+    /// Fails with the controller error if none of them were updated.
+    fn finish(self) -> Result<()> {
+        let failed = self.failures.len();
+        let Some(error) = self.failures.into_iter().next() else {
+            return Ok(());
+        };
+        if self.updated == 0 {
+            return Err(error.into());
+        }
+        Err(PartialFailure {
+            failed,
+            total: failed + self.updated,
+        }
+        .into())
+    }
+}
+
+/// Prints a JSON line for scripts to confirm which controller was updated.
+fn print_updated(controller: &dyn Controller) {
+    let line = serde_json::json!({
+        "updated": controller.name(),
+        "product_id_hex": format!("{:04x}", controller.product_id()),
+        "serial": controller.serial(),
+    });
+    println!("{line}");
+}
+
+/// Pretty-prints the output if stdout is a terminal.
+fn print_json<T: Serialize>(output: T) -> Result<()> {
+    write_json(output, std::io::stdout().is_terminal())
+}
+
+fn write_json<T: Serialize>(output: T, pretty: bool) -> Result<()> {
+    let output = if pretty {
+        serde_json::to_string_pretty(&output)
+    } else {
+        serde_json::to_string(&output)
+    }?;
+
+    println!("{output}");
+    Ok(())
+}
+
+/// Colors from the base (`0`) to the warn (`1`) temperature.
+fn temp_ramp() -> ColorRamp {
+    ColorRamp::new(vec![
+        (0.0, Color::new(0x07, 0x05, 0x02)),
+        (0.25, Color::new(0x1B, 0x2E, 0x04)),
+        (0.6, Color::new(0x39, 0x20, 0x02)),
+        (0.7, Color::new(0x79, 0x09, 0x00)),
+        (0.9, Color::new(0xff, 0x00, 0x00)),
+    ])
+    .unwrap()
+}
+
+/// Picks the ramp color for the hwmon temperature (in millidegrees celsius).
+fn temp_color(ramp: &ColorRamp, millidegrees: i64, base: f32, warn: f32) -> Color {
+    ramp.sample_range(millidegrees as f32 / 1000.0, base, warn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_filter() {
+        use logging::Level;
+
+        assert_eq!(parse_log_filter("debug"), Some(Level::Debug));
+        assert_eq!(parse_log_filter("warn,nzxtcli=trace"), Some(Level::Trace));
+        assert_eq!(parse_log_filter("nzxtcli=off,info"), Some(Level::Off));
+        assert_eq!(parse_log_filter("hidapi=debug"), None);
+        assert_eq!(parse_log_filter(""), None);
+    }
+
+    #[test]
+    fn accessory_override_args() {
+        assert_eq!(parse_accessory_override("0x1f=16").unwrap(), (0x1f, 16));
+        assert_eq!(parse_accessory_override("31=16").unwrap(), (0x1f, 16));
+        assert!(parse_accessory_override("0x1f").is_err());
+        assert!(parse_accessory_override("0x1ff=16").is_err());
+        assert!(parse_accessory_override("0x1f=300").is_err());
+        assert!(parse_accessory_override("0=16").is_err());
+        // Known accessories can't be overridden.
+        assert!(parse_accessory_override("0x13=16").is_err());
+    }
+
+    #[test]
+    fn color_order_args() {
+        assert_eq!(parse_color_order("ch2=rgb").unwrap(), (2, ColorOrder::Rgb));
+        assert_eq!(parse_color_order("0=BGR").unwrap(), (0, ColorOrder::Bgr));
+        assert!(parse_color_order("ch2").is_err());
+        assert!(parse_color_order("chx=rgb").is_err());
+        assert!(parse_color_order("ch1=rgbw").is_err());
+
+        let app =
+            App::try_parse_from(["nzxtcli", "set-color", "ff0000", "--color-order", "ch1=rgb"])
+                .unwrap();
+        let SubCmd::SetColor(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(
+            cmd.accessories.color_orders().get(&1),
+            Some(&ColorOrder::Rgb)
+        );
+        assert!(cmd.transform.transform().unwrap().is_identity());
+    }
+
+    #[test]
+    fn color_args() {
+        let red = Color::new(0xff, 0, 0);
+        assert_eq!(parse_color_arg("#ff0000").unwrap(), ColorArg {
+            channel: None,
+            color: red
+        });
+        assert_eq!(parse_color_arg("ch1=#0044ff").unwrap(), ColorArg {
+            channel: Some(1),
+            color: Color::new(0, 0x44, 0xff)
+        });
+        assert_eq!(parse_color_arg("2=red").unwrap().channel, Some(2));
+        assert!(parse_color_arg("chx=red").is_err());
+        assert!(parse_color_arg("ch1=").is_err());
+
+        let app = App::try_parse_from([
+            "nzxtcli",
+            "set-color",
+            "ch0=#ff0000",
+            "ch1=#0044ff",
+            "--channel",
+            "0",
+        ])
+        .unwrap();
+        let SubCmd::SetColor(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(cmd.colors.len(), 2);
+        assert_eq!(cmd.channels.channels, ChannelSelector::Channels(vec![0]));
+        assert!(App::try_parse_from(["nzxtcli", "set-color"]).is_err());
+    }
+
+    #[test]
+    fn device_filter_args() {
+        let app = App::try_parse_from([
+            "nzxtcli",
+            "set-color",
+            "red",
+            "--pid",
+            "2012",
+            "--serial",
+            "ABC",
+            "--index",
+            "1",
+        ])
+        .unwrap();
+        assert_eq!(app.device.device_pid, Some(0x2012));
+        assert_eq!(app.device.device_serial.as_deref(), Some("ABC"));
+        assert_eq!(app.device.device_index, Some(1));
+
+        let options = app.device.find_options(&AccessoryArgs::default()).unwrap();
+        assert!(options.has_filters());
+    }
+
+    #[test]
+    fn set_mode_args() {
+        let app = App::try_parse_from([
+            "nzxtcli",
+            "set-mode",
+            "breathing",
+            "red",
+            "#0044ff",
+            "--speed",
+            "fast",
+            "--direction",
+            "backward",
+        ])
+        .unwrap();
+        let SubCmd::SetMode(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(cmd.mode, LedMode::Breathing);
+        assert_eq!(cmd.colors.len(), 2);
+        assert_eq!(cmd.speed, 3);
+        assert_eq!(cmd.direction, Direction::Backward);
+
+        assert_eq!(parse_effect_speed("0").unwrap(), 0);
+        assert_eq!(parse_effect_speed("Normal").unwrap(), 2);
+        assert!(parse_effect_speed("5").is_err());
+        assert!(App::try_parse_from(["nzxtcli", "set-mode", "sparkle"]).is_err());
+    }
+
+    #[test]
+    fn stream_frames() {
+        let controllers = (0..2)
+            .map(|_| {
+                let (_, controller) = nzxtcli::transport::mock_controller(&[&[0x13], &[0x13], &[]]);
+                Box::new(controller) as Box<dyn Controller>
+            })
+            .collect::<Vec<_>>();
+        let channels = ChannelArgs {
+            channels: ChannelSelector::All,
+        };
+
+        let updates = frame_updates(
+            r#"{"1": ["red"]}"#,
+            &controllers,
+            &channels,
+            LedFill::Repeat,
+        )
+        .unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0][0].0, 1);
+
+        // The frame is counted once, not per controller.
+        let mut stats = StreamStats::default();
+        for line in [r#"{"5": ["red"]}"#, r#"["nope"]"#] {
+            if let Err(e) = frame_updates(line, &controllers, &channels, LedFill::Repeat) {
+                stats.malformed(e);
+            }
+        }
+        assert_eq!(stats.malformed, 2);
+    }
+
+    #[test]
+    fn led_frames() {
+        let red = Color::new(0xff, 0, 0);
+        let blue = Color::new(0, 0, 0xff);
+
+        assert_eq!(
+            parse_led_frame(r##"["#ff0000", "0000ff"]"##).unwrap(),
+            LedFrame::Flat(vec![red, blue])
+        );
+        assert_eq!(
+            parse_led_frame(r#"{"1": ["red"], "0": []}"#).unwrap(),
+            LedFrame::Channels(BTreeMap::from([(0, vec![]), (1, vec![red])]))
+        );
+        assert!(parse_led_frame(r#""red""#).is_err());
+        assert!(parse_led_frame(r#"["nope"]"#).is_err());
+        assert!(parse_led_frame(r#"{"ch0": ["red"]}"#).is_err());
+
+        let colors = [red, blue];
+        assert_eq!(fit_leds(&colors, 4, LedFill::Black).unwrap(), [
+            red,
+            blue,
+            Color::BLACK,
+            Color::BLACK
+        ]);
+        assert_eq!(fit_leds(&colors, 3, LedFill::Repeat).unwrap(), [
+            red, blue, blue
+        ]);
+        assert_eq!(fit_leds(&colors, 2, LedFill::Error).unwrap(), colors);
+        assert!(fit_leds(&colors, 3, LedFill::Error).is_err());
+        assert_eq!(
+            fit_leds(&[], 2, LedFill::Repeat).unwrap(),
+            [Color::BLACK; 2]
+        );
+
+        let error = fit_leds(&colors, 1, LedFill::Black).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "2 colors given, but the channel has only 1 LEDs"
+        );
+    }
+
+    #[test]
+    fn stream_args() {
+        let app = App::try_parse_from([
+            "nzxtcli",
+            "stream",
+            "--channel",
+            "0",
+            "--fps",
+            "60",
+            "--on-exit",
+            "off",
+        ])
+        .unwrap();
+        let SubCmd::Stream(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(cmd.fps, 60.0);
+        assert_eq!(cmd.on_exit, Some(Color::BLACK));
+        assert_eq!(cmd.fill, LedFill::Black);
+    }
+
+    #[test]
+    fn udev_rules_cover_all_devices() {
+        let products = supported_products(&ControllerRegistry::default());
+        let rules = udev_rules(&products, UdevAccess::Uaccess);
+        for (pid, _) in &products {
+            assert!(rules.contains(&format!(
+                r#"ATTRS{{idProduct}}=="{pid:04x}", TAG+="uaccess""#
+            )));
+        }
+        assert!(rules.contains(
+            "# NZXT Smart Device V1\n\
+             SUBSYSTEMS==\"usb|hidraw\", ATTRS{idVendor}==\"1e71\", ATTRS{idProduct}==\"1714\", \
+             TAG+=\"uaccess\"\n"
+        ));
+
+        let rules = udev_rules(&products, UdevAccess::Plugdev);
+        assert!(rules.contains(r#"ATTRS{idProduct}=="2012", MODE="0660", GROUP="plugdev""#));
+        assert!(!rules.contains("uaccess"));
+
+        // The shipped file must be regenerated when devices are added.
+        assert_eq!(
+            include_str!("../contrib/60-nzxtcli.rules"),
+            udev_rules(&products, UdevAccess::Uaccess)
+        );
+    }
+
+    #[test]
+    fn errno_names() {
+        let message = |errno| {
+            let description = std::io::Error::from_raw_os_error(errno).to_string();
+            let description = description.split(" (os error").next().unwrap().to_owned();
+            hidapi::HidError::HidApiError {
+                message: format!("Failed to open a device with path '/dev/hidraw3': {description}"),
+            }
+        };
+        assert_eq!(errno_name(&message(libc::EACCES)), Some("EACCES"));
+        assert_eq!(errno_name(&message(libc::EBUSY)), Some("EBUSY"));
+        assert_eq!(errno_name(&message(libc::ENODEV)), Some("ENODEV"));
+        assert_eq!(errno_name(&message(libc::ENXIO)), Some("ENXIO"));
+
+        let error = hidapi::HidError::IoError {
+            error: std::io::Error::from_raw_os_error(libc::EBUSY),
+        };
+        assert_eq!(errno_name(&error), Some("EBUSY"));
+        let error = hidapi::HidError::HidApiError {
+            message: "hid_error is not implemented yet".to_owned(),
+        };
+        assert_eq!(errno_name(&error), None);
+    }
+
+    #[test]
+    fn error_kinds() {
+        let error = anyhow::Error::from(nzxtcli::Error::Timeout).context("failed to set color");
+        assert_eq!(ErrorKind::of(&error), ErrorKind::Timeout);
+
+        let error = anyhow::Error::from(ControllerError {
+            device: "NZXT RGB Controller".to_owned(),
+            error: nzxtcli::Error::SerialNotFound {
+                serial: "ABC".to_owned(),
+                seen: Vec::new(),
+            }
+            .into(),
+        });
+        assert_eq!(ErrorKind::of(&error), ErrorKind::NoDevice);
+        assert_eq!(
+            error.to_string(),
+            "no controller with serial `ABC`, no controllers found"
+        );
+
+        let error = anyhow::Error::from(PartialFailure {
+            failed: 1,
+            total: 3,
+        });
+        assert_eq!(ErrorKind::of(&error), ErrorKind::PartialFailure);
+        assert_eq!(error.to_string(), "1 of 3 controllers failed to update");
+
+        let error = App::try_parse_from(["nzxtcli", "set-color"]).err().unwrap();
+        assert_eq!(
+            clap_message(&error),
+            "the following required arguments were not provided: <[CHANNEL=]COLOR>..."
+        );
+        assert_eq!(ErrorKind::of(&error.into()), ErrorKind::Usage);
+        assert_eq!(ErrorKind::of(&anyhow::anyhow!("oops")), ErrorKind::Failure);
+
+        let argv = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            OutputFormat::from_args(&argv(&["nzxtcli", "list", "--format", "json"])),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            OutputFormat::from_args(&argv(&["nzxtcli", "--format=json", "list"])),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            OutputFormat::from_args(&argv(&["nzxtcli", "raw", "--", "--format=json"])),
+            OutputFormat::Text
+        );
+    }
+
+    #[test]
+    fn led_state() {
+        let red = Color::new(0xff, 0, 0);
+        let json = r##"{
+            "version": 1,
+            "controllers": {
+                "ABC": {
+                    "name": "NZXT Smart Device (V2)",
+                    "channels": {
+                        "0": { "lighting": { "type": "fixed", "color": "red" }, "brightness": 80 },
+                        "1": { "lighting": { "type": "mode", "mode": "breathing", "colors": ["#ff0000"] } },
+                        "2": { "lighting": { "type": "leds", "colors": ["#ff0000", "off"] } }
+                    }
+                }
+            }
+        }"##;
+
+        let state = serde_json::from_str::<LedState>(json).unwrap();
+        let channels = &state.controllers["ABC"].channels;
+        assert_eq!(channels[&0], ChannelState {
+            lighting: Some(Lighting::Fixed { color: red }),
+            brightness: Some(80),
+        });
+        assert_eq!(
+            channels[&1].lighting,
+            Some(Lighting::Mode {
+                mode: LedMode::Breathing,
+                colors: vec![red],
+                speed: 2,
+                direction: Direction::Forward,
+            })
+        );
+        assert_eq!(channels[&2].colors(3), [red, Color::BLACK, Color::BLACK]);
+        assert_eq!(channels[&0].colors(2), [red; 2]);
+
+        let serialized = serde_json::to_string(&state).unwrap();
+        assert_eq!(
+            serde_json::from_str::<LedState>(&serialized).unwrap(),
+            state
+        );
+
+        let dir = std::env::temp_dir().join(format!("nzxtcli-test-{}", std::process::id()));
+        let path = dir.join("profile.json");
+        state.write(&path).unwrap();
+        assert_eq!(LedState::read(&path).unwrap(), state);
+
+        std::fs::write(&path, r#"{"version": 2, "controllers": {}}"#).unwrap();
+        let error = LedState::read(&path).unwrap_err();
+        assert!(format!("{error:#}").contains("unsupported version 2"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let app =
+            App::try_parse_from(["nzxtcli", "set-color", "red", "--duration", "30s"]).unwrap();
+        let SubCmd::SetColor(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(cmd.duration, Some(Duration::from_secs(30)));
+        assert!(
+            App::try_parse_from(["nzxtcli", "set-color", "red", "--duration", "1s", "--save"])
+                .is_err()
+        );
+    }
+
+    /// A channel with 20 addressable LEDs: an 8 LED strip, an empty slot and
+    /// two 10 LED strips, the last one is truncated.
+    fn strips_channel() -> nzxtcli::RgbChannel {
+        use nzxtcli::{ChannelDeviceInfo, DeviceType};
+
+        let mut channel = nzxtcli::RgbChannel {
+            led_count: 20,
+            ..Default::default()
+        };
+        channel.devices[0] = ChannelDeviceInfo::new(DeviceType::Hue2Strip8);
+        channel.devices[2] = ChannelDeviceInfo::new(DeviceType::Hue2Strip10);
+        channel.devices[3] = ChannelDeviceInfo::new(DeviceType::Hue2Strip10);
+        channel
+    }
+
+    #[test]
+    fn gradients() {
+        let red = Color::new(0xff, 0, 0);
+        let blue = Color::new(0, 0, 0xff);
+        let ramp = ColorRamp::uniform(&[red, blue]).unwrap();
+
+        let colors = gradient_colors(&ramp, &[3], false);
+        assert_eq!(colors, [red, Color::lerp(red, blue, 0.5), blue]);
+        let reversed = gradient_colors(&ramp, &[3], true);
+        assert_eq!(reversed, [blue, colors[1], red]);
+        assert_eq!(gradient_colors(&ramp, &[2, 1], false), [red, blue, red]);
+        assert!(gradient_colors(&ramp, &[0], false).is_empty());
+
+        // Empty slots don't take any positions.
+        assert_eq!(device_segments(&strips_channel()), [8, 10, 2]);
+
+        assert_eq!(
+            parse_color_list("#00f,rgb(0, 255, 255), white").unwrap(),
+            ColorList(vec![blue, Color::new(0, 0xff, 0xff), Color::WHITE])
+        );
+        assert!(parse_color_list("red,,blue").is_err());
+
+        let app = App::try_parse_from(["nzxtcli", "set-gradient", "--stops", "red,blue"]).unwrap();
+        assert!(matches!(app.cmd, SubCmd::SetGradient(_)));
+        assert!(App::try_parse_from(["nzxtcli", "set-gradient"]).is_err());
+    }
+
+    #[test]
+    fn identify_device_leds() {
+        let channel = strips_channel();
+        assert_eq!(device_leds(&channel, 0).unwrap(), 0..8);
+        assert_eq!(device_leds(&channel, 2).unwrap(), 8..18);
+        // Truncated by the channel LED count.
+        assert_eq!(device_leds(&channel, 3).unwrap(), 18..20);
+        assert!(device_leds(&channel, 1).is_err());
+        assert!(device_leds(&channel, 42).is_err());
+
+        assert!(App::try_parse_from(["nzxtcli", "identify", "--device", "1"]).is_err());
+        assert!(
+            App::try_parse_from(["nzxtcli", "identify", "--channel", "3", "--device", "1"]).is_ok()
+        );
+    }
+
+    #[test]
+    fn led_owners() {
+        let mut channel = strips_channel();
+        assert_eq!(led_owner(&channel, 0), Some((0, 0)));
+        assert_eq!(led_owner(&channel, 7), Some((0, 7)));
+        assert_eq!(led_owner(&channel, 8), Some((2, 0)));
+        assert_eq!(led_owner(&channel, 17), Some((2, 9)));
+        assert_eq!(led_owner(&channel, 19), Some((3, 1)));
+
+        // LEDs which don't belong to any known accessory.
+        channel.devices[3] = nzxtcli::ChannelDeviceInfo::default();
+        assert_eq!(led_owner(&channel, 18), None);
+    }
+
+    #[test]
+    fn color_transform_args() {
+        let app = App::try_parse_from([
+            "nzxtcli",
+            "cpu-temp",
+            "/dev/null",
+            "--interval",
+            "1s",
+            "--gamma",
+            "2.2",
+            "--brightness",
+            "50",
+        ])
+        .unwrap();
+        let SubCmd::CpuTemp(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        let transform = cmd.transform.transform().unwrap();
+        assert_eq!(transform.gamma(), 2.2);
+        assert_eq!(transform.brightness(), 0.5);
+
+        let app = App::try_parse_from(["nzxtcli", "set-color", "ff0000", "--gamma", "0"]).unwrap();
+        let SubCmd::SetColor(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert!(cmd.transform.transform().is_err());
+    }
+
+    #[test]
+    fn cpu_temp_exit_color() {
+        let parse = |args: &[&str]| {
+            let argv = ["nzxtcli", "cpu-temp", "/dev/null", "--interval", "1s"];
+            let app = App::try_parse_from(argv.iter().chain(args))?;
+            let SubCmd::CpuTemp(cmd) = app.cmd else {
+                panic!("unexpected command");
+            };
+            Ok::<_, clap::Error>(cmd.on_exit)
+        };
+
+        assert_eq!(parse(&[]).unwrap(), ExitColor::Color(Color::WHITE));
+        assert_eq!(parse(&["--on-exit", "keep"]).unwrap(), ExitColor::Keep);
+        assert_eq!(
+            parse(&["--on-exit", "#102030"]).unwrap(),
+            ExitColor::Color(Color::new(0x10, 0x20, 0x30))
+        );
+        assert!(parse(&["--on-exit", "lukewarm"]).is_err());
+    }
+
+    #[test]
+    fn fan_curve_args() {
+        let app = App::try_parse_from([
+            "nzxtcli",
+            "cpu-temp",
+            "/dev/null",
+            "--interval",
+            "1s",
+            "--fan-curve",
+            "30:20,80:100",
+            "--fan-curve",
+            "1 = 30:40,70:100",
+        ])
+        .unwrap();
+        let SubCmd::CpuTemp(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(cmd.fan_curve(0).unwrap().points(), [
+            (30.0, 20),
+            (80.0, 100)
+        ]);
+        assert_eq!(cmd.fan_curve(1).unwrap().points(), [
+            (30.0, 40),
+            (70.0, 100)
+        ]);
+        assert_eq!(cmd.fan_curve(2).unwrap().points(), [
+            (30.0, 20),
+            (80.0, 100)
+        ]);
+
+        assert!("x=30:20".parse::<FanCurveArg>().is_err());
+        assert!("0=".parse::<FanCurveArg>().is_err());
+        assert_eq!("0=30:20".parse::<FanCurveArg>().unwrap().channel, Some(0));
+    }
+
+    #[test]
+    fn schedule_skips_missed_ticks() {
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let mut schedule = Schedule::new(Duration::from_secs(1), start);
+
+        // In time.
+        assert_eq!(schedule.advance(at(100)), 0);
+        assert_eq!(schedule.next, at(1000));
+        assert_eq!(schedule.advance(at(1999)), 0);
+        assert_eq!(schedule.next, at(2000));
+
+        // The update stalled for 3.5 intervals: the next tick is in the future
+        // and on the original grid.
+        assert_eq!(schedule.advance(at(5500)), 3);
+        assert_eq!(schedule.next, at(6000));
+
+        // Exactly at the next tick.
+        assert_eq!(schedule.advance(at(7000)), 1);
+        assert_eq!(schedule.next, at(8000));
+        assert_eq!(schedule.advance(at(8100)), 0);
+        assert_eq!(schedule.next, at(9000));
+    }
+
+    #[test]
+    fn fan_duty_limiter() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        let mut limiter = DutyLimiter::default();
+        let duties = [
+            (0, 40),
+            (1, 60),  // too soon
+            (5, 60),  // held long enough
+            (11, 62), // too small
+            (12, 70),
+            (13, 40), // too soon
+            (20, 40),
+            (30, 42), // too small
+            (40, 100),
+            (50, 99), // too small
+            (60, 100),
+        ]
+        .map(|(secs, duty)| limiter.update(duty, at(secs)));
+        assert_eq!(duties, [
+            Some(40),
+            None,
+            Some(60),
+            None,
+            Some(70),
+            None,
+            Some(40),
+            None,
+            Some(100),
+            None,
+            None
+        ]);
+
+        // Small steps to the full speed are applied.
+        let mut limiter = DutyLimiter::default();
+        limiter.update(98, at(0));
+        assert_eq!(limiter.update(100, at(10)), Some(100));
+    }
+
+    #[test]
+    fn device_selection_args() {
+        let app = App::try_parse_from(["nzxtcli", "set-color", "ff0000"]).unwrap();
+        assert!(
+            !app.device
+                .find_options(&AccessoryArgs::default())
+                .unwrap()
+                .has_filters()
+        );
+
+        let app = App::try_parse_from([
+            "nzxtcli",
+            "set-color",
+            "ff0000",
+            "--device-pid",
+            "0x2012",
+            "--device-index",
+            "1",
+        ])
+        .unwrap();
+        assert_eq!(app.device.device_pid, Some(0x2012));
+        assert_eq!(app.device.device_index, Some(1));
+        assert!(
+            app.device
+                .find_options(&AccessoryArgs::default())
+                .unwrap()
+                .has_filters()
+        );
+    }
+
+    #[test]
+    fn cpu_temp_oneshot_args() {
+        let parse = |args: &[&str]| {
+            App::try_parse_from(
+                ["nzxtcli", "cpu-temp", "--sensor", "k10temp"]
+                    .iter()
+                    .chain(args),
+            )
+        };
+        let app = parse(&["--oneshot"]).unwrap();
+        let SubCmd::CpuTemp(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert!(cmd.oneshot);
+        assert_eq!(cmd.interval, None);
+
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--oneshot", "--preview"]).is_err());
+    }
+
+    #[test]
+    fn temp_source_args() {
+        let app = App::try_parse_from([
+            "nzxtcli",
+            "temp",
+            "--exec",
+            "nvidia-smi --query-gpu=temperature.gpu --format=csv,noheader",
+            "--source",
+            "amdgpu:edge",
+            "--oneshot",
+        ])
+        .unwrap();
+        let SubCmd::CpuTemp(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(cmd.exec.len(), 1);
+        assert_eq!(cmd.sensor, ["amdgpu:edge".parse::<SensorSpec>().unwrap()]);
+        assert_eq!(cmd.hwmon_path, None);
+    }
+
+    #[test]
+    fn cpu_temp_selection_args() {
+        let app = App::try_parse_from([
+            "nzxtcli",
+            "cpu-temp",
+            "--sensor",
+            "k10temp:Tctl",
+            "--interval",
+            "1s",
+            "--channels",
+            "1",
+            "--serial",
+            "MA123",
+            "--pid",
+            "2002",
+            "--index",
+            "0",
+        ])
+        .unwrap();
+        assert_eq!(app.device.device_serial.as_deref(), Some("MA123"));
+        assert_eq!(app.device.device_pid, Some(0x2002));
+        assert_eq!(app.device.device_index, Some(0));
+        let SubCmd::CpuTemp(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(cmd.channels.channels, "1".parse().unwrap());
+    }
+
+    #[test]
+    fn device_entry_args() {
+        assert_eq!(parse_device_entry("0x2042:6:3").unwrap(), (0x2042, 6, 3));
+        assert_eq!(parse_device_entry("2042:2:0").unwrap(), (0x2042, 2, 0));
+        assert!(parse_device_entry("0x2042:6").is_err());
+        assert!(parse_device_entry("0x2042:6:3:1").is_err());
+        assert!(parse_device_entry("xyz:6:3").is_err());
+    }
+
+    #[test]
+    fn raw_report_args() {
+        assert_eq!(parse_hex_bytes("2003").unwrap(), [0x20, 0x03]);
+        assert_eq!(parse_hex_bytes("0x20 03 ff").unwrap(), [0x20, 0x03, 0xff]);
+        assert!(parse_hex_bytes("200").is_err());
+        assert!(parse_hex_bytes("zz").is_err());
+        assert_eq!(parse_hex_u16("0x2012").unwrap(), 0x2012);
+        assert_eq!(format_hex(&[0x21, 0x03, 0x0a]), "21 03 0a");
+    }
+
+    #[test]
+    fn cpu_temp_color() {
+        let ramp = temp_ramp();
+        assert_eq!(temp_color(&ramp, 20_000, 20.0, 80.0), ramp.stops()[0].1);
+        assert_eq!(temp_color(&ramp, -5_000, 20.0, 80.0), ramp.stops()[0].1);
+        assert_eq!(temp_color(&ramp, 74_000, 20.0, 80.0), Color::RED);
+        assert_eq!(temp_color(&ramp, 120_000, 20.0, 80.0), Color::RED);
+        // Halfway between the first two stops.
+        assert_eq!(
+            temp_color(&ramp, 27_500, 20.0, 80.0),
+            Color::lerp(ramp.stops()[0].1, ramp.stops()[1].1, 0.5)
+        );
+    }
+
+    #[test]
+    fn ramp_interpolation_args() {
+        let argv = [
+            "nzxtcli",
+            "cpu-temp",
+            "--preview",
+            "--ramp",
+            "20:blue,80:red",
+        ];
+        let app = App::try_parse_from(argv.iter().chain(&["--interpolation", "hsv"])).unwrap();
+        let SubCmd::CpuTemp(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        let (ramp, base, warn) = cmd.ramp().unwrap();
+        assert_eq!(ramp.interpolation(), Interpolation::Hsv);
+        assert_eq!(
+            temp_color(&ramp, 50_000, base, warn),
+            Color::new(255, 0, 255)
+        );
+
+        let app = App::try_parse_from(argv).unwrap();
+        let SubCmd::CpuTemp(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(cmd.ramp().unwrap().0.interpolation(), Interpolation::Rgb);
+
+        let app = App::try_parse_from([
+            "nzxtcli",
+            "set-gradient",
+            "blue",
+            "red",
+            "--interpolation",
+            "hsv",
+        ])
+        .unwrap();
+        let SubCmd::SetGradient(cmd) = app.cmd else {
+            panic!("unexpected command");
+        };
+        assert_eq!(cmd.interpolation, Interpolation::Hsv);
     }
 }
@@ -0,0 +1,1313 @@
+//! Home Assistant integration of the daemon over MQTT, enabled by the `[mqtt]`
+//! table of the config:
+//!
+//! ```toml
+//! [mqtt]
+//! host = "broker.lan"
+//! username = "nzxtcli"
+//! password_file = "/etc/nzxtcli/mqtt-password"
+//! ```
+//!
+//! Each RGB channel is announced as a light with a color and a brightness,
+//! each fan channel as a fan with a percentage and the `auto` preset of its
+//! curve. Commands override the bindings like the requests of the control
+//! socket, the availability of a controller goes `offline` while it's
+//! disconnected and with the daemon.
+//!
+//! Topics are `<base_topic>/<controller>/light/<channel>/...` and
+//! `<base_topic>/<controller>/fan/<channel>/...`, where the controller is
+//! its serial (or the product id and the index if it has none). The
+//! discovery configs are sent to `<discovery_prefix>/light/...` and
+//! `<discovery_prefix>/fan/...` again whenever Home Assistant comes online.
+//!
+//! Only MQTT 3.1.1 is implemented, over plain TCP or over TLS with `tls = true`
+//! (the `tls` feature, which links the system OpenSSL). The broker is verified
+//! against the system trust store or `ca_file`, `cert_file` and `key_file`
+//! are a client certificate for brokers which require one.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use nzxtcli::control::Command;
+use nzxtcli::logging::{info, warning};
+use nzxtcli::{ChannelSelector, Color};
+
+use crate::config::{Config, ConfigError};
+use crate::retry::Backoff;
+
+/// Name of the config table.
+pub const TABLE: &str = "mqtt";
+
+const KEYS: &[&str] = &[
+    "host",
+    "port",
+    "tls",
+    "ca_file",
+    "cert_file",
+    "key_file",
+    "username",
+    "password",
+    "password_file",
+    "client_id",
+    "base_topic",
+    "discovery_prefix",
+    "keepalive",
+];
+
+/// Options of the `[mqtt]` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttConfig {
+    host: String,
+    port: u16,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+    username: Option<String>,
+    password: Option<Password>,
+    client_id: String,
+    base_topic: String,
+    discovery_prefix: String,
+    keepalive: Duration,
+}
+
+/// The verification of the broker and the client certificate.
+#[cfg(feature = "tls")]
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TlsConfig {
+    /// The system trust store if it's not set.
+    ca_file: Option<PathBuf>,
+    /// The certificate and the key in PEM.
+    identity: Option<(PathBuf, PathBuf)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Password {
+    Value(String),
+    /// Read on each connection, so that it stays out of the config.
+    File(PathBuf),
+}
+
+impl MqttConfig {
+    /// `None` without the `[mqtt]` table.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, ConfigError> {
+        let Some(fields) = config.table(TABLE) else {
+            return Ok(None);
+        };
+        fields.check_keys(KEYS, "the MQTT table")?;
+        let tls = fields.parse::<bool>("tls")?.unwrap_or_default();
+        let port = match tls {
+            true => 8883,
+            false => 1883,
+        };
+        if !tls {
+            for key in ["ca_file", "cert_file", "key_file"] {
+                if fields.parse::<PathBuf>(key)?.is_some() {
+                    return Err(fields.value_error(key, "requires `tls = true`"));
+                }
+            }
+        }
+        #[cfg(not(feature = "tls"))]
+        if tls {
+            return Err(fields.value_error(
+                "tls",
+                "TLS requires nzxtcli to be built with the `tls` feature",
+            ));
+        }
+        #[cfg(feature = "tls")]
+        let tls = match tls {
+            true => Some(TlsConfig {
+                ca_file: fields.parse("ca_file")?,
+                identity: match (
+                    fields.parse::<PathBuf>("cert_file")?,
+                    fields.parse::<PathBuf>("key_file")?,
+                ) {
+                    (Some(cert), Some(key)) => Some((cert, key)),
+                    (None, None) => None,
+                    (Some(_), None) => {
+                        return Err(fields.value_error("cert_file", "requires the `key_file` key"));
+                    }
+                    (None, Some(_)) => {
+                        return Err(fields.value_error("key_file", "requires the `cert_file` key"));
+                    }
+                },
+            }),
+            false => None,
+        };
+        let host = fields
+            .parse::<String>("host")?
+            .ok_or_else(|| fields.error("missing the `host` key"))?;
+        let password = match (
+            fields.parse::<String>("password")?,
+            fields.parse::<PathBuf>("password_file")?,
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(
+                    fields.value_error("password_file", "can't be combined with `password`")
+                );
+            }
+            (Some(password), None) => Some(Password::Value(password)),
+            (None, Some(path)) => Some(Password::File(path)),
+            (None, None) => None,
+        };
+        let username = fields.parse::<String>("username")?;
+        if password.is_some() && username.is_none() {
+            return Err(fields.error("a password requires the `username` key"));
+        }
+        let topic = |key: &str, default: &str| {
+            let topic = fields
+                .parse::<String>(key)?
+                .unwrap_or_else(|| default.to_owned());
+            let topic = topic.trim_end_matches('/').to_owned();
+            if topic.is_empty() || topic.contains(['+', '#']) {
+                return Err(fields.value_error(key, "expected a topic without wildcards"));
+            }
+            Ok(topic)
+        };
+        let keepalive = fields
+            .parse_with("keepalive", humantime::parse_duration)?
+            .unwrap_or(Duration::from_secs(30));
+        if !(Duration::from_secs(1)..=Duration::from_secs(u16::MAX.into())).contains(&keepalive) {
+            return Err(fields.value_error("keepalive", "expected 1s to 18h"));
+        }
+
+        Ok(Some(Self {
+            host,
+            port: fields.parse("port")?.unwrap_or(port),
+            #[cfg(feature = "tls")]
+            tls,
+            username,
+            password,
+            client_id: fields
+                .parse("client_id")?
+                .unwrap_or_else(|| format!("nzxtcli-{}", hostname())),
+            base_topic: topic("base_topic", "nzxtcli")?,
+            discovery_prefix: topic("discovery_prefix", "homeassistant")?,
+            keepalive,
+        }))
+    }
+
+    /// Availability of the daemon, also the will of the connection.
+    fn status_topic(&self) -> String {
+        format!("{}/{}/status", self.base_topic, self.client_id)
+    }
+}
+
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: The buffer is valid for its length.
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    let len = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    match result {
+        0 if len > 0 => String::from_utf8_lossy(&buffer[..len]).into_owned(),
+        _ => "localhost".to_owned(),
+    }
+}
+
+/// State of a controller for [`MqttBridge::publish`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControllerState {
+    pub name: &'static str,
+    pub serial: Option<String>,
+    pub product_id: u16,
+    pub connected: bool,
+    /// Color of each RGB channel, `None` if it's not known.
+    pub lights: Vec<Option<Color>>,
+    /// Duty of each fan channel and whether it's overridden.
+    pub fans: Vec<(Option<u8>, bool)>,
+}
+
+impl ControllerState {
+    /// The controller in the topics.
+    fn id(&self, index: usize) -> String {
+        match &self.serial {
+            Some(serial) => serial
+                .chars()
+                .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+                    true => c,
+                    false => '_',
+                })
+                .collect(),
+            None => format!("{:04x}-{index}", self.product_id),
+        }
+    }
+}
+
+/// Color and brightness set by Home Assistant, they are kept separately
+/// since the channel only has the scaled color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Light {
+    on: bool,
+    color: Color,
+    brightness: u8,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            on: true,
+            color: Color::WHITE,
+            brightness: u8::MAX,
+        }
+    }
+}
+
+impl Light {
+    fn shown(&self) -> Color {
+        match self.on {
+            true => self.color.scaled(f32::from(self.brightness) / 255.0),
+            false => Color::BLACK,
+        }
+    }
+
+    /// The JSON schema of the MQTT light, fields which are not set are kept.
+    fn command(mut self, payload: &str) -> Result<Self> {
+        #[derive(serde::Deserialize)]
+        struct Rgb {
+            r: u8,
+            g: u8,
+            b: u8,
+        }
+        #[derive(serde::Deserialize)]
+        struct Payload {
+            state: Option<String>,
+            brightness: Option<u8>,
+            color: Option<Rgb>,
+        }
+
+        let payload = serde_json::from_str::<Payload>(payload).context("invalid light command")?;
+        match payload.state.as_deref() {
+            Some("ON") => self.on = true,
+            Some("OFF") => self.on = false,
+            Some(other) => anyhow::bail!("unknown light state `{other}`"),
+            None => {}
+        }
+        if let Some(brightness) = payload.brightness {
+            self.brightness = brightness;
+        }
+        if let Some(Rgb { r, g, b }) = payload.color {
+            self.color = Color::new(r, g, b);
+        }
+        Ok(self)
+    }
+
+    fn state(&self) -> serde_json::Value {
+        let color = self.color;
+        serde_json::json!({
+            "state": if self.on { "ON" } else { "OFF" },
+            "color_mode": "rgb",
+            "brightness": self.brightness,
+            "color": { "r": color.red(), "g": color.green(), "b": color.blue() },
+        })
+    }
+
+    /// A color set by other means, shown at the full brightness.
+    fn from_color(color: Color) -> Self {
+        match color.max_component() {
+            0 => Self {
+                on: false,
+                ..Self::default()
+            },
+            max => Self {
+                on: true,
+                color: color.scaled(255.0 / f32::from(max)),
+                brightness: max,
+            },
+        }
+    }
+}
+
+enum Event {
+    Connected,
+    Message { topic: String, payload: String },
+}
+
+/// A connection to the broker.
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(crate::tls::TlsStream),
+}
+
+impl Stream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        match self {
+            Self::Tcp(stream) => stream.try_clone().map(Self::Tcp),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => Ok(Self::Tls(stream.clone())),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    fn shutdown(&self) -> std::io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.shutdown(Shutdown::Both),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+type Writer = Arc<Mutex<Option<Stream>>>;
+
+/// Connection to the broker, which is kept by a background thread and
+/// reconnected with a backoff.
+///
+/// Commands are answered by [`MqttBridge::handle`] on the main thread.
+pub struct MqttBridge {
+    config: MqttConfig,
+    writer: Writer,
+    events: mpsc::Receiver<Event>,
+    /// Events which were received but not handled yet.
+    pending: Arc<AtomicUsize>,
+    /// Stops the reconnects once the bridge is dropped.
+    stopped: Arc<AtomicBool>,
+    /// Payloads of the retained topics as last sent, cleared on connect.
+    published: HashMap<String, String>,
+    /// Topic id, serial and product id of the controllers of the last publish.
+    controllers: Vec<(String, Option<String>, u16)>,
+    /// Lights set by Home Assistant by the controller id and the channel,
+    /// forgotten once the channel is no longer overridden.
+    lights: HashMap<(String, usize), Light>,
+}
+
+impl MqttBridge {
+    pub fn start(config: MqttConfig) -> Self {
+        let writer = Writer::default();
+        let (sender, events) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let stopped = Arc::new(AtomicBool::new(false));
+        {
+            let config = config.clone();
+            let writer = writer.clone();
+            let pending = pending.clone();
+            let stopped = stopped.clone();
+            std::thread::spawn(move || {
+                let mut backoff = Backoff::default();
+                loop {
+                    let result = session(&config, &writer, &sender, &pending, &mut backoff);
+                    *writer.lock().unwrap() = None;
+                    if result.is_ok() || stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let delay = backoff.failed(Instant::now());
+                    if let Err(e) = result {
+                        warning!(
+                            "MQTT broker {}:{}: {e:#}, reconnecting in {}",
+                            config.host,
+                            config.port,
+                            humantime::format_duration(delay)
+                        );
+                    }
+                    std::thread::sleep(delay);
+                }
+            });
+        }
+        Self {
+            config,
+            writer,
+            events,
+            pending,
+            stopped,
+            published: HashMap::new(),
+            controllers: Vec::new(),
+            lights: HashMap::new(),
+        }
+    }
+
+    /// Whether there are commands for [`MqttBridge::handle`].
+    pub fn pending(&self) -> bool {
+        self.pending.load(Ordering::Relaxed) > 0
+    }
+
+    /// Passes the received commands to `control` as the requests
+    /// of the control socket, failures are printed.
+    pub fn handle(&mut self, mut control: impl FnMut(Command) -> Result<serde_json::Value>) {
+        while let Ok(event) = self.events.try_recv() {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            let (topic, payload) = match event {
+                Event::Connected => {
+                    self.published.clear();
+                    continue;
+                }
+                Event::Message { topic, payload } => (topic, payload),
+            };
+            // Home Assistant lost the retained configs if it was restarted.
+            if topic == format!("{}/status", self.config.discovery_prefix) {
+                if payload == "online" {
+                    self.published.clear();
+                }
+                continue;
+            }
+            if let Err(e) = self.command(&topic, &payload).and_then(&mut control) {
+                warning!("MQTT command on `{topic}` failed: {e:#}");
+            }
+        }
+    }
+
+    /// `<base>/<id>/light/<channel>/set` etc.
+    fn command(&mut self, topic: &str, payload: &str) -> Result<Command> {
+        let parts = topic
+            .strip_prefix(&self.config.base_topic)
+            .and_then(|topic| topic.strip_prefix('/'))
+            .map(|topic| topic.split('/').collect::<Vec<_>>())
+            .unwrap_or_default();
+        let (id, kind, channel, command) = match parts[..] {
+            [id, kind, channel, "set"] => (id, kind, channel, None),
+            [id, kind, channel, command, "set"] => (id, kind, channel, Some(command)),
+            _ => anyhow::bail!("unknown topic"),
+        };
+        let (id, serial, product_id) = self
+            .controllers
+            .iter()
+            .find(|(other, ..)| other == id)
+            .cloned()
+            .with_context(|| format!("unknown controller `{id}`"))?;
+        let channel = channel
+            .parse::<usize>()
+            .with_context(|| format!("invalid channel `{channel}`"))?;
+        let (serial, product_id) = match serial {
+            Some(serial) => (Some(serial), None),
+            None => (None, Some(product_id)),
+        };
+        let payload = payload.trim();
+
+        match (kind, command) {
+            ("light", None) => {
+                let key = (id, channel);
+                let light = self.lights.get(&key).copied().unwrap_or_default();
+                let light = light.command(payload)?;
+                self.lights.insert(key, light);
+                Ok(Command::SetColor {
+                    color: light.shown(),
+                    channels: ChannelSelector::Channels(vec![channel]),
+                    serial,
+                    product_id,
+                    duration_ms: None,
+                })
+            }
+            // Off stops the fan, on returns it to its curve.
+            ("fan", None) => match payload {
+                "OFF" => Ok(Command::SetFan {
+                    duty: 0,
+                    fans: ChannelSelector::Channels(vec![channel]),
+                    serial,
+                    product_id,
+                }),
+                "ON" => Ok(Command::ReleaseFan {
+                    fans: ChannelSelector::Channels(vec![channel]),
+                    serial,
+                    product_id,
+                }),
+                other => anyhow::bail!("unknown fan state `{other}`"),
+            },
+            ("fan", Some("percentage")) => Ok(Command::SetFan {
+                duty: payload
+                    .parse()
+                    .with_context(|| format!("invalid percentage `{payload}`"))?,
+                fans: ChannelSelector::Channels(vec![channel]),
+                serial,
+                product_id,
+            }),
+            ("fan", Some("preset")) => {
+                anyhow::ensure!(payload == "auto", "unknown preset `{payload}`");
+                Ok(Command::ReleaseFan {
+                    fans: ChannelSelector::Channels(vec![channel]),
+                    serial,
+                    product_id,
+                })
+            }
+            _ => anyhow::bail!("unknown topic"),
+        }
+    }
+
+    /// Sends the changed states, and the discovery configs after connecting.
+    pub fn publish(&mut self, controllers: &[ControllerState]) {
+        self.controllers = controllers
+            .iter()
+            .enumerate()
+            .map(|(index, state)| (state.id(index), state.serial.clone(), state.product_id))
+            .collect();
+        let mut messages = vec![(self.config.status_topic(), "online".to_owned())];
+        for (index, state) in controllers.iter().enumerate() {
+            let id = state.id(index);
+            messages.extend(self.discovery(state, &id));
+            let base = format!("{}/{id}", self.config.base_topic);
+            let availability = match state.connected {
+                true => "online",
+                false => "offline",
+            };
+            messages.push((format!("{base}/availability"), availability.to_owned()));
+
+            for (channel, color) in state.lights.iter().enumerate() {
+                let key = (id.clone(), channel);
+                // Overrides show the color, other changes forget the brightness.
+                let light = match (self.lights.get(&key), color) {
+                    (Some(light), Some(color)) if light.shown() == *color => *light,
+                    (_, Some(color)) => {
+                        self.lights.remove(&key);
+                        Light::from_color(*color)
+                    }
+                    (_, None) => continue,
+                };
+                let state = light.state().to_string();
+                messages.push((format!("{base}/light/{channel}/state"), state));
+            }
+            for (channel, (duty, overridden)) in state.fans.iter().enumerate() {
+                let topic = format!("{base}/fan/{channel}");
+                let preset = match overridden {
+                    true => "None",
+                    false => "auto",
+                };
+                messages.push((format!("{topic}/preset"), preset.to_owned()));
+                if let Some(duty) = duty {
+                    let on = match duty {
+                        0 => "OFF",
+                        _ => "ON",
+                    };
+                    messages.push((format!("{topic}/state"), on.to_owned()));
+                    messages.push((format!("{topic}/percentage"), duty.to_string()));
+                }
+            }
+        }
+
+        let mut writer = self.writer.lock().unwrap();
+        let Some(stream) = writer.as_mut() else {
+            return;
+        };
+        for (topic, payload) in messages {
+            if self.published.get(&topic) == Some(&payload) {
+                continue;
+            }
+            // The reading thread reconnects.
+            if stream
+                .write_all(&publish_packet(&topic, payload.as_bytes(), true))
+                .is_err()
+            {
+                _ = stream.shutdown();
+                *writer = None;
+                return;
+            }
+            self.published.insert(topic, payload);
+        }
+    }
+
+    /// Retained configs of the lights and the fans of the controller.
+    fn discovery(&self, state: &ControllerState, id: &str) -> Vec<(String, String)> {
+        let base = format!("{}/{id}", self.config.base_topic);
+        let prefix = &self.config.discovery_prefix;
+        let device = serde_json::json!({
+            "identifiers": [format!("nzxtcli_{id}")],
+            "name": state.name,
+            "manufacturer": "NZXT",
+            "model": state.name,
+            "serial_number": state.serial,
+        });
+        let availability = serde_json::json!([
+            { "topic": self.config.status_topic() },
+            { "topic": format!("{base}/availability") },
+        ]);
+
+        let mut configs = Vec::new();
+        for channel in 0..state.lights.len() {
+            let topic = format!("{base}/light/{channel}");
+            let config = serde_json::json!({
+                "name": format!("Channel {channel}"),
+                "unique_id": format!("nzxtcli_{id}_light{channel}"),
+                "schema": "json",
+                "command_topic": format!("{topic}/set"),
+                "state_topic": format!("{topic}/state"),
+                "supported_color_modes": ["rgb"],
+                "brightness": true,
+                "availability": availability,
+                "availability_mode": "all",
+                "device": device,
+            });
+            let config_topic = format!("{prefix}/light/nzxtcli_{id}/light{channel}/config");
+            configs.push((config_topic, config.to_string()));
+        }
+        for channel in 0..state.fans.len() {
+            let topic = format!("{base}/fan/{channel}");
+            let config = serde_json::json!({
+                "name": format!("Fan {channel}"),
+                "unique_id": format!("nzxtcli_{id}_fan{channel}"),
+                "command_topic": format!("{topic}/set"),
+                "state_topic": format!("{topic}/state"),
+                "percentage_command_topic": format!("{topic}/percentage/set"),
+                "percentage_state_topic": format!("{topic}/percentage"),
+                "preset_modes": ["auto"],
+                "preset_mode_command_topic": format!("{topic}/preset/set"),
+                "preset_mode_state_topic": format!("{topic}/preset"),
+                "availability": availability,
+                "availability_mode": "all",
+                "device": device,
+            });
+            let config_topic = format!("{prefix}/fan/nzxtcli_{id}/fan{channel}/config");
+            configs.push((config_topic, config.to_string()));
+        }
+        configs
+    }
+}
+
+impl Drop for MqttBridge {
+    /// The will is only sent by the broker if the connection is lost.
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(stream) = self.writer.lock().unwrap().as_mut() {
+            let status = publish_packet(&self.config.status_topic(), b"offline", true);
+            _ = stream.write_all(&status);
+            _ = stream.write_all(&DISCONNECT);
+            _ = stream.shutdown();
+        }
+    }
+}
+
+/// A connection to the broker, returns `Ok` once the bridge is dropped.
+fn session(
+    config: &MqttConfig,
+    writer: &Writer,
+    events: &mpsc::Sender<Event>,
+    pending: &AtomicUsize,
+    backoff: &mut Backoff,
+) -> Result<()> {
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    let address = (config.host.as_str(), config.port)
+        .to_socket_addrs()
+        .context("failed to resolve the address")?
+        .next()
+        .context("the host has no addresses")?;
+    let socket = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.set_write_timeout(Some(TIMEOUT))?;
+    #[cfg(feature = "tls")]
+    let mut stream = match &config.tls {
+        Some(tls) => Stream::Tls(crate::tls::TlsStream::connect(
+            socket,
+            &config.host,
+            tls.ca_file.as_deref(),
+            tls.identity
+                .as_ref()
+                .map(|(cert, key)| (cert.as_path(), key.as_path())),
+        )?),
+        None => Stream::Tcp(socket),
+    };
+    #[cfg(not(feature = "tls"))]
+    let mut stream = Stream::Tcp(socket);
+    let password = match &config.password {
+        Some(Password::Value(password)) => Some(password.clone()),
+        Some(Password::File(path)) => Some(
+            std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?
+                .trim_end_matches(['\r', '\n'])
+                .to_owned(),
+        ),
+        None => None,
+    };
+    stream.write_all(&connect_packet(config, password.as_deref()))?;
+    match read_packet(&mut stream)? {
+        (CONNACK, body) if body.len() == 2 => match body[1] {
+            0 => {}
+            4 => anyhow::bail!("bad username or password"),
+            5 => anyhow::bail!("not authorized"),
+            code => anyhow::bail!("connection refused with code {code}"),
+        },
+        (kind, _) => anyhow::bail!("expected CONNACK, got packet type {}", kind >> 4),
+    }
+    let base = &config.base_topic;
+    let filters = [
+        format!("{base}/+/light/+/set"),
+        format!("{base}/+/fan/+/set"),
+        format!("{base}/+/fan/+/+/set"),
+        format!("{}/status", config.discovery_prefix),
+    ];
+    stream.write_all(&subscribe_packet(1, &filters))?;
+    let failures = backoff.succeeded();
+    if failures > 0 {
+        info!("reconnected to the MQTT broker after {failures} attempts");
+    }
+
+    // The states are published by the main thread from now on.
+    stream.set_read_timeout(Some(config.keepalive))?;
+    *writer.lock().unwrap() = Some(stream.try_clone()?);
+    pending.fetch_add(1, Ordering::Relaxed);
+    if events.send(Event::Connected).is_err() {
+        return Ok(());
+    }
+
+    let mut pinged = false;
+    loop {
+        let packet = match read_packet(&mut stream) {
+            Ok(packet) => packet,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                anyhow::ensure!(!pinged, "no response to the ping");
+                pinged = true;
+                let mut writer = writer.lock().unwrap();
+                let stream = writer.as_mut().context("the connection was closed")?;
+                stream.write_all(&PINGREQ)?;
+                continue;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                anyhow::bail!("the broker closed the connection")
+            }
+            Err(e) => return Err(e.into()),
+        };
+        pinged = false;
+        let (kind, body) = packet;
+        if kind & 0xf0 != PUBLISH {
+            continue;
+        }
+        let (topic, packet_id, payload) = parse_publish(kind, &body)?;
+        if let Some(packet_id) = packet_id {
+            let mut writer = writer.lock().unwrap();
+            let stream = writer.as_mut().context("the connection was closed")?;
+            let [high, low] = packet_id.to_be_bytes();
+            stream.write_all(&[PUBACK, 2, high, low])?;
+        }
+        pending.fetch_add(1, Ordering::Relaxed);
+        let event = Event::Message {
+            topic,
+            payload: String::from_utf8_lossy(payload).into_owned(),
+        };
+        if events.send(event).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: [u8; 2] = [0xc0, 0];
+const DISCONNECT: [u8; 2] = [0xe0, 0];
+
+/// Fixed header with the remaining length.
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        match len {
+            0 => {
+                packet.push(byte);
+                break;
+            }
+            _ => packet.push(byte | 0x80),
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn push_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn connect_packet(config: &MqttConfig, password: Option<&str>) -> Vec<u8> {
+    const CLEAN_SESSION: u8 = 0x02;
+    const WILL: u8 = 0x04;
+    const WILL_RETAIN: u8 = 0x20;
+    const PASSWORD: u8 = 0x40;
+    const USERNAME: u8 = 0x80;
+
+    let mut flags = CLEAN_SESSION | WILL | WILL_RETAIN;
+    if config.username.is_some() {
+        flags |= USERNAME;
+    }
+    if password.is_some() {
+        flags |= PASSWORD;
+    }
+    let mut body = Vec::new();
+    push_bytes(&mut body, b"MQTT");
+    body.extend([4, flags]);
+    body.extend_from_slice(&(config.keepalive.as_secs() as u16).to_be_bytes());
+    push_bytes(&mut body, config.client_id.as_bytes());
+    push_bytes(&mut body, config.status_topic().as_bytes());
+    push_bytes(&mut body, b"offline");
+    if let Some(username) = &config.username {
+        push_bytes(&mut body, username.as_bytes());
+    }
+    if let Some(password) = password {
+        push_bytes(&mut body, password.as_bytes());
+    }
+    packet(CONNECT, &body)
+}
+
+/// QoS 0, which is enough for states that are sent again on changes.
+fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    push_bytes(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    packet(PUBLISH | u8::from(retain), &body)
+}
+
+fn subscribe_packet(packet_id: u16, filters: &[String]) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    for filter in filters {
+        push_bytes(&mut body, filter.as_bytes());
+        body.push(0);
+    }
+    packet(SUBSCRIBE, &body)
+}
+
+/// The first byte of the fixed header and the rest of the packet.
+fn read_packet(reader: &mut impl Read) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut kind = [0];
+    reader.read_exact(&mut kind)?;
+    let mut len = 0usize;
+    for shift in (0..4).map(|i| i * 7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        len |= usize::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body)?;
+            return Ok((kind[0], body));
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "invalid remaining length",
+    ))
+}
+
+/// The topic, the packet id of QoS 1 and 2 and the payload.
+fn parse_publish(kind: u8, body: &[u8]) -> Result<(String, Option<u16>, &[u8])> {
+    let len = body
+        .get(..2)
+        .map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+        .context("truncated PUBLISH")?;
+    let topic = body.get(2..2 + len).context("truncated PUBLISH")?;
+    let topic = String::from_utf8(topic.to_vec()).context("invalid topic")?;
+    let mut rest = &body[2 + len..];
+    let packet_id = match (kind >> 1) & 0x03 {
+        0 => None,
+        _ => {
+            let id = rest.get(..2).context("truncated PUBLISH")?;
+            let id = u16::from_be_bytes([id[0], id[1]]);
+            rest = &rest[2..];
+            Some(id)
+        }
+    };
+    Ok((topic, packet_id, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn config(text: &str) -> Result<Option<MqttConfig>, ConfigError> {
+        MqttConfig::from_config(&Config::parse(PathBuf::from("nzxt.toml"), text).unwrap())
+    }
+
+    #[test]
+    fn mqtt_config() {
+        assert_eq!(config("[daemon]\ninterval = \"2s\"").unwrap(), None);
+        let parsed = config(
+            "[mqtt]\nhost = \"broker.lan\"\nusername = \"nzxt\"\npassword = \"secret\"\n\
+             client_id = \"desk\"\nbase_topic = \"home/nzxt/\"",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(parsed, MqttConfig {
+            host: "broker.lan".to_owned(),
+            port: 1883,
+            #[cfg(feature = "tls")]
+            tls: None,
+            username: Some("nzxt".to_owned()),
+            password: Some(Password::Value("secret".to_owned())),
+            client_id: "desk".to_owned(),
+            base_topic: "home/nzxt".to_owned(),
+            discovery_prefix: "homeassistant".to_owned(),
+            keepalive: Duration::from_secs(30),
+        });
+        assert_eq!(parsed.status_topic(), "home/nzxt/desk/status");
+
+        let error = |text| config(text).unwrap_err().to_string();
+        assert_eq!(
+            error("[mqtt]\nport = 1883"),
+            "nzxt.toml:1: `mqtt`: missing the `host` key"
+        );
+        assert_eq!(
+            error("[mqtt]\nhost = \"a\"\nca_file = \"ca.pem\""),
+            "nzxt.toml:3: `mqtt.ca_file`: requires `tls = true`"
+        );
+        #[cfg(not(feature = "tls"))]
+        assert_eq!(
+            error("[mqtt]\nhost = \"a\"\ntls = true"),
+            "nzxt.toml:3: `mqtt.tls`: TLS requires nzxtcli to be built with the `tls` feature"
+        );
+        #[cfg(feature = "tls")]
+        {
+            let parsed = config(
+                "[mqtt]\nhost = \"a\"\ntls = true\ncert_file = \"c.pem\"\nkey_file = \"k.pem\"",
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(parsed.port, 8883);
+            assert_eq!(
+                parsed.tls,
+                Some(TlsConfig {
+                    ca_file: None,
+                    identity: Some(("c.pem".into(), "k.pem".into())),
+                })
+            );
+            assert_eq!(
+                error("[mqtt]\nhost = \"a\"\ntls = true\nkey_file = \"k.pem\""),
+                "nzxt.toml:4: `mqtt.key_file`: requires the `cert_file` key"
+            );
+        }
+        assert_eq!(
+            error("[mqtt]\nhost = \"a\"\nbase_topic = \"nzxt/#\""),
+            "nzxt.toml:3: `mqtt.base_topic`: expected a topic without wildcards"
+        );
+        assert_eq!(
+            error("[mqtt]\nhost = \"a\"\npassword = \"b\""),
+            "nzxt.toml:1: `mqtt`: a password requires the `username` key"
+        );
+        assert_eq!(
+            error("[mqtt]\nhost = \"a\"\nqos = 1"),
+            "nzxt.toml:3: `mqtt.qos`: unknown key of the MQTT table"
+        );
+    }
+
+    #[test]
+    fn mqtt_packets() {
+        assert_eq!(packet(0x30, &[]), [0x30, 0]);
+        assert_eq!(packet(0x30, &[0; 127])[..2], [0x30, 127]);
+        assert_eq!(packet(0x30, &[0; 128])[..3], [0x30, 0x80, 0x01]);
+        assert_eq!(packet(0x30, &[0; 16384])[..4], [0x30, 0x80, 0x80, 0x01]);
+        let data = packet(0x30, &[7; 16384]);
+        assert_eq!(
+            read_packet(&mut data.as_slice()).unwrap(),
+            (0x30, vec![7; 16384])
+        );
+
+        let data = publish_packet("a/b", b"ON", true);
+        assert_eq!(data, b"\x31\x07\x00\x03a/bON");
+        let (kind, body) = read_packet(&mut data.as_slice()).unwrap();
+        assert_eq!(
+            parse_publish(kind, &body).unwrap(),
+            ("a/b".to_owned(), None, &b"ON"[..])
+        );
+        // QoS 1 has a packet id.
+        assert_eq!(
+            parse_publish(0x32, b"\x00\x01a\x00\x2aON").unwrap(),
+            ("a".to_owned(), Some(42), &b"ON"[..])
+        );
+        assert!(parse_publish(0x30, b"\x00\x05a").is_err());
+
+        let mut config = config("[mqtt]\nhost = \"a\"\nclient_id = \"c\"\nusername = \"u\"")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            connect_packet(&config, Some("p")),
+            b"\x10\x2e\x00\x04MQTT\x04\xe6\x00\x1e\x00\x01c\x00\x10nzxtcli/c/status\
+              \x00\x07offline\x00\x01u\x00\x01p"
+        );
+        config.username = None;
+        assert_eq!(connect_packet(&config, None)[9], 0x26);
+    }
+
+    #[test]
+    fn mqtt_commands() {
+        let light = Light::default();
+        let light = light
+            .command(r#"{"state":"ON","color":{"r":255,"g":0,"b":0}}"#)
+            .unwrap();
+        assert_eq!(light.shown(), Color::RED);
+        let light = light.command(r#"{"brightness":128}"#).unwrap();
+        assert_eq!(light.shown(), Color::new(128, 0, 0));
+        assert_eq!(
+            light.state(),
+            serde_json::json!({
+                "state": "ON",
+                "color_mode": "rgb",
+                "brightness": 128,
+                "color": {"r": 255, "g": 0, "b": 0},
+            })
+        );
+        let off = light.command(r#"{"state":"OFF"}"#).unwrap();
+        assert_eq!(off.shown(), Color::BLACK);
+        // The color is kept.
+        assert_eq!(off.command(r#"{"state":"ON"}"#).unwrap(), light);
+        assert!(light.command(r#"{"state":"BLINK"}"#).is_err());
+        assert_eq!(Light::from_color(Color::new(0, 128, 64)), Light {
+            on: true,
+            color: Color::new(0, 255, 128),
+            brightness: 128,
+        });
+    }
+
+    fn state() -> ControllerState {
+        ControllerState {
+            name: "NZXT RGB Controller",
+            serial: Some("MA01".to_owned()),
+            product_id: 0x2012,
+            connected: true,
+            lights: vec![Some(Color::BLUE), None],
+            fans: vec![(Some(40), false)],
+        }
+    }
+
+    /// Reads the packets until the one for which `f` returns a value.
+    fn read_until<T>(stream: &mut impl Read, mut f: impl FnMut(u8, &[u8]) -> Option<T>) -> T {
+        loop {
+            let (kind, body) = read_packet(stream).unwrap();
+            if let Some(value) = f(kind, &body) {
+                return value;
+            }
+        }
+    }
+
+    /// Publishes of the topic.
+    fn published(stream: &mut impl Read, topic: &str) -> String {
+        read_until(stream, |kind, body| {
+            if kind & 0xf0 != PUBLISH {
+                return None;
+            }
+            let (other, _, payload) = parse_publish(kind, body).unwrap();
+            (other == topic).then(|| String::from_utf8_lossy(payload).into_owned())
+        })
+    }
+
+    /// Handles the events until a command is received.
+    fn next_command(bridge: &mut MqttBridge) -> Command {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut command = None;
+        while command.is_none() {
+            assert!(Instant::now() < deadline, "no command received");
+            bridge.handle(|received| {
+                command = Some(received);
+                Ok(serde_json::Value::Null)
+            });
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        command.unwrap()
+    }
+
+    #[test]
+    fn mqtt_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let text = format!(
+            "[mqtt]\nhost = \"127.0.0.1\"\nport = {port}\nclient_id = \"desk\"\n\
+             username = \"u\"\npassword = \"p\""
+        );
+        let mut bridge = MqttBridge::start(config(&text).unwrap().unwrap());
+
+        let accept = || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let (kind, body) = read_packet(&mut stream).unwrap();
+            assert_eq!(kind, CONNECT);
+            assert!(body.ends_with(b"\x00\x01u\x00\x01p"));
+            stream.write_all(&[CONNACK, 2, 0, 0]).unwrap();
+            let (kind, _) = read_packet(&mut stream).unwrap();
+            assert_eq!(kind, SUBSCRIBE);
+            stream
+        };
+        let mut broker = accept();
+        let wait = |bridge: &MqttBridge, connected: bool| {
+            while bridge.writer.lock().unwrap().is_some() != connected {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+        let publish = |bridge: &mut MqttBridge| {
+            wait(bridge, true);
+            // Connected once the event is handled.
+            bridge.handle(|_| unreachable!());
+            bridge.publish(&[state()]);
+        };
+        publish(&mut bridge);
+
+        let config = published(
+            &mut broker,
+            "homeassistant/light/nzxtcli_MA01/light0/config",
+        );
+        let config = serde_json::from_str::<serde_json::Value>(&config).unwrap();
+        assert_eq!(config["command_topic"], "nzxtcli/MA01/light/0/set");
+        assert_eq!(config["availability"][0]["topic"], "nzxtcli/desk/status");
+        assert_eq!(
+            published(&mut broker, "nzxtcli/MA01/availability"),
+            "online"
+        );
+        assert_eq!(
+            published(&mut broker, "nzxtcli/MA01/light/0/state"),
+            r#"{"state":"ON","color_mode":"rgb","brightness":255,"color":{"r":0,"g":0,"b":255}}"#
+        );
+        assert_eq!(
+            published(&mut broker, "nzxtcli/MA01/fan/0/percentage"),
+            "40"
+        );
+
+        broker
+            .write_all(&publish_packet(
+                "nzxtcli/MA01/light/1/set",
+                br#"{"color":{"r":0,"g":255,"b":0},"brightness":51}"#,
+                false,
+            ))
+            .unwrap();
+        assert_eq!(next_command(&mut bridge), Command::SetColor {
+            color: Color::new(0, 51, 0),
+            channels: ChannelSelector::Channels(vec![1]),
+            serial: Some("MA01".to_owned()),
+            product_id: None,
+            duration_ms: None,
+        });
+        broker
+            .write_all(&publish_packet(
+                "nzxtcli/MA01/fan/0/percentage/set",
+                b"75",
+                false,
+            ))
+            .unwrap();
+        assert_eq!(next_command(&mut bridge), Command::SetFan {
+            duty: 75,
+            fans: ChannelSelector::Channels(vec![0]),
+            serial: Some("MA01".to_owned()),
+            product_id: None,
+        });
+
+        // The overridden channel keeps the brightness of the command, unchanged
+        // states are not sent again.
+        let mut changed = state();
+        changed.lights[1] = Some(Color::new(0, 51, 0));
+        changed.connected = false;
+        bridge.publish(&[changed]);
+        assert_eq!(
+            published(&mut broker, "nzxtcli/MA01/availability"),
+            "offline"
+        );
+        assert_eq!(
+            published(&mut broker, "nzxtcli/MA01/light/1/state"),
+            r#"{"state":"ON","color_mode":"rgb","brightness":51,"color":{"r":0,"g":255,"b":0}}"#
+        );
+
+        // Everything is sent again after reconnecting.
+        drop(broker);
+        wait(&bridge, false);
+        let mut broker = accept();
+        publish(&mut bridge);
+        assert_eq!(published(&mut broker, "nzxtcli/desk/status"), "online");
+        assert_eq!(
+            published(&mut broker, "nzxtcli/MA01/availability"),
+            "online"
+        );
+
+        drop(bridge);
+        assert_eq!(published(&mut broker, "nzxtcli/desk/status"), "offline");
+        let (kind, _) = read_packet(&mut broker).unwrap();
+        assert_eq!(kind, DISCONNECT[0]);
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn mqtt_broker_tls() {
+        let Some(certificates) = crate::tls::tests::certificates("mqtt") else {
+            return;
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let text = format!(
+            "[mqtt]\nhost = \"localhost\"\nport = {port}\ntls = true\nca_file = {:?}\n\
+             client_id = \"desk\"\nusername = \"u\"\npassword = \"p\"",
+            certificates.cert.display()
+        );
+        let mut bridge = MqttBridge::start(config(&text).unwrap().unwrap());
+
+        let (socket, _) = listener.accept().unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut broker =
+            crate::tls::TlsStream::accept(socket, &certificates.cert, &certificates.key).unwrap();
+        let (kind, body) = read_packet(&mut broker).unwrap();
+        assert_eq!(kind, CONNECT);
+        assert!(body.ends_with(b"\x00\x01u\x00\x01p"));
+        broker.write_all(&[CONNACK, 2, 0, 0]).unwrap();
+        let (kind, _) = read_packet(&mut broker).unwrap();
+        assert_eq!(kind, SUBSCRIBE);
+
+        while bridge.writer.lock().unwrap().is_none() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        bridge.handle(|_| unreachable!());
+        bridge.publish(&[state()]);
+        assert_eq!(published(&mut broker, "nzxtcli/desk/status"), "online");
+        assert_eq!(
+            published(&mut broker, "nzxtcli/MA01/availability"),
+            "online"
+        );
+        broker
+            .write_all(&publish_packet(
+                "nzxtcli/MA01/fan/0/percentage/set",
+                b"75",
+                false,
+            ))
+            .unwrap();
+        assert_eq!(next_command(&mut bridge), Command::SetFan {
+            duty: 75,
+            fans: ChannelSelector::Channels(vec![0]),
+            serial: Some("MA01".to_owned()),
+            product_id: None,
+        });
+
+        drop(bridge);
+        assert_eq!(published(&mut broker, "nzxtcli/desk/status"), "offline");
+        let (kind, _) = read_packet(&mut broker).unwrap();
+        assert_eq!(kind, DISCONNECT[0]);
+    }
+}
@@ -0,0 +1,220 @@
+//! Notifications of the service manager for `Type=notify` systemd units,
+//! sent to `NOTIFY_SOCKET` as described in `sd_notify(3)`.
+
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use nzxtcli::logging::warning;
+
+/// Sends the state of a long-running command to systemd.
+///
+/// Does nothing if the process was not started by systemd.
+#[derive(Default)]
+pub struct Notifier {
+    socket: Option<UnixDatagram>,
+    watchdog: Option<Duration>,
+    last_ping: Option<Instant>,
+    /// The last sent status, unchanged ones are not sent again.
+    status: Option<String>,
+}
+
+impl Notifier {
+    pub fn from_env() -> Self {
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET").filter(|path| !path.is_empty()) else {
+            return Self::default();
+        };
+        let socket = match connect(Path::new(&path)) {
+            Ok(socket) => socket,
+            Err(e) => {
+                warning!(
+                    "failed to connect to the notify socket {}: {e}",
+                    path.display()
+                );
+                return Self::default();
+            }
+        };
+        let watchdog = watchdog_interval(
+            std::env::var("WATCHDOG_USEC").ok().as_deref(),
+            std::env::var("WATCHDOG_PID").ok().as_deref(),
+            std::process::id(),
+        );
+        Self {
+            socket: Some(socket),
+            watchdog,
+            last_ping: None,
+            status: None,
+        }
+    }
+
+    /// Startup has finished, e.g. after the first update of the devices.
+    pub fn ready(&mut self, status: &str) {
+        self.status = Some(status.to_owned());
+        self.send(&[("READY", "1"), ("STATUS", status)]);
+    }
+
+    /// The config is being reloaded, followed by [`Notifier::ready`].
+    pub fn reloading(&mut self) {
+        let usec = monotonic_usec().to_string();
+        self.send(&[("RELOADING", "1"), ("MONOTONIC_USEC", &usec)]);
+    }
+
+    pub fn status(&mut self, status: &str) {
+        if self.status.as_deref() == Some(status) {
+            return;
+        }
+        self.status = Some(status.to_owned());
+        self.send(&[("STATUS", status)]);
+    }
+
+    /// Pings the watchdog if it's enabled for the service.
+    pub fn watchdog(&mut self) {
+        if self.watchdog.is_some() {
+            self.last_ping = Some(Instant::now());
+            self.send(&[("WATCHDOG", "1")]);
+        }
+    }
+
+    /// The watchdog interval has passed since the last ping, waits are
+    /// interrupted to ping it even if the update interval is longer.
+    pub fn watchdog_due(&self) -> bool {
+        self.watchdog
+            .is_some_and(|interval| self.last_ping.is_none_or(|last| last.elapsed() >= interval))
+    }
+
+    pub fn stopping(&self) {
+        self.send(&[("STOPPING", "1")]);
+    }
+
+    /// Failures are ignored like in `sd_notify`, the service keeps running.
+    fn send(&self, fields: &[(&str, &str)]) {
+        if let Some(socket) = &self.socket {
+            _ = socket.send(message(fields).as_bytes());
+        }
+    }
+}
+
+/// Newline-separated `KEY=value` assignments, newlines of the values are
+/// replaced since they would start a new assignment.
+fn message(fields: &[(&str, &str)]) -> String {
+    let mut message = String::new();
+    for (key, value) in fields {
+        message.push_str(key);
+        message.push('=');
+        message.extend(value.chars().map(|c| if c == '\n' { ' ' } else { c }));
+        message.push('\n');
+    }
+    message
+}
+
+/// Half of the watchdog timeout as recommended by `sd_watchdog_enabled(3)`,
+/// `None` if the watchdog is disabled or meant for another process.
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.trim().parse::<u32>() != Ok(own_pid)) {
+        return None;
+    }
+    let usec = usec?.trim().parse::<u64>().ok().filter(|&usec| usec > 0)?;
+    Some(Duration::from_micros(usec) / 2)
+}
+
+/// Paths starting with `@` are in the abstract namespace.
+fn connect(path: &Path) -> std::io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    match path.as_os_str().as_encoded_bytes().strip_prefix(b"@") {
+        Some(name) => connect_abstract(&socket, name)?,
+        None => socket.connect(path)?,
+    }
+    Ok(socket)
+}
+
+#[cfg(target_os = "linux")]
+fn connect_abstract(socket: &UnixDatagram, name: &[u8]) -> std::io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+
+    let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+    socket.connect_addr(&address)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn connect_abstract(_: &UnixDatagram, _: &[u8]) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "abstract sockets are only supported on Linux",
+    ))
+}
+
+/// `CLOCK_MONOTONIC` in microseconds, compared by systemd with the time of the reload request.
+fn monotonic_usec() -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: The pointer is valid for the duration of the call.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+    time.tv_sec as u64 * 1_000_000 + time.tv_nsec as u64 / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        assert_eq!(
+            message(&[("READY", "1"), ("STATUS", "45.0°C -> #ff0000")]),
+            "READY=1\nSTATUS=45.0°C -> #ff0000\n"
+        );
+        assert_eq!(message(&[("STATUS", "a\nb")]), "STATUS=a b\n");
+
+        assert_eq!(
+            watchdog_interval(Some("10000000"), None, 42),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            watchdog_interval(Some("10000000"), Some("42"), 42),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(watchdog_interval(Some("10000000"), Some("7"), 42), None);
+        assert_eq!(watchdog_interval(Some("0"), None, 42), None);
+        assert_eq!(watchdog_interval(Some("soon"), None, 42), None);
+        assert_eq!(watchdog_interval(None, None, 42), None);
+    }
+
+    #[test]
+    fn notify_socket() {
+        let path = std::env::temp_dir().join(format!("nzxtcli-notify-{}.sock", std::process::id()));
+        _ = std::fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+        let mut notifier = Notifier {
+            socket: Some(connect(&path).unwrap()),
+            watchdog: Some(Duration::from_secs(5)),
+            last_ping: None,
+            status: None,
+        };
+
+        notifier.ready("started");
+        notifier.status("started");
+        notifier.status("reconnecting NZXT RGB Controller");
+        assert!(notifier.watchdog_due());
+        notifier.watchdog();
+        assert!(!notifier.watchdog_due());
+        let mut buffer = [0; 256];
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let len = server.recv(&mut buffer).unwrap();
+            received.push(String::from_utf8_lossy(&buffer[..len]).into_owned());
+        }
+        // The unchanged status is not sent again.
+        assert_eq!(received, [
+            "READY=1\nSTATUS=started\n",
+            "STATUS=reconnecting NZXT RGB Controller\n",
+            "WATCHDOG=1\n",
+        ]);
+        _ = std::fs::remove_file(&path);
+
+        // Never due without a watchdog.
+        let mut notifier = Notifier::default();
+        notifier.watchdog();
+        assert!(!notifier.watchdog_due());
+    }
+}
@@ -0,0 +1,736 @@
+//! `nzxtcli serve-openrgb`: the OpenRGB SDK protocol, so that its clients
+//! (Artemis, game integrations, OpenRGB itself) can stream colors while
+//! nzxtcli stays the only process accessing the controllers.
+//!
+//! Each controller is a device with a single `Direct` mode and a linear zone
+//! per RGB channel, the LEDs are named after the accessories of the channels.
+//! Zones can't be resized, the LED counts come from the detected (or the
+//! configured) accessories.
+//!
+//! Packets start with a header of the `ORGB` magic and the device index,
+//! packet id and data size as little-endian `u32`. Protocol versions up to
+//! [`PROTOCOL_VERSION`] are supported, older clients get the layouts of their
+//! version: version 1 added the vendor string and version 3 the brightness
+//! of the modes. Profiles (version 2) are reported as an empty list.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use hidapi::HidApi;
+use nzxtcli::logging::{self, error, info, warning};
+use nzxtcli::{Color, Controller};
+
+use crate::retry::Backoff;
+use crate::{AccessoryArgs, DeviceArgs, TransformArgs};
+
+/// Latest protocol version of the server.
+const PROTOCOL_VERSION: u32 = 3;
+const MAGIC: &[u8; 4] = b"ORGB";
+const HEADER_LEN: usize = 16;
+/// Far larger than the frames of all channels.
+const MAX_DATA_LEN: usize = 1 << 20;
+
+const REQUEST_CONTROLLER_COUNT: u32 = 0;
+const REQUEST_CONTROLLER_DATA: u32 = 1;
+const REQUEST_PROTOCOL_VERSION: u32 = 40;
+const SET_CLIENT_NAME: u32 = 50;
+const REQUEST_PROFILE_LIST: u32 = 150;
+const RESIZE_ZONE: u32 = 1000;
+const UPDATE_LEDS: u32 = 1050;
+const UPDATE_ZONE_LEDS: u32 = 1051;
+const UPDATE_SINGLE_LED: u32 = 1052;
+const SET_CUSTOM_MODE: u32 = 1100;
+const UPDATE_MODE: u32 = 1101;
+const SAVE_MODE: u32 = 1102;
+
+const DEVICE_TYPE_COOLER: i32 = 3;
+const DEVICE_TYPE_LEDSTRIP: i32 = 4;
+const ZONE_TYPE_LINEAR: i32 = 1;
+const MODE_FLAG_HAS_PER_LED_COLOR: u32 = 1 << 5;
+const MODE_COLORS_PER_LED: u32 = 1;
+
+/// Serve the controllers to OpenRGB SDK clients.
+///
+/// Clients see each controller as a device with a zone per RGB channel and
+/// can set the colors of individual LEDs in its `Direct` mode.
+#[derive(Parser)]
+pub struct CmdServeOpenrgb {
+    /// Port to listen on.
+    #[clap(long, default_value_t = 6742)]
+    port: u16,
+
+    /// Address to listen on, clients on other hosts need `0.0.0.0`.
+    #[clap(long, default_value = "127.0.0.1")]
+    address: String,
+
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+
+    #[clap(flatten)]
+    transform: TransformArgs,
+}
+
+impl CmdServeOpenrgb {
+    pub fn run(self, device: &DeviceArgs) -> Result<()> {
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let options = device
+            .find_options(&self.accessories)?
+            .color_transform(self.transform.transform()?);
+        let controllers = crate::find_controllers(&api, &options)?;
+        anyhow::ensure!(!controllers.is_empty(), nzxtcli::Error::NoMatchingDevice);
+        let mut server = Server::new(controllers);
+
+        let address = (self.address.as_str(), self.port);
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("failed to listen on {}:{}", self.address, self.port))?;
+        info!("serving OpenRGB clients at {}", listener.local_addr()?);
+
+        // The controllers stay on this thread, the clients are read by their own.
+        let (sender, requests) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warning!("failed to accept a connection: {e}");
+                        continue;
+                    }
+                };
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    let peer = stream
+                        .peer_addr()
+                        .map_or_else(|_| "unknown".to_owned(), |addr| addr.to_string());
+                    if let Err(e) = serve_client(stream, &sender) {
+                        warning!("OpenRGB client {peer}: {e:#}");
+                    }
+                });
+            }
+        });
+
+        crate::catch_interrupts();
+        while !crate::INTERRUPTED.load(Ordering::Relaxed) {
+            match requests.recv_timeout(crate::INTERRUPT_POLL) {
+                Ok(request) => {
+                    server.reconnect(&api, Instant::now());
+                    let reply = server.handle(&request);
+                    // The client is gone if it can't be sent.
+                    _ = request.reply.send(reply);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A packet passed from a client to the controllers, answered with the data
+/// of the reply (if the packet has one).
+struct Request {
+    device: u32,
+    id: u32,
+    data: Vec<u8>,
+    /// Negotiated version of the client.
+    protocol: u32,
+    reply: mpsc::Sender<Option<Vec<u8>>>,
+}
+
+/// Reads the packets of a client until it disconnects.
+fn serve_client(mut stream: TcpStream, requests: &mpsc::Sender<Request>) -> Result<()> {
+    let (reply, replies) = mpsc::channel();
+    // Clients which don't ask for the version speak the first one.
+    let mut protocol = 0;
+    loop {
+        let mut header = [0u8; HEADER_LEN];
+        match stream.read_exact(&mut header) {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result.context("failed to read a packet")?,
+        }
+        anyhow::ensure!(
+            &header[..4] == MAGIC,
+            "invalid packet magic {:02x?}",
+            &header[..4]
+        );
+        let device = u32_at(&header, 4);
+        let id = u32_at(&header, 8);
+        let len = u32_at(&header, 12) as usize;
+        anyhow::ensure!(
+            len <= MAX_DATA_LEN,
+            "packet {id} is too large ({len} bytes)"
+        );
+        let mut data = vec![0; len];
+        stream
+            .read_exact(&mut data)
+            .with_context(|| format!("failed to read the data of packet {id}"))?;
+
+        match id {
+            // The server answers with its version, both use the lower one.
+            REQUEST_PROTOCOL_VERSION => {
+                let client = data.get(..4).map_or(0, |data| u32_at(data, 0));
+                protocol = client.min(PROTOCOL_VERSION);
+                let packet = packet(device, id, &PROTOCOL_VERSION.to_le_bytes());
+                stream.write_all(&packet)?;
+            }
+            SET_CLIENT_NAME => {
+                let name = String::from_utf8_lossy(&data);
+                info!("OpenRGB client `{}` connected", name.trim_end_matches('\0'));
+            }
+            _ => {
+                let request = Request {
+                    device,
+                    id,
+                    data,
+                    protocol,
+                    reply: reply.clone(),
+                };
+                requests
+                    .send(request)
+                    .ok()
+                    .context("the server has stopped")?;
+                if let Some(data) = replies.recv().context("the server has stopped")? {
+                    stream.write_all(&packet(device, id, &data))?;
+                }
+            }
+        }
+    }
+}
+
+fn packet(device: u32, id: u32, data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_LEN + data.len());
+    packet.extend_from_slice(MAGIC);
+    packet.extend_from_slice(&device.to_le_bytes());
+    packet.extend_from_slice(&id.to_le_bytes());
+    packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    packet.extend_from_slice(data);
+    packet
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    let bytes = data[offset..offset + 4].try_into().expect("4 bytes");
+    u32::from_le_bytes(bytes)
+}
+
+/// Controllers and the colors last set by the clients.
+struct Server {
+    devices: Vec<Device>,
+}
+
+struct Device {
+    controller: Box<dyn Controller>,
+    reconnect: Backoff,
+    /// Colors of all LEDs, in the order of the zones.
+    colors: Vec<Color>,
+}
+
+impl Device {
+    fn zone_leds(&self) -> impl Iterator<Item = usize> + '_ {
+        let channels = self.controller.rgb_channels().iter();
+        channels.map(|channel| channel.led_count)
+    }
+
+    /// The first LED of the zone.
+    fn zone_start(&self, zone: usize) -> usize {
+        self.zone_leds().take(zone).sum()
+    }
+}
+
+impl Server {
+    fn new(controllers: Vec<Box<dyn Controller>>) -> Self {
+        let devices = controllers
+            .into_iter()
+            .map(|controller| {
+                let leds = controller.rgb_channels().iter().map(|c| c.led_count).sum();
+                Device {
+                    controller,
+                    reconnect: Backoff::default(),
+                    colors: vec![Color::BLACK; leds],
+                }
+            })
+            .collect();
+        Self { devices }
+    }
+
+    /// Disconnected controllers are reconnected with a backoff.
+    fn reconnect(&mut self, api: &HidApi, now: Instant) {
+        for device in &mut self.devices {
+            let controller = &mut device.controller;
+            if controller.is_connected() || !device.reconnect.ready(now) {
+                continue;
+            }
+            match controller.reconnect(api) {
+                Ok(()) => {
+                    device.reconnect.succeeded();
+                }
+                Err(e) => {
+                    let delay = device.reconnect.failed(now);
+                    let _span = logging::controller_span(controller.as_ref());
+                    error!(
+                        "failed to reconnect: {e:?}, retrying in {}",
+                        humantime::format_duration(delay)
+                    );
+                }
+            }
+        }
+    }
+
+    /// Malformed packets and those for unknown devices are ignored, like
+    /// the OpenRGB server does.
+    fn handle(&mut self, request: &Request) -> Option<Vec<u8>> {
+        let data = &request.data[..];
+        if request.id == REQUEST_CONTROLLER_COUNT {
+            return Some((self.devices.len() as u32).to_le_bytes().to_vec());
+        }
+        if request.id == REQUEST_PROFILE_LIST {
+            let mut list = Vec::new();
+            list.extend_from_slice(&6u32.to_le_bytes());
+            list.extend_from_slice(&0u16.to_le_bytes());
+            return Some(list);
+        }
+        let device = self.devices.get_mut(request.device as usize)?;
+
+        match request.id {
+            REQUEST_CONTROLLER_DATA => {
+                // Since version 1 the request has the version of the client.
+                let protocol = match data.get(..4) {
+                    Some(version) => u32_at(version, 0).min(PROTOCOL_VERSION),
+                    None => request.protocol,
+                };
+                Some(controller_data(device, protocol))
+            }
+            UPDATE_LEDS => {
+                let colors = parse_colors(data.get(4..)?)?;
+                let len = colors.len().min(device.colors.len());
+                device.colors[..len].copy_from_slice(&colors[..len]);
+                let zones = device.zone_leds().collect::<Vec<_>>();
+                for (zone, leds) in zones.into_iter().enumerate() {
+                    if leds > 0 {
+                        write_zone(device, zone);
+                    }
+                }
+                None
+            }
+            UPDATE_ZONE_LEDS => {
+                let zone = u32_at(data.get(..8)?, 4) as usize;
+                let colors = parse_colors(data.get(8..)?)?;
+                let leds = device.zone_leds().nth(zone)?;
+                let start = device.zone_start(zone);
+                let len = colors.len().min(leds);
+                device.colors[start..start + len].copy_from_slice(&colors[..len]);
+                write_zone(device, zone);
+                None
+            }
+            UPDATE_SINGLE_LED => {
+                let led = u32_at(data.get(..8)?, 0) as usize;
+                let color = parse_color(&data[4..8]);
+                *device.colors.get_mut(led)? = color;
+                // The zone of the LED.
+                let mut start = 0;
+                let zones = device.zone_leds().collect::<Vec<_>>();
+                let zone = zones.into_iter().position(|leds| {
+                    start += leds;
+                    led < start
+                })?;
+                write_zone(device, zone);
+                None
+            }
+            // There is a single mode and the zones have fixed sizes.
+            RESIZE_ZONE | SET_CUSTOM_MODE | UPDATE_MODE | SAVE_MODE => None,
+            _ => None,
+        }
+    }
+}
+
+/// Failures are printed, the next update (or reconnect) tries again.
+fn write_zone(device: &Device, zone: usize) {
+    let controller = device.controller.as_ref();
+    if !controller.is_connected() {
+        return;
+    }
+    let start = device.zone_start(zone);
+    let leds = controller.rgb_channels()[zone].led_count;
+    if let Err(e) = controller.set_channel_leds(zone, &device.colors[start..start + leds]) {
+        let _span = logging::controller_span(controller);
+        error!("failed to set the LEDs of channel {zone}: {e:?}");
+    }
+}
+
+/// A `u16` count and the colors as `r, g, b, 0`.
+fn parse_colors(data: &[u8]) -> Option<Vec<Color>> {
+    let count = u16::from_le_bytes(data.get(..2)?.try_into().ok()?) as usize;
+    let colors = data.get(2..2 + count * 4)?;
+    Some(colors.chunks_exact(4).map(parse_color).collect())
+}
+
+fn parse_color(bytes: &[u8]) -> Color {
+    Color::new(bytes[0], bytes[1], bytes[2])
+}
+
+/// The `RGBController` description in the layout of the protocol version.
+fn controller_data(device: &Device, protocol: u32) -> Vec<u8> {
+    let controller = device.controller.as_ref();
+    let mut data = Data::default();
+    // Replaced with the total size at the end.
+    data.u32(0);
+    data.i32(match controller.is_liquid_cooler() {
+        true => DEVICE_TYPE_COOLER,
+        false => DEVICE_TYPE_LEDSTRIP,
+    });
+    data.string(controller.name());
+    if protocol >= 1 {
+        data.string("NZXT");
+    }
+    data.string(&format!("{} (nzxtcli)", controller.name()));
+    data.string(env!("CARGO_PKG_VERSION"));
+    data.string(controller.serial().unwrap_or_default());
+    let location = controller
+        .info()
+        .map(|info| format!("HID: {}", info.path().to_string_lossy()));
+    data.string(location.as_deref().unwrap_or_default());
+
+    // Modes and the active one.
+    data.u16(1);
+    data.i32(0);
+    data.string("Direct");
+    data.i32(0);
+    data.u32(MODE_FLAG_HAS_PER_LED_COLOR);
+    // Speed and brightness ranges.
+    data.u32(0);
+    data.u32(0);
+    if protocol >= 3 {
+        data.u32(0);
+        data.u32(0);
+    }
+    // Mode colors range, speed, brightness and direction.
+    data.u32(0);
+    data.u32(0);
+    data.u32(0);
+    if protocol >= 3 {
+        data.u32(0);
+    }
+    data.u32(0);
+    data.u32(MODE_COLORS_PER_LED);
+    data.u16(0);
+
+    let channels = controller.rgb_channels();
+    data.u16(channels.len() as u16);
+    for (index, channel) in channels.iter().enumerate() {
+        data.string(&format!("Channel {index}"));
+        data.i32(ZONE_TYPE_LINEAR);
+        // Minimum, maximum and current LED counts.
+        for _ in 0..3 {
+            data.u32(channel.led_count as u32);
+        }
+        // No matrix map.
+        data.u16(0);
+    }
+
+    data.u16(device.colors.len() as u16);
+    let mut value = 0;
+    for (index, channel) in channels.iter().enumerate() {
+        for led in 0..channel.led_count {
+            let accessory = (0..channel.devices.len()).find_map(|slot| {
+                let leds = channel.device_leds(slot)?;
+                let name = channel.devices[slot].name;
+                leds.contains(&led).then(|| (name, led - leds.start))
+            });
+            let name = match accessory {
+                Some((name, led)) => format!("Channel {index} {name} LED {}", led + 1),
+                None => format!("Channel {index} LED {}", led + 1),
+            };
+            data.string(&name);
+            data.u32(value);
+            value += 1;
+        }
+    }
+
+    data.u16(device.colors.len() as u16);
+    for color in &device.colors {
+        data.color(*color);
+    }
+
+    let mut data = data.0;
+    let len = (data.len() as u32).to_le_bytes();
+    data[..4].copy_from_slice(&len);
+    data
+}
+
+/// Little-endian fields of the packets.
+#[derive(Default)]
+struct Data(Vec<u8>);
+
+impl Data {
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// The length includes the terminating null.
+    fn string(&mut self, value: &str) {
+        self.u16(value.len() as u16 + 1);
+        self.0.extend_from_slice(value.as_bytes());
+        self.0.push(0);
+    }
+
+    fn color(&mut self, color: Color) {
+        let bytes = [color.red(), color.green(), color.blue(), 0];
+        self.0.extend_from_slice(&bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    use nzxtcli::transport;
+
+    use super::*;
+
+    /// Fields of the documented layout of the controller data.
+    struct Fields<'a>(&'a [u8]);
+
+    impl Fields<'_> {
+        fn take(&mut self, len: usize) -> &[u8] {
+            let (head, rest) = self.0.split_at(len);
+            self.0 = rest;
+            head
+        }
+
+        fn u16(&mut self) -> u16 {
+            u16::from_le_bytes(self.take(2).try_into().unwrap())
+        }
+
+        fn u32(&mut self) -> u32 {
+            u32::from_le_bytes(self.take(4).try_into().unwrap())
+        }
+
+        fn string(&mut self) -> String {
+            let len = self.u16() as usize;
+            let bytes = self.take(len);
+            assert_eq!(bytes.last(), Some(&0), "strings are null-terminated");
+            String::from_utf8(bytes[..len - 1].to_vec()).unwrap()
+        }
+    }
+
+    /// The header is `ORGB`, the device index, the packet id and the length
+    /// of the data, all little-endian.
+    fn send(stream: &mut TcpStream, device: u32, id: u32, data: &[u8]) {
+        let mut packet = b"ORGB".to_vec();
+        for value in [device, id, data.len() as u32] {
+            packet.extend_from_slice(&value.to_le_bytes());
+        }
+        packet.extend_from_slice(data);
+        stream.write_all(&packet).unwrap();
+    }
+
+    fn receive(stream: &mut TcpStream) -> (u32, u32, Vec<u8>) {
+        let mut header = [0u8; 16];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(&header[..4], b"ORGB");
+        let mut fields = Fields(&header[4..]);
+        let (device, id, len) = (fields.u32(), fields.u32(), fields.u32());
+        let mut data = vec![0; len as usize];
+        stream.read_exact(&mut data).unwrap();
+        (device, id, data)
+    }
+
+    /// The count and the colors as red, green, blue and a padding byte.
+    fn colors(count: u16, colors: &[Color]) -> Vec<u8> {
+        let mut data = count.to_le_bytes().to_vec();
+        for color in colors {
+            data.extend_from_slice(&[color.red(), color.green(), color.blue(), 0]);
+        }
+        data
+    }
+
+    #[test]
+    fn openrgb_ids() {
+        // Values of the SDK documentation.
+        let packets = [
+            REQUEST_CONTROLLER_COUNT,
+            REQUEST_CONTROLLER_DATA,
+            REQUEST_PROTOCOL_VERSION,
+            SET_CLIENT_NAME,
+            REQUEST_PROFILE_LIST,
+            RESIZE_ZONE,
+            UPDATE_LEDS,
+            UPDATE_ZONE_LEDS,
+            UPDATE_SINGLE_LED,
+            SET_CUSTOM_MODE,
+            UPDATE_MODE,
+            SAVE_MODE,
+        ];
+        assert_eq!(packets, [
+            0, 1, 40, 50, 150, 1000, 1050, 1051, 1052, 1100, 1101, 1102
+        ]);
+        assert_eq!((DEVICE_TYPE_COOLER, DEVICE_TYPE_LEDSTRIP), (3, 4));
+        assert_eq!(ZONE_TYPE_LINEAR, 1);
+        assert_eq!(MODE_FLAG_HAS_PER_LED_COLOR, 0x20);
+        assert_eq!(MODE_COLORS_PER_LED, 1);
+    }
+
+    #[test]
+    fn openrgb_protocol() {
+        let (transport, controller) = transport::mock_controller_boxed();
+        let (expected_transport, expected) = transport::mock_controller_boxed();
+        let leds = controller
+            .rgb_channels()
+            .iter()
+            .map(|channel| channel.led_count)
+            .collect::<Vec<_>>();
+        assert!(leds.iter().all(|&leds| leds > 1));
+        let mut server = Server::new(vec![controller]);
+
+        let (sender, requests) = mpsc::channel::<Request>();
+        std::thread::spawn(move || {
+            for request in requests {
+                let reply = server.handle(&request);
+                _ = request.reply.send(reply);
+            }
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let sender = sender.clone();
+                std::thread::spawn(move || serve_client(stream.unwrap(), &sender));
+            }
+        });
+        let connect = || {
+            let client = TcpStream::connect(address).unwrap();
+            client
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            client
+        };
+        let mut client = connect();
+
+        // Newer clients get the version of the server.
+        send(
+            &mut client,
+            0,
+            REQUEST_PROTOCOL_VERSION,
+            &5u32.to_le_bytes(),
+        );
+        let reply = receive(&mut client);
+        assert_eq!(
+            reply,
+            (0, REQUEST_PROTOCOL_VERSION, 3u32.to_le_bytes().to_vec())
+        );
+        send(&mut client, 0, SET_CLIENT_NAME, b"test\0");
+        send(&mut client, 0, REQUEST_CONTROLLER_COUNT, &[]);
+        let reply = receive(&mut client);
+        assert_eq!(
+            reply,
+            (0, REQUEST_CONTROLLER_COUNT, 1u32.to_le_bytes().to_vec())
+        );
+        send(&mut client, 0, REQUEST_PROFILE_LIST, &[]);
+        assert_eq!(receive(&mut client).2, [6, 0, 0, 0, 0, 0]);
+
+        send(&mut client, 0, REQUEST_CONTROLLER_DATA, &3u32.to_le_bytes());
+        let (device, id, data) = receive(&mut client);
+        assert_eq!((device, id), (0, REQUEST_CONTROLLER_DATA));
+        let mut fields = Fields(&data);
+        assert_eq!(fields.u32() as usize, data.len());
+        assert_eq!(fields.u32() as i32, DEVICE_TYPE_LEDSTRIP);
+        assert_eq!(fields.string(), "NZXT RGB Controller");
+        assert_eq!(fields.string(), "NZXT");
+        assert_eq!(fields.string(), "NZXT RGB Controller (nzxtcli)");
+        assert_eq!(fields.string(), env!("CARGO_PKG_VERSION"));
+        // Serial and location of the mock.
+        assert_eq!(fields.string(), "");
+        assert_eq!(fields.string(), "");
+        assert_eq!((fields.u16(), fields.u32()), (1, 0));
+        assert_eq!(fields.string(), "Direct");
+        assert_eq!(fields.u32(), 0);
+        assert_eq!(fields.u32(), MODE_FLAG_HAS_PER_LED_COLOR);
+        // Speed, brightness and colors ranges, speed, brightness, direction.
+        fields.take(4 * 9);
+        assert_eq!(fields.u32(), MODE_COLORS_PER_LED);
+        assert_eq!(fields.u16(), 0);
+        assert_eq!(fields.u16(), 3);
+        for (index, &leds) in leds.iter().enumerate() {
+            assert_eq!(fields.string(), format!("Channel {index}"));
+            assert_eq!(fields.u32() as i32, ZONE_TYPE_LINEAR);
+            let counts = [fields.u32(), fields.u32(), fields.u32()];
+            assert_eq!(counts, [leds as u32; 3]);
+            assert_eq!(fields.u16(), 0);
+        }
+        let total = leds.iter().sum::<usize>();
+        assert_eq!(fields.u16() as usize, total);
+        for value in 0..total {
+            let name = fields.string();
+            assert!(name.starts_with("Channel "), "{name}");
+            assert_eq!(fields.u32() as usize, value);
+        }
+        assert_eq!(fields.u16() as usize, total);
+        assert_eq!(fields.take(total * 4), vec![0; total * 4]);
+        assert!(fields.0.is_empty());
+
+        // The vendor and the brightness of the modes are missing in version 0.
+        let mut old_client = connect();
+        send(&mut old_client, 0, REQUEST_CONTROLLER_DATA, &[]);
+        let old = receive(&mut old_client).2;
+        assert_eq!(old.len(), data.len() - 7 - 3 * 4);
+
+        // A zone is written like `set_channel_leds`.
+        let zone = [Color::RED, Color::GREEN];
+        let mut update = 0u32.to_le_bytes().to_vec();
+        update.extend_from_slice(&1u32.to_le_bytes());
+        update.extend(colors(2, &zone));
+        send(&mut client, 0, UPDATE_ZONE_LEDS, &update);
+        let mut single = 0u32.to_le_bytes().to_vec();
+        single.extend_from_slice(&[0, 0, 0xff, 0]);
+        send(&mut client, 0, UPDATE_SINGLE_LED, &single);
+        // Replies are sent in order, so it has been applied after this one.
+        send(&mut client, 0, REQUEST_CONTROLLER_COUNT, &[]);
+        receive(&mut client);
+        let mut channel = vec![Color::BLACK; leds[1]];
+        channel[..2].copy_from_slice(&zone);
+        expected.set_channel_leds(1, &channel).unwrap();
+        let mut channel = vec![Color::BLACK; leds[0]];
+        channel[0] = Color::BLUE;
+        expected.set_channel_leds(0, &channel).unwrap();
+        assert_eq!(transport.written(), expected_transport.written());
+
+        // Frames of all LEDs are split into the zones.
+        transport.clear_written();
+        expected_transport.clear_written();
+        let frame = (0..total)
+            .map(|led| Color::new(led as u8, 0, 0xff))
+            .collect::<Vec<_>>();
+        let mut update = 0u32.to_le_bytes().to_vec();
+        update.extend(colors(total as u16, &frame));
+        send(&mut client, 0, UPDATE_LEDS, &update);
+        // Unknown devices and packets are ignored.
+        send(&mut client, 7, UPDATE_LEDS, &update);
+        send(&mut client, 0, 9999, &[1, 2, 3]);
+        send(&mut client, 0, REQUEST_CONTROLLER_DATA, &3u32.to_le_bytes());
+        let data = receive(&mut client).2;
+        let mut start = 0;
+        for (channel, &leds) in leds.iter().enumerate() {
+            let colors = &frame[start..start + leds];
+            expected.set_channel_leds(channel, colors).unwrap();
+            start += leds;
+        }
+        assert_eq!(transport.written(), expected_transport.written());
+        assert_eq!(&data[data.len() - total * 4..], &colors(0, &frame)[2..]);
+
+        // Other protocols are disconnected.
+        client.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        assert_eq!(client.read(&mut [0; 16]).unwrap(), 0);
+    }
+}
@@ -0,0 +1,489 @@
+//! `nzxtcli export-prometheus`: fan, pump and liquid temperature metrics of
+//! all controllers in the Prometheus text format.
+//!
+//! ```text
+//! # HELP nzxt_liquid_temp_celsius Liquid temperature of the cooler.
+//! # TYPE nzxt_liquid_temp_celsius gauge
+//! nzxt_liquid_temp_celsius{controller="MA0123456789"} 31.4
+//! ```
+//!
+//! The `controller` label is the serial, or the name of controllers without
+//! a serial. Metrics of a controller which stopped responding are dropped
+//! and its `nzxt_controller_up` is `0` until it's reconnected.
+
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use hidapi::HidApi;
+use nzxtcli::logging::{self, error, info, warning};
+use nzxtcli::{AioStatus, Controller, FanStatus};
+
+use crate::retry::Backoff;
+use crate::{AccessoryArgs, DeviceArgs};
+
+/// Serve metrics of all controllers for Prometheus at `/metrics`.
+///
+/// The devices are read on scrapes, but at most once per `--max-age`.
+#[derive(Parser)]
+pub struct CmdExportPrometheus {
+    /// Address to listen on.
+    #[clap(long, default_value = "127.0.0.1:9122")]
+    listen: String,
+
+    /// How long the values read from the devices are reused by scrapes.
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "5s")]
+    max_age: Duration,
+
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+}
+
+impl CmdExportPrometheus {
+    pub fn run(self, device: &DeviceArgs) -> Result<()> {
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let mut controllers = device.find_controllers(&api, &self.accessories)?;
+        anyhow::ensure!(!controllers.is_empty(), nzxtcli::Error::NoMatchingDevice);
+        let mut reconnects = vec![Backoff::default(); controllers.len()];
+
+        let listener = TcpListener::bind(&self.listen)
+            .with_context(|| format!("failed to listen on {}", self.listen))?;
+        // Polled, so that the interrupts are noticed.
+        listener.set_nonblocking(true)?;
+        info!(
+            "serving metrics at http://{}/metrics",
+            listener.local_addr()?
+        );
+
+        let mut cache = Cache::new(self.max_age);
+        crate::catch_interrupts();
+        while !crate::INTERRUPTED.load(Ordering::Relaxed) {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(crate::INTERRUPT_POLL);
+                    continue;
+                }
+                Err(e) => {
+                    warning!("failed to accept a connection: {e}");
+                    std::thread::sleep(crate::INTERRUPT_POLL);
+                    continue;
+                }
+            };
+            // Failures of a single scrape only concern its client.
+            _ = serve(stream, || {
+                let now = Instant::now();
+                cache
+                    .get(now, || {
+                        render(&collect(&api, &mut controllers, &mut reconnects, now))
+                    })
+                    .to_owned()
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The rendered metrics, collected again once they are older than `max_age`.
+struct Cache {
+    max_age: Duration,
+    metrics: Option<(Instant, String)>,
+}
+
+impl Cache {
+    fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            metrics: None,
+        }
+    }
+
+    fn get(&mut self, now: Instant, collect: impl FnOnce() -> String) -> &str {
+        let fresh = self
+            .metrics
+            .as_ref()
+            .is_some_and(|(time, _)| now.saturating_duration_since(*time) < self.max_age);
+        if !fresh {
+            self.metrics = Some((now, collect()));
+        }
+        &self.metrics.as_ref().expect("metrics are collected").1
+    }
+}
+
+/// Values read from a controller.
+#[derive(Debug, Default)]
+struct Sample {
+    controller: String,
+    name: &'static str,
+    /// Whether all values were read, the others are empty otherwise.
+    up: bool,
+    fans: Vec<FanStatus>,
+    noise_db: Option<u8>,
+    aio: Option<AioStatus>,
+    led_counts: Vec<usize>,
+}
+
+/// Reads all controllers, disconnected ones are reconnected with a backoff.
+fn collect(
+    api: &HidApi,
+    controllers: &mut [Box<dyn Controller>],
+    reconnects: &mut [Backoff],
+    now: Instant,
+) -> Vec<Sample> {
+    let mut samples = Vec::with_capacity(controllers.len());
+    for (controller, reconnect) in controllers.iter_mut().zip(reconnects) {
+        let mut sample = Sample {
+            controller: controller.serial().unwrap_or(controller.name()).to_owned(),
+            name: controller.name(),
+            ..Sample::default()
+        };
+        let _span = logging::controller_span(controller.as_ref());
+        if !controller.is_connected() {
+            if !reconnect.ready(now) {
+                samples.push(sample);
+                continue;
+            }
+            if let Err(e) = controller.reconnect(api) {
+                let delay = reconnect.failed(now);
+                error!(
+                    "failed to reconnect: {e:?}, retrying in {}",
+                    humantime::format_duration(delay)
+                );
+                samples.push(sample);
+                continue;
+            }
+            reconnect.succeeded();
+        }
+
+        let result = (|| {
+            let fans = controller.fan_status()?;
+            let noise_db = controller.noise_level_db()?;
+            let aio = match controller.is_liquid_cooler() {
+                true => Some(controller.aio_status()?),
+                false => None,
+            };
+            Ok::<_, nzxtcli::Error>((fans, noise_db, aio))
+        })();
+        match result {
+            Ok((fans, noise_db, aio)) => {
+                sample.up = true;
+                sample.fans = fans;
+                sample.noise_db = noise_db;
+                sample.aio = aio;
+                sample.led_counts = controller
+                    .rgb_channels()
+                    .iter()
+                    .map(|channel| channel.led_count)
+                    .collect();
+            }
+            Err(e) => error!("failed to read the status: {e:?}"),
+        }
+        samples.push(sample);
+    }
+    samples
+}
+
+/// The text exposition format, the samples of each metric are grouped together.
+fn render(samples: &[Sample]) -> String {
+    type Values = fn(&Sample) -> Vec<(Option<usize>, f64)>;
+    let metrics: [(&str, &str, Values); 8] = [
+        ("nzxt_fan_rpm", "Fan speed in RPM.", |sample| {
+            let fans = sample.fans.iter().enumerate();
+            fans.map(|(channel, fan)| (Some(channel), f64::from(fan.rpm)))
+                .collect()
+        }),
+        ("nzxt_fan_duty", "Fan duty cycle in percent.", |sample| {
+            let fans = sample.fans.iter().enumerate();
+            fans.map(|(channel, fan)| (Some(channel), f64::from(fan.duty)))
+                .collect()
+        }),
+        (
+            "nzxt_liquid_temp_celsius",
+            "Liquid temperature of the cooler.",
+            |sample| {
+                let temp = sample.aio.map(|aio| f64::from(aio.liquid_temp));
+                temp.map(|temp| (None, temp)).into_iter().collect()
+            },
+        ),
+        (
+            "nzxt_pump_rpm",
+            "Pump speed of the cooler in RPM.",
+            |sample| {
+                let rpm = sample.aio.map(|aio| f64::from(aio.pump_rpm));
+                rpm.map(|rpm| (None, rpm)).into_iter().collect()
+            },
+        ),
+        (
+            "nzxt_pump_duty",
+            "Pump duty cycle of the cooler in percent.",
+            |sample| {
+                let duty = sample.aio.and_then(|aio| aio.pump_duty).map(f64::from);
+                duty.map(|duty| (None, duty)).into_iter().collect()
+            },
+        ),
+        (
+            "nzxt_noise_db",
+            "Noise level of the built-in sensor.",
+            |sample| {
+                let noise = sample.noise_db.map(f64::from);
+                noise.map(|noise| (None, noise)).into_iter().collect()
+            },
+        ),
+        (
+            "nzxt_led_count",
+            "Addressable LEDs of the channel.",
+            |sample| {
+                let counts = sample.led_counts.iter().enumerate();
+                counts
+                    .map(|(channel, &count)| (Some(channel), count as f64))
+                    .collect()
+            },
+        ),
+        (
+            "nzxt_controller_up",
+            "Whether the controller responded to the last read.",
+            |sample| vec![(None, f64::from(u8::from(sample.up)))],
+        ),
+    ];
+
+    let mut text = String::new();
+    for (metric, help, values) in metrics {
+        let lines = samples
+            .iter()
+            .flat_map(|sample| {
+                values(sample)
+                    .into_iter()
+                    .map(move |(channel, value)| (sample, channel, value))
+            })
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            continue;
+        }
+        _ = writeln!(text, "# HELP {metric} {help}\n# TYPE {metric} gauge");
+        for (sample, channel, value) in lines {
+            _ = write!(
+                text,
+                "{metric}{{controller=\"{}\"",
+                escape(&sample.controller)
+            );
+            if let Some(channel) = channel {
+                _ = write!(text, ",channel=\"{channel}\"");
+            }
+            if metric == "nzxt_controller_up" {
+                _ = write!(text, ",name=\"{}\"", escape(sample.name));
+            }
+            _ = writeln!(text, "}} {value}");
+        }
+    }
+    text
+}
+
+/// Label values are quoted.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Answers a single HTTP request, the connection is closed afterwards.
+fn serve(mut stream: TcpStream, metrics: impl FnOnce() -> String) -> std::io::Result<()> {
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    const MAX_HEAD: usize = 8192;
+
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    // Only the request line is needed, the rest of the head is skipped.
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let len = stream.read(&mut buffer)?;
+        if len == 0 || head.len() > MAX_HEAD {
+            return respond(
+                &mut stream,
+                "400 Bad Request",
+                "text/plain",
+                "bad request\n",
+                true,
+            );
+        }
+        head.extend_from_slice(&buffer[..len]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request = head.lines().next().unwrap_or_default().split(' ');
+    let (method, target) = (request.next(), request.next().unwrap_or_default());
+    let path = target.split('?').next().unwrap_or_default();
+
+    let body = matches!(method, Some("GET"));
+    match (method, path) {
+        (Some("GET" | "HEAD"), "/metrics") => respond(
+            &mut stream,
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            &metrics(),
+            body,
+        ),
+        (Some("GET" | "HEAD"), "/") => respond(
+            &mut stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            "<html><body><a href=\"/metrics\">Metrics</a></body></html>\n",
+            body,
+        ),
+        (Some("GET" | "HEAD"), _) => respond(
+            &mut stream,
+            "404 Not Found",
+            "text/plain",
+            "not found\n",
+            body,
+        ),
+        _ => respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n",
+            true,
+        ),
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    content: &str,
+    body: bool,
+) -> std::io::Result<()> {
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        content.len()
+    );
+    if body {
+        response.push_str(content);
+    }
+    stream.write_all(response.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use nzxtcli::FanType;
+
+    use super::*;
+
+    #[test]
+    fn metrics() {
+        let samples = [
+            Sample {
+                controller: "MA0123456789".to_owned(),
+                name: "NZXT Kraken X3",
+                up: true,
+                fans: vec![FanStatus {
+                    rpm: 1500,
+                    duty: 40,
+                    fan_type: FanType::Pwm,
+                }],
+                noise_db: Some(32),
+                aio: Some(AioStatus {
+                    liquid_temp: 31.5,
+                    pump_rpm: 2000,
+                    pump_duty: None,
+                }),
+                led_counts: vec![8, 0],
+            },
+            Sample {
+                controller: "NZXT \"RGB\" Controller".to_owned(),
+                name: "NZXT RGB Controller",
+                ..Sample::default()
+            },
+        ];
+        assert_eq!(
+            render(&samples),
+            r#"# HELP nzxt_fan_rpm Fan speed in RPM.
+# TYPE nzxt_fan_rpm gauge
+nzxt_fan_rpm{controller="MA0123456789",channel="0"} 1500
+# HELP nzxt_fan_duty Fan duty cycle in percent.
+# TYPE nzxt_fan_duty gauge
+nzxt_fan_duty{controller="MA0123456789",channel="0"} 40
+# HELP nzxt_liquid_temp_celsius Liquid temperature of the cooler.
+# TYPE nzxt_liquid_temp_celsius gauge
+nzxt_liquid_temp_celsius{controller="MA0123456789"} 31.5
+# HELP nzxt_pump_rpm Pump speed of the cooler in RPM.
+# TYPE nzxt_pump_rpm gauge
+nzxt_pump_rpm{controller="MA0123456789"} 2000
+# HELP nzxt_noise_db Noise level of the built-in sensor.
+# TYPE nzxt_noise_db gauge
+nzxt_noise_db{controller="MA0123456789"} 32
+# HELP nzxt_led_count Addressable LEDs of the channel.
+# TYPE nzxt_led_count gauge
+nzxt_led_count{controller="MA0123456789",channel="0"} 8
+nzxt_led_count{controller="MA0123456789",channel="1"} 0
+# HELP nzxt_controller_up Whether the controller responded to the last read.
+# TYPE nzxt_controller_up gauge
+nzxt_controller_up{controller="MA0123456789",name="NZXT Kraken X3"} 1
+nzxt_controller_up{controller="NZXT \"RGB\" Controller",name="NZXT RGB Controller"} 0
+"#
+        );
+
+        // Scrapes reuse fresh values.
+        let now = Instant::now();
+        let mut cache = Cache::new(Duration::from_secs(5));
+        assert_eq!(cache.get(now, || "a".to_owned()), "a");
+        assert_eq!(
+            cache.get(now + Duration::from_secs(4), || "b".to_owned()),
+            "a"
+        );
+        assert_eq!(
+            cache.get(now + Duration::from_secs(5), || "c".to_owned()),
+            "c"
+        );
+    }
+
+    #[test]
+    fn metrics_endpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let request = |request: &'static str| {
+            let client = std::thread::spawn(move || {
+                let mut stream = TcpStream::connect(address).unwrap();
+                stream.write_all(request.as_bytes()).unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                response
+            });
+            let (stream, _) = listener.accept().unwrap();
+            serve(stream, || "nzxt_controller_up 1\n".to_owned()).unwrap();
+            client.join().unwrap()
+        };
+
+        let response = request("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.contains("Content-Length: 21\r\n"), "{response}");
+        assert!(
+            response.ends_with("\r\n\r\nnzxt_controller_up 1\n"),
+            "{response}"
+        );
+
+        let response = request("HEAD /metrics?x=1 HTTP/1.1\r\n\r\n");
+        assert!(
+            response.ends_with("Connection: close\r\n\r\n"),
+            "{response}"
+        );
+        let response = request("GET /other HTTP/1.1\r\n\r\n");
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{response}"
+        );
+        let response = request("POST /metrics HTTP/1.1\r\n\r\n");
+        assert!(
+            response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"),
+            "{response}"
+        );
+    }
+}
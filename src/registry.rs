@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use crate::controller::{ControllerBriefInfo, NZXTHue2Controller};
+
+/// Hue 2 compatible controllers by product id.
+///
+/// The default registry contains all controllers known to this crate.
+#[derive(Debug, Clone)]
+pub struct ControllerRegistry {
+    entries: HashMap<u16, ControllerBriefInfo>,
+}
+
+impl ControllerRegistry {
+    /// Creates a registry without any controllers.
+    pub fn empty() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Adds a controller which speaks the Hue 2 protocol.
+    ///
+    /// Registered controllers are not assumed to support saving state.
+    /// Returns the name of the replaced entry if the product id was already known.
+    pub fn register(
+        &mut self,
+        product_id: u16,
+        name: &'static str,
+        rgb_channels: usize,
+        fan_channels: usize,
+    ) -> Option<&'static str> {
+        self.entries
+            .insert(product_id, (name, rgb_channels, fan_channels, false))
+            .map(|(name, ..)| name)
+    }
+
+    /// Returns name, RGB channels, fan channels and persistent storage support.
+    pub fn get(&self, product_id: u16) -> Option<&ControllerBriefInfo> {
+        self.entries.get(&product_id)
+    }
+
+    pub fn contains(&self, product_id: u16) -> bool {
+        self.entries.contains_key(&product_id)
+    }
+
+    /// Iterates over the registered controllers in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &ControllerBriefInfo)> {
+        self.entries
+            .iter()
+            .map(|(&product_id, info)| (product_id, info))
+    }
+}
+
+impl Default for ControllerRegistry {
+    fn default() -> Self {
+        Self {
+            entries: NZXTHue2Controller::known_controllers().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_controllers() {
+        let mut registry = ControllerRegistry::default();
+        assert!(registry.contains(0x2012));
+        assert!(!registry.contains(0x2042));
+
+        assert_eq!(registry.register(0x2042, "Custom", 6, 3), None);
+        assert_eq!(registry.get(0x2042), Some(&("Custom", 6, 3, false)));
+
+        assert_eq!(
+            registry.register(0x2012, "Custom", 3, 0),
+            Some("NZXT RGB Controller")
+        );
+        assert_eq!(registry.get(0x2012), Some(&("Custom", 3, 0, false)));
+
+        assert!(ControllerRegistry::empty().get(0x2012).is_none());
+    }
+}
@@ -0,0 +1,172 @@
+//! Backoff for retrying sensors and devices which stopped working
+//! (e.g. during suspend and resume).
+
+use std::time::{Duration, Instant};
+
+use nzxtcli::TempSource;
+use nzxtcli::logging::{info, warning};
+
+/// Delays retries exponentially while the failures continue.
+#[derive(Debug, Default, Clone)]
+pub struct Backoff {
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl Backoff {
+    const INITIAL: Duration = Duration::from_secs(1);
+    const MAX: Duration = Duration::from_secs(30);
+
+    /// Returns the delay after the number of consecutive failures.
+    pub fn delay(failures: u32) -> Duration {
+        match failures.checked_sub(1) {
+            None => Duration::ZERO,
+            Some(doublings) => Self::INITIAL
+                .saturating_mul(1 << doublings.min(16))
+                .min(Self::MAX),
+        }
+    }
+
+    /// Whether the next attempt can be made.
+    pub fn ready(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|retry_at| now >= retry_at)
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Records a failed attempt, returns the delay before the next one.
+    pub fn failed(&mut self, now: Instant) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        let delay = Self::delay(self.failures);
+        self.retry_at = Some(now + delay);
+        delay
+    }
+
+    /// Records a successful attempt, returns the number of the previous failures.
+    pub fn succeeded(&mut self) -> u32 {
+        self.retry_at = None;
+        std::mem::take(&mut self.failures)
+    }
+}
+
+/// Temperature source which is retried with a backoff after failures.
+///
+/// The failure is printed only once until the source is readable again.
+pub struct RetryingSource {
+    source: Box<dyn TempSource>,
+    backoff: Backoff,
+}
+
+impl RetryingSource {
+    pub fn new(source: Box<dyn TempSource>) -> Self {
+        Self {
+            source,
+            backoff: Backoff::default(),
+        }
+    }
+
+    pub fn source(&self) -> &dyn TempSource {
+        self.source.as_ref()
+    }
+
+    /// Returns `None` if the source failed or is waiting for the next retry.
+    pub fn poll(&mut self, now: Instant) -> Option<i64> {
+        if !self.backoff.ready(now) {
+            return None;
+        }
+        match self.source.read() {
+            Ok(millidegrees) => {
+                let failures = self.backoff.succeeded();
+                if failures > 0 {
+                    info!(
+                        "{}: the temperature is readable again after {failures} failed attempts",
+                        self.source
+                    );
+                }
+                Some(millidegrees)
+            }
+            Err(e) => {
+                let delay = self.backoff.failed(now);
+                if self.backoff.failures() == 1 {
+                    warning!(
+                        "{}: {e}, retrying in {}",
+                        self.source,
+                        humantime::format_duration(delay)
+                    );
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::fmt;
+
+    use super::*;
+
+    /// Returns the scripted readings, `None` is a failure.
+    struct Scripted(VecDeque<Option<i64>>);
+
+    impl fmt::Display for Scripted {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("scripted")
+        }
+    }
+
+    impl TempSource for Scripted {
+        fn read(&mut self) -> nzxtcli::Result<i64> {
+            self.0
+                .pop_front()
+                .flatten()
+                .ok_or_else(|| nzxtcli::Error::TempSource("no such file".to_owned()))
+        }
+    }
+
+    #[test]
+    fn delays() {
+        let delays = [0, 1, 2, 3, 5, 6, 100].map(|failures| Backoff::delay(failures).as_secs());
+        assert_eq!(delays, [0, 1, 2, 4, 16, 30, 30]);
+
+        let start = Instant::now();
+        let mut backoff = Backoff::default();
+        assert!(backoff.ready(start));
+
+        assert_eq!(backoff.failed(start), Duration::from_secs(1));
+        assert!(!backoff.ready(start + Duration::from_millis(500)));
+        assert!(backoff.ready(start + Duration::from_secs(1)));
+
+        let next = start + Duration::from_secs(1);
+        assert_eq!(backoff.failed(next), Duration::from_secs(2));
+        assert!(!backoff.ready(next + Duration::from_secs(1)));
+        assert_eq!(backoff.failures(), 2);
+
+        assert_eq!(backoff.succeeded(), 2);
+        assert!(backoff.ready(next));
+        assert_eq!(backoff.failures(), 0);
+    }
+
+    #[test]
+    fn retrying_source() {
+        let readings = [Some(40_000), None, None, Some(41_000), Some(42_000)];
+        let mut source = RetryingSource::new(Box::new(Scripted(readings.into())));
+
+        let start = Instant::now();
+        let at = |millis: u64| start + Duration::from_millis(millis);
+        let polls = [0, 500, 1000, 1500, 2500, 3500, 3600].map(|millis| source.poll(at(millis)));
+        assert_eq!(polls, [
+            Some(40_000),
+            None,         // failed, retry in 1s
+            None,         // waiting
+            None,         // failed again, retry in 2s
+            None,         // waiting
+            Some(41_000), // recovered
+            Some(42_000),
+        ]);
+        assert_eq!(source.source().to_string(), "scripted");
+    }
+}
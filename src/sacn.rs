@@ -0,0 +1,836 @@
+//! `nzxtcli serve-sacn`: shows a universe of E1.31 (Streaming ACN) on the
+//! LEDs, so that a lighting console can drive them like any other fixture.
+//!
+//! Without `--channel-map` every LED takes three slots (red, green, blue)
+//! in probe order: the LEDs of the first channel of the first controller
+//! start at slot 1. A map file patches them explicitly with `[[led]]` tables:
+//!
+//! ```toml
+//! # Slots 10-15: the first two LEDs of channel 1, wired as GRB.
+//! [[led]]
+//! serial = "MA0123456789"
+//! channels = "1"
+//! leds = "0-1"
+//! start = 10
+//! order = "grb"
+//!
+//! # Slots 16-18: a single color for all LEDs of channels 0 and 2.
+//! [[led]]
+//! channels = "0,2"
+//! single = true
+//! ```
+//!
+//! Tables select the controllers like the bindings of the daemon (`serial`,
+//! `pid` and `channels`) and follow the previous table unless `start` is set.
+//!
+//! Sources are merged as the spec allows: only the sources with the highest
+//! priority are shown, several of them by the highest value of each slot.
+//! A source is dropped once it terminates its stream or after 2.5s without
+//! data, the LEDs are blanked when the last one is gone (or keep the last
+//! frame with `--on-loss hold`). Preview data and other start codes than DMX
+//! are ignored. Universe synchronization is not supported, frames are shown
+//! as they arrive but at most `--fps` times per second.
+
+use std::net::{Ipv4Addr, UdpSocket};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use clap::{Parser, ValueEnum};
+use hidapi::HidApi;
+use nzxtcli::logging::{self, error, info, warning};
+use nzxtcli::{ChannelSelector, Color, ColorOrder, Controller};
+
+use crate::config::{Config, ConfigError};
+use crate::daemon::Target;
+use crate::retry::Backoff;
+use crate::{AccessoryArgs, DeviceArgs, TransformArgs};
+
+/// Port of E1.31, for multicast and unicast packets.
+const PORT: u16 = 5568;
+/// DMX slots of a universe.
+const SLOTS: usize = 512;
+const DATA_LOSS_TIMEOUT: Duration = Duration::from_millis(2500);
+
+const ACN_PACKET_IDENTIFIER: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const VECTOR_ROOT_E131_DATA: u32 = 0x04;
+const VECTOR_E131_DATA_PACKET: u32 = 0x02;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+const OPTION_PREVIEW_DATA: u8 = 0x80;
+const OPTION_STREAM_TERMINATED: u8 = 0x40;
+const MAX_PRIORITY: u8 = 200;
+const DMX_START_CODE: u8 = 0x00;
+
+/// Receive a universe of E1.31 (sACN) and show it on the LEDs.
+///
+/// Packets are received from the multicast group of the universe and
+/// as unicast on port 5568.
+#[derive(Parser)]
+pub struct CmdServeSacn {
+    /// Universe to receive.
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..=63999))]
+    universe: u16,
+
+    /// Map of the DMX slots to the LEDs [default: three slots per LED in probe order].
+    #[clap(long, value_name = "PATH")]
+    channel_map: Option<PathBuf>,
+
+    /// Max number of frames sent to the controllers per second.
+    #[clap(long, default_value_t = 30.0)]
+    fps: f32,
+
+    /// What the LEDs show once all sources are lost.
+    #[clap(long, value_enum, default_value_t = OnLoss::Blank)]
+    on_loss: OnLoss,
+
+    /// Address of the interface which joins the multicast group [default: any].
+    #[clap(long, default_value_t = Ipv4Addr::UNSPECIFIED, hide_default_value = true)]
+    interface: Ipv4Addr,
+
+    #[clap(flatten)]
+    accessories: AccessoryArgs,
+
+    #[clap(flatten)]
+    transform: TransformArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OnLoss {
+    /// Turn off the mapped LEDs.
+    Blank,
+    /// Keep the last frame.
+    Hold,
+}
+
+impl CmdServeSacn {
+    pub fn run(self, device: &DeviceArgs) -> Result<()> {
+        anyhow::ensure!(
+            self.fps.is_finite() && self.fps > 0.0,
+            "frame rate must be positive"
+        );
+        let frame_interval = Duration::from_secs_f32(1.0 / self.fps);
+
+        let api = HidApi::new().context("failed to initialize HID api")?;
+        let options = device
+            .find_options(&self.accessories)?
+            .color_transform(self.transform.transform()?);
+        let controllers = crate::find_controllers(&api, &options)?;
+        let map = match &self.channel_map {
+            Some(path) => {
+                let config = Config::load(Some(path.clone()))?.expect("explicit map is loaded");
+                parse_map(&config, &controllers)?
+            }
+            None => default_map(&controllers),
+        };
+        anyhow::ensure!(!map.is_empty(), "no LEDs are mapped to the DMX slots");
+        let mut output = Output::new(controllers, map);
+
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, PORT))
+            .with_context(|| format!("failed to bind the sACN port {PORT}"))?;
+        let group = multicast_group(self.universe);
+        socket
+            .join_multicast_v4(&group, &self.interface)
+            .with_context(|| format!("failed to join the multicast group {group}"))?;
+        info!(
+            "receiving universe {} at {group}:{PORT}, {} slots are mapped",
+            self.universe,
+            output.slots()
+        );
+
+        let mut merger = Merger::default();
+        // A frame waits for the rate limit.
+        let mut pending = false;
+        let mut next_frame = Instant::now();
+        let mut buffer = [0u8; 1500];
+        crate::catch_interrupts();
+        while !crate::INTERRUPTED.load(Ordering::Relaxed) {
+            let timeout = match pending {
+                true => next_frame.saturating_duration_since(Instant::now()),
+                false => crate::INTERRUPT_POLL,
+            };
+            socket.set_read_timeout(Some(
+                timeout.clamp(Duration::from_millis(1), crate::INTERRUPT_POLL),
+            ))?;
+            match socket.recv(&mut buffer) {
+                Ok(len) => {
+                    let packet = parse_packet(&buffer[..len])
+                        .filter(|packet| packet.universe == self.universe);
+                    if let Some(packet) = packet {
+                        pending |= merger.receive(&packet, Instant::now());
+                    }
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) => {}
+                Err(e) => return Err(e).context("failed to receive sACN packets"),
+            }
+
+            let now = Instant::now();
+            pending |= merger.expire(now);
+            if !pending || now < next_frame {
+                continue;
+            }
+            pending = false;
+            next_frame = now + frame_interval;
+            output.reconnect(&api, now);
+            match merger.output() {
+                Some(slots) => output.show(&slots),
+                None if self.on_loss == OnLoss::Blank => output.show(&[0; SLOTS]),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `239.255.<high byte>.<low byte>` of the universe.
+fn multicast_group(universe: u16) -> Ipv4Addr {
+    let [high, low] = universe.to_be_bytes();
+    Ipv4Addr::new(239, 255, high, low)
+}
+
+/// An E1.31 data packet.
+#[derive(Debug, PartialEq)]
+struct DataPacket<'a> {
+    /// Component identifier of the source.
+    cid: [u8; 16],
+    source: String,
+    priority: u8,
+    sequence: u8,
+    options: u8,
+    universe: u16,
+    start_code: u8,
+    slots: &'a [u8],
+}
+
+/// The layout of the data packets in ANSI E1.31-2018, section 4.1. Other
+/// packets (synchronization, discovery) and invalid ones are `None`.
+fn parse_packet(data: &[u8]) -> Option<DataPacket<'_>> {
+    // The start code and the header.
+    const MIN_LEN: usize = 126;
+    if data.len() < MIN_LEN {
+        return None;
+    }
+    let u16_at = |offset: usize| u16::from_be_bytes([data[offset], data[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+    // The length of a layer is in the low 12 bits, the high ones are flags.
+    let pdu_len = |offset: usize| usize::from(u16_at(offset) & 0x0fff);
+
+    let valid = u16_at(0) == 0x0010
+        && u16_at(2) == 0x0000
+        && &data[4..16] == ACN_PACKET_IDENTIFIER
+        && u32_at(18) == VECTOR_ROOT_E131_DATA
+        && u32_at(40) == VECTOR_E131_DATA_PACKET
+        && data[117] == VECTOR_DMP_SET_PROPERTY
+        // Address and data types, the first address and the increment.
+        && data[118] == 0xa1
+        && u16_at(119) == 0x0000
+        && u16_at(121) == 0x0001
+        && data[108] <= MAX_PRIORITY;
+    // The start code and the slots.
+    let count = usize::from(u16_at(123));
+    let len = 125 + count;
+    if !valid || !(1..=SLOTS + 1).contains(&count) || data.len() < len {
+        return None;
+    }
+    if pdu_len(16) != len - 16 || pdu_len(38) != len - 38 || pdu_len(115) != len - 115 {
+        return None;
+    }
+
+    let name = &data[44..108];
+    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+    Some(DataPacket {
+        cid: data[22..38].try_into().unwrap(),
+        source: String::from_utf8_lossy(name).into_owned(),
+        priority: data[108],
+        sequence: data[111],
+        options: data[112],
+        universe: u16_at(113),
+        start_code: data[125],
+        slots: &data[126..len],
+    })
+}
+
+struct Source {
+    cid: [u8; 16],
+    name: String,
+    priority: u8,
+    sequence: u8,
+    slots: [u8; SLOTS],
+    received: Instant,
+}
+
+/// The sources of the universe.
+#[derive(Default)]
+struct Merger {
+    sources: Vec<Source>,
+}
+
+impl Merger {
+    /// Whether the packet has changed the sources.
+    fn receive(&mut self, packet: &DataPacket<'_>, now: Instant) -> bool {
+        if packet.options & OPTION_PREVIEW_DATA != 0 || packet.start_code != DMX_START_CODE {
+            return false;
+        }
+        let index = self
+            .sources
+            .iter()
+            .position(|source| source.cid == packet.cid);
+        if let Some(index) = index {
+            // Late packets are discarded, unless the source has restarted
+            // (section 6.7.2).
+            let diff = packet.sequence.wrapping_sub(self.sources[index].sequence) as i8;
+            if diff <= 0 && diff > -20 {
+                return false;
+            }
+        }
+        if packet.options & OPTION_STREAM_TERMINATED != 0 {
+            let Some(index) = index else {
+                return false;
+            };
+            let source = self.sources.remove(index);
+            info!("sACN source `{}` has terminated", source.name);
+            return true;
+        }
+
+        let mut slots = [0; SLOTS];
+        slots[..packet.slots.len()].copy_from_slice(packet.slots);
+        let source = Source {
+            cid: packet.cid,
+            name: packet.source.clone(),
+            priority: packet.priority,
+            sequence: packet.sequence,
+            slots,
+            received: now,
+        };
+        match index {
+            Some(index) => self.sources[index] = source,
+            None => {
+                info!(
+                    "sACN source `{}` with priority {}",
+                    source.name, source.priority
+                );
+                self.sources.push(source);
+            }
+        }
+        true
+    }
+
+    /// Drops the sources without data for the timeout, returns whether there
+    /// were any.
+    fn expire(&mut self, now: Instant) -> bool {
+        let expired = self
+            .sources
+            .extract_if(.., |source| {
+                now.saturating_duration_since(source.received) >= DATA_LOSS_TIMEOUT
+            })
+            .collect::<Vec<_>>();
+        for source in &expired {
+            warning!("sACN source `{}` is lost", source.name);
+        }
+        !expired.is_empty()
+    }
+
+    /// The highest value of each slot of the sources with the highest
+    /// priority, `None` without sources.
+    fn output(&self) -> Option<[u8; SLOTS]> {
+        let priority = self.sources.iter().map(|source| source.priority).max()?;
+        let mut slots = [0; SLOTS];
+        for source in self.sources.iter().filter(|s| s.priority == priority) {
+            for (slot, value) in slots.iter_mut().zip(source.slots) {
+                *slot = (*slot).max(value);
+            }
+        }
+        Some(slots)
+    }
+}
+
+/// The LED with its color in three slots.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Slot {
+    device: usize,
+    channel: usize,
+    led: usize,
+    /// The first slot, from 0.
+    slot: usize,
+    order: ColorOrder,
+}
+
+/// Three slots per LED of all channels in probe order, for as many LEDs
+/// as fit in the universe.
+fn default_map(controllers: &[Box<dyn Controller>]) -> Vec<Slot> {
+    let leds = controllers
+        .iter()
+        .enumerate()
+        .flat_map(|(device, controller)| {
+            let channels = controller.rgb_channels().iter().enumerate();
+            channels.flat_map(move |(channel, info)| {
+                (0..info.led_count).map(move |led| (device, channel, led))
+            })
+        })
+        .collect::<Vec<_>>();
+    if leds.len() > SLOTS / 3 {
+        warning!(
+            "only {} of {} LEDs fit in the universe, use a channel map for the others",
+            SLOTS / 3,
+            leds.len()
+        );
+    }
+    leds.into_iter()
+        .take(SLOTS / 3)
+        .enumerate()
+        .map(|(index, (device, channel, led))| Slot {
+            device,
+            channel,
+            led,
+            slot: index * 3,
+            order: ColorOrder::Rgb,
+        })
+        .collect()
+}
+
+const MAP_KEYS: &[&str] = &[
+    "serial", "pid", "channels", "leds", "start", "order", "single",
+];
+
+/// The `[[led]]` tables of the map file, errors point at them.
+fn parse_map(
+    config: &Config,
+    controllers: &[Box<dyn Controller>],
+) -> Result<Vec<Slot>, ConfigError> {
+    let mut map = Vec::new();
+    let mut next = 0;
+    for fields in config.array_tables("led") {
+        fields.check_keys(MAP_KEYS, "an LED mapping")?;
+        let channels = fields.parse_with("channels", crate::parse_channels)?;
+        let target = Target::parse(&fields, channels.unwrap_or_default())?;
+        let leds = fields
+            .parse_with("leds", |s| {
+                s.parse::<ChannelSelector>()
+                    .map_err(|_| "expected `all`, an LED index or a range (e.g. `0-7`)")
+            })?
+            .unwrap_or_default();
+        let order = fields.parse("order")?.unwrap_or(ColorOrder::Rgb);
+        let single = fields.parse::<bool>("single")?.unwrap_or_default();
+        if let Some(start) = fields.parse::<usize>("start")? {
+            if !(1..=SLOTS).contains(&start) {
+                return Err(
+                    fields.value_error("start", format!("expected a slot from 1 to {SLOTS}"))
+                );
+            }
+            next = start - 1;
+        }
+
+        let first = next;
+        let mapped = map.len();
+        let selected = controllers
+            .iter()
+            .enumerate()
+            .filter(|(_, controller)| target.matches(controller.as_ref()));
+        for (device, controller) in selected {
+            let rgb_channels = controller.rgb_channels();
+            let channels = target.channels.resolve(rgb_channels.len()).map_err(|e| {
+                fields.value_error("channels", format!("{e} of {}", controller.name()))
+            })?;
+            for channel in channels {
+                let led_count = rgb_channels[channel].led_count;
+                let leds = leds.resolve(led_count).map_err(|_| {
+                    let name = controller.name();
+                    let message = format!("channel {channel} of {name} has {led_count} LEDs");
+                    fields.value_error("leds", message)
+                })?;
+                for led in leds {
+                    map.push(Slot {
+                        device,
+                        channel,
+                        led,
+                        slot: next,
+                        order,
+                    });
+                    if !single {
+                        next += 3;
+                    }
+                }
+            }
+        }
+        // The slots are kept for fixtures which are not connected.
+        if single {
+            next = first + 3;
+        }
+        if map.len() == mapped {
+            warning!("`{}` doesn't match any LEDs", fields.path());
+        }
+        if next > SLOTS {
+            return Err(fields.error(format!(
+                "the LEDs need slots up to {next}, a universe has {SLOTS}"
+            )));
+        }
+    }
+    Ok(map)
+}
+
+/// Colors of the slots in the order of the components.
+fn decode(order: ColorOrder, slots: &[u8]) -> Color {
+    // Positions of the components in the encoded order.
+    let positions = order.encode(Color::new(0, 1, 2));
+    let mut rgb = [0; 3];
+    for (value, position) in slots.iter().zip(positions) {
+        rgb[usize::from(position)] = *value;
+    }
+    Color::new(rgb[0], rgb[1], rgb[2])
+}
+
+struct Device {
+    controller: Box<dyn Controller>,
+    reconnect: Backoff,
+    /// Colors of the channels, unmapped LEDs stay off.
+    colors: Vec<Vec<Color>>,
+    /// Channels with mapped LEDs.
+    mapped: Vec<usize>,
+}
+
+/// The controllers and their mapped LEDs.
+struct Output {
+    devices: Vec<Device>,
+    map: Vec<Slot>,
+}
+
+impl Output {
+    fn new(controllers: Vec<Box<dyn Controller>>, map: Vec<Slot>) -> Self {
+        let devices = controllers
+            .into_iter()
+            .enumerate()
+            .map(|(index, controller)| {
+                let colors = controller
+                    .rgb_channels()
+                    .iter()
+                    .map(|channel| vec![Color::BLACK; channel.led_count])
+                    .collect();
+                let mut mapped = map
+                    .iter()
+                    .filter(|slot| slot.device == index)
+                    .map(|slot| slot.channel)
+                    .collect::<Vec<_>>();
+                mapped.sort_unstable();
+                mapped.dedup();
+                Device {
+                    controller,
+                    reconnect: Backoff::default(),
+                    colors,
+                    mapped,
+                }
+            })
+            .collect();
+        Self { devices, map }
+    }
+
+    /// Number of the used slots.
+    fn slots(&self) -> usize {
+        let mut slots = self.map.iter().map(|slot| slot.slot).collect::<Vec<_>>();
+        slots.sort_unstable();
+        slots.dedup();
+        slots.len() * 3
+    }
+
+    /// Disconnected controllers are reconnected with a backoff.
+    fn reconnect(&mut self, api: &HidApi, now: Instant) {
+        for device in &mut self.devices {
+            let controller = &mut device.controller;
+            if controller.is_connected() || !device.reconnect.ready(now) {
+                continue;
+            }
+            match controller.reconnect(api) {
+                Ok(()) => {
+                    device.reconnect.succeeded();
+                }
+                Err(e) => {
+                    let delay = device.reconnect.failed(now);
+                    let _span = logging::controller_span(controller.as_ref());
+                    error!(
+                        "failed to reconnect: {e:?}, retrying in {}",
+                        humantime::format_duration(delay)
+                    );
+                }
+            }
+        }
+    }
+
+    /// Sends the mapped channels of each controller as a single frame,
+    /// failures are printed.
+    fn show(&mut self, slots: &[u8; SLOTS]) {
+        for slot in &self.map {
+            let color = decode(slot.order, &slots[slot.slot..slot.slot + 3]);
+            self.devices[slot.device].colors[slot.channel][slot.led] = color;
+        }
+        for device in &self.devices {
+            let controller = device.controller.as_ref();
+            if device.mapped.is_empty() || !controller.is_connected() {
+                continue;
+            }
+            let channels = device
+                .mapped
+                .iter()
+                .map(|&channel| (channel, &device.colors[channel][..]))
+                .collect::<Vec<_>>();
+            if let Err(e) = controller.set_frame(&channels) {
+                let _span = logging::controller_span(controller);
+                error!("failed to set the LEDs: {e:?}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nzxtcli::transport;
+
+    use super::*;
+
+    /// A data packet with the documented layout.
+    fn data_packet(cid: u8, priority: u8, sequence: u8, options: u8, slots: &[u8]) -> Vec<u8> {
+        let len = 126 + slots.len();
+        let flags_len = |offset: usize| (0x7000 | (len - offset) as u16).to_be_bytes();
+        let mut packet = Vec::with_capacity(len);
+        packet.extend_from_slice(&[0x00, 0x10, 0x00, 0x00]);
+        packet.extend_from_slice(ACN_PACKET_IDENTIFIER);
+        packet.extend_from_slice(&flags_len(16));
+        packet.extend_from_slice(&VECTOR_ROOT_E131_DATA.to_be_bytes());
+        packet.extend_from_slice(&[cid; 16]);
+        packet.extend_from_slice(&flags_len(38));
+        packet.extend_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+        let mut name = [0u8; 64];
+        name[..7].copy_from_slice(b"console");
+        packet.extend_from_slice(&name);
+        packet.push(priority);
+        // Synchronization address.
+        packet.extend_from_slice(&[0, 0]);
+        packet.push(sequence);
+        packet.push(options);
+        packet.extend_from_slice(&42u16.to_be_bytes());
+        packet.extend_from_slice(&flags_len(115));
+        packet.extend_from_slice(&[VECTOR_DMP_SET_PROPERTY, 0xa1, 0, 0, 0, 1]);
+        packet.extend_from_slice(&(slots.len() as u16 + 1).to_be_bytes());
+        packet.push(DMX_START_CODE);
+        packet.extend_from_slice(slots);
+        assert_eq!(packet.len(), len);
+        packet
+    }
+
+    #[test]
+    fn sacn_packets() {
+        assert_eq!(multicast_group(42), Ipv4Addr::new(239, 255, 0, 42));
+        assert_eq!(multicast_group(0x1234), Ipv4Addr::new(239, 255, 0x12, 0x34));
+
+        let data = data_packet(7, 150, 3, OPTION_STREAM_TERMINATED, &[1, 2, 3]);
+        assert_eq!(
+            parse_packet(&data),
+            Some(DataPacket {
+                cid: [7; 16],
+                source: "console".to_owned(),
+                priority: 150,
+                sequence: 3,
+                options: OPTION_STREAM_TERMINATED,
+                universe: 42,
+                start_code: DMX_START_CODE,
+                slots: &[1, 2, 3],
+            })
+        );
+        let full = data_packet(7, 100, 0, 0, &[0xff; SLOTS]);
+        assert_eq!(parse_packet(&full).unwrap().slots.len(), SLOTS);
+
+        // Trailing bytes are padding, other changes make it invalid.
+        let mut padded = data.clone();
+        padded.push(0);
+        assert!(parse_packet(&padded).is_some());
+        let invalid: [(usize, u8); 5] = [
+            // Identifier, the extended root vector, layer length, priority
+            // and the property count.
+            (4, b'X'),
+            (21, 0x08),
+            (17, 0x00),
+            (108, MAX_PRIORITY + 1),
+            (124, 0x05),
+        ];
+        for (offset, value) in invalid {
+            let mut packet = data.clone();
+            packet[offset] = value;
+            assert_eq!(parse_packet(&packet), None, "byte {offset}");
+        }
+        assert_eq!(parse_packet(&data[..data.len() - 1]), None);
+    }
+
+    #[test]
+    fn sacn_merge() {
+        let now = Instant::now();
+        let mut merger = Merger::default();
+        let receive = |merger: &mut Merger, packet: &[u8], now| {
+            merger.receive(&parse_packet(packet).unwrap(), now)
+        };
+        assert_eq!(merger.output(), None);
+
+        // Sources with the same priority are merged by the highest values.
+        assert!(receive(
+            &mut merger,
+            &data_packet(1, 100, 0, 0, &[10, 0]),
+            now
+        ));
+        assert!(receive(
+            &mut merger,
+            &data_packet(2, 100, 0, 0, &[0, 20, 5]),
+            now
+        ));
+        assert_eq!(merger.output().unwrap()[..4], [10, 20, 5, 0]);
+
+        // A higher priority takes over until it terminates.
+        assert!(receive(&mut merger, &data_packet(3, 150, 10, 0, &[1]), now));
+        assert_eq!(merger.output().unwrap()[..3], [1, 0, 0]);
+        let terminated = data_packet(3, 150, 11, OPTION_STREAM_TERMINATED, &[1]);
+        assert!(receive(&mut merger, &terminated, now));
+        assert_eq!(merger.output().unwrap()[..3], [10, 20, 5]);
+
+        // Late packets are discarded, unless the sequence jumped back far.
+        assert!(!receive(
+            &mut merger,
+            &data_packet(1, 100, 0, 0, &[99]),
+            now
+        ));
+        assert!(!receive(
+            &mut merger,
+            &data_packet(1, 100, 240, 0, &[99]),
+            now
+        ));
+        assert!(receive(
+            &mut merger,
+            &data_packet(1, 100, 200, 0, &[30]),
+            now
+        ));
+        assert!(receive(
+            &mut merger,
+            &data_packet(1, 100, 201, 0, &[40]),
+            now
+        ));
+        assert_eq!(merger.output().unwrap()[0], 40);
+
+        // Preview data and other start codes are not shown.
+        let preview = data_packet(4, 200, 0, OPTION_PREVIEW_DATA, &[0xff]);
+        assert!(!receive(&mut merger, &preview, now));
+        let mut text = data_packet(4, 200, 0, 0, &[0xff]);
+        text[125] = 0x17;
+        assert!(!receive(&mut merger, &text, now));
+
+        // Sources are lost without data.
+        let later = now + DATA_LOSS_TIMEOUT / 2;
+        assert!(receive(
+            &mut merger,
+            &data_packet(2, 100, 1, 0, &[0, 7]),
+            later
+        ));
+        assert!(!merger.expire(now + DATA_LOSS_TIMEOUT - Duration::from_millis(1)));
+        assert!(merger.expire(now + DATA_LOSS_TIMEOUT));
+        assert_eq!(merger.output().unwrap()[..2], [0, 7]);
+        assert!(merger.expire(later + DATA_LOSS_TIMEOUT));
+        assert_eq!(merger.output(), None);
+    }
+
+    fn map(text: &str, controllers: &[Box<dyn Controller>]) -> Result<Vec<Slot>, String> {
+        let config = Config::parse(PathBuf::from("map.toml"), text).map_err(|e| e.to_string())?;
+        parse_map(&config, controllers).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn sacn_map() {
+        let (_, controller) = transport::mock_controller_boxed();
+        let leds = controller.rgb_channels()[0].led_count;
+        let controllers = vec![controller];
+
+        let default = default_map(&controllers);
+        assert_eq!(default.len(), 3 * leds);
+        assert_eq!(default[leds], Slot {
+            device: 0,
+            channel: 1,
+            led: 0,
+            slot: leds * 3,
+            order: ColorOrder::Rgb,
+        });
+
+        let text = "[[led]]\nchannels = \"1\"\nleds = \"0-1\"\nstart = 10\norder = \"grb\"\n\n\
+                    [[led]]\nchannels = \"0,2\"\nsingle = true\n\n\
+                    [[led]]\nserial = \"MA0123456789\"";
+        let patched = map(text, &controllers).unwrap();
+        let slots = patched
+            .iter()
+            .map(|slot| (slot.channel, slot.led, slot.slot));
+        let expected = [(1, 0, 9), (1, 1, 12)]
+            .into_iter()
+            .chain((0..leds).map(|led| (0, led, 15)))
+            .chain((0..leds).map(|led| (2, led, 15)));
+        assert!(slots.eq(expected));
+        assert_eq!(patched[0].order, ColorOrder::Grb);
+
+        let errors = [
+            (
+                "[[led]]\nstart = 511\n",
+                "`led[0]`: the LEDs need slots up to",
+            ),
+            (
+                "[[led]]\nstart = 0\n",
+                "`led[0].start`: expected a slot from 1 to 512",
+            ),
+            (
+                "[[led]]\nleds = \"99\"\n",
+                "`led[0].leds`: channel 0 of NZXT RGB Controller has",
+            ),
+            ("[[led]]\nchannels = \"5\"\n", "`led[0].channels`: "),
+            (
+                "[[led]]\ncolor = \"red\"\n",
+                "`led[0].color`: unknown key of an LED mapping",
+            ),
+        ];
+        for (text, message) in errors {
+            let error = map(text, &controllers).unwrap_err();
+            assert!(error.contains(message), "{error}");
+        }
+    }
+
+    #[test]
+    fn sacn_output() {
+        assert_eq!(decode(ColorOrder::Rgb, &[1, 2, 3]), Color::new(1, 2, 3));
+        assert_eq!(decode(ColorOrder::Grb, &[1, 2, 3]), Color::new(2, 1, 3));
+        assert_eq!(decode(ColorOrder::Bgr, &[1, 2, 3]), Color::new(3, 2, 1));
+
+        let (transport, controller) = transport::mock_controller_boxed();
+        let (expected_transport, expected) = transport::mock_controller_boxed();
+        let leds = controller.rgb_channels()[1].led_count;
+        let controllers = vec![controller];
+        let map = map(
+            "[[led]]\nchannels = \"1\"\nleds = \"1\"\nstart = 4",
+            &controllers,
+        )
+        .unwrap();
+        let mut output = Output::new(controllers, map);
+        assert_eq!(output.slots(), 3);
+
+        let mut slots = [0; SLOTS];
+        slots[3..6].copy_from_slice(&[0xff, 0x80, 0x00]);
+        output.show(&slots);
+        let mut colors = vec![Color::BLACK; leds];
+        colors[1] = Color::new(0xff, 0x80, 0x00);
+        expected.set_frame(&[(1, &colors)]).unwrap();
+        assert!(!transport.written().is_empty());
+        assert_eq!(transport.written(), expected_transport.written());
+
+        // Unchanged frames are not sent again, blanking is.
+        transport.clear_written();
+        output.show(&slots);
+        assert!(transport.written().is_empty());
+        output.show(&[0; SLOTS]);
+        expected_transport.clear_written();
+        expected
+            .set_frame(&[(1, &vec![Color::BLACK; leds])])
+            .unwrap();
+        assert_eq!(transport.written(), expected_transport.written());
+    }
+}
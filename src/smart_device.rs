@@ -0,0 +1,328 @@
+use std::borrow::Cow;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use hidapi::HidApi;
+
+use crate::controller::{
+    ChannelDeviceInfo, Controller, ReapplyTimer, RgbChannel, open_device, reopen_device,
+    write_report,
+};
+use crate::error::{Error, Result};
+use crate::logging::warning;
+use crate::transport::{HidTransport, timeout_ms};
+use crate::types::{Color, DeviceType};
+
+/// First generation NZXT Smart Device (e.g. from H500i/H700i cases).
+///
+/// It has a single RGB channel with up to 40 LEDs.
+pub struct NZXTSmartDeviceV1<'a> {
+    device: Box<dyn HidTransport>,
+    info: Option<Cow<'a, hidapi::DeviceInfo>>,
+    rgb_channels: Vec<RgbChannel>,
+    /// Last color of the channel, restored on reconnect.
+    color: Mutex<Option<Color>>,
+    reapply: Mutex<ReapplyTimer>,
+    connected: AtomicBool,
+}
+
+impl<'a> NZXTSmartDeviceV1<'a> {
+    pub const PRODUCT_ID: u16 = 0x1714;
+    pub const NAME: &'static str = "NZXT Smart Device V1";
+
+    pub fn new(api: &hidapi::HidApi, info: &'a hidapi::DeviceInfo) -> Result<Self> {
+        // The reports start with their report id, so they are written as is.
+        let device = Box::new(open_device(api, info)?);
+        Self::from_parts(device, Some(Cow::Borrowed(info)))
+    }
+
+    /// Creates a controller which talks to the device through the transport.
+    pub fn with_transport(transport: Box<dyn HidTransport>) -> Result<NZXTSmartDeviceV1<'static>> {
+        NZXTSmartDeviceV1::from_parts(transport, None)
+    }
+
+    fn from_parts(
+        device: Box<dyn HidTransport>,
+        info: Option<Cow<'a, hidapi::DeviceInfo>>,
+    ) -> Result<Self> {
+        let report = read_status(device.as_ref())?;
+        let rgb_channels = vec![parse_channel_info(&report)];
+
+        Ok(Self {
+            device,
+            info,
+            rgb_channels,
+            color: Mutex::default(),
+            reapply: Mutex::default(),
+            connected: AtomicBool::new(true),
+        })
+    }
+
+    /// Detaches the controller from the borrowed device info.
+    pub fn into_owned(self) -> NZXTSmartDeviceV1<'static> {
+        NZXTSmartDeviceV1 {
+            device: self.device,
+            info: self.info.map(|info| Cow::Owned(info.into_owned())),
+            rgb_channels: self.rgb_channels,
+            color: self.color,
+            reapply: self.reapply,
+            connected: self.connected,
+        }
+    }
+}
+
+impl Controller for NZXTSmartDeviceV1<'_> {
+    fn info(&self) -> Option<&hidapi::DeviceInfo> {
+        self.info.as_deref()
+    }
+
+    fn product_id(&self) -> u16 {
+        Self::PRODUCT_ID
+    }
+
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn rgb_channels(&self) -> &[RgbChannel] {
+        &self.rgb_channels
+    }
+
+    fn set_channel_color(&self, channel: usize, color: Color) -> Result<()> {
+        if channel >= self.rgb_channels.len() {
+            return Err(Error::InvalidChannel {
+                requested: channel,
+                available: self.rgb_channels.len(),
+            });
+        }
+
+        for packet in fixed_color_packets(color) {
+            let result = write_report(self.device.as_ref(), &packet);
+            self.connected.store(result.is_ok(), Ordering::Relaxed);
+            result?;
+        }
+        *self.color.lock().unwrap() = Some(color);
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// The status is sent by the device on its own, so nothing is written.
+    fn ping(&self) -> Result<()> {
+        let result = read_status(self.device.as_ref()).map(|_| ());
+        self.connected.store(result.is_ok(), Ordering::Relaxed);
+        result
+    }
+
+    /// Opens the device by its serial number, re-reads the accessories and
+    /// restores the last color unless they have changed.
+    fn reconnect(&mut self, api: &HidApi) -> Result<()> {
+        let Some(info) = &self.info else {
+            return Err(Error::Unsupported("reconnection"));
+        };
+        let device: Box<dyn HidTransport> = Box::new(reopen_device(api, info)?);
+        let channel = parse_channel_info(&read_status(device.as_ref())?);
+        self.device = device;
+        self.connected.store(true, Ordering::Relaxed);
+
+        let color = self.color.get_mut().unwrap();
+        if channel.led_count != self.rgb_channels[0].led_count {
+            *color = None;
+        }
+        self.rgb_channels = vec![channel];
+        match *color {
+            Some(color) => self.set_channel_color(0, color),
+            None => Ok(()),
+        }
+    }
+
+    /// Resends the last color every 10 seconds, since a reset of the
+    /// firmware can't be detected.
+    fn ensure_applied(&self) -> Result<()> {
+        if !self.reapply.lock().unwrap().due() {
+            return Ok(());
+        }
+        let color = *self.color.lock().unwrap();
+        match color {
+            Some(color) => self.set_channel_color(0, color),
+            None => Ok(()),
+        }
+    }
+}
+
+fn read_status(device: &dyn HidTransport) -> Result<[u8; REPORT_LEN]> {
+    let deadline = Instant::now() + STATUS_TIMEOUT;
+    let mut buffer = [0u8; REPORT_LEN];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::Timeout);
+        }
+
+        let len = device.read_timeout(&mut buffer, timeout_ms(remaining))?;
+        if len == REPORT_LEN && buffer[0] == 0x04 {
+            return Ok(buffer);
+        }
+    }
+}
+
+/// Unlike Hue 2, the status report contains only the type of the accessories
+/// and their count (all accessories on the channel must be of the same type).
+///
+/// Accessories past the 40 addressable LEDs are dropped.
+fn parse_channel_info(report: &[u8; REPORT_LEN]) -> RgbChannel {
+    let id = report[0x10] >> 3;
+    let count = (report[0x11] as usize).min(MAX_ACCESSORIES);
+
+    let mut channel = RgbChannel::default();
+    let device_type = DeviceType::from(id);
+    if !matches!(device_type, DeviceType::Hue1Strip | DeviceType::Aer1Fan) {
+        return channel;
+    }
+
+    let leds = device_type.led_count() as usize;
+    let fitting = count.min(MAX_LEDS / leds);
+    if fitting < count {
+        warning!(
+            "{count} accessories of {leds} LEDs are reported, only the first {fitting} fit \
+             in {MAX_LEDS} LEDs"
+        );
+    }
+    channel.devices[..fitting].fill(ChannelDeviceInfo::new(device_type));
+    channel.led_count = fitting * leds;
+    channel
+}
+
+/// LED data is always sent for all 40 LEDs and is split into two reports.
+fn fixed_color_packets(color: Color) -> [[u8; 65]; 2] {
+    let mut colors = [0u8; MAX_LEDS * 3];
+    Color::write_bytes(&[color; MAX_LEDS], &mut colors);
+    let (first, second) = colors.split_at(57);
+
+    let mut packets = [[0u8; 65]; 2];
+    packets[0][..5].copy_from_slice(&[0x02, 0x4b, MODE_FIXED, 0x00, SPEED_NORMAL]);
+    packets[0][5..5 + first.len()].copy_from_slice(first);
+    packets[1][0] = 0x03;
+    packets[1][1..1 + second.len()].copy_from_slice(second);
+    packets
+}
+
+const REPORT_LEN: usize = 21;
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+const MAX_ACCESSORIES: usize = 5;
+const MAX_LEDS: usize = 40;
+
+const MODE_FIXED: u8 = 0x00;
+const SPEED_NORMAL: u8 = 0x02;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+
+    fn status_report(device_type: DeviceType, count: u8) -> [u8; REPORT_LEN] {
+        let mut report = [0u8; REPORT_LEN];
+        report[0] = 0x04;
+        report[0x10] = device_type.id() << 3;
+        report[0x11] = count;
+        report
+    }
+
+    #[test]
+    fn accessories_from_status() {
+        let mut report = [0u8; REPORT_LEN];
+        report[0] = 0x04;
+        report[0x10] = DeviceType::Hue1Strip.id() << 3;
+        report[0x11] = 3;
+
+        let channel = parse_channel_info(&report);
+        assert_eq!(channel.led_count, 30);
+        assert!(
+            channel.devices[..3]
+                .iter()
+                .all(|d| d.id == 0x01 && d.led_count == 10)
+        );
+        assert_eq!(channel.devices[3].led_count, 0);
+
+        report[0x10] = DeviceType::Aer1Fan.id() << 3;
+        report[0x11] = 5;
+        let channel = parse_channel_info(&report);
+        assert_eq!(channel.led_count, 40);
+
+        report[0x11] = 0;
+        let channel = parse_channel_info(&report);
+        assert_eq!(channel.led_count, 0);
+
+        // Only 4 strips of 10 LEDs are addressable.
+        let channel = parse_channel_info(&status_report(DeviceType::Hue1Strip, 5));
+        assert_eq!(channel.led_count, 40);
+        assert_eq!(channel.accessory_leds(), 40);
+        assert_eq!(channel.devices[4].led_count, 0);
+    }
+
+    #[test]
+    fn fixed_color_split() {
+        let [first, second] = fixed_color_packets(Color::new(0x11, 0x22, 0x33));
+        assert_eq!(first[..8], [0x02, 0x4b, 0x00, 0x00, 0x02, 0x22, 0x11, 0x33]);
+        assert_eq!(first[59..62], [0x22, 0x11, 0x33]);
+        assert_eq!(first[62..], [0x00; 3]);
+        assert_eq!(second[..4], [0x03, 0x22, 0x11, 0x33]);
+        assert_eq!(second[61..64], [0x22, 0x11, 0x33]);
+        assert_eq!(second[64], 0x00);
+    }
+
+    #[test]
+    fn transport_short_write() {
+        let transport = MockTransport::new();
+        // Unrelated reports are skipped.
+        transport.push_response([0x02, 0x4b]);
+        transport.push_response(status_report(DeviceType::Hue1Strip, 2));
+        let controller = NZXTSmartDeviceV1::with_transport(Box::new(transport.clone())).unwrap();
+        assert_eq!(controller.rgb_channels()[0].led_count, 20);
+
+        controller.set_channel_color(0, Color::RED).unwrap();
+        assert_eq!(transport.written().len(), 2);
+
+        transport.set_write_limit(Some(64));
+        assert!(matches!(
+            controller.set_channel_color(0, Color::RED),
+            Err(Error::ShortWrite {
+                expected: 65,
+                written: 64
+            })
+        ));
+        assert!(!controller.is_connected());
+    }
+
+    #[test]
+    fn transport_reapply() {
+        let transport = MockTransport::new();
+        transport.push_response(status_report(DeviceType::Aer1Fan, 1));
+        let controller = NZXTSmartDeviceV1::with_transport(Box::new(transport.clone())).unwrap();
+        *controller.reapply.lock().unwrap() = ReapplyTimer::new(Some(Duration::ZERO));
+        // Nothing was set yet.
+        controller.ensure_applied().unwrap();
+        assert!(transport.written().is_empty());
+
+        controller.set_channel_color(0, Color::BLUE).unwrap();
+        controller.ensure_applied().unwrap();
+        let written = transport.written();
+        assert_eq!(written.len(), 4);
+        assert_eq!(written[2..], written[..2]);
+    }
+
+    #[test]
+    fn transport_ping() {
+        let transport = MockTransport::new();
+        transport.push_response(status_report(DeviceType::Aer1Fan, 1));
+        let controller = NZXTSmartDeviceV1::with_transport(Box::new(transport.clone())).unwrap();
+
+        transport.push_response(status_report(DeviceType::Aer1Fan, 1));
+        controller.ping().unwrap();
+        assert!(transport.written().is_empty());
+    }
+}
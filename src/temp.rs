@@ -0,0 +1,19 @@
+//! Sources of temperature readings for temperature-driven colors and fan curves.
+
+use std::fmt;
+
+use crate::error::Result;
+
+/// Source of temperature readings, such as a hwmon sensor or an external command.
+///
+/// Failed reads are considered transient, the source is read again later.
+pub trait TempSource: fmt::Display + Send {
+    /// Returns the temperature in millidegrees celsius.
+    fn read(&mut self) -> Result<i64>;
+}
+
+impl<T: TempSource + ?Sized> TempSource for Box<T> {
+    fn read(&mut self) -> Result<i64> {
+        (**self).read()
+    }
+}
@@ -0,0 +1,574 @@
+//! TLS client connections over the system OpenSSL (the `tls` feature), used
+//! by the MQTT bridge.
+//!
+//! Only the few functions needed by a blocking client are bound: the peer is
+//! verified against the default trust store or a CA file and the host name
+//! (or the IP address), a client certificate is optional.
+
+use std::ffi::{CStr, CString, c_char, c_int, c_long, c_ulong, c_void};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, TcpStream};
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[repr(C)]
+struct SslMethod {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct SslCtx {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct SslRaw {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct VerifyParam {
+    _private: [u8; 0],
+}
+
+const SSL_OP_IGNORE_UNEXPECTED_EOF: u64 = 1 << 7;
+const SSL_VERIFY_PEER: c_int = 0x01;
+const SSL_FILETYPE_PEM: c_int = 1;
+const SSL_CTRL_SET_TLSEXT_HOSTNAME: c_int = 55;
+const TLSEXT_NAMETYPE_HOST_NAME: c_long = 0;
+const SSL_ERROR_WANT_READ: c_int = 2;
+const SSL_ERROR_WANT_WRITE: c_int = 3;
+const SSL_ERROR_SYSCALL: c_int = 5;
+const SSL_ERROR_ZERO_RETURN: c_int = 6;
+const X509_V_OK: c_long = 0;
+
+#[link(name = "ssl")]
+unsafe extern "C" {
+    fn TLS_client_method() -> *const SslMethod;
+    #[cfg(test)]
+    fn TLS_server_method() -> *const SslMethod;
+    fn SSL_CTX_new(method: *const SslMethod) -> *mut SslCtx;
+    fn SSL_CTX_free(ctx: *mut SslCtx);
+    fn SSL_CTX_set_options(ctx: *mut SslCtx, options: u64) -> u64;
+    fn SSL_CTX_set_default_verify_paths(ctx: *mut SslCtx) -> c_int;
+    fn SSL_CTX_load_verify_locations(
+        ctx: *mut SslCtx,
+        file: *const c_char,
+        path: *const c_char,
+    ) -> c_int;
+    fn SSL_CTX_set_verify(ctx: *mut SslCtx, mode: c_int, callback: *const c_void);
+    fn SSL_CTX_use_certificate_chain_file(ctx: *mut SslCtx, file: *const c_char) -> c_int;
+    fn SSL_CTX_use_PrivateKey_file(ctx: *mut SslCtx, file: *const c_char, kind: c_int) -> c_int;
+    fn SSL_new(ctx: *mut SslCtx) -> *mut SslRaw;
+    fn SSL_free(ssl: *mut SslRaw);
+    fn SSL_set_fd(ssl: *mut SslRaw, fd: c_int) -> c_int;
+    fn SSL_ctrl(ssl: *mut SslRaw, cmd: c_int, larg: c_long, parg: *mut c_void) -> c_long;
+    fn SSL_set1_host(ssl: *mut SslRaw, host: *const c_char) -> c_int;
+    fn SSL_get0_param(ssl: *mut SslRaw) -> *mut VerifyParam;
+    fn SSL_connect(ssl: *mut SslRaw) -> c_int;
+    #[cfg(test)]
+    fn SSL_accept(ssl: *mut SslRaw) -> c_int;
+    fn SSL_read(ssl: *mut SslRaw, buf: *mut c_void, len: c_int) -> c_int;
+    fn SSL_write(ssl: *mut SslRaw, buf: *const c_void, len: c_int) -> c_int;
+    fn SSL_pending(ssl: *const SslRaw) -> c_int;
+    fn SSL_shutdown(ssl: *mut SslRaw) -> c_int;
+    fn SSL_get_error(ssl: *const SslRaw, ret: c_int) -> c_int;
+    fn SSL_get_verify_result(ssl: *const SslRaw) -> c_long;
+}
+
+#[link(name = "crypto")]
+unsafe extern "C" {
+    fn X509_VERIFY_PARAM_set1_ip_asc(param: *mut VerifyParam, ip: *const c_char) -> c_int;
+    fn X509_verify_cert_error_string(code: c_long) -> *const c_char;
+    fn ERR_get_error() -> c_ulong;
+    fn ERR_error_string_n(code: c_ulong, buf: *mut c_char, len: usize);
+    fn ERR_clear_error();
+}
+
+/// The last error of the OpenSSL error queue, which is cleared.
+fn last_error(what: &str) -> io::Error {
+    // SAFETY: The queue is thread-local and the buffer is valid for its length.
+    let message = unsafe {
+        let code = ERR_get_error();
+        while ERR_get_error() != 0 {}
+        match code {
+            0 => None,
+            code => {
+                let mut buffer = [0 as c_char; 256];
+                ERR_error_string_n(code, buffer.as_mut_ptr(), buffer.len());
+                Some(
+                    CStr::from_ptr(buffer.as_ptr())
+                        .to_string_lossy()
+                        .into_owned(),
+                )
+            }
+        }
+    };
+    match message {
+        Some(message) => io::Error::other(format!("{what}: {message}")),
+        None => io::Error::other(what.to_owned()),
+    }
+}
+
+fn c_path(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the path contains a NUL byte"))
+}
+
+/// The context and the connection once it's started, freed together.
+struct Ssl {
+    ctx: *mut SslCtx,
+    ssl: *mut SslRaw,
+}
+
+// SAFETY: The connection is only used behind the mutex of `Shared`.
+unsafe impl Send for Ssl {}
+
+impl Drop for Ssl {
+    fn drop(&mut self) {
+        // SAFETY: Both were created by `Ssl` and are not used anymore, a null
+        // connection is ignored.
+        unsafe {
+            SSL_free(self.ssl);
+            SSL_CTX_free(self.ctx);
+        }
+    }
+}
+
+impl Ssl {
+    fn new(method: *const SslMethod) -> io::Result<Self> {
+        // SAFETY: The method is static.
+        let ctx = unsafe {
+            ERR_clear_error();
+            SSL_CTX_new(method)
+        };
+        if ctx.is_null() {
+            return Err(last_error("failed to create the TLS context"));
+        }
+        // Brokers often close the socket without the close notification, the
+        // packets are framed anyway.
+        // SAFETY: The context is valid.
+        unsafe { SSL_CTX_set_options(ctx, SSL_OP_IGNORE_UNEXPECTED_EOF) };
+        Ok(Self {
+            ctx,
+            ssl: std::ptr::null_mut(),
+        })
+    }
+
+    /// The certificate chain and the key in PEM.
+    fn use_certificate(&mut self, cert_file: &Path, key_file: &Path) -> io::Result<()> {
+        let cert = c_path(cert_file)?;
+        let key = c_path(key_file)?;
+        // SAFETY: The context is valid and the paths outlive the calls.
+        unsafe {
+            if SSL_CTX_use_certificate_chain_file(self.ctx, cert.as_ptr()) != 1 {
+                return Err(last_error(&format!(
+                    "failed to load {}",
+                    cert_file.display()
+                )));
+            }
+            if SSL_CTX_use_PrivateKey_file(self.ctx, key.as_ptr(), SSL_FILETYPE_PEM) != 1 {
+                return Err(last_error(&format!(
+                    "failed to load {}",
+                    key_file.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the connection, the context can't be changed afterwards.
+    fn start(&mut self) -> io::Result<()> {
+        // SAFETY: The context is valid.
+        self.ssl = unsafe { SSL_new(self.ctx) };
+        match self.ssl.is_null() {
+            true => Err(last_error("failed to create the TLS connection")),
+            false => Ok(()),
+        }
+    }
+
+    /// The result of a failed call as an IO error, `None` if the call should
+    /// be repeated.
+    fn error(&self, result: c_int, what: &str) -> Option<io::Error> {
+        // SAFETY: The connection is valid.
+        match unsafe { SSL_get_error(self.ssl, result) } {
+            SSL_ERROR_WANT_READ | SSL_ERROR_WANT_WRITE => None,
+            SSL_ERROR_SYSCALL => {
+                let error = io::Error::last_os_error();
+                Some(match error.raw_os_error() {
+                    Some(0) | None => io::ErrorKind::UnexpectedEof.into(),
+                    _ => error,
+                })
+            }
+            SSL_ERROR_ZERO_RETURN => Some(io::ErrorKind::UnexpectedEof.into()),
+            _ => Some(last_error(what)),
+        }
+    }
+}
+
+/// State shared by the clones, the connection is freed before the socket is
+/// closed.
+struct Shared {
+    ssl: Mutex<Ssl>,
+    socket: TcpStream,
+}
+
+/// A TLS connection over a blocking socket, the timeouts of the socket apply.
+///
+/// Clones share the connection, so it can be read by one thread and written
+/// by another like a [`TcpStream`]: reads wait for the socket without holding
+/// the connection.
+#[derive(Clone)]
+pub struct TlsStream {
+    shared: Arc<Shared>,
+}
+
+impl TlsStream {
+    /// Verifies the peer against `ca_file` (or the default trust store) and
+    /// the `host` it was connected to, with an optional client certificate.
+    pub fn connect(
+        socket: TcpStream,
+        host: &str,
+        ca_file: Option<&Path>,
+        identity: Option<(&Path, &Path)>,
+    ) -> io::Result<Self> {
+        // SAFETY: The method is static.
+        let mut ssl = Ssl::new(unsafe { TLS_client_method() })?;
+        if let Some((cert_file, key_file)) = identity {
+            ssl.use_certificate(cert_file, key_file)?;
+        }
+        let host_name = CString::new(host)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid host"))?;
+        let ca = ca_file.map(c_path).transpose()?;
+        // SAFETY: The connection is valid and the strings outlive the calls.
+        unsafe {
+            let loaded = match &ca {
+                Some(ca) => SSL_CTX_load_verify_locations(ssl.ctx, ca.as_ptr(), std::ptr::null()),
+                None => SSL_CTX_set_default_verify_paths(ssl.ctx),
+            };
+            if loaded != 1 {
+                let what = match ca_file {
+                    Some(path) => format!("failed to load {}", path.display()),
+                    None => "failed to load the default trust store".to_owned(),
+                };
+                return Err(last_error(&what));
+            }
+            SSL_CTX_set_verify(ssl.ctx, SSL_VERIFY_PEER, std::ptr::null());
+        }
+        ssl.start()?;
+        // SAFETY: The connection is valid and the host outlives the calls.
+        unsafe {
+            let checked = match host.parse::<IpAddr>() {
+                Ok(_) => X509_VERIFY_PARAM_set1_ip_asc(SSL_get0_param(ssl.ssl), host_name.as_ptr()),
+                Err(_) => {
+                    SSL_ctrl(
+                        ssl.ssl,
+                        SSL_CTRL_SET_TLSEXT_HOSTNAME,
+                        TLSEXT_NAMETYPE_HOST_NAME,
+                        host_name.as_ptr().cast_mut().cast(),
+                    );
+                    SSL_set1_host(ssl.ssl, host_name.as_ptr())
+                }
+            };
+            if checked != 1 {
+                return Err(last_error("failed to set the host to verify"));
+            }
+        }
+        Self::handshake(ssl, socket, |ssl| {
+            // SAFETY: The connection has a socket.
+            unsafe { SSL_connect(ssl) }
+        })
+    }
+
+    fn handshake(
+        ssl: Ssl,
+        socket: TcpStream,
+        step: impl Fn(*mut SslRaw) -> c_int,
+    ) -> io::Result<Self> {
+        // SAFETY: The socket outlives the connection, see `Shared`.
+        if unsafe { SSL_set_fd(ssl.ssl, socket.as_raw_fd()) } != 1 {
+            return Err(last_error("failed to set the socket"));
+        }
+        // SAFETY: The queue is thread-local.
+        unsafe { ERR_clear_error() };
+        let result = step(ssl.ssl);
+        if result != 1 {
+            // SAFETY: The connection is valid.
+            let verified = unsafe { SSL_get_verify_result(ssl.ssl) };
+            if verified != X509_V_OK {
+                // SAFETY: The string is static.
+                let reason = unsafe { CStr::from_ptr(X509_verify_cert_error_string(verified)) };
+                return Err(io::Error::other(format!(
+                    "certificate verification failed: {}",
+                    reason.to_string_lossy()
+                )));
+            }
+            return Err(match ssl.error(result, "TLS handshake failed") {
+                // The socket is blocking, so only its timeout interrupts the handshake.
+                None => io::ErrorKind::TimedOut.into(),
+                Some(e) if e.kind() == io::ErrorKind::UnexpectedEof => io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the connection was closed during the TLS handshake",
+                ),
+                Some(e) => e,
+            });
+        }
+        Ok(Self {
+            shared: Arc::new(Shared {
+                ssl: Mutex::new(ssl),
+                socket,
+            }),
+        })
+    }
+
+    /// A test server with the certificate and the key in PEM.
+    #[cfg(test)]
+    pub fn accept(socket: TcpStream, cert_file: &Path, key_file: &Path) -> io::Result<Self> {
+        // SAFETY: The method is static.
+        let mut ssl = Ssl::new(unsafe { TLS_server_method() })?;
+        ssl.use_certificate(cert_file, key_file)?;
+        ssl.start()?;
+        Self::handshake(ssl, socket, |ssl| {
+            // SAFETY: The connection has a socket.
+            unsafe { SSL_accept(ssl) }
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<std::time::Duration>) -> io::Result<()> {
+        self.shared.socket.set_read_timeout(timeout)
+    }
+
+    /// Sends the TLS close notification and shuts the socket down.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        if let Ok(ssl) = self.shared.ssl.try_lock() {
+            // SAFETY: The connection is valid, a failure only skips the notification.
+            unsafe { SSL_shutdown(ssl.ssl) };
+        }
+        self.shared.socket.shutdown(how)
+    }
+}
+
+/// Waits until the socket is readable, up to its read timeout.
+fn wait(socket: &TcpStream) -> io::Result<()> {
+    let timeout = match socket.read_timeout()? {
+        Some(timeout) => c_int::try_from(timeout.as_millis().max(1)).unwrap_or(c_int::MAX),
+        None => -1,
+    };
+    let mut fd = libc::pollfd {
+        fd: socket.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    loop {
+        // SAFETY: A single valid pollfd is passed.
+        match unsafe { libc::poll(&mut fd, 1, timeout) } {
+            0 => return Err(io::ErrorKind::WouldBlock.into()),
+            n if n > 0 => return Ok(()),
+            _ => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = c_int::try_from(buf.len()).unwrap_or(c_int::MAX);
+        loop {
+            // SAFETY: The connection is valid.
+            if unsafe { SSL_pending(self.shared.ssl.lock().unwrap().ssl) } == 0 {
+                wait(&self.shared.socket)?;
+            }
+            let ssl = self.shared.ssl.lock().unwrap();
+            // SAFETY: The buffer is valid for `len` bytes.
+            let result = unsafe {
+                ERR_clear_error();
+                SSL_read(ssl.ssl, buf.as_mut_ptr().cast(), len)
+            };
+            if result > 0 {
+                return Ok(result as usize);
+            }
+            match ssl.error(result, "TLS read failed") {
+                // E.g. a session ticket without any data.
+                None => continue,
+                // Like the end of a plain stream.
+                Some(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(0),
+                Some(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = c_int::try_from(buf.len()).unwrap_or(c_int::MAX);
+        let ssl = self.shared.ssl.lock().unwrap();
+        // SAFETY: The buffer is valid for `len` bytes.
+        let result = unsafe {
+            ERR_clear_error();
+            SSL_write(ssl.ssl, buf.as_ptr().cast(), len)
+        };
+        match result {
+            n if n > 0 => Ok(n as usize),
+            n => Err(ssl
+                .error(n, "TLS write failed")
+                .unwrap_or_else(|| io::ErrorKind::TimedOut.into())),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Self-signed certificates of `localhost` and `127.0.0.1` in a temporary
+    /// directory, removed on drop.
+    pub struct Certificates {
+        pub dir: PathBuf,
+        pub cert: PathBuf,
+        pub key: PathBuf,
+    }
+
+    impl Drop for Certificates {
+        fn drop(&mut self) {
+            _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// `None` if the `openssl` command is not installed.
+    pub fn certificates(name: &str) -> Option<Certificates> {
+        let dir = std::env::temp_dir().join(format!("nzxtcli-tls-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let certificates = Certificates {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+            dir,
+        };
+        let status = Command::new("openssl")
+            .args([
+                "req",
+                "-x509",
+                "-newkey",
+                "ec",
+                "-pkeyopt",
+                "ec_paramgen_curve:P-256",
+            ])
+            .args(["-nodes", "-days", "1", "-subj", "/CN=localhost"])
+            .args(["-addext", "subjectAltName=DNS:localhost,IP:127.0.0.1"])
+            .arg("-keyout")
+            .arg(&certificates.key)
+            .arg("-out")
+            .arg(&certificates.cert)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match status {
+            Ok(status) if status.success() => Some(certificates),
+            Ok(status) => panic!("openssl failed with {status}"),
+            Err(e) => {
+                eprintln!("skipping the TLS test, failed to run openssl: {e}");
+                None
+            }
+        }
+    }
+
+    fn server(certificates: &Certificates) -> (u16, std::thread::JoinHandle<io::Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (cert, key) = (certificates.cert.clone(), certificates.key.clone());
+        let server = std::thread::spawn(move || {
+            let (socket, _) = listener.accept()?;
+            socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+            let mut stream = TlsStream::accept(socket, &cert, &key)?;
+            // Echoes a line back in reverse.
+            let mut line = [0; 6];
+            stream.read_exact(&mut line)?;
+            line[..5].reverse();
+            stream.write_all(&line)
+        });
+        (port, server)
+    }
+
+    fn connect(port: u16, host: &str, ca_file: &Path) -> io::Result<TlsStream> {
+        let socket = TcpStream::connect(("127.0.0.1", port))?;
+        socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+        TlsStream::connect(socket, host, Some(ca_file), None)
+    }
+
+    #[test]
+    fn tls_round_trip() {
+        let Some(certificates) = certificates("round-trip") else {
+            return;
+        };
+        for host in ["localhost", "127.0.0.1"] {
+            let (port, server) = server(&certificates);
+            let mut stream = connect(port, host, &certificates.cert).unwrap();
+            // The written half is a clone, like the writer of the MQTT bridge.
+            stream.clone().write_all(b"hello\n").unwrap();
+            let mut line = Vec::new();
+            stream.read_to_end(&mut line).unwrap();
+            assert_eq!(line, b"olleh\n");
+            server.join().unwrap().unwrap();
+        }
+
+        // Nothing is received within the read timeout.
+        let (port, _server) = server(&certificates);
+        let mut stream = connect(port, "localhost", &certificates.cert).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        let error = stream.read(&mut [0; 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+        stream.shutdown(Shutdown::Both).unwrap();
+    }
+
+    #[test]
+    fn tls_verification() {
+        let Some(trusted) = certificates("verification") else {
+            return;
+        };
+        let Some(other) = certificates("verification-other") else {
+            return;
+        };
+
+        let (port, _server) = server(&trusted);
+        let error = connect(port, "broker.lan", &trusted.cert).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "certificate verification failed: hostname mismatch"
+        );
+
+        let (port, _server) = server(&trusted);
+        let error = connect(port, "localhost", &other.cert).err().unwrap();
+        assert!(
+            error
+                .to_string()
+                .starts_with("certificate verification failed"),
+            "{error}"
+        );
+
+        let missing = trusted.dir.join("missing.pem");
+        let (port, _server) = server(&trusted);
+        let error = connect(port, "localhost", &missing).err().unwrap();
+        assert!(
+            error
+                .to_string()
+                .starts_with(&format!("failed to load {}", missing.display())),
+            "{error}"
+        );
+    }
+}
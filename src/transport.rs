@@ -0,0 +1,766 @@
+//! HID transport used by the controllers.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::controller::describe_packet;
+use crate::error::{Error, Result};
+
+/// Minimal interface of a HID device.
+pub trait HidTransport: Send {
+    /// Writes an output report, returns the number of bytes written.
+    fn write(&self, data: &[u8]) -> Result<usize>;
+
+    /// Reads an input report, returns `0` if nothing was received in time.
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize>;
+}
+
+impl HidTransport for hidapi::HidDevice {
+    fn write(&self, data: &[u8]) -> Result<usize> {
+        Ok(hidapi::HidDevice::write(self, data)?)
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        Ok(hidapi::HidDevice::read_timeout(self, buffer, timeout_ms)?)
+    }
+}
+
+impl<T: HidTransport + ?Sized> HidTransport for Box<T> {
+    fn write(&self, data: &[u8]) -> Result<usize> {
+        T::write(self, data)
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        T::read_timeout(self, buffer, timeout_ms)
+    }
+}
+
+/// Timeout of [`HidTransport::read_timeout`], rounded up to whole
+/// milliseconds since `0` doesn't wait at all.
+pub(crate) fn timeout_ms(timeout: Duration) -> i32 {
+    let ms = timeout.as_nanos().div_ceil(1_000_000).max(1);
+    i32::try_from(ms).unwrap_or(i32::MAX)
+}
+
+/// Layout of output reports expected by the HID backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Reports are written as is (Linux hidraw, macOS).
+    Raw,
+    /// Reports are prefixed with the `0x00` report id and padded to the
+    /// output report length of the device (Windows).
+    ReportId { report_len: usize },
+}
+
+impl Framing {
+    /// Framing required by the current platform.
+    pub fn native(report_len: Option<usize>) -> Self {
+        if cfg!(windows) {
+            Self::ReportId {
+                report_len: report_len.unwrap_or(DEFAULT_REPORT_LEN),
+            }
+        } else {
+            Self::Raw
+        }
+    }
+}
+
+/// Transport wrapper which adapts written reports to the [`Framing`].
+///
+/// The returned number of written bytes doesn't include the framing.
+pub struct FramedTransport<T> {
+    inner: T,
+    framing: Framing,
+}
+
+impl<T: HidTransport> FramedTransport<T> {
+    pub fn new(inner: T, framing: Framing) -> Self {
+        Self { inner, framing }
+    }
+}
+
+impl<T: HidTransport> HidTransport for FramedTransport<T> {
+    fn write(&self, data: &[u8]) -> Result<usize> {
+        match self.framing {
+            Framing::Raw => self.inner.write(data),
+            Framing::ReportId { report_len } => {
+                let mut buffer = vec![0u8; 1 + report_len.max(data.len())];
+                buffer[1..1 + data.len()].copy_from_slice(data);
+                let written = self.inner.write(&buffer)?;
+                Ok(written.saturating_sub(1).min(data.len()))
+            }
+        }
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        self.inner.read_timeout(buffer, timeout_ms)
+    }
+}
+
+/// Wraps the opened device with the framing required by the platform.
+pub(crate) fn native_transport(device: hidapi::HidDevice) -> Box<dyn HidTransport> {
+    let framing = Framing::native(None);
+    match framing {
+        Framing::Raw => Box::new(device),
+        Framing::ReportId { .. } => {
+            let mut descriptor = [0u8; hidapi::MAX_REPORT_DESCRIPTOR_SIZE];
+            let report_len = device
+                .get_report_descriptor(&mut descriptor)
+                .ok()
+                .and_then(|len| output_report_len(&descriptor[..len]));
+            Box::new(FramedTransport::new(device, Framing::native(report_len)))
+        }
+    }
+}
+
+/// Returns the length of the largest output report (without the report id)
+/// declared in the report descriptor.
+pub fn output_report_len(descriptor: &[u8]) -> Option<usize> {
+    let mut report_size = 0usize;
+    let mut report_count = 0usize;
+    let mut report_id = 0u8;
+    let mut bits = Vec::<(u8, usize)>::new();
+
+    let mut i = 0;
+    while i < descriptor.len() {
+        let prefix = descriptor[i];
+        if prefix == 0xfe {
+            // Long item: data size is stored in the next byte.
+            i += 3 + *descriptor.get(i + 1)? as usize;
+            continue;
+        }
+
+        let size = match prefix & 0x03 {
+            3 => 4,
+            n => n as usize,
+        };
+        let data = descriptor.get(i + 1..i + 1 + size)?;
+        let value = data
+            .iter()
+            .rev()
+            .fold(0usize, |acc, &b| (acc << 8) | b as usize);
+
+        match prefix & 0xfc {
+            0x74 => report_size = value,
+            0x94 => report_count = value,
+            0x84 => report_id = value as u8,
+            0x90 => match bits.iter_mut().find(|(id, _)| *id == report_id) {
+                Some((_, total)) => *total += report_size * report_count,
+                None => bits.push((report_id, report_size * report_count)),
+            },
+            _ => {}
+        }
+        i += 1 + size;
+    }
+
+    bits.into_iter()
+        .map(|(_, bits)| bits.div_ceil(8))
+        .max()
+        .filter(|&len| len > 0)
+}
+
+const DEFAULT_REPORT_LEN: usize = 64;
+
+/// In-memory transport which records written reports and replays
+/// queued responses.
+///
+/// Clones share the same state, so a clone can be kept to inspect the
+/// traffic after the transport was moved into a controller. Only built for
+/// tests and with the `mock` feature.
+#[cfg(any(test, feature = "mock"))]
+#[derive(Default, Clone)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[cfg(any(test, feature = "mock"))]
+#[derive(Default)]
+struct MockState {
+    written: Vec<Vec<u8>>,
+    responses: VecDeque<Vec<u8>>,
+    write_limit: Option<usize>,
+    responder: Option<Box<Responder>>,
+}
+
+#[cfg(any(test, feature = "mock"))]
+type Responder = dyn FnMut(&[u8]) -> Vec<Vec<u8>> + Send;
+
+#[cfg(any(test, feature = "mock"))]
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a report which will be returned by the next read.
+    pub fn push_response(&self, report: impl Into<Vec<u8>>) {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .push_back(report.into());
+    }
+
+    /// Returns all reports written so far.
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().written.clone()
+    }
+
+    pub fn clear_written(&self) {
+        self.state.lock().unwrap().written.clear();
+    }
+
+    /// Sets a function which produces responses queued after each write
+    /// (to simulate a device answering requests).
+    pub fn set_responder(&self, responder: impl FnMut(&[u8]) -> Vec<Vec<u8>> + Send + 'static) {
+        self.state.lock().unwrap().responder = Some(Box::new(responder));
+    }
+
+    /// Limits the number of bytes reported as written (to simulate short writes).
+    pub fn set_write_limit(&self, limit: Option<usize>) {
+        self.state.lock().unwrap().write_limit = limit;
+    }
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl HidTransport for MockTransport {
+    fn write(&self, data: &[u8]) -> Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.written.push(data.to_vec());
+        if let Some(responder) = &mut state.responder {
+            let responses = responder(data);
+            state.responses.extend(responses);
+        }
+        Ok(state
+            .write_limit
+            .map_or(data.len(), |limit| data.len().min(limit)))
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        let response = self.state.lock().unwrap().responses.pop_front();
+        match response {
+            Some(response) => {
+                let len = response.len().min(buffer.len());
+                buffer[..len].copy_from_slice(&response[..len]);
+                Ok(len)
+            }
+            None => {
+                // Behave like a real device which has nothing to send.
+                std::thread::sleep(std::time::Duration::from_millis(
+                    timeout_ms.clamp(0, 10) as u64
+                ));
+                Ok(0)
+            }
+        }
+    }
+}
+
+/// Reply of a Hue 2 controller to the accessory query with the accessory
+/// ids of each channel.
+#[cfg(any(test, feature = "mock"))]
+pub fn mock_accessory_page(ids: &[&[u8]]) -> [u8; 64] {
+    use crate::controller::{ACCESSORIES_OFFSET, HUE_2_NUM_CHANNELS};
+
+    let mut page = [0u8; 64];
+    page[..2].copy_from_slice(&[0x21, 0x03]);
+    for (channel, ids) in ids.iter().enumerate() {
+        let start = ACCESSORIES_OFFSET + channel * HUE_2_NUM_CHANNELS;
+        page[start..start + ids.len()].copy_from_slice(ids);
+    }
+    page
+}
+
+/// RGB Controller (3 RGB and 3 fan channels) with the accessories of each
+/// channel, the reports written while opening it are cleared.
+#[cfg(any(test, feature = "mock"))]
+pub fn mock_controller(ids: &[&[u8]]) -> (MockTransport, crate::NZXTHue2Controller<'static>) {
+    let transport = MockTransport::new();
+    transport.push_response(mock_accessory_page(ids));
+    let controller = crate::NZXTHue2Controller::with_transport(
+        Box::new(transport.clone()),
+        0x2012,
+        "NZXT RGB Controller",
+        3,
+        3,
+        true,
+    )
+    .unwrap();
+    transport.clear_written();
+    (transport, controller)
+}
+
+/// [`mock_controller`] with an accessory on each of the 3 channels, boxed like
+/// the controllers found by the discovery.
+#[cfg(any(test, feature = "mock"))]
+pub fn mock_controller_boxed() -> (MockTransport, Box<dyn crate::Controller>) {
+    let (transport, controller) = mock_controller(&[&[0x13], &[0x13], &[0x13]]);
+    (transport, Box::new(controller))
+}
+
+/// Shared sink for [`RecordingTransport`]s which writes one JSON object per line.
+pub struct HidRecorder {
+    output: Mutex<Box<dyn Write + Send>>,
+    started_at: Instant,
+}
+
+impl std::fmt::Debug for HidRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HidRecorder")
+            .field("started_at", &self.started_at)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HidRecorder {
+    pub fn new(output: impl Write + Send + 'static) -> Arc<Self> {
+        Arc::new(Self {
+            output: Mutex::new(Box::new(output)),
+            started_at: Instant::now(),
+        })
+    }
+
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Arc<Self>> {
+        let file = std::fs::File::create(path)?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+
+    fn record(&self, device: &str, direction: RecordDirection, data: &[u8]) {
+        let entry = RecordEntry {
+            time_us: self.started_at.elapsed().as_micros() as u64,
+            device: device.to_owned(),
+            direction,
+            data: to_hex(data),
+        };
+
+        // Recording is best effort and must not break the session.
+        let mut output = self.output.lock().unwrap();
+        if let Ok(line) = serde_json::to_string(&entry) {
+            _ = writeln!(output, "{line}");
+            _ = output.flush();
+        }
+    }
+}
+
+/// Single HID transfer in the recorded session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordEntry {
+    /// Time since the start of the recording in microseconds.
+    pub time_us: u64,
+    /// Label of the device (usually its product id).
+    pub device: String,
+    #[serde(rename = "dir")]
+    pub direction: RecordDirection,
+    /// Transferred bytes in hex.
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordDirection {
+    Write,
+    Read,
+}
+
+/// Transport wrapper which logs all transfers to the [`HidRecorder`].
+pub struct RecordingTransport<T> {
+    inner: T,
+    recorder: Arc<HidRecorder>,
+    device: String,
+}
+
+impl<T: HidTransport> RecordingTransport<T> {
+    pub fn new(inner: T, recorder: Arc<HidRecorder>, device: impl Into<String>) -> Self {
+        Self {
+            inner,
+            recorder,
+            device: device.into(),
+        }
+    }
+}
+
+impl<T: HidTransport> HidTransport for RecordingTransport<T> {
+    fn write(&self, data: &[u8]) -> Result<usize> {
+        self.recorder
+            .record(&self.device, RecordDirection::Write, data);
+        self.inner.write(data)
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        let len = self.inner.read_timeout(buffer, timeout_ms)?;
+        if len > 0 {
+            self.recorder
+                .record(&self.device, RecordDirection::Read, &buffer[..len]);
+        }
+        Ok(len)
+    }
+}
+
+/// Shared sink for [`DryRunTransport`]s which prints annotated hex dumps.
+pub struct PacketPrinter {
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+impl std::fmt::Debug for PacketPrinter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PacketPrinter").finish_non_exhaustive()
+    }
+}
+
+impl PacketPrinter {
+    pub fn new(output: impl Write + Send + 'static) -> Arc<Self> {
+        Arc::new(Self {
+            output: Mutex::new(Box::new(output)),
+        })
+    }
+
+    pub fn stdout() -> Arc<Self> {
+        Self::new(std::io::stdout())
+    }
+
+    fn print(&self, device: &str, packet: &[u8]) {
+        let mut dump = format!("{device}: {}\n", describe_packet(packet));
+        for (i, row) in packet.chunks(16).enumerate() {
+            let bytes = row
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            dump += &format!("  {:04x}  {bytes}\n", i * 16);
+        }
+
+        let mut output = self.output.lock().unwrap();
+        _ = output.write_all(dump.as_bytes());
+        _ = output.flush();
+    }
+}
+
+/// Transport which prints the outgoing reports instead of sending them.
+///
+/// Reads fail with [`Error::DryRun`], the device is never accessed.
+pub struct DryRunTransport {
+    printer: Arc<PacketPrinter>,
+    device: String,
+}
+
+impl DryRunTransport {
+    pub fn new(printer: Arc<PacketPrinter>, device: impl Into<String>) -> Self {
+        Self {
+            printer,
+            device: device.into(),
+        }
+    }
+}
+
+impl HidTransport for DryRunTransport {
+    fn write(&self, data: &[u8]) -> Result<usize> {
+        self.printer.print(&self.device, data);
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, _buffer: &mut [u8], _timeout_ms: i32) -> Result<usize> {
+        Err(Error::DryRun)
+    }
+}
+
+/// Transport which plays back a recorded session.
+///
+/// Writes must match the recorded ones byte-for-byte (except ignored
+/// offsets), reads return the recorded replies in order.
+pub struct ReplayTransport {
+    entries: Mutex<VecDeque<(RecordDirection, Vec<u8>)>>,
+    ignored: Vec<Range<usize>>,
+}
+
+impl ReplayTransport {
+    pub fn new(entries: impl IntoIterator<Item = RecordEntry>) -> Result<Self> {
+        let entries = entries
+            .into_iter()
+            .map(|entry| Ok((entry.direction, from_hex(&entry.data)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            entries: Mutex::new(entries),
+            ignored: Vec::new(),
+        })
+    }
+
+    /// Loads the recording, optionally keeping only entries of the device.
+    pub fn from_jsonl(reader: impl BufRead, device: Option<&str>) -> Result<Self> {
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line.map_err(|e| Error::Replay(e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry = serde_json::from_str::<RecordEntry>(&line)
+                .map_err(|e| Error::Replay(format!("invalid record `{line}`: {e}")))?;
+            if device.is_none_or(|device| device == entry.device) {
+                entries.push(entry);
+            }
+        }
+        Self::new(entries)
+    }
+
+    /// Excludes bytes of written reports from the comparison
+    /// (e.g. timing-dependent fields).
+    pub fn ignore_bytes(mut self, range: Range<usize>) -> Self {
+        self.ignored.push(range);
+        self
+    }
+
+    /// Returns the number of transfers which were not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    fn matches(&self, expected: &[u8], actual: &[u8]) -> bool {
+        expected.len() == actual.len()
+            && expected
+                .iter()
+                .zip(actual)
+                .enumerate()
+                .all(|(i, (a, b))| a == b || self.ignored.iter().any(|r| r.contains(&i)))
+    }
+}
+
+impl HidTransport for ReplayTransport {
+    fn write(&self, data: &[u8]) -> Result<usize> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.pop_front() {
+            Some((RecordDirection::Write, expected)) if self.matches(&expected, data) => {
+                Ok(data.len())
+            }
+            Some((RecordDirection::Write, expected)) => Err(Error::Replay(format!(
+                "unexpected write: expected {}, got {}",
+                to_hex(&expected),
+                to_hex(data)
+            ))),
+            Some((RecordDirection::Read, expected)) => Err(Error::Replay(format!(
+                "unexpected write {}, expected a read of {}",
+                to_hex(data),
+                to_hex(&expected)
+            ))),
+            None => Err(Error::Replay(format!(
+                "unexpected write {} after the end of the recording",
+                to_hex(data)
+            ))),
+        }
+    }
+
+    fn read_timeout(&self, buffer: &mut [u8], _timeout_ms: i32) -> Result<usize> {
+        let mut entries = self.entries.lock().unwrap();
+        if !matches!(entries.front(), Some((RecordDirection::Read, _))) {
+            // Nothing was received at this point of the recording.
+            return Ok(0);
+        }
+
+        let (_, data) = entries.pop_front().unwrap();
+        let len = data.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(data: &str) -> Result<Vec<u8>> {
+    if !data.is_ascii() || !data.len().is_multiple_of(2) {
+        return Err(Error::Replay(format!("invalid hex data `{data}`")));
+    }
+
+    (0..data.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&data[i..i + 2], 16)
+                .map_err(|_| Error::Replay(format!("invalid hex data `{data}`")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::{Controller, ControllerOptions, NZXTHue2Controller};
+    use crate::types::Color;
+
+    /// Cloneable in-memory output for the recorder.
+    #[derive(Default, Clone)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn open(transport: impl HidTransport + 'static) -> Result<NZXTHue2Controller<'static>> {
+        NZXTHue2Controller::with_transport(Box::new(transport), 0x2012, "test", 3, 0, true)
+    }
+
+    #[test]
+    fn read_timeouts() {
+        assert_eq!(timeout_ms(Duration::ZERO), 1);
+        assert_eq!(timeout_ms(Duration::from_micros(500)), 1);
+        assert_eq!(timeout_ms(Duration::from_micros(1500)), 2);
+        assert_eq!(timeout_ms(Duration::from_millis(250)), 250);
+        assert_eq!(timeout_ms(Duration::from_secs(u64::MAX)), i32::MAX);
+    }
+
+    #[test]
+    fn report_framing() {
+        let packet = [0x22, 0x10, 0x01, 0x00];
+        for (framing, expected) in [
+            (Framing::Raw, packet.to_vec()),
+            (Framing::ReportId { report_len: 8 }, vec![
+                0x00, 0x22, 0x10, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ]),
+        ] {
+            let mock = MockTransport::new();
+            let transport = FramedTransport::new(mock.clone(), framing);
+            assert_eq!(transport.write(&packet).unwrap(), packet.len());
+            assert_eq!(mock.written(), [expected]);
+
+            // Short writes are still detected.
+            mock.set_write_limit(Some(3));
+            assert!(transport.write(&packet).unwrap() < packet.len());
+        }
+    }
+
+    #[test]
+    fn output_report_len_from_descriptor() {
+        // Vendor-defined page with 64-byte input and output reports.
+        let descriptor = [
+            0x06, 0x72, 0xff, // Usage Page (Vendor 0xff72)
+            0x09, 0xa1, // Usage
+            0xa1, 0x01, // Collection (Application)
+            0x09, 0x10, // Usage
+            0x15, 0x00, // Logical Minimum (0)
+            0x26, 0xff, 0x00, // Logical Maximum (255)
+            0x75, 0x08, // Report Size (8)
+            0x95, 0x40, // Report Count (64)
+            0x81, 0x02, // Input
+            0x09, 0x11, // Usage
+            0x91, 0x02, // Output
+            0xc0, // End Collection
+        ];
+        assert_eq!(output_report_len(&descriptor), Some(64));
+
+        // Input-only descriptors don't declare the output length.
+        assert_eq!(output_report_len(&descriptor[..20]), None);
+        // Truncated items are rejected.
+        assert_eq!(output_report_len(&descriptor[..12]), None);
+    }
+
+    #[test]
+    fn dry_run_dump() {
+        let output = SharedBuffer::default();
+        let transport = DryRunTransport::new(PacketPrinter::new(output.clone()), "RGB (2012)");
+
+        let mut packet = [0u8; 20];
+        packet[..4].copy_from_slice(&[0x22, 0xa0, 0x04, 0x00]);
+        assert_eq!(transport.write(&packet).unwrap(), 20);
+        assert!(matches!(
+            transport.read_timeout(&mut [0u8; 64], 10),
+            Err(Error::DryRun)
+        ));
+
+        let dump = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            dump,
+            "RGB (2012): apply for channels [2] (20 bytes)\n\
+             \x20 0000  22 a0 04 00 00 00 00 00 00 00 00 00 00 00 00 00\n\
+             \x20 0010  00 00 00 00\n"
+        );
+    }
+
+    #[test]
+    fn dry_run_controller() {
+        let mut page = [0u8; 64];
+        page[..2].copy_from_slice(&[0x21, 0x03]);
+        page[0x0f] = 0x13;
+
+        let mock = MockTransport::new();
+        mock.push_response(page);
+
+        let output = SharedBuffer::default();
+        let options = ControllerOptions::new().dry_run(PacketPrinter::new(output.clone()));
+        let controller = NZXTHue2Controller::with_transport_options(
+            Box::new(mock.clone()),
+            0x2012,
+            &("NZXT RGB Controller", 3, 0, true),
+            &options,
+        )
+        .unwrap();
+        controller.set_channel_color(0, Color::RED).unwrap();
+        assert!(matches!(controller.firmware_version(), Err(Error::DryRun)));
+
+        // Only the accessory query reaches the device.
+        assert_eq!(mock.written().len(), 1);
+        let dump = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let headers = dump
+            .lines()
+            .filter(|line| !line.starts_with(' '))
+            .collect::<Vec<_>>();
+        assert_eq!(headers, [
+            "NZXT RGB Controller (2012): direct group 0 for channels [0] (64 bytes)",
+            "NZXT RGB Controller (2012): apply for channels [0] (64 bytes)",
+            "NZXT RGB Controller (2012): firmware query (64 bytes)",
+        ]);
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mock = MockTransport::new();
+        mock.push_response(mock_accessory_page(&[&[0x13]]));
+
+        let output = SharedBuffer::default();
+        let recorder = HidRecorder::new(output.clone());
+        let controller = open(RecordingTransport::new(mock, recorder, "2012")).unwrap();
+        controller.set_channel_color(0, Color::RED).unwrap();
+        drop(controller);
+
+        let recording = output.0.lock().unwrap().clone();
+        let entries = recording
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<RecordEntry>(line).unwrap())
+            .collect::<Vec<_>>();
+        let directions = entries.iter().map(|e| e.direction).collect::<Vec<_>>();
+        assert_eq!(directions, [
+            RecordDirection::Write,
+            RecordDirection::Read,
+            RecordDirection::Write,
+            RecordDirection::Write,
+        ]);
+
+        // The same session replays without errors.
+        let replay = ReplayTransport::from_jsonl(&recording[..], Some("2012")).unwrap();
+        let controller = open(replay).unwrap();
+        controller.set_channel_color(0, Color::RED).unwrap();
+
+        // Different colors are reported as a mismatch.
+        let replay = ReplayTransport::from_jsonl(&recording[..], None).unwrap();
+        let controller = open(replay).unwrap();
+        let result = controller.set_channel_color(0, Color::BLUE);
+        assert!(matches!(result, Err(Error::Replay(_))));
+
+        // Unless the color bytes are ignored.
+        let replay = ReplayTransport::from_jsonl(&recording[..], None)
+            .unwrap()
+            .ignore_bytes(0x04..0x04 + 18 * 3);
+        let controller = open(replay).unwrap();
+        controller.set_channel_color(0, Color::BLUE).unwrap();
+    }
+}
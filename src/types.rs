@@ -1,5 +1,7 @@
 use std::str::FromStr;
 
+use crate::error::{Error, ensure_arg};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct Color([u8; 3]);
@@ -11,6 +13,172 @@ impl Color {
     pub const GREEN: Self = Self::new(0, 0xff, 0);
     pub const BLUE: Self = Self::new(0, 0, 0xff);
 
+    /// Colors which can be parsed by their (case-insensitive) names.
+    ///
+    /// Unlike CSS, `green` is the full intensity green.
+    pub const NAMED: &[(&str, Self)] = &[
+        ("black", Self::BLACK),
+        ("off", Self::BLACK),
+        ("white", Self::WHITE),
+        ("red", Self::RED),
+        ("green", Self::GREEN),
+        ("blue", Self::BLUE),
+        ("cyan", Self::new(0, 0xff, 0xff)),
+        ("magenta", Self::new(0xff, 0, 0xff)),
+        ("yellow", Self::new(0xff, 0xff, 0)),
+        ("orange", Self::new(0xff, 0xa5, 0)),
+        ("purple", Self::new(0x80, 0, 0x80)),
+        ("pink", Self::new(0xff, 0xc0, 0xcb)),
+    ];
+
+    /// Converts hue (in degrees), saturation and value (in `0..=1`) to RGB.
+    ///
+    /// Hue wraps around, saturation and value are clamped.
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let hue = hue.rem_euclid(360.0) / 60.0;
+        let saturation = saturation.clamp(0.0, 1.0);
+        let value = value.clamp(0.0, 1.0);
+
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        let m = value - chroma;
+        let to_byte = |c: f32| ((c + m) * 255.0).round() as u8;
+        Self::new(to_byte(r), to_byte(g), to_byte(b))
+    }
+
+    /// Returns hue (in degrees), saturation and value (in `0..=1`).
+    ///
+    /// Hue of gray colors is zero.
+    pub fn to_hsv(self) -> (f32, f32, f32) {
+        let [r, g, b] = [self.red(), self.green(), self.blue()].map(|c| c as f32 / 255.0);
+        let max = r.max(g).max(b);
+        let chroma = max - r.min(g).min(b);
+
+        let hue = if chroma == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / chroma).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / chroma + 2.0)
+        } else {
+            60.0 * ((r - g) / chroma + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+        (hue, saturation, max)
+    }
+
+    /// Approximates the color of a black body at the temperature (in kelvins).
+    ///
+    /// Uses the Tanner Helland fit, temperatures are clamped to `1000..=12000`.
+    pub fn from_kelvin(kelvin: u32) -> Self {
+        let t = kelvin.clamp(1000, 12000) as f32 / 100.0;
+        let red = match t {
+            ..=66.0 => 255.0,
+            _ => 329.698_73 * (t - 60.0).powf(-0.133_204_76),
+        };
+        let green = match t {
+            ..=66.0 => 99.470_8 * t.ln() - 161.119_57,
+            _ => 288.122_17 * (t - 60.0).powf(-0.075_514_85),
+        };
+        let blue = match t {
+            66.0.. => 255.0,
+            ..=19.0 => 0.0,
+            _ => 138.517_73 * (t - 10.0).ln() - 305.044_8,
+        };
+
+        let to_byte = |c: f32| c.round().clamp(0.0, 255.0) as u8;
+        Self::new(to_byte(red), to_byte(green), to_byte(blue))
+    }
+
+    /// Linearly interpolates between two colors, `t` is clamped to `0..=1`.
+    pub fn lerp(a: Color, b: Color, t: f32) -> Color {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Self::new(
+            mix(a.red(), b.red()),
+            mix(a.green(), b.green()),
+            mix(a.blue(), b.blue()),
+        )
+    }
+
+    /// Interpolates hue, saturation and value between two colors, `t` is clamped
+    /// to `0..=1`.
+    ///
+    /// Hue goes along the shorter arc, gray colors take the hue of the other one.
+    pub fn lerp_hsv(a: Color, b: Color, t: f32) -> Color {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        if t == 0.0 {
+            return a;
+        }
+        if t == 1.0 {
+            return b;
+        }
+
+        let (mut hue_a, sat_a, value_a) = a.to_hsv();
+        let (mut hue_b, sat_b, value_b) = b.to_hsv();
+        if sat_a == 0.0 {
+            hue_a = hue_b;
+        } else if sat_b == 0.0 {
+            hue_b = hue_a;
+        }
+
+        let mut delta = (hue_b - hue_a).rem_euclid(360.0);
+        if delta > 180.0 {
+            delta -= 360.0;
+        }
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        Self::from_hsv(hue_a + delta * t, mix(sat_a, sat_b), mix(value_a, value_b))
+    }
+
+    /// Multiplies all components by the factor (negative factors give black),
+    /// saturating at 255.
+    pub fn scaled(self, factor: f32) -> Self {
+        let factor = if factor.is_nan() {
+            0.0
+        } else {
+            factor.max(0.0)
+        };
+        let scale = |c: u8| (c as f32 * factor).round().min(255.0) as u8;
+        Self::new(scale(self.red()), scale(self.green()), scale(self.blue()))
+    }
+
+    /// Same as [`Color::lerp`] from this color to the other one.
+    pub fn blend(self, other: Color, t: f32) -> Self {
+        Self::lerp(self, other, t)
+    }
+
+    /// Returns the brightest component.
+    pub fn max_component(&self) -> u8 {
+        self.0.into_iter().max().unwrap_or_default()
+    }
+
+    /// Looks up the color in [`Color::NAMED`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMED
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name))
+            .map(|(_, color)| *color)
+    }
+
+    /// Writes the colors in the wire (`G, R, B`) order into the buffer,
+    /// returns the number of bytes written.
+    pub fn write_bytes(colors: &[Color], out: &mut [u8]) -> usize {
+        for (color, chunk) in colors.iter().zip(out.chunks_exact_mut(3)) {
+            chunk.copy_from_slice(&color.0);
+        }
+        colors.len().min(out.len() / 3) * 3
+    }
+
+    #[deprecated(note = "use `Color::write_bytes` instead")]
     pub const fn wrap_slice(colors: &[Color]) -> &[u8] {
         let len = colors.len() * 3;
         // SAFETY: `Color` layout is the same as `[u8; 3]`.
@@ -55,26 +223,1121 @@ impl std::fmt::Display for Color {
     }
 }
 
+/// Accepts `rrggbb` and the `rgb` shorthand (each digit is repeated),
+/// with or without the leading `#`, the names from [`Color::NAMED`],
+/// `rgb(r, g, b)` with components in `0..=255`, `hsv(h, s, v)` with hue
+/// in `0..=360` degrees and saturation and value in `0..=100` percent
+/// and color temperatures like `4500K`.
+///
+/// Strings without `#` are looked up as names first, so a name which is
+/// also a valid hex number must be written with `#` to be parsed as hex.
 impl FromStr for Color {
-    type Err = anyhow::Error;
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(kelvin) = s.strip_suffix(['K', 'k']) {
+            if let Ok(kelvin) = kelvin.trim().parse::<u32>() {
+                return Ok(Self::from_kelvin(kelvin));
+            }
+        }
+        if let Some(args) = function_args(s, "rgb") {
+            let mut rgb = [0u8; 3];
+            for ((name, part), value) in ["red", "green", "blue"]
+                .into_iter()
+                .zip(split_components(s, args)?)
+                .zip(&mut rgb)
+            {
+                *value = part.parse().map_err(|_| {
+                    Error::InvalidColor(format!(
+                        "invalid {name} component `{part}`, expected an integer in 0..=255"
+                    ))
+                })?;
+            }
+            let [r, g, b] = rgb;
+            return Ok(Self::new(r, g, b));
+        }
+        if let Some(args) = function_args(s, "hsv") {
+            let mut hsv = [0f32; 3];
+            for (((name, max), part), value) in [("hue", 360), ("saturation", 100), ("value", 100)]
+                .into_iter()
+                .zip(split_components(s, args)?)
+                .zip(&mut hsv)
+            {
+                *value = part
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|value| (0.0..=max as f32).contains(value))
+                    .ok_or_else(|| {
+                        Error::InvalidColor(format!(
+                            "invalid {name} component `{part}`, expected a number in 0..={max}"
+                        ))
+                    })?;
+            }
+            let [h, s, v] = hsv;
+            return Ok(Self::from_hsv(h, s / 100.0, v / 100.0));
+        }
+
+        let (digits, offset) = match s.strip_prefix("#") {
+            None => match Self::from_name(s) {
+                Some(color) => return Ok(color),
+                None => (s, 0),
+            },
+            Some(digits) => (digits, 1),
+        };
+
+        let digits = digits.chars().collect::<Vec<_>>();
+        let digit = |index: usize| {
+            let c = digits[index];
+            c.to_digit(16).map(|d| d as u8).ok_or_else(|| {
+                Error::InvalidColor(format!(
+                    "invalid hex digit `{c}` at position {} in `{s}`",
+                    offset + index + 1
+                ))
+            })
+        };
+
+        let mut rgb = [0u8; 3];
+        match digits.len() {
+            6 => {
+                for (i, component) in rgb.iter_mut().enumerate() {
+                    *component = digit(i * 2)? << 4 | digit(i * 2 + 1)?;
+                }
+            }
+            3 => {
+                for (i, component) in rgb.iter_mut().enumerate() {
+                    *component = digit(i)? * 0x11;
+                }
+            }
+            _ => {
+                return Err(Error::InvalidColor(format!(
+                    "expected 3 or 6 hex digits, got `{s}`"
+                )));
+            }
+        }
+
+        let [r, g, b] = rgb;
+        Ok(Self::new(r, g, b))
+    }
+}
+
+/// See [`Color::scaled`].
+impl std::ops::Mul<f32> for Color {
+    type Output = Color;
+
+    fn mul(self, factor: f32) -> Self::Output {
+        self.scaled(factor)
+    }
+}
+
+/// Serialized as a `#rrggbb` string.
+impl serde::Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts all formats supported by [`Color::from_str`].
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// How colors between the stops of a [`ColorRamp`] are mixed.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Each RGB component separately (see [`Color::lerp`]).
+    #[default]
+    Rgb,
+    /// Along the hue circle (see [`Color::lerp_hsv`]), which keeps the colors
+    /// saturated (e.g. blue to red goes through magenta instead of dark purple).
+    Hsv,
+}
+
+impl Interpolation {
+    pub const ALL: [Self; 2] = [Self::Rgb, Self::Hsv];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Rgb => "rgb",
+            Self::Hsv => "hsv",
+        }
+    }
+
+    /// Mixes the colors, `t` is clamped to `0..=1`.
+    pub fn mix(&self, a: Color, b: Color, t: f32) -> Color {
+        match self {
+            Self::Rgb => Color::lerp(a, b, t),
+            Self::Hsv => Color::lerp_hsv(a, b, t),
+        }
+    }
+}
+
+impl std::fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Interpolation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|interpolation| interpolation.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "unknown interpolation `{s}`, expected one of: rgb, hsv"
+                ))
+            })
+    }
+}
+
+impl clap::ValueEnum for Interpolation {
+    fn value_variants<'a>() -> &'a [Self] {
+        &Self::ALL
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.name()))
+    }
+}
+
+/// Gradient defined by colors at increasing positions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorRamp {
+    stops: Vec<(f32, Color)>,
+    interpolation: Interpolation,
+}
+
+impl ColorRamp {
+    /// Stops must be non-empty and sorted by their (finite) positions.
+    pub fn new(stops: Vec<(f32, Color)>) -> Result<Self, Error> {
+        ensure_arg!(!stops.is_empty(), "color ramp must have at least one stop");
+        ensure_arg!(
+            stops.iter().all(|(position, _)| position.is_finite()),
+            "color ramp positions must be finite"
+        );
+        ensure_arg!(
+            stops.windows(2).all(|pair| pair[0].0 <= pair[1].0),
+            "color ramp stops must be sorted by their positions"
+        );
+        Ok(Self {
+            stops,
+            interpolation: Interpolation::Rgb,
+        })
+    }
+
+    /// Spreads the colors evenly over `0..=1`.
+    pub fn uniform(colors: &[Color]) -> Result<Self, Error> {
+        let last = colors.len().saturating_sub(1).max(1) as f32;
+        let stops = colors
+            .iter()
+            .enumerate()
+            .map(|(i, color)| (i as f32 / last, *color))
+            .collect();
+        Self::new(stops)
+    }
+
+    pub fn stops(&self) -> &[(f32, Color)] {
+        &self.stops
+    }
+
+    /// Sets how colors between the stops are mixed (RGB by default).
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// Returns the color at the position, which is clamped to the range
+    /// of the stops.
+    pub fn sample(&self, t: f32) -> Color {
+        let (first, last) = (self.stops[0], self.stops[self.stops.len() - 1]);
+        if t.is_nan() || t <= first.0 {
+            return first.1;
+        }
+        if t >= last.0 {
+            return last.1;
+        }
+
+        let next = self.stops.partition_point(|(position, _)| *position <= t);
+        let (from, to) = (self.stops[next - 1], self.stops[next]);
+        self.interpolation
+            .mix(from.1, to.1, (t - from.0) / (to.0 - from.0))
+    }
+
+    /// Maps the value from `low..=high` to the `0..=1` positions of the ramp
+    /// (e.g. a temperature between the base and the warn thresholds).
+    ///
+    /// Values outside of the range get the edge colors. If the range is empty,
+    /// values below `low` get the first color and the rest get the last one.
+    pub fn sample_range(&self, value: f32, low: f32, high: f32) -> Color {
+        if high <= low {
+            return self.sample(if value < low {
+                f32::NEG_INFINITY
+            } else {
+                f32::INFINITY
+            });
+        }
+        let t = (value.clamp(low, high) - low) / (high - low);
+        self.sample(t)
+    }
+}
+
+/// Parses comma-separated `position:color` stops (e.g. `30:#0044ff,85:red`).
+///
+/// Colors accept all formats supported by [`Color::from_str`].
+impl FromStr for ColorRamp {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut stops = Vec::<(f32, Color)>::new();
+        for (index, stop) in split_top_level(s).into_iter().enumerate() {
+            let number = index + 1;
+            let invalid = |reason: String| {
+                Error::InvalidArgument(format!(
+                    "invalid color ramp stop {number} `{stop}`: {reason}"
+                ))
+            };
+
+            let (position, color) = stop
+                .split_once(':')
+                .ok_or_else(|| invalid("expected `position:color`".to_owned()))?;
+            let position = position
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|position| position.is_finite())
+                .ok_or_else(|| invalid(format!("invalid position `{}`", position.trim())))?;
+            let color = color
+                .trim()
+                .parse::<Color>()
+                .map_err(|e| invalid(e.to_string()))?;
+
+            if let Some(&(previous, _)) = stops.last() {
+                if position < previous {
+                    return Err(invalid(format!(
+                        "position is below the previous stop at {previous}"
+                    )));
+                }
+            }
+            stops.push((position, color));
+        }
+        Self::new(stops)
+    }
+}
+
+/// Fan duty cycles in percent at increasing temperatures (in degrees celsius).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FanCurve {
+    points: Vec<(f32, u8)>,
+}
+
+impl FanCurve {
+    /// Points must be non-empty and sorted by their (finite) temperatures,
+    /// duty cycles must not exceed 100%.
+    pub fn new(points: Vec<(f32, u8)>) -> Result<Self, Error> {
+        ensure_arg!(!points.is_empty(), "fan curve must have at least one point");
+        ensure_arg!(
+            points.iter().all(|(temp, _)| temp.is_finite()),
+            "fan curve temperatures must be finite"
+        );
+        ensure_arg!(
+            points.windows(2).all(|pair| pair[0].0 <= pair[1].0),
+            "fan curve points must be sorted by their temperatures"
+        );
+        ensure_arg!(
+            points.iter().all(|&(_, duty)| duty <= 100),
+            "fan curve duty cycles must be at most 100%"
+        );
+        Ok(Self { points })
+    }
+
+    pub fn points(&self) -> &[(f32, u8)] {
+        &self.points
+    }
+
+    /// Returns the duty cycle at the temperature, interpolated between
+    /// the points and clamped to the first and the last ones.
+    pub fn duty(&self, celsius: f32) -> u8 {
+        let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+        if celsius.is_nan() || celsius <= first.0 {
+            return first.1;
+        }
+        if celsius >= last.0 {
+            return last.1;
+        }
+
+        let next = self.points.partition_point(|(temp, _)| *temp <= celsius);
+        let (from, to) = (self.points[next - 1], self.points[next]);
+        let t = (celsius - from.0) / (to.0 - from.0);
+        (from.1 as f32 + (to.1 as f32 - from.1 as f32) * t).round() as u8
+    }
+}
+
+/// Parses comma-separated `temperature:percent` points (e.g. `30:20,60:50,80:100`).
+impl FromStr for FanCurve {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut points = Vec::<(f32, u8)>::new();
+        for (index, point) in split_top_level(s).into_iter().enumerate() {
+            let number = index + 1;
+            let invalid = |reason: String| {
+                Error::InvalidArgument(format!(
+                    "invalid fan curve point {number} `{point}`: {reason}"
+                ))
+            };
+
+            let (temp, duty) = point
+                .split_once(':')
+                .ok_or_else(|| invalid("expected `temperature:percent`".to_owned()))?;
+            let temp = temp
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|temp| temp.is_finite())
+                .ok_or_else(|| invalid(format!("invalid temperature `{}`", temp.trim())))?;
+            let duty = duty.trim();
+            let duty = duty
+                .strip_suffix('%')
+                .unwrap_or(duty)
+                .parse::<u8>()
+                .ok()
+                .filter(|duty| *duty <= 100)
+                .ok_or_else(|| invalid(format!("invalid duty cycle `{duty}`")))?;
+
+            if let Some(&(previous, _)) = points.last() {
+                if temp < previous {
+                    return Err(invalid(format!(
+                        "temperature is below the previous point at {previous}"
+                    )));
+                }
+            }
+            points.push((temp, duty));
+        }
+        Self::new(points)
+    }
+}
+
+/// Splits by commas which are not inside parentheses (e.g. of `rgb(...)`).
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(s[start..].trim());
+    parts.retain(|part| !part.is_empty());
+    parts
+}
+
+/// Returns arguments of `name(...)` (the name is case-insensitive).
+fn function_args<'s>(s: &'s str, name: &str) -> Option<&'s str> {
+    let s = s.trim();
+    let (prefix, rest) = s.split_at_checked(name.len())?;
+    if !prefix.eq_ignore_ascii_case(name) {
+        return None;
+    }
+    rest.trim_start().strip_prefix('(')?.strip_suffix(')')
+}
+
+fn split_components<'s>(s: &str, args: &'s str) -> Result<[&'s str; 3], Error> {
+    let parts = args.split(',').map(str::trim).collect::<Vec<_>>();
+    <[&str; 3]>::try_from(parts)
+        .map_err(|_| Error::InvalidColor(format!("expected 3 components, got `{s}`")))
+}
+
+/// Order of color components expected by the LEDs.
+///
+/// Most Hue 2 accessories expect [`ColorOrder::Grb`],
+/// third-party strips are often wired as RGB.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorOrder {
+    Rgb,
+    Rbg,
+    #[default]
+    Grb,
+    Gbr,
+    Brg,
+    Bgr,
+}
+
+impl ColorOrder {
+    pub const ALL: [Self; 6] = [
+        Self::Rgb,
+        Self::Rbg,
+        Self::Grb,
+        Self::Gbr,
+        Self::Brg,
+        Self::Bgr,
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Rgb => "rgb",
+            Self::Rbg => "rbg",
+            Self::Grb => "grb",
+            Self::Gbr => "gbr",
+            Self::Brg => "brg",
+            Self::Bgr => "bgr",
+        }
+    }
+
+    /// Returns color components in the order they are sent to the device.
+    pub const fn encode(&self, color: Color) -> [u8; 3] {
+        let (r, g, b) = (color.red(), color.green(), color.blue());
+        match self {
+            Self::Rgb => [r, g, b],
+            Self::Rbg => [r, b, g],
+            Self::Grb => [g, r, b],
+            Self::Gbr => [g, b, r],
+            Self::Brg => [b, r, g],
+            Self::Bgr => [b, g, r],
+        }
+    }
+
+    /// Writes encoded colors into the buffer, returns the number of bytes written.
+    pub fn encode_into(&self, colors: &[Color], buffer: &mut [u8]) -> usize {
+        for (color, chunk) in colors.iter().zip(buffer.chunks_exact_mut(3)) {
+            chunk.copy_from_slice(&self.encode(*color));
+        }
+        colors.len().min(buffer.len() / 3) * 3
+    }
+}
+
+impl std::fmt::Display for ColorOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
 
-    fn from_str(mut s: &str) -> Result<Self, Self::Err> {
-        s = match s.strip_prefix("#") {
-            None => s,
-            Some(s) => s,
+impl FromStr for ColorOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|order| order.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!(
+                    "unknown color order `{s}`, expected one of: rgb, rbg, grb, gbr, brg, bgr"
+                ))
+            })
+    }
+}
+
+/// Set of RGB channels selected on the command line.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum ChannelSelector {
+    /// All channels of the controller.
+    #[default]
+    All,
+    /// Explicit channel indices without duplicates, in the specified order.
+    Channels(Vec<usize>),
+}
+
+impl ChannelSelector {
+    /// Returns the selected channel indices for a controller
+    /// with `available` channels.
+    pub fn resolve(&self, available: usize) -> Result<Vec<usize>, Error> {
+        match self {
+            Self::All => Ok((0..available).collect()),
+            Self::Channels(channels) => {
+                if let Some(&requested) = channels.iter().find(|&&channel| channel >= available) {
+                    return Err(Error::InvalidChannel {
+                        requested,
+                        available,
+                    });
+                }
+                Ok(channels.clone())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ChannelSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => f.write_str("all"),
+            Self::Channels(channels) => {
+                for (i, channel) in channels.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{channel}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Serialized as a string, e.g. `all` or `0,2,3`.
+impl serde::Serialize for ChannelSelector {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts all formats supported by [`ChannelSelector::from_str`].
+impl<'de> serde::Deserialize<'de> for ChannelSelector {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Accepts `all` or a comma-separated list of indices and inclusive
+/// ranges (e.g. `0,2-4`).
+impl FromStr for ChannelSelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(Self::All);
+        }
+
+        let invalid = |part: &str| {
+            Error::InvalidArgument(format!(
+                "invalid channel `{part}` in `{s}`, expected `all`, an index or a range (e.g. `1-3`)"
+            ))
         };
+        let parse_index = |part: &str| part.trim().parse::<usize>().map_err(|_| invalid(part));
+
+        let mut channels = Vec::new();
+        for part in s.split(',') {
+            let range = match part.split_once('-') {
+                Some((start, end)) => parse_index(start)?..=parse_index(end)?,
+                None => {
+                    let index = parse_index(part)?;
+                    index..=index
+                }
+            };
+            ensure_arg!(
+                !range.is_empty(),
+                "invalid channel range `{part}` in `{s}`, the start is past the end"
+            );
+            for channel in range {
+                if !channels.contains(&channel) {
+                    channels.push(channel);
+                }
+            }
+        }
+        Ok(Self::Channels(channels))
+    }
+}
+
+/// Gamma correction and brightness scaling of the color components.
+///
+/// The transform is precomputed into a lookup table, so applying it
+/// costs a single table access per byte.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorTransform {
+    gamma: f32,
+    brightness: f32,
+    lut: [u8; 256],
+}
+
+impl ColorTransform {
+    pub const IDENTITY: Self = Self {
+        gamma: 1.0,
+        brightness: 1.0,
+        lut: identity_lut(),
+    };
+
+    /// Values are mapped as `255 * (v / 255) ^ gamma * brightness`.
+    ///
+    /// `gamma` must be positive and `brightness` must be in range `0..=1`.
+    pub fn new(gamma: f32, brightness: f32) -> Result<Self, Error> {
+        if !(gamma.is_finite() && gamma > 0.0) {
+            return Err(Error::InvalidArgument(format!(
+                "invalid gamma {gamma}, must be a positive number"
+            )));
+        }
+        if !(0.0..=1.0).contains(&brightness) {
+            return Err(Error::InvalidArgument(format!(
+                "invalid brightness {brightness}, must be in range 0..=1"
+            )));
+        }
+
+        let mut lut = [0u8; 256];
+        for (i, value) in lut.iter_mut().enumerate() {
+            let normalized = i as f32 / 255.0;
+            *value = (normalized.powf(gamma) * brightness * 255.0).round() as u8;
+        }
+        Ok(Self {
+            gamma,
+            brightness,
+            lut,
+        })
+    }
+
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    pub fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.lut == Self::IDENTITY.lut
+    }
+
+    pub fn apply(&self, value: u8) -> u8 {
+        self.lut[value as usize]
+    }
+
+    pub fn apply_slice(&self, values: &mut [u8]) {
+        for value in values {
+            *value = self.lut[*value as usize];
+        }
+    }
+}
+
+impl Default for ColorTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+const fn identity_lut() -> [u8; 256] {
+    let mut lut = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        lut[i] = i as u8;
+        i += 1;
+    }
+    lut
+}
+
+/// Type of the accessory connected to the RGB channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceType {
+    Hue1Strip,
+    Aer1Fan,
+    Hue2Strip10,
+    Hue2Strip8,
+    Hue2Strip6,
+    Hue2CableComb,
+    Hue2Underglow300,
+    Hue2Underglow200,
+    Aer2Fan120,
+    Aer2Fan140,
+    KrakenX3Ring,
+    KrakenX3Logo,
+    F120RgbFan,
+    F140RgbFan,
+    F120RgbDuoFan,
+    F140RgbDuoFan,
+    F120RgbCoreFan,
+    F140RgbCoreFan,
+    F120RgbCoreCaseFan,
+    F360RgbCoreCaseFan,
+    KrakenEliteRing,
+    /// Accessory with an unknown id (or an empty slot for `0x00`).
+    Unknown(u8),
+}
+
+impl DeviceType {
+    /// All accessory types except [`DeviceType::Unknown`].
+    pub const KNOWN: [Self; 21] = [
+        Self::Hue1Strip,
+        Self::Aer1Fan,
+        Self::Hue2Strip10,
+        Self::Hue2Strip8,
+        Self::Hue2Strip6,
+        Self::Hue2CableComb,
+        Self::Hue2Underglow300,
+        Self::Hue2Underglow200,
+        Self::Aer2Fan120,
+        Self::Aer2Fan140,
+        Self::KrakenX3Ring,
+        Self::KrakenX3Logo,
+        Self::F120RgbFan,
+        Self::F140RgbFan,
+        Self::F120RgbDuoFan,
+        Self::F140RgbDuoFan,
+        Self::F120RgbCoreFan,
+        Self::F140RgbCoreFan,
+        Self::F120RgbCoreCaseFan,
+        Self::F360RgbCoreCaseFan,
+        Self::KrakenEliteRing,
+    ];
+
+    pub const fn id(&self) -> u8 {
+        match self {
+            Self::Hue1Strip => 0x01,
+            Self::Aer1Fan => 0x02,
+            Self::Hue2Strip10 => 0x04,
+            Self::Hue2Strip8 => 0x05,
+            Self::Hue2Strip6 => 0x06,
+            Self::Hue2CableComb => 0x08,
+            Self::Hue2Underglow300 => 0x09,
+            Self::Hue2Underglow200 => 0x0a,
+            Self::Aer2Fan120 => 0x0b,
+            Self::Aer2Fan140 => 0x0c,
+            Self::KrakenX3Ring => 0x10,
+            Self::KrakenX3Logo => 0x11,
+            Self::F120RgbFan => 0x13,
+            Self::F140RgbFan => 0x14,
+            Self::F120RgbDuoFan => 0x15,
+            Self::F140RgbDuoFan => 0x16,
+            Self::F120RgbCoreFan => 0x17,
+            Self::F140RgbCoreFan => 0x18,
+            Self::F120RgbCoreCaseFan => 0x19,
+            Self::F360RgbCoreCaseFan => 0x1d,
+            Self::KrakenEliteRing => 0x1e,
+            Self::Unknown(id) => *id,
+        }
+    }
+
+    /// Number of LEDs of the accessory (`0` for unknown ones).
+    pub const fn led_count(&self) -> u8 {
+        match self {
+            Self::Hue1Strip => 10,
+            Self::Aer1Fan => 8,
+            Self::Hue2Strip10 => 10,
+            Self::Hue2Strip8 => 8,
+            Self::Hue2Strip6 => 6,
+            Self::Hue2CableComb => 14,
+            Self::Hue2Underglow300 => 15,
+            Self::Hue2Underglow200 => 10,
+            Self::Aer2Fan120 => 8,
+            Self::Aer2Fan140 => 8,
+            Self::KrakenX3Ring => 8,
+            Self::KrakenX3Logo => 1,
+            Self::F120RgbFan => 18,
+            Self::F140RgbFan => 18,
+            Self::F120RgbDuoFan => 20,
+            Self::F140RgbDuoFan => 20,
+            Self::F120RgbCoreFan => 8,
+            Self::F140RgbCoreFan => 8,
+            Self::F120RgbCoreCaseFan => 8,
+            Self::F360RgbCoreCaseFan => 24,
+            Self::KrakenEliteRing => 24,
+            Self::Unknown(_) => 0,
+        }
+    }
+
+    /// Layout of the accessory with the specified number of LEDs.
+    pub const fn layout_with(&self, leds: u8) -> Layout {
+        match self {
+            Self::Aer1Fan
+            | Self::Aer2Fan120
+            | Self::Aer2Fan140
+            | Self::KrakenX3Ring
+            | Self::F120RgbFan
+            | Self::F140RgbFan
+            | Self::F120RgbDuoFan
+            | Self::F140RgbDuoFan
+            | Self::F120RgbCoreFan
+            | Self::F140RgbCoreFan
+            | Self::F120RgbCoreCaseFan
+            | Self::KrakenEliteRing => Layout::Ring { leds },
+            // Three fans in a row are approximated as a strip.
+            Self::F360RgbCoreCaseFan
+            | Self::Hue1Strip
+            | Self::Hue2Strip10
+            | Self::Hue2Strip8
+            | Self::Hue2Strip6
+            | Self::Hue2CableComb
+            | Self::Hue2Underglow300
+            | Self::Hue2Underglow200
+            | Self::KrakenX3Logo
+            | Self::Unknown(_) => Layout::Strip { leds },
+        }
+    }
 
-        if s.len() != 6 {
-            anyhow::bail!("invalid color string length");
+    pub const fn layout(&self) -> Layout {
+        self.layout_with(self.led_count())
+    }
+
+    /// Human readable name of the accessory.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Hue1Strip => "Hue 1 strip",
+            Self::Aer1Fan => "Aer 1 fan",
+            Self::Hue2Strip10 => "Hue 2 strip (10 LEDs)",
+            Self::Hue2Strip8 => "Hue 2 strip (8 LEDs)",
+            Self::Hue2Strip6 => "Hue 2 strip (6 LEDs)",
+            Self::Hue2CableComb => "Hue 2 Cable Comb (14 LEDs)",
+            Self::Hue2Underglow300 => "Hue 2 Underglow (300mm) (15 LEDs)",
+            Self::Hue2Underglow200 => "Hue 2 Underglow (200mm) (10 LEDs)",
+            Self::Aer2Fan120 => "Aer 2 fan (120mm)",
+            Self::Aer2Fan140 => "Aer 2 fan (140mm)",
+            Self::KrakenX3Ring => "Kraken X3 ring",
+            Self::KrakenX3Logo => "Kraken X3 logo",
+            Self::F120RgbFan => "F120 RGB fan (120mm)",
+            Self::F140RgbFan => "F140 RGB fan (140mm)",
+            Self::F120RgbDuoFan => "F120 RGB Duo fan (120mm)",
+            Self::F140RgbDuoFan => "F140 RGB Duo fan (140mm)",
+            Self::F120RgbCoreFan => "F120 RGB Core fan (120mm)",
+            Self::F140RgbCoreFan => "F140 RGB Core fan (140mm)",
+            Self::F120RgbCoreCaseFan => "F120 RGB Core fan case version (120mm)",
+            Self::F360RgbCoreCaseFan => "F360 RGB Core Fan Case Version (360mm)",
+            Self::KrakenEliteRing => "Kraken Elite Ring",
+            Self::Unknown(_) => "<unknown>",
+        }
+    }
+
+    /// Stable machine readable identifier of the accessory type.
+    pub const fn type_name(&self) -> &'static str {
+        match self {
+            Self::Hue1Strip => "hue1-strip",
+            Self::Aer1Fan => "aer1-fan",
+            Self::Hue2Strip10 => "hue2-strip-10",
+            Self::Hue2Strip8 => "hue2-strip-8",
+            Self::Hue2Strip6 => "hue2-strip-6",
+            Self::Hue2CableComb => "hue2-cable-comb",
+            Self::Hue2Underglow300 => "hue2-underglow-300",
+            Self::Hue2Underglow200 => "hue2-underglow-200",
+            Self::Aer2Fan120 => "aer2-fan-120",
+            Self::Aer2Fan140 => "aer2-fan-140",
+            Self::KrakenX3Ring => "kraken-x3-ring",
+            Self::KrakenX3Logo => "kraken-x3-logo",
+            Self::F120RgbFan => "f120-rgb-fan",
+            Self::F140RgbFan => "f140-rgb-fan",
+            Self::F120RgbDuoFan => "f120-rgb-duo-fan",
+            Self::F140RgbDuoFan => "f140-rgb-duo-fan",
+            Self::F120RgbCoreFan => "f120-rgb-core-fan",
+            Self::F140RgbCoreFan => "f140-rgb-core-fan",
+            Self::F120RgbCoreCaseFan => "f120-rgb-core-case-fan",
+            Self::F360RgbCoreCaseFan => "f360-rgb-core-case-fan",
+            Self::KrakenEliteRing => "kraken-elite-ring",
+            Self::Unknown(_) => "unknown",
+        }
+    }
+}
+
+/// Static description of a known accessory type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct AccessoryInfo {
+    #[serde(skip)]
+    pub device_type: DeviceType,
+    /// Raw id reported by the controller.
+    pub id: u8,
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub type_name: &'static str,
+    pub led_count: u8,
+}
+
+impl AccessoryInfo {
+    pub const fn new(device_type: DeviceType) -> Self {
+        Self {
+            device_type,
+            id: device_type.id(),
+            name: device_type.name(),
+            type_name: device_type.type_name(),
+            led_count: device_type.led_count(),
+        }
+    }
+}
+
+static KNOWN_ACCESSORIES: [AccessoryInfo; DeviceType::KNOWN.len()] = {
+    let mut table = [AccessoryInfo::new(DeviceType::Unknown(0)); DeviceType::KNOWN.len()];
+    let mut i = 0;
+    while i < table.len() {
+        table[i] = AccessoryInfo::new(DeviceType::KNOWN[i]);
+        i += 1;
+    }
+    table
+};
+
+/// Lists every accessory type recognized in the channel reports, ordered by id.
+pub fn known_accessories() -> &'static [AccessoryInfo] {
+    &KNOWN_ACCESSORIES
+}
+
+/// Physical arrangement of the accessory LEDs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// LEDs go clockwise starting from the top (fans, pump heads).
+    Ring { leds: u8 },
+    /// LEDs go from left to right.
+    Strip { leds: u8 },
+}
+
+impl Layout {
+    pub const fn leds(&self) -> u8 {
+        match self {
+            Self::Ring { leds } | Self::Strip { leds } => *leds,
+        }
+    }
+
+    /// Returns the position of the LED within the accessory.
+    ///
+    /// Coordinates are normalized to `0..=1` with `y` pointing down.
+    pub fn position(&self, index: u8) -> Option<LedPosition> {
+        if index >= self.leds() {
+            return None;
+        }
+
+        Some(match *self {
+            Self::Ring { leds } => {
+                let angle = 360.0 * index as f32 / leds as f32;
+                let (sin, cos) = angle.to_radians().sin_cos();
+                LedPosition {
+                    device: 0,
+                    x: 0.5 + 0.5 * sin,
+                    y: 0.5 - 0.5 * cos,
+                    angle: Some(angle),
+                }
+            }
+            Self::Strip { leds } => LedPosition {
+                device: 0,
+                x: match leds {
+                    1 => 0.5,
+                    _ => index as f32 / (leds - 1) as f32,
+                },
+                y: 0.5,
+                angle: None,
+            },
+        })
+    }
+}
+
+/// Position of a single LED on the channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LedPosition {
+    /// Index of the accessory on the channel.
+    pub device: usize,
+    /// Horizontal position, accessories are placed side by side,
+    /// so the n-th accessory occupies `n..=n + 1`.
+    pub x: f32,
+    pub y: f32,
+    /// Clockwise angle from the top in degrees (for ring layouts).
+    pub angle: Option<f32>,
+}
+
+/// Firmware version of the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl Version {
+    pub const fn new(major: u8, minor: u8, patch: u8) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Decodes the 0x11 0x01 firmware report.
+    pub fn from_report(report: &[u8]) -> Result<Self, Error> {
+        const VERSION: std::ops::Range<usize> = 0x11..0x14;
+
+        if report.len() < VERSION.end {
+            return Err(Error::ShortRead {
+                expected: VERSION.end,
+                read: report.len(),
+            });
+        }
+        if report[..2] != [0x11, 0x01] {
+            return Err(Error::InvalidArgument(format!(
+                "unexpected firmware report header {:02x} {:02x}",
+                report[0], report[1]
+            )));
         }
+        let [major, minor, patch] = [report[0x11], report[0x12], report[0x13]];
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Serialized as a `major.minor.patch` string.
+impl serde::Serialize for Version {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts all formats supported by [`Version::from_str`].
+impl<'de> serde::Deserialize<'de> for Version {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Accepts `major[.minor[.patch]]`, missing components are zero.
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidArgument(format!("invalid version `{s}`"));
 
-        s = s.trim_start_matches("0");
-        if s.is_empty() {
-            s = "0";
+        let mut components = [0u8; 3];
+        let mut parts = s.trim().split('.');
+        for (i, component) in components.iter_mut().enumerate() {
+            match parts.next() {
+                Some(part) => *component = part.parse().map_err(|_| invalid())?,
+                None if i > 0 => break,
+                None => return Err(invalid()),
+            }
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
         }
 
-        let [_, r, g, b] = u32::from_str_radix(s, 16)?.to_be_bytes();
-        Ok(Self([g, r, b]))
+        let [major, minor, patch] = components;
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl Default for DeviceType {
+    #[inline]
+    fn default() -> Self {
+        Self::Unknown(0x00)
+    }
+}
+
+impl From<u8> for DeviceType {
+    fn from(id: u8) -> Self {
+        known_accessories()
+            .iter()
+            .find(|accessory| accessory.id == id)
+            .map_or(Self::Unknown(id), |accessory| accessory.device_type)
+    }
+}
+
+impl From<DeviceType> for u8 {
+    #[inline]
+    fn from(value: DeviceType) -> Self {
+        value.id()
+    }
+}
+
+impl std::fmt::Display for DeviceType {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
     }
 }
 
@@ -95,9 +1358,575 @@ mod tests {
             ("#00ff00", Color::GREEN),
             ("#0000ff", Color::BLUE),
             ("#200800", Color::new(32, 8, 0)),
+            ("#f00", Color::RED),
+            ("0f0", Color::GREEN),
+            ("#FfF", Color::WHITE),
+            ("#000", Color::BLACK),
+            ("#1a2", Color::new(0x11, 0xaa, 0x22)),
+            ("#AbCdEf", Color::new(0xab, 0xcd, 0xef)),
         ] {
             let color = str.parse::<Color>().unwrap();
             assert_eq!(color, expected);
         }
+
+        for str in [
+            "#ff", "#ffff", "ff00000", "#12345", "#1234567", "0x112233", "#",
+        ] {
+            assert!(str.parse::<Color>().is_err(), "{str}");
+        }
+        for (str, message) in [
+            ("#g00", "`g` at position 2 in `#g00`"),
+            ("#0g0000", "`g` at position 3 in `#0g0000`"),
+            ("00000g", "`g` at position 6 in `00000g`"),
+            ("#zz0000", "`z` at position 2 in `#zz0000`"),
+            ("#12345é", "`é` at position 7"),
+        ] {
+            let error = str.parse::<Color>().unwrap_err().to_string();
+            assert!(error.contains(message), "{error}");
+        }
+        let error = "#ffff".parse::<Color>().unwrap_err().to_string();
+        assert!(error.contains("3 or 6"), "{error}");
+    }
+
+    #[test]
+    fn parse_color_functions() {
+        for (str, expected) in [
+            ("rgb(255, 128, 0)", Color::new(255, 128, 0)),
+            ("RGB( 1,2 ,3 )", Color::new(1, 2, 3)),
+            ("hsv(30, 100, 100)", Color::new(255, 128, 0)),
+            ("hsv(0,0,0)", Color::BLACK),
+            ("hsv(360, 100, 100)", Color::RED),
+            ("hsv(210, 50, 40)", Color::new(51, 77, 102)),
+        ] {
+            assert_eq!(str.parse::<Color>().unwrap(), expected, "{str}");
+        }
+
+        for (str, component) in [
+            ("rgb(256, 0, 0)", "red"),
+            ("rgb(0, -1, 0)", "green"),
+            ("rgb(0, 0, 1.5)", "blue"),
+            ("hsv(361, 0, 0)", "hue"),
+            ("hsv(0, 101, 0)", "saturation"),
+            ("hsv(0, 0, x)", "value"),
+        ] {
+            let error = str.parse::<Color>().unwrap_err().to_string();
+            assert!(error.contains(component), "{error}");
+        }
+        assert!("rgb(1, 2)".parse::<Color>().is_err());
+        assert!("rgb(1, 2, 3".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn color_serde() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Config {
+            color: Color,
+        }
+
+        let config = Config {
+            color: Color::new(0xff, 0x88, 0x00),
+        };
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json, serde_json::json!({ "color": "#ff8800" }));
+        assert_eq!(serde_json::from_value::<Config>(json).unwrap(), config);
+
+        for str in ["\"red\"", "\"#f80\"", "\"rgb(255, 136, 0)\"", "\"ff8800\""] {
+            let color = serde_json::from_str::<Color>(str).unwrap();
+            assert!(color == Color::RED || color == config.color, "{str}");
+        }
+        // The internal GRB order doesn't leak.
+        assert_eq!(serde_json::to_string(&Color::GREEN).unwrap(), "\"#00ff00\"");
+        assert!(serde_json::from_str::<Color>("\"#ff88\"").is_err());
+        assert!(serde_json::from_str::<Color>("[255, 0, 0]").is_err());
+    }
+
+    #[test]
+    fn color_lerp() {
+        let a = Color::new(0, 100, 255);
+        let b = Color::new(255, 0, 255);
+        assert_eq!(Color::lerp(a, b, 0.0), a);
+        assert_eq!(Color::lerp(a, b, 1.0), b);
+        assert_eq!(Color::lerp(a, b, 0.5), Color::new(128, 50, 255));
+        assert_eq!(Color::lerp(a, b, -1.0), a);
+        assert_eq!(Color::lerp(a, b, 2.0), b);
+    }
+
+    #[test]
+    fn color_wire_bytes() {
+        let colors = [Color::new(1, 2, 3), Color::new(0xaa, 0xbb, 0xcc)];
+
+        let mut out = [0u8; 7];
+        assert_eq!(Color::write_bytes(&colors, &mut out), 6);
+        assert_eq!(out, [2, 1, 3, 0xbb, 0xaa, 0xcc, 0]);
+
+        let mut short = [0u8; 4];
+        assert_eq!(Color::write_bytes(&colors, &mut short), 3);
+        assert_eq!(short, [2, 1, 3, 0]);
+
+        #[allow(deprecated)]
+        let wrapped = Color::wrap_slice(&colors);
+        assert_eq!(wrapped, &out[..6]);
+    }
+
+    #[test]
+    fn color_scaling() {
+        let colors = [
+            Color::BLACK,
+            Color::WHITE,
+            Color::new(1, 2, 3),
+            Color::new(0x12, 0x80, 0xfe),
+            Color::new(0xff, 0x00, 0x7f),
+        ];
+        for a in colors {
+            assert_eq!(a.scaled(0.0), Color::BLACK);
+            assert_eq!(a.scaled(-1.0), Color::BLACK);
+            assert_eq!(a.scaled(1.0), a);
+            assert_eq!(a * 1.0, a);
+            for b in colors {
+                assert_eq!(a.blend(b, 0.0), a);
+                assert_eq!(a.blend(b, 1.0), b);
+            }
+        }
+
+        // Components keep their meaning despite the GRB storage.
+        let color = Color::new(200, 100, 10);
+        assert_eq!(color * 0.5, Color::new(100, 50, 5));
+        assert_eq!(color * 2.0, Color::new(255, 200, 20));
+        assert_eq!(color.max_component(), 200);
+        assert_eq!(Color::new(1, 2, 3).max_component(), 3);
+    }
+
+    #[test]
+    fn color_ramp() {
+        let ramp = ColorRamp::new(vec![
+            (0.0, Color::BLACK),
+            (0.5, Color::new(100, 0, 0)),
+            (0.5, Color::new(200, 0, 0)),
+            (1.0, Color::WHITE),
+        ])
+        .unwrap();
+        assert_eq!(ramp.sample(0.0), Color::BLACK);
+        assert_eq!(ramp.sample(0.25), Color::new(50, 0, 0));
+        // Duplicate positions make a hard edge.
+        assert_eq!(ramp.sample(0.5), Color::new(200, 0, 0));
+        assert_eq!(ramp.sample(0.75), Color::new(228, 128, 128));
+        assert_eq!(ramp.sample(1.0), Color::WHITE);
+        assert_eq!(ramp.sample(-3.0), Color::BLACK);
+        assert_eq!(ramp.sample(7.0), Color::WHITE);
+        assert_eq!(ramp.sample(f32::NAN), Color::BLACK);
+
+        let single = ColorRamp::new(vec![(0.3, Color::RED)]).unwrap();
+        assert_eq!(single.sample(0.0), Color::RED);
+        assert_eq!(single.sample(1.0), Color::RED);
+
+        assert!(ColorRamp::new(Vec::new()).is_err());
+        assert!(ColorRamp::new(vec![(0.5, Color::RED), (0.2, Color::BLUE)]).is_err());
+        assert!(ColorRamp::new(vec![(f32::NAN, Color::RED)]).is_err());
+
+        let uniform = ColorRamp::uniform(&[Color::RED, Color::GREEN, Color::BLUE]).unwrap();
+        assert_eq!(uniform.stops(), [
+            (0.0, Color::RED),
+            (0.5, Color::GREEN),
+            (1.0, Color::BLUE)
+        ]);
+        assert_eq!(ColorRamp::uniform(&[Color::RED]).unwrap().stops(), [(
+            0.0,
+            Color::RED
+        )]);
+        assert!(ColorRamp::uniform(&[]).is_err());
+    }
+
+    #[test]
+    fn parse_color_ramp() {
+        let ramp = "30:#0044ff, 50:rgb(0, 255, 136),70:#ffaa00,85:red"
+            .parse::<ColorRamp>()
+            .unwrap();
+        assert_eq!(ramp.stops(), [
+            (30.0, Color::new(0x00, 0x44, 0xff)),
+            (50.0, Color::new(0x00, 0xff, 0x88)),
+            (70.0, Color::new(0xff, 0xaa, 0x00)),
+            (85.0, Color::RED),
+        ]);
+        assert_eq!("-5.5:blue".parse::<ColorRamp>().unwrap().stops(), [(
+            -5.5,
+            Color::BLUE
+        )]);
+
+        let error = |s: &str| s.parse::<ColorRamp>().unwrap_err().to_string();
+        assert_eq!(
+            error("30:#0044ff,50:#00ff8"),
+            "invalid color ramp stop 2 `50:#00ff8`: invalid color: expected 3 or 6 hex digits, \
+             got `#00ff8`"
+        );
+        assert_eq!(
+            error("30:red,blue"),
+            "invalid color ramp stop 2 `blue`: expected `position:color`"
+        );
+        assert_eq!(
+            error("hot:red"),
+            "invalid color ramp stop 1 `hot:red`: invalid position `hot`"
+        );
+        assert_eq!(
+            error("50:red,40:blue"),
+            "invalid color ramp stop 2 `40:blue`: position is below the previous stop at 50"
+        );
+        assert_eq!(error(""), "color ramp must have at least one stop");
+    }
+
+    #[test]
+    fn fan_curve() {
+        let curve = "30:20, 60:50%,80:100".parse::<FanCurve>().unwrap();
+        assert_eq!(curve.points(), [(30.0, 20), (60.0, 50), (80.0, 100)]);
+        assert_eq!(curve.duty(f32::NAN), 20);
+        assert_eq!(curve.duty(10.0), 20);
+        assert_eq!(curve.duty(30.0), 20);
+        assert_eq!(curve.duty(45.0), 35);
+        assert_eq!(curve.duty(61.0), 53);
+        assert_eq!(curve.duty(80.0), 100);
+        assert_eq!(curve.duty(95.0), 100);
+
+        let error = |s: &str| s.parse::<FanCurve>().unwrap_err().to_string();
+        assert_eq!(
+            error("30:20,60:150"),
+            "invalid fan curve point 2 `60:150`: invalid duty cycle `150`"
+        );
+        assert_eq!(
+            error("30"),
+            "invalid fan curve point 1 `30`: expected `temperature:percent`"
+        );
+        assert_eq!(
+            error("60:50,50:40"),
+            "invalid fan curve point 2 `50:40`: temperature is below the previous point at 60"
+        );
+        assert_eq!(error(""), "fan curve must have at least one point");
+    }
+
+    #[test]
+    fn hsv_interpolation() {
+        let rgb = Color::lerp(Color::BLUE, Color::RED, 0.5);
+        assert_eq!(rgb, Color::new(128, 0, 128));
+        let hsv = Color::lerp_hsv(Color::BLUE, Color::RED, 0.5);
+        assert_eq!(hsv, Color::new(255, 0, 255));
+        let (_, saturation, value) = hsv.to_hsv();
+        assert_eq!((saturation, value), (1.0, 1.0));
+
+        // The shorter arc is taken in both directions.
+        assert_eq!(
+            Color::lerp_hsv(Color::RED, Color::BLUE, 0.25),
+            Color::lerp_hsv(Color::BLUE, Color::RED, 0.75)
+        );
+        assert_eq!(
+            Color::lerp_hsv(
+                Color::from_hsv(350.0, 1.0, 1.0),
+                Color::from_hsv(10.0, 1.0, 1.0),
+                0.5
+            ),
+            Color::RED
+        );
+        // Gray colors don't change the hue.
+        assert_eq!(
+            Color::lerp_hsv(Color::WHITE, Color::GREEN, 0.5),
+            Color::from_hsv(120.0, 0.5, 1.0)
+        );
+
+        // Endpoints are exact.
+        let (a, b) = (Color::new(0x12, 0x34, 0x56), Color::new(0xfe, 0xdc, 0xba));
+        assert_eq!(Color::lerp_hsv(a, b, 0.0), a);
+        assert_eq!(Color::lerp_hsv(a, b, 1.0), b);
+        assert_eq!(Color::lerp_hsv(a, b, -1.0), a);
+        assert_eq!(Color::lerp_hsv(a, b, f32::NAN), a);
+
+        for color in [a, b, Color::BLUE, Color::new(51, 77, 102), Color::BLACK] {
+            let (h, s, v) = color.to_hsv();
+            assert_eq!(Color::from_hsv(h, s, v), color);
+        }
+
+        let ramp = ColorRamp::uniform(&[Color::BLUE, Color::RED])
+            .unwrap()
+            .with_interpolation(Interpolation::Hsv);
+        assert_eq!(ramp.sample(0.5), hsv);
+        assert_eq!(ramp.sample(0.0), Color::BLUE);
+        assert_eq!(ramp.sample(1.0), Color::RED);
+        assert_eq!("HSV".parse::<Interpolation>().unwrap(), Interpolation::Hsv);
+    }
+
+    #[test]
+    fn color_ramp_range() {
+        let first = Color::new(0x07, 0x05, 0x02);
+        let ramp = ColorRamp::new(vec![
+            (0.2, first),
+            (0.6, Color::new(0x79, 0x09, 0x00)),
+            (0.9, Color::RED),
+        ])
+        .unwrap();
+
+        for (base, warn) in [(30.0, 80.0), (20.0, 90.0), (0.0, 100.0), (-10.0, 10.0)] {
+            let at = |t: f32| ramp.sample_range(base + (warn - base) * t, base, warn);
+            assert_eq!(ramp.sample_range(base, base, warn), first);
+            assert_eq!(ramp.sample_range(base - 50.0, base, warn), first);
+            assert_eq!(ramp.sample_range(warn, base, warn), Color::RED);
+            assert_eq!(ramp.sample_range(warn + 50.0, base, warn), Color::RED);
+            // Below the first stop the color doesn't change.
+            assert_eq!(at(0.19), first);
+            assert_eq!(
+                at(0.4),
+                Color::lerp(first, Color::new(0x79, 0x09, 0x00), 0.5)
+            );
+            assert_eq!(at(0.5), ramp.sample(0.5));
+        }
+
+        assert_eq!(ramp.sample_range(f32::NAN, 30.0, 80.0), first);
+        assert_eq!(ramp.sample_range(49.0, 50.0, 50.0), first);
+        assert_eq!(ramp.sample_range(50.0, 50.0, 50.0), Color::RED);
+    }
+
+    #[test]
+    fn version_parsing() {
+        assert_eq!("1.8.0".parse::<Version>().unwrap(), Version::new(1, 8, 0));
+        assert_eq!("1.5".parse::<Version>().unwrap(), Version::new(1, 5, 0));
+        assert_eq!("2".parse::<Version>().unwrap(), Version::new(2, 0, 0));
+        for s in ["", "1.", "1..2", "1.2.3.4", "1.256", "v1.2", "1.-2"] {
+            assert!(s.parse::<Version>().is_err(), "{s}");
+        }
+
+        for version in [Version::new(0, 0, 0), Version::new(1, 12, 255)] {
+            assert_eq!(version.to_string().parse::<Version>().unwrap(), version);
+        }
+
+        let mut report = [0u8; 64];
+        report[..2].copy_from_slice(&[0x11, 0x01]);
+        report[0x11..0x14].copy_from_slice(&[1, 4, 2]);
+        assert_eq!(
+            Version::from_report(&report).unwrap(),
+            Version::new(1, 4, 2)
+        );
+        assert!(matches!(
+            Version::from_report(&report[..0x12]),
+            Err(Error::ShortRead { read: 0x12, .. })
+        ));
+        report[1] = 0x02;
+        assert!(Version::from_report(&report).is_err());
+    }
+
+    #[test]
+    fn version_ordering() {
+        let sorted = [
+            Version::new(0, 9, 9),
+            Version::new(1, 0, 0),
+            Version::new(1, 0, 1),
+            Version::new(1, 4, 9),
+            Version::new(1, 5, 0),
+            Version::new(1, 10, 0),
+            Version::new(2, 0, 0),
+        ];
+        for (i, a) in sorted.iter().enumerate() {
+            for (j, b) in sorted.iter().enumerate() {
+                assert_eq!(a.cmp(b), i.cmp(&j), "{a} vs {b}");
+            }
+        }
+        assert!("1.4.2".parse::<Version>().unwrap() < "1.5".parse().unwrap());
+    }
+
+    #[test]
+    fn kelvin_colors() {
+        for (kelvin, expected) in [
+            (1000, [255, 68, 0]),
+            (2700, [255, 167, 87]),
+            (6600, [255, 255, 255]),
+            (10000, [202, 218, 255]),
+            (500, [255, 68, 0]),
+            (40000, [191, 211, 255]),
+        ] {
+            let color = Color::from_kelvin(kelvin);
+            let actual = [color.red(), color.green(), color.blue()];
+            assert!(
+                actual.iter().zip(expected).all(|(a, e)| a.abs_diff(e) <= 2),
+                "{kelvin}K: {actual:?} != {expected:?}"
+            );
+        }
+
+        assert_eq!("2700K".parse::<Color>().unwrap(), Color::from_kelvin(2700));
+        assert_eq!("6500k".parse::<Color>().unwrap(), Color::from_kelvin(6500));
+        assert!("K".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn hsv_conversion() {
+        for (hue, expected) in [
+            (0.0, Color::RED),
+            (60.0, Color::new(255, 255, 0)),
+            (120.0, Color::GREEN),
+            (180.0, Color::new(0, 255, 255)),
+            (240.0, Color::BLUE),
+            (300.0, Color::new(255, 0, 255)),
+            (-120.0, Color::BLUE),
+        ] {
+            assert_eq!(Color::from_hsv(hue, 1.0, 1.0), expected, "{hue}");
+        }
+        assert_eq!(Color::from_hsv(123.0, 0.0, 1.0), Color::WHITE);
+        assert_eq!(Color::from_hsv(0.0, 1.0, 0.5), Color::new(128, 0, 0));
+    }
+
+    #[test]
+    fn parse_named_color() {
+        for (str, expected) in [
+            ("red", Color::RED),
+            ("Blue", Color::BLUE),
+            ("OFF", Color::BLACK),
+            ("orange", Color::new(0xff, 0xa5, 0)),
+        ] {
+            assert_eq!(str.parse::<Color>().unwrap(), expected);
+        }
+        for (name, color) in Color::NAMED {
+            assert_eq!(Color::from_name(name), Some(*color));
+        }
+
+        // Names are never looked up after `#`.
+        assert!("#red".parse::<Color>().is_err());
+        assert!("teal".parse::<Color>().is_err());
+        assert_eq!(Color::from_name("ff0000"), None);
+        assert_eq!(
+            "#add".parse::<Color>().unwrap(),
+            Color::new(0xaa, 0xdd, 0xdd)
+        );
+    }
+
+    #[test]
+    fn color_order_encoding() {
+        let color = Color::new(1, 2, 3);
+        assert_eq!(ColorOrder::default().encode(color), *color.inner());
+        assert_eq!(ColorOrder::Rgb.encode(color), [1, 2, 3]);
+        assert_eq!(ColorOrder::Bgr.encode(color), [3, 2, 1]);
+
+        for order in ColorOrder::ALL {
+            assert_eq!(order.to_string().parse::<ColorOrder>().unwrap(), order);
+        }
+        assert_eq!("RGB".parse::<ColorOrder>().unwrap(), ColorOrder::Rgb);
+        assert!("rgbw".parse::<ColorOrder>().is_err());
+
+        let mut buffer = [0u8; 7];
+        let len = ColorOrder::Rgb.encode_into(&[color; 3], &mut buffer);
+        assert_eq!(len, 6);
+        assert_eq!(buffer, [1, 2, 3, 1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn color_transform_lut() {
+        let identity = ColorTransform::new(1.0, 1.0).unwrap();
+        assert!(identity.is_identity());
+        assert_eq!(identity, ColorTransform::default());
+
+        let gamma = ColorTransform::new(2.2, 1.0).unwrap();
+        assert!(!gamma.is_identity());
+        assert_eq!(gamma.apply(0), 0);
+        assert_eq!(gamma.apply(0x20), 3);
+        assert_eq!(gamma.apply(0xff), 0xff);
+
+        let mut values = [0x80, 0xff];
+        ColorTransform::new(1.0, 0.5)
+            .unwrap()
+            .apply_slice(&mut values);
+        assert_eq!(values, [0x40, 0x80]);
+
+        assert!(ColorTransform::new(0.0, 1.0).is_err());
+        assert!(ColorTransform::new(f32::NAN, 1.0).is_err());
+        assert!(ColorTransform::new(1.0, 1.5).is_err());
+    }
+
+    #[test]
+    fn led_layouts() {
+        let ring = DeviceType::KrakenX3Ring.layout();
+        assert_eq!(ring, Layout::Ring { leds: 8 });
+        let top = ring.position(0).unwrap();
+        assert!((top.x - 0.5).abs() < 1e-6 && top.y.abs() < 1e-6);
+        let right = ring.position(2).unwrap();
+        assert_eq!(right.angle, Some(90.0));
+        assert!((right.x - 1.0).abs() < 1e-6 && (right.y - 0.5).abs() < 1e-6);
+        assert_eq!(ring.position(8), None);
+
+        let strip = DeviceType::Hue2Strip6.layout();
+        assert_eq!(strip.position(0).unwrap().x, 0.0);
+        assert_eq!(strip.position(5).unwrap().x, 1.0);
+        assert_eq!(
+            DeviceType::KrakenX3Logo.layout().position(0).unwrap().x,
+            0.5
+        );
+        assert_eq!(DeviceType::Unknown(0x42).layout().position(0), None);
+    }
+
+    #[test]
+    fn device_type_round_trip() {
+        for id in 0..=u8::MAX {
+            let device_type = DeviceType::from(id);
+            assert_eq!(u8::from(device_type), id);
+            assert_eq!(
+                device_type.led_count() == 0,
+                matches!(device_type, DeviceType::Unknown(_))
+            );
+            assert!(!device_type.name().starts_with("//"));
+        }
+
+        assert_eq!(DeviceType::from(0x05), DeviceType::Hue2Strip8);
+        assert_eq!(DeviceType::Hue2Strip8.to_string(), "Hue 2 strip (8 LEDs)");
+        assert_eq!(DeviceType::from(0x42), DeviceType::Unknown(0x42));
+    }
+
+    #[test]
+    fn channel_selector() {
+        assert_eq!(
+            "all".parse::<ChannelSelector>().unwrap(),
+            ChannelSelector::All
+        );
+        assert_eq!(ChannelSelector::All.resolve(3).unwrap(), [0, 1, 2]);
+
+        let parse = |s: &str| match s.parse::<ChannelSelector>().unwrap() {
+            ChannelSelector::Channels(channels) => channels,
+            ChannelSelector::All => panic!("unexpected `all` for `{s}`"),
+        };
+        assert_eq!(parse("0"), [0]);
+        assert_eq!(parse("0,2,5"), [0, 2, 5]);
+        assert_eq!(parse("1-3"), [1, 2, 3]);
+        assert_eq!(parse(" 4, 1 - 2 "), [4, 1, 2]);
+        // Duplicates and overlapping ranges keep the first occurrence.
+        assert_eq!(parse("2,0,2"), [2, 0]);
+        assert_eq!(parse("3-5,1-4"), [3, 4, 5, 1, 2]);
+
+        for s in ["", " ", ",", "0,", "1-", "-1", "3-1", "x", "1-2-3"] {
+            assert!(s.parse::<ChannelSelector>().is_err(), "{s:?}");
+        }
+
+        let selector = "0,2".parse::<ChannelSelector>().unwrap();
+        assert_eq!(selector.to_string(), "0,2");
+        assert_eq!(selector.resolve(3).unwrap(), [0, 2]);
+        let error = selector.resolve(2).unwrap_err();
+        assert!(matches!(error, Error::InvalidChannel {
+            requested: 2,
+            available: 2
+        }));
+        assert!(error.to_string().contains("2 channels"));
+    }
+
+    #[test]
+    fn accessory_table() {
+        let accessories = known_accessories();
+        assert_eq!(accessories.len(), DeviceType::KNOWN.len());
+        assert!(accessories.windows(2).all(|pair| pair[0].id < pair[1].id));
+        for accessory in accessories {
+            assert!(!accessory.name.is_empty());
+            assert!(!accessory.name.starts_with("//"), "{}", accessory.name);
+            assert!(accessory.led_count > 0);
+            assert_eq!(DeviceType::from(accessory.id), accessory.device_type);
+        }
+
+        let json = serde_json::to_value(AccessoryInfo::new(DeviceType::Hue2Strip8)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "id": 5,
+                "name": "Hue 2 strip (8 LEDs)",
+                "type": "hue2-strip-8",
+                "led_count": 8,
+            })
+        );
     }
 }
@@ -0,0 +1,196 @@
+//! Hot-plug detection of supported controllers.
+
+use std::time::Duration;
+
+use hidapi::HidApi;
+use serde::Serialize;
+
+use crate::controller::Controller;
+use crate::error::Result;
+use crate::info::ControllerInfo;
+use crate::{FindOptions, is_supported, open_controller, physical_devices};
+
+/// Change of the set of connected controllers.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ControllerEvent {
+    /// The path of the opened interface is stored in the info.
+    Added {
+        #[serde(flatten)]
+        info: ControllerInfo,
+    },
+    Removed {
+        path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        serial: Option<String>,
+    },
+}
+
+/// Calls the `callback` for every connected or disconnected controller.
+///
+/// Controllers which are present at the start are reported as added.
+/// Returns only if the device list can't be refreshed.
+pub fn watch_controllers<F>(api: &mut HidApi, interval: Duration, callback: F) -> Result<()>
+where
+    F: FnMut(ControllerEvent),
+{
+    watch_controllers_with(api, interval, &FindOptions::new(), callback)
+}
+
+/// Same as [`watch_controllers`] but only for devices matching the filters.
+pub fn watch_controllers_with<F>(
+    api: &mut HidApi,
+    interval: Duration,
+    options: &FindOptions,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(ControllerEvent),
+{
+    let mut watcher = ControllerWatcher::new(options.clone());
+    loop {
+        for event in watcher.poll(api)? {
+            callback(event);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// Keeps the set of connected controllers.
+///
+/// Devices are opened when they appear, so ones which are still enumerated
+/// but stopped answering are reported as removed too.
+pub struct ControllerWatcher {
+    options: FindOptions,
+    known: Vec<(DeviceKey, Box<dyn Controller>)>,
+}
+
+impl ControllerWatcher {
+    pub fn new(options: FindOptions) -> Self {
+        Self {
+            options,
+            known: Vec::new(),
+        }
+    }
+
+    /// Connected controllers in the order they were added.
+    pub fn controllers(&self) -> impl Iterator<Item = &dyn Controller> {
+        self.known.iter().map(|(_, controller)| controller.as_ref())
+    }
+
+    /// Refreshes the device list and returns changes since the last poll.
+    pub fn poll(&mut self, api: &mut HidApi) -> Result<Vec<ControllerEvent>> {
+        api.refresh_devices()?;
+
+        let devices = api
+            .device_list()
+            .filter(|device| is_supported(device, &self.options))
+            .collect::<Vec<_>>();
+        let devices = physical_devices(devices);
+        let present = devices
+            .iter()
+            .map(|interfaces| DeviceKey::new(interfaces[0]))
+            .collect::<Vec<_>>();
+
+        let mut events = Vec::new();
+        let known = std::mem::take(&mut self.known);
+        for (key, controller) in known {
+            if present.iter().any(|k| k.same_device(&key)) && controller.ping().is_ok() {
+                self.known.push((key, controller));
+            } else {
+                events.push(ControllerEvent::Removed {
+                    path: key.path,
+                    serial: key.serial,
+                });
+            }
+        }
+
+        for (key, interfaces) in present.into_iter().zip(devices) {
+            if self.known.iter().any(|(k, _)| k.same_device(&key)) {
+                continue;
+            }
+
+            // Devices which fail to open are retried on the next poll.
+            let Some(controller) = interfaces
+                .into_iter()
+                .find_map(|device| open_controller(api, device, &self.options).ok())
+            else {
+                continue;
+            };
+
+            events.push(ControllerEvent::Added {
+                info: controller.snapshot(),
+            });
+            self.known.push((key, controller));
+        }
+
+        Ok(events)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DeviceKey {
+    product_id: u16,
+    path: String,
+    serial: Option<String>,
+}
+
+impl DeviceKey {
+    fn new(info: &hidapi::DeviceInfo) -> Self {
+        Self {
+            product_id: info.product_id(),
+            path: info.path().to_string_lossy().into_owned(),
+            serial: info
+                .serial_number()
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned),
+        }
+    }
+
+    /// Devices are identified by their serial number if it is known,
+    /// and by the path otherwise.
+    fn same_device(&self, other: &Self) -> bool {
+        match (&self.serial, &other.serial) {
+            (Some(a), Some(b)) => self.product_id == other.product_id && a.eq_ignore_ascii_case(b),
+            _ => self.path == other.path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(product_id: u16, path: &str, serial: Option<&str>) -> DeviceKey {
+        DeviceKey {
+            product_id,
+            path: path.to_owned(),
+            serial: serial.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn device_identity() {
+        let device = key(0x2012, "/dev/hidraw1", Some("ABC"));
+        // The same controller on another interface or after re-plugging.
+        assert!(device.same_device(&key(0x2012, "/dev/hidraw4", Some("abc"))));
+        assert!(!device.same_device(&key(0x2021, "/dev/hidraw4", Some("ABC"))));
+        assert!(!device.same_device(&key(0x2012, "/dev/hidraw1", Some("DEF"))));
+
+        let anonymous = key(0x2012, "/dev/hidraw2", None);
+        assert!(anonymous.same_device(&key(0x2012, "/dev/hidraw2", None)));
+        assert!(!anonymous.same_device(&key(0x2012, "/dev/hidraw3", None)));
+    }
+
+    #[test]
+    fn event_json() {
+        let event = ControllerEvent::Removed {
+            path: "/dev/hidraw1".to_owned(),
+            serial: None,
+        };
+        assert_eq!(
+            serde_json::to_value(event).unwrap(),
+            serde_json::json!({ "event": "removed", "path": "/dev/hidraw1" })
+        );
+    }
+}
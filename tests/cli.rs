@@ -0,0 +1,83 @@
+//! Exit codes and error reports of the CLI, no devices are required.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Serial which doesn't match any connected controller.
+const MISSING_SERIAL: &str = "nzxtcli-test-missing-serial";
+
+fn nzxtcli(args: &[&str]) -> Output {
+    // Keep the user config and state out of the tests.
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    Command::new(env!("CARGO_BIN_EXE_nzxtcli"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_STATE_HOME", dir.join("state"))
+        .output()
+        .unwrap()
+}
+
+fn json_error(output: &Output) -> serde_json::Value {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let report = serde_json::from_str::<serde_json::Value>(stderr.trim())
+        .unwrap_or_else(|e| panic!("stderr is not a JSON object ({e}): {stderr}"));
+    report["error"].clone()
+}
+
+#[test]
+fn success() {
+    let output = nzxtcli(&["list-accessories"]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn usage_error() {
+    let output = nzxtcli(&["set-color"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage:"));
+
+    let output = nzxtcli(&["set-color", "--format", "json"]);
+    assert_eq!(output.status.code(), Some(2));
+    let error = json_error(&output);
+    assert_eq!(error["kind"], "usage");
+    assert!(error["device"].is_null());
+}
+
+#[test]
+fn no_matching_device() {
+    let output = nzxtcli(&["--serial", MISSING_SERIAL, "set-color", "red"]);
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = nzxtcli(&["--serial", MISSING_SERIAL, "--format=json", "off"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(json_error(&output)["kind"], "no-device");
+}
+
+#[test]
+fn config_error() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("invalid-config.toml");
+    std::fs::write(&path, "[cpu-temp]\nintervl = \"2s\"\n").unwrap();
+
+    let output = nzxtcli(&[
+        "--config",
+        path.to_str().unwrap(),
+        "--format",
+        "json",
+        "list",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+    let error = json_error(&output);
+    assert_eq!(error["kind"], "usage");
+    let message = error["message"].as_str().unwrap();
+    assert!(message.contains("invalid-config.toml:2: `cpu-temp.intervl`"));
+}
+
+#[test]
+fn exit_codes_in_help() {
+    let output = nzxtcli(&["--help"]);
+    assert_eq!(output.status.code(), Some(0));
+    let help = String::from_utf8_lossy(&output.stdout);
+    assert!(help.contains("Exit codes:"));
+    assert!(help.contains("3  no matching device"));
+    assert!(help.contains("8  some of the controllers failed to update"));
+}